@@ -0,0 +1,44 @@
+use crate::{storage::Storage, Config, Result};
+use clap::ArgMatches;
+use colored::Colorize;
+use pbmx_kit::state::State;
+
+pub fn run(_: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let sk = store.load_key()?;
+    let chain = store.load_chain()?;
+
+    let mut state = State::new(sk);
+    if !cfg.tokens.is_empty() {
+        state.declare_tokens(cfg.tokens.keys().cloned());
+    }
+    let findings = state.audit(&chain);
+
+    for f in findings.iter() {
+        let block = chain.blocks().find(|b| b.id() == f.block);
+        let payload = f
+            .payload
+            .and_then(|id| block?.payloads().find(|p| p.id() == id));
+        print!("{}", format!("{:16}", f.block).yellow());
+        match payload {
+            Some(p) => print!(" {}", p.display_short_with(chain.hash_algorithm())),
+            None => print!(" {}", "(block)".blue()),
+        }
+        println!(" {}", f.error.to_string().red().bold());
+    }
+
+    if findings.is_empty() {
+        println!(
+            "{} {} blocks",
+            " + No problems found in".green().bold(),
+            chain.count()
+        );
+    } else {
+        println!(
+            "{}",
+            format!(" ! {} problems found", findings.len())
+                .yellow()
+                .bold()
+        );
+    }
+    Ok(())
+}
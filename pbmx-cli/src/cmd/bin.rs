@@ -1,9 +1,9 @@
-use crate::{state::State, Config, Error, Result};
+use crate::{state::State, storage::Storage, Config, Error, Result};
 use clap::{value_t, ArgMatches};
 use pbmx_kit::chain::Payload;
 use std::{fs, path::PathBuf};
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let data = if let Ok(string) = value_t!(m, "DATA", String) {
         base64::decode_config(&string, base64::URL_SAFE_NO_PAD)
             .map_err(|_| pbmx_kit::Error::Decoding)?
@@ -13,7 +13,7 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
         return Err(Error::InvalidData);
     };
 
-    let mut state = State::read(false)?;
+    let mut state = State::read(false, cfg, store)?;
 
     state.payloads.push(Payload::Bytes(data));
 
@@ -0,0 +1,15 @@
+use crate::{state::State, storage::Storage, Config, Result};
+use clap::ArgMatches;
+use colored::Colorize;
+use pbmx_kit::chain::Payload;
+
+pub fn run(_: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let mut state = State::read(true, cfg, store)?;
+
+    let digest = state.base.digest();
+    state.payloads.push(Payload::Checkpoint(digest));
+    println!("{} {:16}", " + Checkpoint".green().bold(), digest);
+
+    state.save_payloads()?;
+    Ok(())
+}
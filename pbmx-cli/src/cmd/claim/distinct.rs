@@ -0,0 +1,58 @@
+use crate::{state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::chain::Payload;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let name = value_t!(m, "STACK", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let id = state
+        .base
+        .stacks
+        .get_by_str(&name)
+        .ok_or(Error::InvalidData)?
+        .id();
+    let check = state.base.distinct_checks.get(&id);
+    if check.is_none() && !state.base.has_only_fixed_tokens() {
+        println!(
+            "{}",
+            " ! Some open tokens have several points, use a deck template"
+                .yellow()
+                .bold(),
+        );
+        return Err(Error::InvalidData);
+    }
+
+    match state.base.prove_distinct(&id) {
+        Some(payload) => {
+            match payload {
+                Payload::ProveDistinct(..) => {
+                    println!("{} {:16}", " + Blind stack".green().bold(), id)
+                }
+                _ => println!("{} {:16}", " + Distinct secret".green().bold(), id),
+            }
+            state.payloads.push(payload);
+            state.save_payloads()?;
+        }
+        None => {
+            let check = check.ok_or(Error::InvalidData)?;
+            let parties = state.base.vtmf.parties();
+            match check.result(&state.base.vtmf) {
+                Some(true) => println!("{} {}", " - No duplicates".green().bold(), name),
+                Some(false) => println!("{} {}", " - Duplicates".red().bold(), name),
+                None => println!(
+                    "{}: {}/{} blindings, {}/{} secrets",
+                    name.yellow(),
+                    check.blinders().len(),
+                    parties,
+                    check.secret_parties().len(),
+                    parties
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
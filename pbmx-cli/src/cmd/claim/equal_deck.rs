@@ -0,0 +1,60 @@
+use crate::{state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::{
+    chain::Payload,
+    crypto::{perm::Shuffles, vtmf::Mask},
+};
+use rand::{thread_rng, Rng};
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let name = value_t!(m, "HIDDEN", String)?;
+    let ref_id = value_t!(m, "REFERENCE", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let reference = state
+        .base
+        .stacks
+        .get_by_str(&ref_id)
+        .ok_or(Error::InvalidData)?
+        .clone();
+    if !reference.iter().all(Mask::is_open) {
+        println!(
+            "{} {}",
+            " ! Reference stack is not open".yellow().bold(),
+            ref_id
+        );
+        return Err(Error::InvalidData);
+    }
+
+    // the witness only exists while building the hidden stack, so the claim
+    // is made for a fresh one
+    let pi = thread_rng().sample(Shuffles(reference.len()));
+    let (hidden, r, proof) = state.base.vtmf.prove_equal_multiset(&reference, &pi);
+
+    let id1 = reference.id();
+    let id2 = hidden.id();
+    println!(
+        "{} {:16} \u{224B} {:16}",
+        " + Shuffle stack".green().bold(),
+        id1,
+        id2
+    );
+    println!("{} {:16} = {:16}", " + Equal deck".green().bold(), id2, id1);
+    println!("{} {:16} {}", " + Name stack".green().bold(), id2, name);
+    state.payloads.push(Payload::ReshuffleFrom(
+        id1,
+        id1,
+        hidden.clone(),
+        proof.clone(),
+    ));
+    state
+        .payloads
+        .push(Payload::ProveEqualMultiset(id2, id1, proof));
+    state.payloads.push(Payload::NameStack(id2, name));
+    state.save_secrets(&hidden, r)?;
+
+    state.save_payloads()?;
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use crate::{state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::crypto::vtmf::Mask;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let greater = value_t!(m, "GREATER", String)?;
+    let lesser = value_t!(m, "LESSER", String)?;
+    let max = match value_t!(m, "MAX", u64) {
+        Ok(max) => max,
+        Err(_) => match cfg.tokens.keys().max() {
+            Some(max) => max + 1,
+            None => {
+                println!("{}", " ! No tokens configured, pass --max".yellow().bold());
+                return Err(Error::InvalidData);
+            }
+        },
+    };
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let a = single_token(&state, &greater)?;
+    let b = single_token(&state, &lesser)?;
+    match state.base.prove_greater(&a, &b, max) {
+        Some(payload) => {
+            println!(
+                "{} {} > {}",
+                " + Prove greater".green().bold(),
+                greater,
+                lesser
+            );
+            state.payloads.push(payload);
+        }
+        None => {
+            println!(
+                "{} {} > {}",
+                " ! Cannot prove".yellow().bold(),
+                greater,
+                lesser
+            );
+            return Err(Error::InvalidData);
+        }
+    }
+
+    state.save_payloads()?;
+    Ok(())
+}
+
+fn single_token(state: &State, id: &str) -> Result<Mask> {
+    let stack = state.base.stacks.get_by_str(id).ok_or(Error::InvalidData)?;
+    match &stack[..] {
+        [m] => Ok(*m),
+        _ => {
+            println!("{} {}", " ! Not a single token".yellow().bold(), id);
+            Err(Error::InvalidData)
+        }
+    }
+}
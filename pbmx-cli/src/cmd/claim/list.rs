@@ -0,0 +1,32 @@
+use crate::{state::State, storage::Storage, Config, Result};
+use clap::ArgMatches;
+use colored::Colorize;
+
+pub fn run(_: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let state = State::read(false, cfg, store)?;
+
+    for claim in state.base.claims_view() {
+        print!(
+            "{} {}",
+            format!("{:8}", claim.block).yellow(),
+            claim.kind.to_string().green().bold()
+        );
+        for id in claim.stacks.iter() {
+            print!(" {:8}", id);
+        }
+        print!(" {}", "by".blue().bold());
+        for fp in claim.parties.iter() {
+            match state.base.names.get(fp) {
+                Some(name) => print!(" {}", name),
+                None => print!(" {:8}", fp),
+            }
+        }
+        if claim.verified {
+            println!(" {}", "ok".green());
+        } else {
+            println!(" {}", "FAILED".red().bold());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use crate::{storage::Storage, Config, Error, Result};
+use clap::ArgMatches;
+
+pub mod distinct;
+pub mod equal_deck;
+pub mod greater;
+pub mod list;
+pub mod peek;
+pub mod verify;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    match m.subcommand() {
+        ("distinct", Some(sub_m)) => distinct::run(sub_m, cfg, store),
+        ("equal-deck", Some(sub_m)) => equal_deck::run(sub_m, cfg, store),
+        ("greater", Some(sub_m)) => greater::run(sub_m, cfg, store),
+        ("peek", Some(sub_m)) => peek::run(sub_m, cfg, store),
+        ("list", Some(sub_m)) => list::run(sub_m, cfg, store),
+        ("verify", Some(sub_m)) => verify::run(sub_m, cfg, store),
+        _ => Err(Error::InvalidSubcommand),
+    }
+}
@@ -0,0 +1,35 @@
+use crate::{state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::chain::Payload;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let id = value_t!(m, "STACK", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let id1 = state
+        .base
+        .stacks
+        .get_by_str(&id)
+        .ok_or(Error::InvalidData)?
+        .id();
+    let nonce = store.load_peek_nonce(id1)?;
+    let payloads = state
+        .base
+        .open_peek(&id1, &nonce)
+        .ok_or(Error::InvalidData)?;
+
+    for payload in payloads {
+        match payload {
+            Payload::PublishShares(..) => {
+                println!("{} {:16}", " + Publish secrets".green().bold(), id1)
+            }
+            _ => println!("{} {:16}", " + Open peek".green().bold(), id1),
+        }
+        state.payloads.push(payload);
+    }
+
+    state.save_payloads()?;
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use crate::{cmd::claim::distinct, state::State, storage::Storage, Config, Result};
+use clap::ArgMatches;
+use colored::Colorize;
+use pbmx_kit::state::DistinctStep;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    if m.is_present("STACK") {
+        return distinct::run(m, cfg, store);
+    }
+
+    let state = State::read(false, cfg, store)?;
+    let my_fp = state.base.vtmf.fingerprint();
+
+    let mut checks: Vec<_> = state.base.distinct_checks.iter().collect();
+    checks.sort_by_key(|(id, _)| id.to_string());
+    for (id, check) in checks {
+        print!("{:16} ", id);
+        match check.next_step(&my_fp) {
+            DistinctStep::Blind => println!("{}", "blind the stack".yellow().bold()),
+            DistinctStep::Reveal => println!("{}", "publish secret shares".yellow().bold()),
+            DistinctStep::Wait => {
+                let parties = state.base.vtmf.parties();
+                println!(
+                    "waiting: {}/{} blindings, {}/{} secrets",
+                    check.blinders().len(),
+                    parties,
+                    check.secret_parties().len(),
+                    parties
+                )
+            }
+            DistinctStep::Done => match check.result(&state.base.vtmf) {
+                Some(true) => println!("{}", "no duplicates".green().bold()),
+                _ => println!("{}", "duplicates".red().bold()),
+            },
+        }
+    }
+
+    Ok(())
+}
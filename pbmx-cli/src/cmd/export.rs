@@ -0,0 +1,39 @@
+use crate::{constants::BLOCK_EXTENSION, file, state::State, storage::Storage, Config, Result};
+use clap::{value_t, values_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::{chain::Id, serde::Message};
+use std::{fs, path::PathBuf};
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let dir = value_t!(m, "OUTPUT", PathBuf)?;
+    let since = values_t!(m, "SINCE", String).unwrap_or_default();
+
+    let state = State::read(false, cfg, store)?;
+
+    let heads = since
+        .iter()
+        .map(|s| s.parse::<Id>())
+        .collect::<pbmx_kit::Result<Vec<_>>>()?;
+    let blocks = state.base.chain.blocks_since(&heads);
+
+    if cfg.dry_run {
+        for block in blocks {
+            println!(
+                "{} {:16}",
+                " ~ Dry run, block not exported".yellow().bold(),
+                block.id()
+            );
+        }
+        return Ok(());
+    }
+
+    fs::create_dir_all(&dir)?;
+    for block in blocks {
+        let id = block.id();
+        let mut path = dir.clone();
+        path.push(format!("{}.{}", id, BLOCK_EXTENSION));
+        file::write_new(path, block.encode()?)?;
+        println!("{} {:16}", " > Export block".green().bold(), id);
+    }
+    Ok(())
+}
@@ -3,19 +3,38 @@ use crate::{
         BLOCKS_FOLDER_NAME, CONFIG_FILE_CONTENTS, CONFIG_FILE_NAME, CURRENT_BLOCK_FILE_NAME,
         IGNORE_FILE_CONTENTS, IGNORE_FILE_NAME, KEY_FILE_NAME, SECRETS_FOLDER_NAME,
     },
-    file, Config, Result,
+    file,
+    storage::Storage,
+    Config, Result,
 };
 use clap::{value_t, ArgMatches};
-use pbmx_kit::{chain::Payload, crypto::keys::PrivateKey, serde::Message};
+use colored::Colorize;
+use pbmx_kit::{
+    chain::Payload,
+    crypto::{hash::HashAlgorithm, keys::PrivateKey},
+    serde::Message,
+};
 use rand::thread_rng;
 use std::{fs, path::PathBuf};
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, _: &dyn Storage) -> Result<()> {
     let mut path = value_t!(m, "PATH", PathBuf).unwrap_or_else(|_| PathBuf::from("."));
 
+    if cfg.dry_run {
+        println!(
+            "{} {}",
+            " ~ Dry run, game folder not created".yellow().bold(),
+            path.display()
+        );
+        return Ok(());
+    }
+
     let mut rng = thread_rng();
     let sk = PrivateKey::random(&mut rng);
-    let current = <Vec<Payload>>::new();
+    let mut current = <Vec<Payload>>::new();
+    if let Ok(hash) = value_t!(m, "HASH", HashAlgorithm) {
+        current.push(Payload::SelectHash(hash));
+    }
 
     fs::create_dir_all(&path)?;
 
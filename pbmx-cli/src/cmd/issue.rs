@@ -1,16 +1,9 @@
-use crate::{
-    constants::{BLOCKS_FOLDER_NAME, BLOCK_EXTENSION},
-    file,
-    state::State,
-    Config, Result,
-};
+use crate::{state::State, storage::Storage, Config, Result};
 use clap::ArgMatches;
 use colored::Colorize;
-use pbmx_kit::serde::Message;
-use std::path::PathBuf;
 
-pub fn run(_: &ArgMatches, _: &Config) -> Result<()> {
-    let mut state = State::read(false)?;
+pub fn run(_: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let mut state = State::read(false, cfg, store)?;
 
     let block = {
         let mut builder = state.base.chain.build_block();
@@ -21,10 +14,16 @@ pub fn run(_: &ArgMatches, _: &Config) -> Result<()> {
     };
     let id = block.id();
 
-    let block_file = format!("{}.{}", id, BLOCK_EXTENSION);
-    let mut path = PathBuf::from(BLOCKS_FOLDER_NAME);
-    path.push(block_file);
-    file::write_new(path, block.encode()?)?;
+    if cfg.dry_run {
+        println!(
+            "{} {:16}",
+            " ~ Dry run, block not issued".yellow().bold(),
+            id
+        );
+        return Ok(());
+    }
+
+    store.append_block(&block)?;
     println!("{} {:16}", " ^ Issue block".green().bold(), id);
 
     state.payloads.clear();
@@ -1,18 +1,62 @@
-use crate::{state::State, Config, Result};
+use crate::{state::State, storage::Storage, Config, Result};
 use clap::ArgMatches;
 use colored::Colorize;
 use pbmx_kit::chain::Payload;
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
-    let name = value_t!(m, "NAME", String)?;
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let name = value_t!(m, "NAME", String)
+        .ok()
+        .or_else(|| cfg.player_name.clone())
+        .ok_or_else(|| {
+            clap::Error::with_description(
+                "no player name given and none configured",
+                clap::ErrorKind::MissingRequiredArgument,
+            )
+        })?;
 
-    let mut state = State::read(false)?;
+    let mut state = State::read(false, cfg, store)?;
 
     let key = state.base.vtmf.public_key();
-    let fp = key.fingerprint();
 
+    if !state.base.chain.is_empty() {
+        println!(
+            "{} {} blocks",
+            " + Verified existing chain".green().bold(),
+            state.base.chain.count()
+        );
+    }
+    let payload = Payload::PublishKey(name.clone(), key);
+    let mut preview = state.base.chain.build_block();
+    // pending payloads go first, they may choose the game's hash function
+    for p in state.payloads.iter().cloned() {
+        preview.add_payload(p);
+    }
+    preview.add_payload(payload.clone());
+    state.base.add_block(&preview.build(&store.load_key()?))?;
+    let fp = state.base.vtmf.fingerprint();
+    if state.base.is_late_join(&fp) {
+        println!(
+            "{}",
+            " ! Joining after masking, earlier masks cannot be revealed by all players"
+                .yellow()
+                .bold()
+        );
+        let mut names: Vec<_> = state
+            .base
+            .stacks
+            .names()
+            .filter(|n| {
+                let id = state.base.stacks.get_by_name(n).unwrap().id();
+                state.base.masked_before_join(&id)
+            })
+            .collect();
+        names.sort();
+        for n in names {
+            println!("{} {}", " ! Cannot privately reveal".yellow().bold(), n);
+        }
+    }
     println!("{} {} {}", " + Publish key ".green().bold(), &name, fp);
-    state.payloads.push(Payload::PublishKey(name, key));
+    state.payloads.push(payload);
 
     state.save_payloads()?;
     Ok(())
@@ -0,0 +1,48 @@
+use super::read_passphrase;
+use crate::{file, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::serde::Message;
+use std::path::PathBuf;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let out = value_t!(m, "OUT", PathBuf).ok();
+    let unencrypted = m.is_present("UNENCRYPTED");
+
+    let sk = store.load_key()?;
+
+    let bytes = if unencrypted {
+        sk.encode()?
+    } else {
+        let passphrase = read_passphrase("Passphrase: ")?;
+        if passphrase.is_empty() {
+            println!(
+                "{}",
+                " ! Empty passphrase, use --unencrypted to export without one"
+                    .yellow()
+                    .bold()
+            );
+            return Err(Error::InvalidData);
+        }
+        if read_passphrase("Repeat passphrase: ")? != passphrase {
+            println!("{}", " ! Passphrases do not match".yellow().bold());
+            return Err(Error::InvalidData);
+        }
+        sk.seal(&passphrase).encode()?
+    };
+
+    if cfg.dry_run {
+        println!("{}", " ~ Dry run, key not exported".yellow().bold());
+        return Ok(());
+    }
+
+    match out {
+        Some(out) => {
+            file::write_new(&out, &bytes)?;
+            println!("{} {}", " > Export key".green().bold(), out.display());
+        }
+        None => println!("{}", base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)),
+    }
+
+    Ok(())
+}
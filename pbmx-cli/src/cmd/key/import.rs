@@ -0,0 +1,51 @@
+use super::read_passphrase;
+use crate::{storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::{
+    crypto::keys::{PrivateKey, SealedKey},
+    serde::Message,
+};
+use std::{fs, path::PathBuf, str};
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let file = value_t!(m, "FILE", PathBuf)?;
+    let force = m.is_present("FORCE");
+
+    let raw = fs::read(&file)?;
+    let bytes = str::from_utf8(&raw)
+        .ok()
+        .and_then(|s| base64::decode_config(s.trim(), base64::URL_SAFE_NO_PAD).ok())
+        .unwrap_or(raw);
+    let sk = match SealedKey::decode(&bytes) {
+        Ok(sealed) => sealed.open(&read_passphrase("Passphrase: ")?)?,
+        Err(_) => PrivateKey::decode(&bytes)?,
+    };
+    let fp = sk.fingerprint();
+
+    if let Ok(current) = store.load_key() {
+        if current == sk {
+            println!("{} {}", " - Key already in use".green().bold(), fp);
+            return Ok(());
+        }
+        if !force {
+            println!(
+                "{} {}",
+                " ! A different key is in use".yellow().bold(),
+                current.fingerprint()
+            );
+            println!("{}", " ! Use --force to replace it".yellow().bold());
+            return Err(Error::InvalidData);
+        }
+    }
+
+    if cfg.dry_run {
+        println!("{} {}", " ~ Dry run, key not imported".yellow().bold(), fp);
+        return Ok(());
+    }
+
+    store.save_key(&sk)?;
+    println!("{} {}", " + Import key".green().bold(), fp);
+
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use crate::{storage::Storage, Config, Error, Result};
+use clap::ArgMatches;
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+pub mod export;
+pub mod import;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    match m.subcommand() {
+        ("export", Some(sub_m)) => export::run(sub_m, cfg, store),
+        ("import", Some(sub_m)) => import::run(sub_m, cfg, store),
+        _ => Err(Error::InvalidSubcommand),
+    }
+}
+
+fn read_passphrase(prompt: &str) -> Result<String> {
+    eprint!("{}", prompt);
+    io::stderr().flush()?;
+    let hidden = set_echo(false);
+    let mut line = String::new();
+    let read = io::stdin().read_line(&mut line);
+    if hidden {
+        set_echo(true);
+        eprintln!();
+    }
+    read?;
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string())
+}
+
+fn set_echo(on: bool) -> bool {
+    let status = Command::new("stty")
+        .arg(if on { "echo" } else { "-echo" })
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .status();
+    matches!(status, Ok(s) if s.success())
+}
@@ -1,27 +1,121 @@
-use crate::{indices::display_indices, state::State, Config, Result};
-use clap::ArgMatches;
+use crate::{indices::display_indices, state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
 use colored::Colorize;
+use curve25519_dalek::scalar::Scalar;
 use pbmx_kit::{
     chain::{Block, BlockVisitor, ChainVisitor, Id, PayloadVisitor},
     crypto::{
-        keys::PublicKey,
+        hash::HashAlgorithm,
+        keys::{Fingerprint, PublicKey},
         vtmf::{
-            EntanglementProof, Mask, MaskProof, SecretShare, SecretShareProof, ShiftProof,
-            ShuffleProof, Stack,
+            DistinctProof, EntanglementProof, Mask, MaskProof, PrivateMaskProof, SecretShare,
+            SecretShareProof, ShiftProof, ShuffleProof, Stack,
         },
     },
 };
 use std::fmt::{self, Display, Formatter};
 
-pub fn run(_: &ArgMatches, cfg: &Config) -> Result<()> {
-    let state = State::read(false)?;
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let state = State::read(false, cfg, store)?;
 
-    state.base.chain.visit(&mut LogPrinter(&state, cfg));
+    if m.is_present("GRAPH") {
+        print_graph(&state);
+    } else if m.is_present("PROOFS") {
+        print_proofs(&state);
+    } else if let Some(query) = m.value_of("GREP") {
+        for (id, text) in state.base.chain.search_text(query) {
+            println!("{} {}", format!("{:8}", id).yellow(), text);
+        }
+    } else if let Some(since) = m.value_of("SINCE") {
+        let id = find_block(&state, since).ok_or(Error::InvalidData)?;
+        let mut printer = LogPrinter(&state, cfg);
+        for block in state.base.chain.descendants(&id) {
+            printer.visit_block(block);
+        }
+    } else if m.is_present("LAST") {
+        let n = value_t!(m, "LAST", usize)?;
+        let chain = &state.base.chain;
+        let mut printer = LogPrinter(&state, cfg);
+        for block in chain.blocks().skip(chain.count().saturating_sub(n)) {
+            printer.visit_block(block);
+        }
+    } else {
+        state.base.chain.visit(&mut LogPrinter(&state, cfg));
+    }
 
     Ok(())
 }
 
-struct LogPrinter<'a>(&'a State, &'a Config);
+fn find_block(state: &State, prefix: &str) -> Option<Id> {
+    let mut found = state
+        .base
+        .chain
+        .blocks()
+        .map(Block::id)
+        .filter(|id| id.to_string().starts_with(prefix));
+    found.next().xor(found.next())
+}
+
+fn print_graph(state: &State) {
+    let chain = &state.base.chain;
+    println!("digraph pbmx {{");
+    println!("    node [shape=box, fontname=monospace];");
+    for block in chain.blocks() {
+        let id = block.id();
+        let mut label = format!("{:8}", id);
+        let fp = block.signer();
+        if let Some(n) = state.base.names.get(&fp) {
+            label.push_str(&format!(" by {}", dot_escape(n)));
+        } else {
+            label.push_str(&format!(" by {:8}", fp));
+        }
+        label.push_str("\\l");
+        for payload in block.payloads() {
+            label.push_str(&dot_escape(
+                &payload
+                    .display_short_with(chain.hash_algorithm())
+                    .to_string(),
+            ));
+            label.push_str("\\l");
+        }
+        let mut attrs = String::new();
+        if chain.roots().contains(&id) {
+            attrs.push_str(", peripheries=2");
+        }
+        if chain.heads().contains(&id) {
+            attrs.push_str(", style=filled, fillcolor=lightgrey");
+        }
+        println!("    \"{}\" [label=\"{}\"{}];", id, label, attrs);
+        for parent in block.parent_ids() {
+            println!("    \"{}\" -> \"{}\";", parent, id);
+        }
+    }
+    println!("}}");
+}
+
+fn print_proofs(state: &State) {
+    for record in state.base.proof_log() {
+        print!(
+            "{} {}",
+            format!("{:8}", record.block).yellow(),
+            record.kind.to_string().green().bold()
+        );
+        for id in record.stacks.iter() {
+            print!(" {:8}", id);
+        }
+        if record.verified {
+            println!(" {}", "ok".green());
+        } else {
+            println!(" {}", "FAILED".red().bold());
+        }
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+struct LogPrinter<'a>(&'a State<'a>, &'a Config);
 
 impl<'a> ChainVisitor for LogPrinter<'a> {}
 
@@ -53,13 +147,26 @@ impl<'a> BlockVisitor for LogPrinter<'a> {
 
 impl<'a> PayloadVisitor for LogPrinter<'a> {
     fn visit_publish_key(&mut self, _: &Block, name: &str, pk: &PublicKey) {
-        println!("    {} {} {}", "key".green().bold(), name, pk.fingerprint());
+        println!(
+            "    {} {} {}",
+            "key".green().bold(),
+            name,
+            self.0.base.vtmf.fingerprint_of(pk)
+        );
+    }
+
+    fn visit_rename_player(&mut self, _: &Block, fp: Fingerprint, name: &str) {
+        println!("    {} {} {}", "rename".green().bold(), name, fp);
     }
 
     fn visit_open_stack(&mut self, _: &Block, stack: &Stack) {
         println!("    {} {:8}", "stack".green().bold(), stack.id());
     }
 
+    fn visit_deck_template(&mut self, _: &Block, name: &str) {
+        println!("    {} {}", "deck template".green().bold(), name);
+    }
+
     fn visit_mask_stack(&mut self, _: &Block, id: Id, stack: &Stack, _: &[MaskProof]) {
         println!(
             "    {} {:8} \u{21AC} {:8}",
@@ -70,11 +177,36 @@ impl<'a> PayloadVisitor for LogPrinter<'a> {
     }
 
     fn visit_shuffle_stack(&mut self, _: &Block, id: Id, stack: &Stack, _: &ShuffleProof) {
+        match self.0.base.reshuffled_from(&stack.id()) {
+            Some(ids) => println!(
+                "    {} {:8} \u{224B} {:8}",
+                "reshuffle".green().bold(),
+                DisplayPile(ids),
+                stack.id()
+            ),
+            None => println!(
+                "    {} {:8} \u{224B} {:8}",
+                "shuffle".green().bold(),
+                id,
+                stack.id()
+            ),
+        }
+    }
+
+    fn visit_reshuffle_from(
+        &mut self,
+        _: &Block,
+        reference: Id,
+        id: Id,
+        stack: &Stack,
+        _: &ShuffleProof,
+    ) {
         println!(
-            "    {} {:8} \u{224B} {:8}",
-            "shuffle".green().bold(),
+            "    {} {:8} \u{224B} {:8} from {:8}",
+            "reshuffle".green().bold(),
             id,
-            stack.id()
+            stack.id(),
+            reference
         );
     }
 
@@ -110,6 +242,10 @@ impl<'a> PayloadVisitor for LogPrinter<'a> {
         println!("    {} {:8} {}", "name".green().bold(), id, name);
     }
 
+    fn visit_tag_stack(&mut self, _: &Block, id: Id, tags: &[String]) {
+        println!("    {} {:8} {}", "tag".green().bold(), id, tags.join(" "));
+    }
+
     fn visit_publish_shares(
         &mut self,
         _: &Block,
@@ -120,6 +256,22 @@ impl<'a> PayloadVisitor for LogPrinter<'a> {
         println!("    {} {:8}", "secret".green().bold(), id);
     }
 
+    fn visit_publish_shares_at(
+        &mut self,
+        _: &Block,
+        id: Id,
+        indices: &[usize],
+        _: &[SecretShare],
+        _: &[SecretShareProof],
+    ) {
+        println!(
+            "    {} {:8}{}",
+            "secret".green().bold(),
+            id,
+            display_indices(indices)
+        );
+    }
+
     fn visit_random_spec(&mut self, _: &Block, id: &str, spec: &str) {
         println!("    {} {}: {}", "rng".green().bold(), id, spec);
     }
@@ -128,6 +280,10 @@ impl<'a> PayloadVisitor for LogPrinter<'a> {
         println!("    {} {}", "rng entropy".green().bold(), id);
     }
 
+    fn visit_beacon_entropy(&mut self, _: &Block, id: &str, round: u64, _: &[u8], _: &[u8]) {
+        println!("    {} {} #{}", "rng beacon".green().bold(), id, round);
+    }
+
     fn visit_random_reveal(&mut self, _: &Block, id: &str, _: &SecretShare, _: &SecretShareProof) {
         println!("    {} {}", "rng reveal".green().bold(), id);
     }
@@ -147,6 +303,141 @@ impl<'a> PayloadVisitor for LogPrinter<'a> {
         );
     }
 
+    fn visit_burn_stack(&mut self, _: &Block, id1: Id, indices: &[usize], id2: Id) {
+        println!(
+            "    {} {:8}{} \u{219B} {:8}",
+            "burn".green().bold(),
+            id1,
+            display_indices(indices),
+            id2
+        );
+    }
+
+    fn visit_prove_equal_multiset(&mut self, _: &Block, id1: Id, id2: Id, _: &ShuffleProof) {
+        println!("    {} {:8} = {:8}", "equal deck".green().bold(), id1, id2);
+    }
+
+    fn visit_select_hash(&mut self, _: &Block, algorithm: HashAlgorithm) {
+        println!("    {} {}", "hash".green().bold(), algorithm);
+    }
+
+    fn visit_insert_stack(&mut self, _: &Block, id1: Id, id2: Id, rng: &str, id3: Id) {
+        println!(
+            "    {} {:8} @{} \u{21A3} {:8} \u{2192} {:8}",
+            "insert".green().bold(),
+            id2,
+            rng,
+            id1,
+            id3
+        );
+    }
+
+    fn visit_deal_face_up(&mut self, _: &Block, id1: Id, indices: &[usize], id2: Id) {
+        println!(
+            "    {} {:8}{} \u{219B} {:8}",
+            "deal up".green().bold(),
+            id1,
+            display_indices(indices),
+            id2
+        );
+    }
+
+    fn visit_cast_ballot(&mut self, _: &Block, topic: &str, _: &Mask, _: &PrivateMaskProof) {
+        println!("    {} {}", "vote".green().bold(), topic);
+    }
+
+    fn visit_reveal_tally(
+        &mut self,
+        _: &Block,
+        topic: &str,
+        _: &SecretShare,
+        _: &SecretShareProof,
+    ) {
+        println!("    {} {}", "tally reveal".green().bold(), topic);
+    }
+
+    fn visit_peek(&mut self, _: &Block, id: Id, commit: Id) {
+        println!("    {} {:8} {:8}", "peek".green().bold(), id, commit);
+    }
+
+    fn visit_open_peek(&mut self, _: &Block, id: Id, _: Id) {
+        println!("    {} {:8}", "open peek".green().bold(), id);
+    }
+
+    fn visit_commit_shift(&mut self, _: &Block, id: Id, commit: Id) {
+        println!("    {} {:8} {:8}", "cut commit".green().bold(), id, commit);
+    }
+
+    fn visit_reveal_shift(&mut self, _: &Block, id: Id, k: usize, _: Id, _: &[Scalar]) {
+        println!("    {} {:8} {}", "cut reveal".green().bold(), id, k);
+    }
+
+    fn visit_commit_shares(&mut self, _: &Block, id: Id, rounds: u64, round: u64, commit: Id) {
+        println!(
+            "    {} {:8} {}/{} {:8}",
+            "reveal commit".green().bold(),
+            id,
+            round + 1,
+            rounds,
+            commit
+        );
+    }
+
+    fn visit_open_shares(&mut self, _: &Block, id: Id, round: u64, _: Id) {
+        println!(
+            "    {} {:8} {}",
+            "reveal open".green().bold(),
+            id,
+            round + 1
+        );
+    }
+
+    fn visit_prove_greater(
+        &mut self,
+        _: &Block,
+        _: &Mask,
+        _: &Mask,
+        max: usize,
+        _: &PrivateMaskProof,
+    ) {
+        println!("    {} below {}", "greater".green().bold(), max);
+    }
+
+    fn visit_prove_field(
+        &mut self,
+        _: &Block,
+        _: &Mask,
+        field_mask: u64,
+        field_value: u64,
+        _: u64,
+        _: &PrivateMaskProof,
+    ) {
+        println!(
+            "    {} {:#x}={:#x}",
+            "field".green().bold(),
+            field_mask,
+            field_value
+        );
+    }
+
+    fn visit_checkpoint(&mut self, _: &Block, state: Id) {
+        println!("    {} {:16}", "checkpoint".green().bold(), state);
+    }
+
+    fn visit_prove_distinct(&mut self, _: &Block, id: Id, _: &Stack, _: &DistinctProof) {
+        println!("    {} {:8}", "distinct blind".green().bold(), id);
+    }
+
+    fn visit_reveal_distinct(
+        &mut self,
+        _: &Block,
+        id: Id,
+        _: &[SecretShare],
+        _: &[SecretShareProof],
+    ) {
+        println!("    {} {:8}", "distinct reveal".green().bold(), id);
+    }
+
     fn visit_text(&mut self, _: &Block, text: &str) {
         println!("    {} {}", "text".green().bold(), text);
     }
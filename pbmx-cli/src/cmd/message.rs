@@ -1,9 +1,9 @@
-use crate::{state::State, Config, Error, Result};
+use crate::{state::State, storage::Storage, Config, Error, Result};
 use clap::{value_t, ArgMatches};
 use pbmx_kit::chain::Payload;
 use std::{fs, path::PathBuf};
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let text = if let Ok(string) = value_t!(m, "MESSAGE", String) {
         string
     } else if let Ok(path) = value_t!(m, "FILE", PathBuf) {
@@ -12,7 +12,7 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
         return Err(Error::InvalidData);
     };
 
-    let mut state = State::read(false)?;
+    let mut state = State::read(false, cfg, store)?;
 
     state.payloads.push(Payload::Text(text));
 
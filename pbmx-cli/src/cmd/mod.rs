@@ -1,10 +1,19 @@
+pub mod audit;
 pub mod bin;
+pub mod checkpoint;
+pub mod claim;
+pub mod export;
 pub mod init;
 pub mod issue;
 pub mod join;
+pub mod key;
 pub mod log;
 pub mod message;
+pub mod players;
+pub mod rename;
 pub mod reset;
 pub mod rng;
 pub mod stack;
 pub mod status;
+pub mod vote;
+pub mod whoami;
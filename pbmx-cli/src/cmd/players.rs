@@ -0,0 +1,47 @@
+use crate::{state::State, storage::Storage, Config, Result};
+use clap::ArgMatches;
+use colored::Colorize;
+use pbmx_kit::{
+    chain::{Block, PayloadVisitor},
+    crypto::keys::PublicKey,
+    serde::ToBase64,
+};
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let verbose = m.is_present("VERBOSE");
+
+    let state = State::read(false, cfg, store)?;
+
+    let mut players = JoinOrder(Vec::new());
+    for block in state.base.chain.blocks() {
+        for payload in block.payloads() {
+            players.visit_payload(block, payload);
+        }
+    }
+
+    let my_fp = state.base.vtmf.fingerprint();
+    for (name, pk) in players.0.iter() {
+        let fp = state.base.vtmf.fingerprint_of(pk);
+        let name = state.base.names.get(&fp).unwrap_or(name);
+        print!("{} {}", format!("{:16}", fp).yellow(), name.bold());
+        if fp == my_fp {
+            print!(" {}", "(you)".blue());
+        }
+        println!();
+        if verbose {
+            println!("    {}", pk.to_base64()?);
+        }
+    }
+
+    Ok(())
+}
+
+struct JoinOrder(Vec<(String, PublicKey)>);
+
+impl PayloadVisitor for JoinOrder {
+    fn visit_publish_key(&mut self, _: &Block, name: &str, pk: &PublicKey) {
+        if self.0.iter().all(|(_, k)| k != pk) {
+            self.0.push((name.to_string(), pk.clone()));
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use crate::{state::State, storage::Storage, Config, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::chain::Payload;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let name = value_t!(m, "NEW_NAME", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let fp = state.base.vtmf.fingerprint();
+    if !state.base.names.contains_key(&fp) {
+        return Err(clap::Error::with_description(
+            "cannot rename before joining the game",
+            clap::ErrorKind::InvalidValue,
+        )
+        .into());
+    }
+
+    println!("{} {} {}", " + Rename player ".green().bold(), fp, &name);
+    state.payloads.push(Payload::RenamePlayer(fp, name));
+
+    state.save_payloads()?;
+    Ok(())
+}
@@ -1,17 +1,24 @@
-use crate::{state::State, Config, Result};
+use crate::{state::State, storage::Storage, Config, Result};
 use clap::ArgMatches;
+use colored::Colorize;
+use std::io::{self, Write};
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let last = m.is_present("LAST");
     let index = value_t!(m, "INDEX", usize).ok();
 
-    let mut state = State::read(false)?;
+    let mut state = State::read(false, cfg, store)?;
 
     if last {
         state.payloads.pop();
     } else if let Some(index) = index {
         state.payloads.remove(index);
     } else {
+        let n = state.payloads.len();
+        if m.is_present("ALL") && n > 0 && !m.is_present("FORCE") && !confirm(n)? {
+            println!("{}", " ! Nothing discarded".yellow().bold());
+            return Ok(());
+        }
         state.clear_payloads();
     }
 
@@ -19,3 +26,13 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
 
     Ok(())
 }
+
+fn confirm(n: usize) -> Result<bool> {
+    print!("Discard all {} staged payloads? [y/N] ", n);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer)? == 0 {
+        println!();
+    }
+    Ok(answer.trim().eq_ignore_ascii_case("y") || answer.trim().eq_ignore_ascii_case("yes"))
+}
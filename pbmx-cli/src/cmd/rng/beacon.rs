@@ -0,0 +1,42 @@
+use crate::{state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::chain::Payload;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let name = value_t!(m, "NAME", String)?;
+    let value = parse_hex(&value_t!(m, "VALUE", String)?)?;
+    let proof = match value_t!(m, "PROOF", String).ok() {
+        Some(proof) => parse_hex(&proof)?,
+        None => Vec::new(),
+    };
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let rng = state.base.rngs.get(&name).ok_or(Error::InvalidData)?;
+    if !rng.awaits_beacon() {
+        return Err(Error::InvalidData);
+    }
+    let round = rng.beacon_round().ok_or(Error::InvalidData)?;
+
+    println!("{} {} #{}", " + Beacon".green().bold(), name, round);
+    state
+        .payloads
+        .push(Payload::BeaconEntropy(name, round, value, proof));
+
+    state.save_payloads()?;
+    Ok(())
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|c| {
+            std::str::from_utf8(c)
+                .ok()
+                .filter(|c| c.len() == 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .ok_or(Error::InvalidData)
+        })
+        .collect()
+}
@@ -1,20 +1,54 @@
-use crate::{state::State, Config, Error, Result};
+use crate::{state::State, storage::Storage, Config, Error, Result};
 use clap::{value_t, ArgMatches};
 use colored::Colorize;
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let name = value_t!(m, "NAME", String)?;
 
-    let state = State::read(true)?;
+    let state = State::read(true, cfg, store)?;
 
     let rng = state.base.rngs.get(&name).ok_or(Error::InvalidData)?;
-    if rng.entropy_parties().len() < state.base.vtmf.parties()
-        || rng.secret_parties().len() < state.base.vtmf.parties()
-    {
+    let parties: Vec<_> = state.base.names.keys().cloned().collect();
+    let missing_secrets: Vec<_> = parties
+        .iter()
+        .filter(|fp| !rng.secret_parties().contains(fp))
+        .collect();
+    if !rng.entropy_complete(&parties) || !missing_secrets.is_empty() {
+        for fp in parties.iter() {
+            if !rng.entropy_parties().contains(fp) {
+                let player = &state.base.names[fp];
+                println!("{} {}", " ! Missing entropy from".yellow().bold(), player);
+            }
+        }
+        for fp in missing_secrets {
+            let player = &state.base.names[fp];
+            println!("{} {}", " ! Missing secret from".yellow().bold(), player);
+        }
         return Err(Error::InvalidData);
     }
+    if let (true, Some(round)) = (rng.awaits_beacon(), rng.beacon_round()) {
+        println!("{} #{}", " ! Missing beacon round".yellow().bold(), round);
+        return Err(Error::InvalidData);
+    }
+
+    if m.is_present("RAW") {
+        let n = if m.is_present("BYTES") {
+            value_t!(m, "BYTES", usize)?
+        } else {
+            32
+        };
+        let bytes = rng
+            .gen_bytes(&state.base.vtmf, n)
+            .ok_or(Error::InvalidData)?;
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        println!("{} {} = {}", " - Random bytes".green().bold(), name, hex);
+        return Ok(());
+    }
 
-    let n = rng.gen(&state.base.vtmf);
+    let n = rng.gen(&state.base.vtmf).ok_or_else(|| {
+        println!("{}", " ! Sum exceeds the generator's bound".yellow().bold());
+        Error::InvalidData
+    })?;
     println!("{} {} = {}", " - Random".green().bold(), name, n);
 
     Ok(())
@@ -1,9 +1,9 @@
-use crate::{state::State, Config, Result};
+use crate::{state::State, storage::Storage, Config, Result};
 use clap::ArgMatches;
 use colored::Colorize;
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
-    let state = State::read(true)?;
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let state = State::read(true, cfg, store)?;
 
     let keys = state.base.rngs.iter().map(|(k, v)| (k, v.is_revealed()));
 
@@ -20,6 +20,7 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
                 k.yellow(),
                 rng.spec(),
                 rng.gen(&state.base.vtmf)
+                    .map_or_else(|| "?".into(), |n| n.to_string())
             );
         }
     }
@@ -1,19 +1,40 @@
-use crate::{Config, Error, Result};
-use clap::ArgMatches;
+use crate::{storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use pbmx_kit::{
+    crypto::{
+        map,
+        vtmf::{Mask, Vtmf},
+    },
+    state::Rng,
+};
+use rand::thread_rng;
 
+pub mod beacon;
 pub mod entropy;
 pub mod get;
 pub mod list;
 pub mod new;
 pub mod reveal;
 
-pub fn run(m: &ArgMatches, cfg: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     match m.subcommand() {
-        ("new", Some(sub_m)) => new::run(sub_m, cfg),
-        ("list", Some(sub_m)) => list::run(sub_m, cfg),
-        ("entropy", Some(sub_m)) => entropy::run(sub_m, cfg),
-        ("reveal", Some(sub_m)) => reveal::run(sub_m, cfg),
-        ("get", Some(sub_m)) => get::run(sub_m, cfg),
+        ("new", Some(sub_m)) => new::run(sub_m, cfg, store),
+        ("list", Some(sub_m)) => list::run(sub_m, cfg, store),
+        ("entropy", Some(sub_m)) => entropy::run(sub_m, cfg, store),
+        ("beacon", Some(sub_m)) => beacon::run(sub_m, cfg, store),
+        ("reveal", Some(sub_m)) => reveal::run(sub_m, cfg, store),
+        ("get", Some(sub_m)) => get::run(sub_m, cfg, store),
         _ => Err(Error::InvalidSubcommand),
     }
 }
+
+fn contribution(m: &ArgMatches, rng: &Rng, vtmf: &Vtmf) -> Result<Mask> {
+    match (rng.is_sum(), m.is_present("VALUE")) {
+        (true, true) => {
+            let value = value_t!(m, "VALUE", u64)?;
+            Ok(vtmf.mask(&map::to_curve_additive(value)).0)
+        }
+        (false, false) => Ok(vtmf.mask_random(&mut thread_rng())),
+        _ => Err(Error::InvalidData),
+    }
+}
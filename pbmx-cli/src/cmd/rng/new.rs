@@ -1,20 +1,21 @@
-use crate::{state::State, Config, Error, Result};
+use super::contribution;
+use crate::{state::State, storage::Storage, Config, Error, Result};
 use clap::ArgMatches;
 use colored::Colorize;
 use pbmx_kit::{chain::Payload, state::Rng};
-use rand::thread_rng;
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let name = value_t!(m, "NAME", String)?;
     let spec = value_t!(m, "SPEC", String)?;
 
-    let mut state = State::read(true)?;
+    let mut state = State::read(true, cfg, store)?;
 
     if state.base.rngs.contains_key(&name) {
         return Err(Error::InvalidData);
     }
 
-    let _ = Rng::new(state.base.vtmf.parties(), &spec)?;
+    let rng = Rng::new(state.base.vtmf.parties(), &spec)?;
+    let mask = contribution(m, &rng, &state.base.vtmf)?;
     println!(
         "{} {}: {}",
         " + Random number generator".green().bold(),
@@ -23,8 +24,6 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
     );
     state.payloads.push(Payload::RandomSpec(name.clone(), spec));
 
-    let mask = state.base.vtmf.mask_random(&mut thread_rng());
-
     println!("{} {}", " + Entropy".green().bold(), name);
     state.payloads.push(Payload::RandomEntropy(name, mask));
 
@@ -1,18 +1,22 @@
-use crate::{state::State, Config, Error, Result};
+use crate::{state::State, storage::Storage, Config, Error, Result};
 use clap::{value_t, ArgMatches};
 use colored::Colorize;
 use pbmx_kit::chain::Payload;
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let name = value_t!(m, "NAME", String)?;
 
-    let mut state = State::read(true)?;
+    let mut state = State::read(true, cfg, store)?;
 
-    let fp = state.base.vtmf.private_key().fingerprint();
+    let fp = state.base.vtmf.fingerprint();
     let rng = state.base.rngs.get(&name).ok_or(Error::InvalidData)?;
     if rng.secret_parties().contains(&fp) {
         return Err(Error::InvalidData);
     }
+    if let (true, Some(round)) = (rng.awaits_beacon(), rng.beacon_round()) {
+        println!("{} #{}", " ! Missing beacon round".yellow().bold(), round);
+        return Err(Error::InvalidData);
+    }
 
     let (share, proof) = state.base.vtmf.unmask_share(rng.mask());
 
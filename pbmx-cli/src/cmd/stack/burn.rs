@@ -0,0 +1,70 @@
+use crate::{
+    indices::{display_indices, parse_indices},
+    state::State,
+    storage::Storage,
+    Config, Error, Result,
+};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::chain::Payload;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let id = value_t!(m, "SOURCE", String)?;
+    let indices = values_t!(m, "INDICES", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let stack = state
+        .base
+        .stacks
+        .get_by_str(&id)
+        .ok_or(Error::InvalidData)?
+        .clone();
+    let indices: Vec<_> = indices
+        .iter()
+        .map(|s| parse_indices(s).ok_or(Error::InvalidData))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    let id1 = stack.id();
+    let (_, id3) = state
+        .base
+        .stacks
+        .preview_take(&id1, &indices)
+        .ok_or(Error::InvalidData)?;
+
+    // the source cannot be used after the burn, so keep the other tokens
+    let rest_indices: Vec<_> = (0..stack.len()).filter(|i| !indices.contains(i)).collect();
+    let (_, id2) = state
+        .base
+        .stacks
+        .preview_take(&id1, &rest_indices)
+        .ok_or(Error::InvalidData)?;
+    println!(
+        "{} {:16}{} \u{219B} {:16}",
+        " + Take tokens".green().bold(),
+        id1,
+        display_indices(&rest_indices),
+        id2
+    );
+    state
+        .payloads
+        .push(Payload::TakeStack(id1, rest_indices, id2));
+    if state.base.stacks.is_name(&id) {
+        println!("{} {:16} {}", " + Name stack".green().bold(), id2, id);
+        state.payloads.push(Payload::NameStack(id2, id));
+    }
+
+    println!(
+        "{} {:16}{} \u{219B} {:16}",
+        " + Burn tokens".green().bold(),
+        id1,
+        display_indices(&indices),
+        id3
+    );
+    state.payloads.push(Payload::BurnStack(id1, indices, id3));
+
+    state.save_payloads()?;
+    Ok(())
+}
@@ -1,15 +1,19 @@
-use crate::{state::State, Config, Error, Result};
+use crate::{state::State, storage::Storage, Config, Error, Result};
 use clap::ArgMatches;
 use colored::Colorize;
 use itertools::Itertools;
 use pbmx_kit::{chain::Payload, crypto::perm::Permutation};
 use rand::{thread_rng, Rng};
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let ids = values_t!(m, "STACK", String)?;
     let n = value_t!(m, "N", usize).ok();
 
-    let mut state = State::read(true)?;
+    let mut state = State::read(true, cfg, store)?;
+
+    if m.is_present("REVEAL_TOP") {
+        return reveal_top(&mut state, &ids);
+    }
 
     let stacks: Vec<_> = ids
         .iter()
@@ -83,3 +87,37 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
     state.save_payloads()?;
     Ok(())
 }
+
+fn reveal_top(state: &mut State, ids: &[String]) -> Result<()> {
+    if ids.len() != 1 {
+        return Err(Error::InvalidData);
+    }
+    let id = &ids[0];
+    let id1 = state
+        .base
+        .stacks
+        .get_by_str(id)
+        .ok_or(Error::InvalidData)?
+        .id();
+    let (payloads, shifted, secrets) = state
+        .base
+        .cut_and_reveal_top(id)
+        .ok_or(Error::InvalidData)?;
+    let id2 = shifted.id();
+
+    println!(
+        "{} {:16} \u{224B} {:16}",
+        " + Cut stack".green().bold(),
+        id1,
+        id2
+    );
+    println!("{} {:16}[0]", " + Publish secrets".green().bold(), id2);
+    if state.base.stacks.is_name(id) {
+        println!("{} {:16} {}", " + Name stack".green().bold(), id2, id);
+    }
+    state.save_secrets(&shifted, secrets)?;
+    state.payloads.extend(payloads);
+
+    state.save_payloads()?;
+    Ok(())
+}
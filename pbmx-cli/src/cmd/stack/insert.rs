@@ -0,0 +1,49 @@
+use super::move_tokens::push_payloads;
+use crate::{state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::state::State as BaseState;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let stack = value_t!(m, "STACK", String)?;
+    let tokens = value_t!(m, "TOKENS", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let source = state
+        .base
+        .stacks
+        .get_by_str(&stack)
+        .ok_or(Error::InvalidData)?
+        .id();
+    let inserted = state
+        .base
+        .stacks
+        .get_by_str(&tokens)
+        .ok_or(Error::InvalidData)?
+        .id();
+    let rng = BaseState::insert_rng_name(&source, &inserted);
+    match state.base.rngs.get(&rng) {
+        None => {
+            println!("{} {}", " ! Missing generator".yellow().bold(), rng);
+            return Err(Error::InvalidData);
+        }
+        Some(r) if !r.is_revealed() => {
+            println!(
+                "{} {}",
+                " ! Generator not revealed yet".yellow().bold(),
+                rng
+            );
+            return Err(Error::InvalidData);
+        }
+        _ => {}
+    }
+    let payloads = state
+        .base
+        .insert_at_rng(&stack, &tokens)
+        .ok_or(Error::InvalidData)?;
+    push_payloads(&mut state, payloads);
+
+    state.save_payloads()?;
+    Ok(())
+}
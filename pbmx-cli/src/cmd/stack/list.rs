@@ -1,33 +1,61 @@
-use crate::{state::State, Config, Result};
+use crate::{state::State, storage::Storage, Config, Result};
 use clap::ArgMatches;
 use colored::Colorize;
+use pbmx_kit::{chain::Id, state::StackMap};
 use std::collections::HashSet;
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
-    let state = State::read(true)?;
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let state = State::read(true, cfg, store)?;
+
+    let deck = m.value_of("DECK");
+    let tag = m.value_of("TAG");
+    let stacks = &state.base.stacks;
+    let tagged = |id: &Id| tag.is_none() || stacks.tags(id).any(|t| Some(t) == tag);
 
     let mut named = HashSet::new();
-    let mut names: Vec<_> = state.base.stacks.names().collect();
+    let mut names: Vec<_> = stacks
+        .names()
+        .map(|n| (StackMap::split_name(n), n))
+        .filter(|((d, _), _)| deck.is_none() || *d == deck)
+        .collect();
     names.sort();
-    for n in names {
-        let stack = state.base.stacks.get_by_name(n).unwrap();
+    for (_, n) in names {
+        let stack = stacks.get_by_name(n).unwrap();
         let id = stack.id();
         named.insert(id);
+        if !tagged(&id) {
+            continue;
+        }
         println!(
-            "{} {:4}\t{}",
+            "{} {:4}\t{}{}",
             format!("{:16}", id).yellow(),
             stack.len(),
-            n.bold()
+            n.bold(),
+            tag_list(stacks, &id)
         );
     }
     if m.is_present("ALL") {
-        for id in state.base.stacks.ids() {
-            if !named.contains(id) {
-                let s = state.base.stacks.get_by_id(&id).unwrap();
-                println!("{} {:4}", format!("{:16}", id).yellow(), s.len());
+        for id in stacks.ids() {
+            if !named.contains(id) && tagged(id) {
+                let s = stacks.get_by_id(&id).unwrap();
+                println!(
+                    "{} {:4}{}",
+                    format!("{:16}", id).yellow(),
+                    s.len(),
+                    tag_list(stacks, id)
+                );
             }
         }
     }
 
     Ok(())
 }
+
+fn tag_list(stacks: &StackMap, id: &Id) -> String {
+    let tags: Vec<_> = stacks.tags(id).collect();
+    if tags.is_empty() {
+        String::new()
+    } else {
+        format!(" {}", format!("[{}]", tags.join(", ")).blue())
+    }
+}
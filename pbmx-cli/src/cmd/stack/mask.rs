@@ -1,7 +1,10 @@
-use crate::{state::State, Config, Error, Result};
+use crate::{state::State, storage::Storage, Config, Error, Result};
 use clap::{value_t, ArgMatches};
 use colored::Colorize;
-use pbmx_kit::{chain::Payload, crypto::vtmf::Stack};
+use pbmx_kit::{
+    chain::Payload,
+    crypto::vtmf::{Mask, Stack},
+};
 use std::iter;
 
 trait IteratorEx: Iterator + Sized {
@@ -28,10 +31,10 @@ trait IteratorEx: Iterator + Sized {
 
 impl<T: Iterator> IteratorEx for T {}
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let id = value_t!(m, "STACK", String)?;
 
-    let mut state = State::read(true)?;
+    let mut state = State::read(true, cfg, store)?;
 
     let stack = state
         .base
@@ -39,8 +42,12 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
         .get_by_str(&id)
         .ok_or(Error::InvalidData)?;
 
-    let (s, r, p): (Stack, Vec<_>, Vec<_>) =
-        stack.iter().map(|m| state.base.vtmf.remask(m)).unzip3();
+    let (s, r, p): (Stack, Vec<_>, Vec<_>) = if stack.iter().all(Mask::is_open) {
+        let points: Vec<_> = stack.iter().map(|m| m.1).collect();
+        state.base.vtmf.mask_all(&points)
+    } else {
+        stack.iter().map(|m| state.base.vtmf.remask(m)).unzip3()
+    };
     state.save_secrets(&s, r)?;
 
     let id1 = stack.id();
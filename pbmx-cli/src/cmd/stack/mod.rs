@@ -1,29 +1,45 @@
-use crate::{Config, Error, Result};
+use crate::{storage::Storage, Config, Error, Result};
 use clap::ArgMatches;
 
+pub mod burn;
 pub mod cut;
+pub mod insert;
 pub mod list;
 pub mod mask;
+pub mod move_tokens;
 pub mod name;
 pub mod new;
+pub mod peek;
 pub mod pile;
+pub mod redraw;
+pub mod reshuffle_from;
+pub mod return_tokens;
 pub mod reveal;
 pub mod show;
 pub mod shuffle;
+pub mod tag;
 pub mod take;
 
-pub fn run(m: &ArgMatches, cfg: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     match m.subcommand() {
-        ("new", Some(sub_m)) => new::run(sub_m, cfg),
-        ("list", Some(sub_m)) => list::run(sub_m, cfg),
-        ("show", Some(sub_m)) => show::run(sub_m, cfg),
-        ("name", Some(sub_m)) => name::run(sub_m, cfg),
-        ("reveal", Some(sub_m)) => reveal::run(sub_m, cfg),
-        ("mask", Some(sub_m)) => mask::run(sub_m, cfg),
-        ("shuffle", Some(sub_m)) => shuffle::run(sub_m, cfg),
-        ("cut", Some(sub_m)) => cut::run(sub_m, cfg),
-        ("take", Some(sub_m)) => take::run(sub_m, cfg),
-        ("pile", Some(sub_m)) => pile::run(sub_m, cfg),
+        ("new", Some(sub_m)) => new::run(sub_m, cfg, store),
+        ("list", Some(sub_m)) => list::run(sub_m, cfg, store),
+        ("show", Some(sub_m)) => show::run(sub_m, cfg, store),
+        ("name", Some(sub_m)) => name::run(sub_m, cfg, store),
+        ("tag", Some(sub_m)) => tag::run(sub_m, cfg, store),
+        ("reveal", Some(sub_m)) => reveal::run(sub_m, cfg, store),
+        ("peek", Some(sub_m)) => peek::run(sub_m, cfg, store),
+        ("mask", Some(sub_m)) => mask::run(sub_m, cfg, store),
+        ("shuffle", Some(sub_m)) => shuffle::run(sub_m, cfg, store),
+        ("reshuffle-from", Some(sub_m)) => reshuffle_from::run(sub_m, cfg, store),
+        ("cut", Some(sub_m)) => cut::run(sub_m, cfg, store),
+        ("take", Some(sub_m)) => take::run(sub_m, cfg, store),
+        ("pile", Some(sub_m)) => pile::run(sub_m, cfg, store),
+        ("move", Some(sub_m)) => move_tokens::run(sub_m, cfg, store),
+        ("redraw", Some(sub_m)) => redraw::run(sub_m, cfg, store),
+        ("burn", Some(sub_m)) => burn::run(sub_m, cfg, store),
+        ("insert", Some(sub_m)) => insert::run(sub_m, cfg, store),
+        ("return", Some(sub_m)) => return_tokens::run(sub_m, cfg, store),
         _ => Err(Error::InvalidSubcommand),
     }
 }
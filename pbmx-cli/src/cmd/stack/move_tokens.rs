@@ -0,0 +1,72 @@
+use crate::{
+    indices::{display_indices, parse_indices},
+    state::State,
+    storage::Storage,
+    Config, Error, Result,
+};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::chain::Payload;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let source = value_t!(m, "SOURCE", String)?;
+    let indices = values_t!(m, "INDICES", String)?;
+    let target = value_t!(m, "TARGET", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let indices: Vec<_> = indices
+        .iter()
+        .map(|s| parse_indices(s).ok_or(Error::InvalidData))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    let payloads = state
+        .base
+        .move_tokens(&source, &indices, &target)
+        .ok_or(Error::InvalidData)?;
+    push_payloads(&mut state, payloads);
+
+    state.save_payloads()?;
+    Ok(())
+}
+
+pub fn push_payloads(state: &mut State, payloads: Vec<Payload>) {
+    for payload in payloads {
+        match &payload {
+            Payload::TakeStack(id1, idxs, id2) => println!(
+                "{} {:16}{} \u{219B} {:16}",
+                " + Take tokens".green().bold(),
+                id1,
+                display_indices(idxs),
+                id2
+            ),
+            Payload::PileStacks(ids, id2) => println!(
+                "{} {:16?} \u{21A3} {:16}",
+                " + Pile stacks".green().bold(),
+                ids,
+                id2
+            ),
+            Payload::ShuffleStack(id1, stack, _) => println!(
+                "{} {:16} \u{224B} {:16}",
+                " + Shuffle stack".green().bold(),
+                id1,
+                stack.id()
+            ),
+            Payload::InsertStack(id1, id2, rng, id3) => println!(
+                "{} {:16} @{} \u{21A3} {:16} \u{2192} {:16}",
+                " + Insert stack".green().bold(),
+                id2,
+                rng,
+                id1,
+                id3
+            ),
+            Payload::NameStack(id, name) => {
+                println!("{} {:16} {}", " + Name stack".green().bold(), id, name)
+            }
+            _ => {}
+        }
+        state.payloads.push(payload);
+    }
+}
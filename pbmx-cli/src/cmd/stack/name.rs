@@ -1,13 +1,13 @@
-use crate::{state::State, Config, Error, Result};
+use crate::{state::State, storage::Storage, Config, Error, Result};
 use clap::{value_t, ArgMatches};
 use colored::Colorize;
 use pbmx_kit::chain::Payload;
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let name = value_t!(m, "NAME", String)?;
     let id = value_t!(m, "ID", String)?;
 
-    let mut state = State::read(true)?;
+    let mut state = State::read(true, cfg, store)?;
 
     let stack = state
         .base
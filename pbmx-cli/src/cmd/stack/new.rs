@@ -1,5 +1,6 @@
 use crate::{
-    indices::parse_indices, stack_map::display_stack_contents, state::State, Config, Error, Result,
+    indices::parse_indices, stack_map::display_stack_contents, state::State, storage::Storage,
+    Config, Error, Result,
 };
 use clap::{value_t, ArgMatches};
 use colored::Colorize;
@@ -9,37 +10,42 @@ use pbmx_kit::{
         map,
         vtmf::{Mask, Stack},
     },
+    state::{DeckTemplate, DECK_SEPARATOR},
 };
-use std::collections::HashMap;
 
-pub fn run(m: &ArgMatches, cfg: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let name = value_t!(m, "NAME", String).ok();
+    let deck = value_t!(m, "DECK", String).ok();
+    let template = value_t!(m, "TEMPLATE", String).ok();
     let stack = values_t!(m, "TOKENS", String).unwrap_or_else(|_| vec![]);
 
-    let mut state = State::read(true)?;
+    let mut state = State::read(true, cfg, store)?;
 
-    let stack: Stack = stack
-        .iter()
-        .map(|s| parse_indices(s).ok_or(Error::InvalidData))
-        .collect::<Result<Vec<_>>>()?
-        .into_iter()
-        .flatten()
-        .map(|i| Mask::open(map::to_curve(i as u64)))
-        .collect();
+    let stack: Stack = match &template {
+        Some(t) => DeckTemplate::get(t).ok_or(Error::InvalidData)?.stack(),
+        None => stack
+            .iter()
+            .map(|s| parse_indices(s).ok_or(Error::InvalidData))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .map(|i| Mask::open(map::to_curve(i as u64)))
+            .collect(),
+    };
     let id = stack.id();
     println!(
         "{} {}",
         " + Open stack".green().bold(),
-        display_stack_contents(
-            &stack.clone(),
-            &HashMap::new(),
-            &HashMap::new(),
-            &state.base.vtmf,
-            cfg
-        )
+        display_stack_contents(&stack.clone(), &state.base, cfg)
     );
-    state.payloads.push(Payload::OpenStack(stack));
-    if let Some(name) = name {
+    match template {
+        Some(t) => state.payloads.push(Payload::DeckTemplate(t)),
+        None => state.payloads.push(Payload::OpenStack(stack)),
+    }
+    if let Some(mut name) = name {
+        if let Some(deck) = deck {
+            name = format!("{}{}{}", deck, DECK_SEPARATOR, name);
+        }
         let name_change = state
             .base
             .stacks
@@ -0,0 +1,29 @@
+use crate::{
+    stack_map::display_stack_contents, state::State, storage::Storage, Config, Error, Result,
+};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::chain::Payload;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let id = value_t!(m, "STACK", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let stack = state
+        .base
+        .stacks
+        .get_by_str(&id)
+        .ok_or(Error::InvalidData)?;
+    let id1 = stack.id();
+    let (open, nonce, commit) = state.base.peek(&id1).ok_or(Error::InvalidData)?;
+
+    println!("{}", display_stack_contents(&open, &state.base, cfg));
+    state.save_peek_nonce(id1, &nonce)?;
+
+    state.payloads.push(Payload::Peek(id1, commit));
+    println!("{} {:16}", " + Peek".green().bold(), id1);
+
+    state.save_payloads()?;
+    Ok(())
+}
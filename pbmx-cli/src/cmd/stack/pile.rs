@@ -1,16 +1,16 @@
-use crate::{state::State, Config, Error, Result};
+use crate::{state::State, storage::Storage, Config, Error, Result};
 use clap::{value_t, ArgMatches};
 use colored::Colorize;
 use pbmx_kit::{chain::Payload, crypto::vtmf::Stack};
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let in_ids = values_t!(m, "STACKS", String)?;
     let name = value_t!(m, "TARGET", String).ok();
     let remove = !m.is_present("CLONE");
 
-    let mut state = State::read(true)?;
+    let mut state = State::read(true, cfg, store)?;
 
-    let stacks: Vec<_> = in_ids
+    let ids: Vec<_> = in_ids
         .iter()
         .map(|id| {
             state
@@ -18,15 +18,14 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
                 .stacks
                 .get_by_str(&id)
                 .ok_or(Error::InvalidData)
-                .map(Clone::clone)
+                .map(Stack::id)
         })
         .collect::<Result<_>>()?;
-    let ids: Vec<_> = stacks.iter().map(Stack::id).collect();
-
-    let tokens: Stack = stacks
-        .into_iter()
-        .flat_map(IntoIterator::into_iter)
-        .collect();
+    let (_, id2) = state
+        .base
+        .stacks
+        .preview_pile(&ids)
+        .ok_or(Error::InvalidData)?;
 
     if remove {
         let empty = Stack::default();
@@ -50,7 +49,6 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
             }
         }
     }
-    let id2 = tokens.id();
     println!(
         "{} {:16?} \u{21A3} {:16}",
         " + Pile stacks".green().bold(),
@@ -0,0 +1,28 @@
+use super::move_tokens::push_payloads;
+use crate::{indices::parse_indices, state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let hand = value_t!(m, "HAND", String)?;
+    let indices = values_t!(m, "INDICES", String)?;
+    let deck = value_t!(m, "DECK", String)?;
+    let discard = value_t!(m, "DISCARD", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let indices: Vec<_> = indices
+        .iter()
+        .map(|s| parse_indices(s).ok_or(Error::InvalidData))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    let payloads = state
+        .base
+        .discard_draw(&hand, &indices, &deck, &discard)
+        .ok_or(Error::InvalidData)?;
+    push_payloads(&mut state, payloads);
+
+    state.save_payloads()?;
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use crate::{state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::{chain::Payload, crypto::perm::Shuffles};
+use rand::{thread_rng, Rng};
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let ref_id = value_t!(m, "REF", String)?;
+    let id = value_t!(m, "STACK", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let reference = state
+        .base
+        .stacks
+        .get_by_str(&ref_id)
+        .ok_or(Error::InvalidData)?
+        .id();
+    let stack = state
+        .base
+        .stacks
+        .get_by_str(&id)
+        .ok_or(Error::InvalidData)?
+        .clone();
+    if !state.base.is_permutation_of(&stack.id(), &reference) {
+        return Err(Error::InvalidData);
+    }
+
+    let perm = thread_rng().sample(Shuffles(stack.len()));
+    let (s, r, proof) = state
+        .base
+        .vtmf
+        .prove_permutation_of(&reference, &stack, &perm);
+
+    let id1 = stack.id();
+    let id2 = s.id();
+    println!(
+        "{} {:16} \u{224B} {:16} from {:16}",
+        " + Reshuffle stack".green().bold(),
+        id1,
+        id2,
+        reference
+    );
+    state
+        .payloads
+        .push(Payload::ReshuffleFrom(reference, id1, s.clone(), proof));
+    if state.base.stacks.is_name(&id) {
+        println!("{} {:16} {}", " + Name stack".green().bold(), id2, id);
+        state.payloads.push(Payload::NameStack(id2, id));
+    }
+    state.save_secrets(&s, r)?;
+
+    state.save_payloads()?;
+    Ok(())
+}
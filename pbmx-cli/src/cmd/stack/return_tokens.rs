@@ -0,0 +1,20 @@
+use super::move_tokens::push_payloads;
+use crate::{state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let tokens = value_t!(m, "TOKENS", String)?;
+    let deck = value_t!(m, "DECK", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let (payloads, shuffled, secrets) = state
+        .base
+        .return_and_reshuffle(&tokens, &deck)
+        .ok_or(Error::InvalidData)?;
+    state.save_secrets(&shuffled, secrets)?;
+    push_payloads(&mut state, payloads);
+
+    state.save_payloads()?;
+    Ok(())
+}
@@ -1,12 +1,18 @@
-use crate::{state::State, Config, Error, Result};
+use crate::{
+    indices::{display_indices, parse_indices},
+    state::State,
+    storage::Storage,
+    Config, Error, Result,
+};
 use clap::{value_t, ArgMatches};
 use colored::Colorize;
 use pbmx_kit::chain::Payload;
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let id = value_t!(m, "STACK", String)?;
+    let indices = values_t!(m, "INDICES", String).ok();
 
-    let mut state = State::read(true)?;
+    let mut state = State::read(true, cfg, store)?;
 
     let stack = state
         .base
@@ -14,14 +20,45 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
         .get_by_str(&id)
         .ok_or(Error::InvalidData)?;
 
-    let (s, p): (Vec<_>, Vec<_>) = stack
-        .iter()
-        .map(|m| state.base.vtmf.unmask_share(m))
-        .unzip();
-
     let id1 = stack.id();
-    state.payloads.push(Payload::PublishShares(id1, s, p));
-    println!("{} {:16}", " + Publish secrets".green().bold(), id1,);
+    if let Some(indices) = indices {
+        let indices: Vec<_> = indices
+            .iter()
+            .map(|s| parse_indices(s).ok_or(Error::InvalidData))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        let (s, p): (Vec<_>, Vec<_>) = indices
+            .iter()
+            .map(|&i| {
+                stack
+                    .get(i)
+                    .map(|m| state.base.vtmf.unmask_share(m))
+                    .ok_or(Error::InvalidData)
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+
+        println!(
+            "{} {:16}{}",
+            " + Publish secrets".green().bold(),
+            id1,
+            display_indices(&indices)
+        );
+        state
+            .payloads
+            .push(Payload::PublishSharesAt(id1, indices, s, p));
+    } else {
+        let (s, p): (Vec<_>, Vec<_>) = stack
+            .iter()
+            .map(|m| state.base.vtmf.unmask_share(m))
+            .unzip();
+
+        state.payloads.push(Payload::PublishShares(id1, s, p));
+        println!("{} {:16}", " + Publish secrets".green().bold(), id1,);
+    }
 
     state.save_payloads()?;
     Ok(())
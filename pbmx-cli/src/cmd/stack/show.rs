@@ -1,15 +1,38 @@
-use crate::{stack_map::display_stack_contents, state::State, Config, Error, Result};
+use crate::{
+    stack_map::{display_stack_with, StackFormat},
+    state::State,
+    storage::Storage,
+    Config, Error, Result,
+};
 use clap::{value_t, ArgMatches};
 use colored::Colorize;
-use pbmx_kit::crypto::vtmf::Stack;
+use pbmx_kit::crypto::{
+    map,
+    vtmf::{Mask, Stack},
+};
 use std::{collections::HashSet, iter::FromIterator};
 
-pub fn run(m: &ArgMatches, cfg: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let id = value_t!(m, "STACK", String).ok();
+    let format = match m.value_of("FORMAT") {
+        Some(f) => f.parse()?,
+        None => cfg.stack_format(),
+    };
 
-    let state = State::read(true)?;
+    let state = State::read(true, cfg, store)?;
 
-    if let Some(id) = id {
+    if let (Some(id), Some(other)) = (&id, m.value_of("DIFF")) {
+        let a = state.base.stacks.get_by_str(id).ok_or(Error::InvalidData)?;
+        let b = state
+            .base
+            .stacks
+            .get_by_str(other)
+            .ok_or(Error::InvalidData)?;
+        print_diff(a, b, &state);
+    } else if let (Some(id), true) = (&id, m.is_present("COUNT")) {
+        let stack = state.base.stacks.get_by_str(id).ok_or(Error::InvalidData)?;
+        print_count(id, stack, &state);
+    } else if let Some(id) = id {
         let stack = state
             .base
             .stacks
@@ -18,7 +41,7 @@ pub fn run(m: &ArgMatches, cfg: &Config) -> Result<()> {
         if state.base.stacks.is_name(&id) {
             print!("{} ", id.bold());
         }
-        print_stack(m.is_present("VERBOSE"), &stack, &state, cfg);
+        print_stack(m.is_present("VERBOSE"), &stack, &state, format, cfg);
     } else {
         let mut named = HashSet::new();
         let mut names: Vec<_> = state.base.stacks.names().collect();
@@ -28,14 +51,14 @@ pub fn run(m: &ArgMatches, cfg: &Config) -> Result<()> {
             let id = stack.id();
             named.insert(id);
             print!("{} ", n.bold());
-            print_stack(m.is_present("VERBOSE"), &stack, &state, cfg);
+            print_stack(m.is_present("VERBOSE"), &stack, &state, format, cfg);
         }
         if m.is_present("ALL") {
             for id in state.base.stacks.ids() {
                 if !named.contains(id) {
                     let stack = state.base.stacks.get_by_id(&id).unwrap();
                     print!("{:16} ", id);
-                    print_stack(m.is_present("VERBOSE"), &stack, &state, cfg);
+                    print_stack(m.is_present("VERBOSE"), &stack, &state, format, cfg);
                 }
             }
         }
@@ -44,16 +67,10 @@ pub fn run(m: &ArgMatches, cfg: &Config) -> Result<()> {
     Ok(())
 }
 
-fn print_stack(verbose: bool, stack: &Stack, state: &State, cfg: &Config) {
+fn print_stack(verbose: bool, stack: &Stack, state: &State, format: StackFormat, cfg: &Config) {
     print!(
         "{}",
-        display_stack_contents(
-            stack,
-            &state.base.stacks.secrets(),
-            &state.base.stacks.private_secrets(),
-            &state.base.vtmf,
-            cfg
-        )
+        display_stack_with(stack, &state.base, format.formatter(cfg))
     );
     if verbose {
         let empty = HashSet::new();
@@ -85,3 +102,61 @@ fn print_stack(verbose: bool, stack: &Stack, state: &State, cfg: &Config) {
         println!();
     }
 }
+
+fn print_count(name: &str, stack: &Stack, state: &State) {
+    let open = stack.iter().filter(|m| m.is_open()).count();
+    let revealed = stack
+        .iter()
+        .filter(|m| !m.is_open() && state.base.missing_shares(m).is_empty())
+        .count();
+    if state.base.stacks.is_name(name) {
+        print!("{} ", name.bold());
+    }
+    println!("{:16}", stack.id());
+    println!("    {}    {}", "Tokens".blue().bold(), stack.len());
+    println!("    {}      {}", "Open".blue().bold(), open);
+    println!("    {}    {}", "Hidden".blue().bold(), stack.len() - open);
+    println!("    {}  {}", "Revealed".blue().bold(), revealed);
+}
+
+fn print_diff(a: &Stack, b: &Stack, state: &State) {
+    if a.len() != b.len() {
+        println!(
+            "{} {} \u{2260} {}",
+            " ! Length mismatch".red().bold(),
+            a.len(),
+            b.len()
+        );
+        return;
+    }
+
+    let open_value = |m: &Mask| {
+        if m.is_open() {
+            map::from_curve(&state.base.vtmf.unmask_open(m))
+        } else {
+            None
+        }
+    };
+    let mut count = 0;
+    for (i, (ma, mb)) in a.iter().zip(b.iter()).enumerate() {
+        if ma == mb {
+            continue;
+        }
+        count += 1;
+        if let (Some(va), Some(vb)) = (open_value(ma), open_value(mb)) {
+            println!("{:4} {} \u{2260} {}", i, va, vb);
+        } else {
+            println!("{:4} {}", i, "masks differ".yellow());
+        }
+    }
+    if count == 0 {
+        println!("{}", " = Stacks are equal".green().bold());
+    } else {
+        println!(
+            "{} {}/{}",
+            " ! Positions differ".red().bold(),
+            count,
+            a.len()
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{indices::parse_indices, state::State, Config, Error, Result};
+use crate::{indices::parse_indices, state::State, storage::Storage, Config, Error, Result};
 use clap::ArgMatches;
 use colored::Colorize;
 use itertools::Itertools;
@@ -9,11 +9,11 @@ use pbmx_kit::{
 use rand::{thread_rng, Rng};
 use std::convert::TryFrom;
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let ids = values_t!(m, "STACK", String)?;
     let indices = values_t!(m, "ORDER", String).ok();
 
-    let mut state = State::read(true)?;
+    let mut state = State::read(true, cfg, store)?;
 
     let stacks: Vec<_> = ids
         .iter()
@@ -39,7 +39,16 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
             .into_iter()
             .flatten()
             .collect();
-        Permutation::try_from(v).map_err(|_| Error::InvalidData)?
+        let perm = Permutation::try_from(v).map_err(|_| Error::InvalidData)?;
+        if perm.is_identity() {
+            println!(
+                "{}",
+                " ! Order is the identity, tokens stay in place"
+                    .yellow()
+                    .bold()
+            );
+        }
+        perm
     } else {
         thread_rng().sample(&Shuffles(len))
     };
@@ -0,0 +1,29 @@
+use crate::{state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, values_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::chain::Payload;
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let id = value_t!(m, "STACK", String)?;
+    let tags = values_t!(m, "TAGS", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+
+    let stack = state
+        .base
+        .stacks
+        .get_by_str(&id)
+        .ok_or(Error::InvalidData)?;
+
+    let id = stack.id();
+    println!(
+        "{} {:16} {}",
+        " + Tag stack".green().bold(),
+        id,
+        tags.join(" ")
+    );
+    state.payloads.push(Payload::TagStack(id, tags));
+
+    state.save_payloads()?;
+    Ok(())
+}
@@ -1,21 +1,23 @@
 use crate::{
     indices::{display_indices, parse_indices},
     state::State,
+    storage::Storage,
     Config, Error, Result,
 };
 use clap::{value_t, ArgMatches};
 use colored::Colorize;
 use pbmx_kit::{chain::Payload, crypto::vtmf::Stack};
 
-pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
     let id = value_t!(m, "SOURCE", String)?;
     let indices = values_t!(m, "INDICES", String)?;
     let target = value_t!(m, "TARGET", String).ok();
     let over = value_t!(m, "OVER", String).ok();
     let under = value_t!(m, "UNDER", String).ok();
     let remove = !m.is_present("CLONE");
+    let face_up = m.is_present("FACE_UP");
 
-    let mut state = State::read(true)?;
+    let mut state = State::read(true, cfg, store)?;
 
     let stack = state
         .base
@@ -33,7 +35,14 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
 
     if remove && state.base.stacks.is_name(&id) {
         let rev_indices: Vec<_> = (0..stack.len()).filter(|i| !indices.contains(&i)).collect();
-        take(&stack, rev_indices, Some(id), Stacking::Replace, &mut state)?;
+        take(
+            &stack,
+            rev_indices,
+            Some(id),
+            Stacking::Replace,
+            false,
+            &mut state,
+        )?;
     }
     let stacking = if let Some(over) = over {
         Stacking::Over(over)
@@ -42,7 +51,7 @@ pub fn run(m: &ArgMatches, _: &Config) -> Result<()> {
     } else {
         Stacking::Replace
     };
-    take(&stack, indices, target, stacking, &mut state)?;
+    take(&stack, indices, target, stacking, face_up, &mut state)?;
 
     state.save_payloads()?;
     Ok(())
@@ -59,24 +68,40 @@ fn take(
     indices: Vec<usize>,
     target: Option<String>,
     stacking: Stacking,
+    face_up: bool,
     state: &mut State,
 ) -> Result<()> {
-    let tokens: Stack = stack
-        .iter()
-        .enumerate()
-        .filter_map(|(i, m)| if indices.contains(&i) { Some(*m) } else { None })
-        .collect();
-
     let id1 = stack.id();
-    let id2 = tokens.id();
-    println!(
-        "{} {:16}{} \u{219B} {:16}",
-        " + Take tokens".green().bold(),
-        id1,
-        display_indices(&indices),
-        id2
-    );
-    state.payloads.push(Payload::TakeStack(id1, indices, id2));
+    let (tokens, id2) = state
+        .base
+        .stacks
+        .preview_take(&id1, &indices)
+        .ok_or(Error::InvalidData)?;
+    if face_up {
+        println!(
+            "{} {:16}{} \u{219B} {:16}",
+            " + Deal tokens face up".green().bold(),
+            id1,
+            display_indices(&indices),
+            id2
+        );
+        state.payloads.push(Payload::DealFaceUp(id1, indices, id2));
+        let (s, p): (Vec<_>, Vec<_>) = tokens
+            .iter()
+            .map(|m| state.base.vtmf.unmask_share(m))
+            .unzip();
+        println!("{} {:16}", " + Publish secrets".green().bold(), id2);
+        state.payloads.push(Payload::PublishShares(id2, s, p));
+    } else {
+        println!(
+            "{} {:16}{} \u{219B} {:16}",
+            " + Take tokens".green().bold(),
+            id1,
+            display_indices(&indices),
+            id2
+        );
+        state.payloads.push(Payload::TakeStack(id1, indices, id2));
+    }
     let (name, result) = match stacking {
         Stacking::Over(over) => {
             let o = state
@@ -1,9 +1,9 @@
-use crate::{state::State, Config, Result};
+use crate::{state::State, storage::Storage, Config, Result};
 use clap::ArgMatches;
 use colored::Colorize;
 
-pub fn run(_: &ArgMatches, _: &Config) -> Result<()> {
-    let state = State::read(false)?;
+pub fn run(_: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let state = State::read(false, cfg, store)?;
 
     println!("   {}", "Chain".blue().bold());
     println!(
@@ -25,11 +25,7 @@ pub fn run(_: &ArgMatches, _: &Config) -> Result<()> {
     }
 
     println!("   {}", "Keys".blue().bold());
-    println!(
-        "    {} {:16}",
-        "Private".blue().bold(),
-        state.base.vtmf.private_key().fingerprint()
-    );
+    println!("    {} {:16}", "Private".blue().bold(), state.fingerprint());
     println!(
         "    {}  {:16}",
         "Shared".blue().bold(),
@@ -52,7 +48,7 @@ pub fn run(_: &ArgMatches, _: &Config) -> Result<()> {
     if !state.payloads.is_empty() {
         println!("   {}", "Next block".blue().bold());
         for payload in state.payloads.iter() {
-            println!("    {}", payload.display_short());
+            println!("    {}", payload.display_short_with(state.hash_algorithm()));
         }
     }
 
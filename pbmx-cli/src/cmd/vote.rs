@@ -0,0 +1,72 @@
+use crate::{state::State, storage::Storage, Config, Error, Result};
+use clap::{value_t, ArgMatches};
+use colored::Colorize;
+use pbmx_kit::{chain::Payload, state::Tally};
+
+pub fn run(m: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let topic = value_t!(m, "TOPIC", String)?;
+
+    let mut state = State::read(true, cfg, store)?;
+    let fp = state.base.vtmf.fingerprint();
+
+    if let Some(choice) = m.value_of("CHOICE") {
+        let idx = match choice {
+            "no" => 0,
+            "yes" => 1,
+            _ => return Err(Error::InvalidData),
+        };
+        if let Some(tally) = state.base.tallies.get(&topic) {
+            if tally.is_closed() || tally.voters().contains(&fp) {
+                return Err(Error::InvalidData);
+            }
+        }
+
+        let (ballot, _, proof) = state.base.vtmf.mask_ballot(&topic, &Tally::choices(), idx);
+
+        println!("{} {}", " + Ballot".green().bold(), topic);
+        state
+            .payloads
+            .push(Payload::CastBallot(topic, ballot, proof));
+
+        state.save_payloads()?;
+        return Ok(());
+    }
+
+    let tally = state.base.tallies.get(&topic).ok_or(Error::InvalidData)?;
+    if m.is_present("REVEAL") {
+        if !tally.is_closed() || tally.secret_parties().contains(&fp) {
+            return Err(Error::InvalidData);
+        }
+
+        let (share, proof) = state.base.vtmf.unmask_share(tally.mask());
+
+        println!("{} {}", " + Tally secret".green().bold(), topic);
+        state
+            .payloads
+            .push(Payload::RevealTally(topic, share, proof));
+
+        state.save_payloads()?;
+        return Ok(());
+    }
+
+    let parties = state.base.vtmf.parties();
+    match tally.result(&state.base.vtmf) {
+        Some(yes) => println!(
+            "{} {}: {} yes, {} no",
+            " - Tally".green().bold(),
+            topic,
+            yes,
+            parties as u64 - yes
+        ),
+        None => println!(
+            "{}: {}/{} votes, {}/{} secrets",
+            topic.yellow(),
+            tally.voters().len(),
+            parties,
+            tally.secret_parties().len(),
+            parties
+        ),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use crate::{state::State, storage::Storage, Config, Result};
+use clap::ArgMatches;
+use colored::Colorize;
+
+pub fn run(_: &ArgMatches, cfg: &Config, store: &dyn Storage) -> Result<()> {
+    let state = State::read(false, cfg, store)?;
+
+    let fp = state.fingerprint();
+    print!("{}", format!("{}", fp).yellow());
+    match state.base.names.get(&fp) {
+        Some(name) => println!(" {}", name.bold()),
+        None => {
+            if let Some(name) = cfg.player_name.as_ref() {
+                print!(" {}", name.bold());
+            }
+            println!(" {}", "(not joined)".blue());
+        }
+    }
+
+    Ok(())
+}
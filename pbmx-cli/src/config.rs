@@ -1,30 +1,272 @@
 //! Configuration reading/writing
 
-use crate::{constants::CONFIG_FILE_NAME, Result};
-use std::{collections::HashMap, fs};
+use crate::{
+    constants::{CONFIG_FILE_NAME, DATA_DIR_VAR, GAMES_FOLDER_NAME, GAME_VAR},
+    stack_map::StackFormat,
+    Error, Result,
+};
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Config {
+    pub data_dir: PathBuf,
+    pub game: Option<String>,
+    pub player_name: Option<String>,
     pub tokens: HashMap<u64, String>,
+    pub format: Option<StackFormat>,
+    pub dry_run: bool,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("."),
+            game: None,
+            player_name: None,
+            tokens: HashMap::new(),
+            format: None,
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct ConfigRaw {
+    data_dir: Option<PathBuf>,
+    game: Option<String>,
+    player_name: Option<String>,
+    tokens_file: Option<PathBuf>,
     tokens: Option<HashMap<String, String>>,
+    stack_format: Option<String>,
 }
 
 impl Config {
-    pub fn read() -> Result<Config> {
-        if fs::metadata(CONFIG_FILE_NAME).is_err() {
-            return Ok(Config::default());
+    pub fn read(flag_dir: Option<PathBuf>, flag_game: Option<String>) -> Result<Config> {
+        let env_dir = env::var_os(DATA_DIR_VAR).map(PathBuf::from);
+        let env_game = env::var(GAME_VAR).ok();
+        let raw = if fs::metadata(CONFIG_FILE_NAME).is_ok() {
+            let s = fs::read_to_string(CONFIG_FILE_NAME)?;
+            toml::from_str(&s)?
+        } else {
+            ConfigRaw::default()
+        };
+        Self::from_raw(raw, (flag_dir, env_dir), (flag_game, env_game))
+    }
+
+    fn from_raw(
+        raw: ConfigRaw,
+        (flag_dir, env_dir): (Option<PathBuf>, Option<PathBuf>),
+        (flag_game, env_game): (Option<String>, Option<String>),
+    ) -> Result<Config> {
+        let data_dir = resolve_data_dir(flag_dir, env_dir, raw.data_dir);
+        if !data_dir.is_dir() {
+            return Err(Error::Config(format!(
+                "data-dir {} is not a folder",
+                data_dir.display()
+            )));
         }
-        let s = fs::read_to_string(CONFIG_FILE_NAME)?;
-        let raw: ConfigRaw = toml::from_str(&s)?;
-        let raw_tokens = raw.tokens.unwrap_or_default();
-        let tokens: HashMap<_, _> = raw_tokens
-            .into_iter()
-            .map(|(k, v)| Ok((str::parse::<u64>(&k)?, v)))
-            .collect::<Result<_>>()?;
-        Ok(Config { tokens })
+
+        let game = flag_game.or(env_game).or(raw.game);
+        if let Some(game) = &game {
+            if !is_valid_game_name(game) {
+                return Err(Error::Config(format!("invalid game name {:?}", game)));
+            }
+        }
+
+        let player_name = raw.player_name.map(|n| n.trim().to_string());
+        if player_name.as_deref() == Some("") {
+            return Err(Error::Config("player-name is empty".into()));
+        }
+
+        let raw_tokens = match (raw.tokens_file, raw.tokens) {
+            (Some(_), Some(t)) if !t.is_empty() => {
+                return Err(Error::Config(
+                    "tokens-file and [tokens] cannot be used together".into(),
+                ));
+            }
+            (Some(path), _) => read_tokens_file(&path)?,
+            (None, t) => t.unwrap_or_default(),
+        };
+        let tokens = parse_tokens(raw_tokens)?;
+        let format = raw.stack_format.as_deref().map(str::parse).transpose()?;
+
+        Ok(Config {
+            data_dir,
+            game,
+            player_name,
+            tokens,
+            format,
+            ..Default::default()
+        })
+    }
+
+    /// Gets the folder holding the selected game: a subfolder of the data
+    /// folder for a named game, or the data folder itself otherwise
+    pub fn game_dir(&self) -> PathBuf {
+        match &self.game {
+            Some(game) => self.data_dir.join(GAMES_FOLDER_NAME).join(game),
+            None => self.data_dir.clone(),
+        }
+    }
+
+    /// Makes the selected game's folder the current one, creating it first
+    /// if asked to
+    ///
+    /// On a dry run nothing is created, and a missing game folder leaves the
+    /// data folder as the current one.
+    pub fn enter_game_dir(&self, create: bool) -> Result<()> {
+        let dir = self.game_dir();
+        if create && self.dry_run && !dir.is_dir() {
+            env::set_current_dir(&self.data_dir)?;
+            return Ok(());
+        } else if create {
+            fs::create_dir_all(&dir)?;
+        } else if !dir.is_dir() {
+            return Err(Error::Config(format!(
+                "game {} not found in {}",
+                self.game.as_deref().unwrap_or_default(),
+                self.data_dir.display()
+            )));
+        }
+        env::set_current_dir(&dir)?;
+        Ok(())
+    }
+
+    /// Gets the format for displaying stacks: token names if any are
+    /// configured, plain numbers otherwise
+    pub fn stack_format(&self) -> StackFormat {
+        self.format.unwrap_or(if self.tokens.is_empty() {
+            StackFormat::Numeric
+        } else {
+            StackFormat::Names
+        })
+    }
+}
+
+/// Picks the data folder: the command line flag beats the environment, which
+/// beats the config file
+fn resolve_data_dir(
+    flag_dir: Option<PathBuf>,
+    env_dir: Option<PathBuf>,
+    cfg_dir: Option<PathBuf>,
+) -> PathBuf {
+    flag_dir
+        .or(env_dir)
+        .or(cfg_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Tests whether a game name can be used as a folder name: it must be
+/// non-empty and must not lead out of the games folder
+fn is_valid_game_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(|c: char| c == '/' || c == '\\' || c.is_control())
+}
+
+fn read_tokens_file(path: &Path) -> Result<HashMap<String, String>> {
+    let s = fs::read_to_string(path)
+        .map_err(|e| Error::Config(format!("cannot read tokens-file {}: {}", path.display(), e)))?;
+    toml::from_str(&s)
+        .map_err(|e| Error::Config(format!("invalid tokens-file {}: {}", path.display(), e)))
+}
+
+fn parse_tokens(raw: HashMap<String, String>) -> Result<HashMap<u64, String>> {
+    raw.into_iter()
+        .map(|(k, v)| {
+            let n = str::parse::<u64>(&k)
+                .map_err(|_| Error::Config(format!("invalid token number {:?}", k)))?;
+            if v.is_empty() {
+                return Err(Error::Config(format!("token {} has an empty name", n)));
+            }
+            Ok((n, v))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resolve_data_dir, Config, ConfigRaw};
+    use std::path::PathBuf;
+
+    fn dirs() -> (PathBuf, PathBuf, PathBuf) {
+        let base = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        (base.join("src"), base.join("src/cmd"), base)
+    }
+
+    #[test]
+    fn data_dir_flag_beats_env_beats_config() {
+        let (flag, env, cfg) = dirs();
+        let all = resolve_data_dir(Some(flag.clone()), Some(env.clone()), Some(cfg.clone()));
+        assert_eq!(all, flag);
+        let no_flag = resolve_data_dir(None, Some(env.clone()), Some(cfg.clone()));
+        assert_eq!(no_flag, env);
+        let only_cfg = resolve_data_dir(None, None, Some(cfg.clone()));
+        assert_eq!(only_cfg, cfg);
+        assert_eq!(resolve_data_dir(None, None, None), PathBuf::from("."));
+    }
+
+    #[test]
+    fn game_flag_beats_env_beats_config() {
+        let raw = || ConfigRaw {
+            game: Some("cfg".into()),
+            ..Default::default()
+        };
+        let game = |flag: Option<&str>, env: Option<&str>| {
+            Config::from_raw(
+                raw(),
+                (None, None),
+                (flag.map(Into::into), env.map(Into::into)),
+            )
+            .unwrap()
+            .game
+        };
+        assert_eq!(game(Some("flag"), Some("env")).as_deref(), Some("flag"));
+        assert_eq!(game(None, Some("env")).as_deref(), Some("env"));
+        assert_eq!(game(None, None).as_deref(), Some("cfg"));
+    }
+
+    #[test]
+    fn config_reads_resolve_overrides() {
+        let (flag, env, cfg) = dirs();
+        let raw = || ConfigRaw {
+            data_dir: Some(cfg.clone()),
+            ..Default::default()
+        };
+        let read = |flag: Option<PathBuf>, env: Option<PathBuf>| {
+            Config::from_raw(raw(), (flag, env), (None, None))
+        };
+        assert_eq!(
+            read(Some(flag.clone()), Some(env.clone()))
+                .unwrap()
+                .data_dir,
+            flag
+        );
+        assert_eq!(read(None, Some(env.clone())).unwrap().data_dir, env);
+        assert_eq!(read(None, None).unwrap().data_dir, cfg);
+
+        let missing = cfg.join("no-such-folder");
+        assert!(read(Some(missing.clone()), Some(env.clone())).is_err());
+        assert!(read(None, Some(missing)).is_err());
+    }
+
+    #[test]
+    fn invalid_game_names_are_rejected_whatever_their_source() {
+        let read = |flag: Option<&str>, env: Option<&str>| {
+            Config::from_raw(
+                ConfigRaw::default(),
+                (None, None),
+                (flag.map(Into::into), env.map(Into::into)),
+            )
+        };
+        assert!(read(Some(".."), None).is_err());
+        assert!(read(None, Some("a/b")).is_err());
+        assert!(read(Some("ok"), Some("..")).is_ok());
     }
 }
@@ -8,3 +8,8 @@ pub const IGNORE_FILE_CONTENTS: &[u8] = include_bytes!("gitignore.default");
 pub const CONFIG_FILE_NAME: &str = "game.cfg";
 pub const CONFIG_FILE_CONTENTS: &[u8] = include_bytes!("config.default");
 pub const SECRET_EXTENSION: &str = "secret";
+pub const PEEK_EXTENSION: &str = "peek";
+pub const VERIFICATION_CACHE_FILE_NAME: &str = "verified.cache";
+pub const DATA_DIR_VAR: &str = "PBMX_DATA_DIR";
+pub const GAME_VAR: &str = "PBMX_GAME";
+pub const GAMES_FOLDER_NAME: &str = "games";
@@ -7,6 +7,7 @@ pub enum Error {
     Clap(clap::Error),
     Pbmx(pbmx_kit::Error),
     Toml(toml::de::Error),
+    Config(String),
     InvalidSubcommand,
     InvalidData,
     InvalidBlock,
@@ -40,6 +41,12 @@ impl Error {
                 info: None,
             }
             .exit(),
+            Error::Config(msg) => clap::Error {
+                message: format!("Invalid configuration: {}", msg),
+                kind: clap::ErrorKind::InvalidValue,
+                info: None,
+            }
+            .exit(),
             Error::InvalidSubcommand => clap::Error {
                 message: "Invalid subcommand".into(),
                 kind: clap::ErrorKind::InvalidSubcommand,
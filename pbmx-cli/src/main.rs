@@ -17,13 +17,19 @@ mod file;
 mod indices;
 mod stack_map;
 mod state;
+mod storage;
+use storage::FileStorage;
 
 mod cmd;
-use cmd::{bin, init, issue, join, log, message, reset, rng, stack, status};
+use cmd::{
+    audit, bin, checkpoint, claim, export, init, issue, join, key, log, message, players, rename,
+    reset, rng, stack, status, vote, whoami,
+};
+use pbmx_kit::{crypto::hash::HashAlgorithm, state::DeckTemplate};
+use stack_map::StackFormat;
+use std::{env, path::PathBuf};
 
 fn main() {
-    let cfg = Config::read().unwrap();
-
     let matches = clap_app!(pbmx =>
         (version: crate_version!())
         (author: crate_authors!())
@@ -32,18 +38,24 @@ fn main() {
         (@setting ColoredHelp)
         (@setting SubcommandRequiredElseHelp)
         (@setting VersionlessSubcommands)
+        (@arg DRY_RUN: --("dry-run") +global "Shows what would be done without saving anything")
+        (@arg DATA_DIR: --("data-dir") +global +takes_value value_name("PATH") "The folder holding the game data (overrides PBMX_DATA_DIR and the config file)")
+        (@arg GAME: --game +global +takes_value value_name("NAME") "The game to use among several in the data folder (overrides PBMX_GAME and the config file)")
         (@subcommand init =>
             (about: "Initializes a new game folder")
             (@setting DeriveDisplayOrder)
             (@setting ColoredHelp)
             (@arg PATH: "The folder to hold game data (default: current folder)")
+            (@arg HASH: --hash +takes_value possible_values(HashAlgorithm::NAMES) "The hash function for player fingerprints and random generators, chosen in the genesis block (default: strobe)")
         )
         (@subcommand reset =>
             (about: "Resets the current block")
             (@setting DeriveDisplayOrder)
             (@setting ColoredHelp)
+            (@arg ALL: -a --all conflicts_with[LAST INDEX] "Discards every payload in the current block, after asking for confirmation")
             (@arg LAST: -l --last conflicts_with[INDEX] "Undoes only the latest payload")
             (@arg INDEX: -p --payload +takes_value +hidden conflicts_with[LAST] "Undoes only the payload with the given index (experimental)")
+            (@arg FORCE: -f --force requires[ALL] "Discards every payload without asking for confirmation")
         )
         (@subcommand issue =>
             (about: "Issues the current block")
@@ -54,17 +66,125 @@ fn main() {
             (about: "Joins the game")
             (@setting DeriveDisplayOrder)
             (@setting ColoredHelp)
-            (@arg NAME: +required "Your player name")
+            (@arg NAME: "Your player name (default: the player-name in the config file)")
+        )
+        (@subcommand rename =>
+            (about: "Changes your player name")
+            (@setting DeriveDisplayOrder)
+            (@setting ColoredHelp)
+            (@arg NEW_NAME: +required "Your new player name")
         )
         (@subcommand status =>
             (about: "Displays the game status")
             (@setting DeriveDisplayOrder)
             (@setting ColoredHelp)
         )
+        (@subcommand players =>
+            (about: "Lists the players in the game")
+            (@setting DeriveDisplayOrder)
+            (@setting ColoredHelp)
+            (@arg VERBOSE: -v --verbose "Includes the full public keys")
+        )
+        (@subcommand whoami =>
+            (about: "Displays your player name and key fingerprint")
+            (@setting DeriveDisplayOrder)
+            (@setting ColoredHelp)
+        )
+        (@subcommand key =>
+            (about: "Private key management")
+            (@setting DeriveDisplayOrder)
+            (@setting ColoredHelp)
+            (@setting SubcommandRequiredElseHelp)
+            (@setting VersionlessSubcommands)
+            (@setting DisableHelpSubcommand)
+            (@subcommand export =>
+                (about: "Backs up your private key, encrypted with a passphrase")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg OUT: -o --out +takes_value "The file to write the key to (default: print it in base64)")
+                (@arg UNENCRYPTED: --unencrypted "Exports the key without encrypting it")
+            )
+            (@subcommand import =>
+                (about: "Restores a private key from a backup")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg FILE: +required "The file with the exported key")
+                (@arg FORCE: -f --force "Replaces a different key that is already in use")
+            )
+        )
         (@subcommand log =>
             (about: "Displays the game log")
             (@setting DeriveDisplayOrder)
             (@setting ColoredHelp)
+            (@arg GRAPH: -g --graph conflicts_with[GREP PROOFS SINCE LAST] "Outputs the chain as a Graphviz DOT graph")
+            (@arg GREP: --grep +takes_value conflicts_with[PROOFS SINCE LAST] "Lists only text payloads containing the given text")
+            (@arg PROOFS: -p --proofs conflicts_with[SINCE LAST] "Lists every proof in the chain and whether it verified")
+            (@arg SINCE: -s --since +takes_value conflicts_with[LAST] "Shows only the blocks descending from this block")
+            (@arg LAST: -l --last +takes_value "Shows only the last N blocks")
+        )
+        (@subcommand export =>
+            (about: "Exports blocks to a folder, for sharing with other players")
+            (@setting DeriveDisplayOrder)
+            (@setting ColoredHelp)
+            (@arg OUTPUT: +required "The folder to write the blocks to")
+            (@arg SINCE: -s --since +takes_value +multiple +use_delimiter "Exports only blocks missing from a chain with these heads")
+        )
+        (@subcommand checkpoint =>
+            (about: "Adds a commitment to the current game state to the current block")
+            (@setting DeriveDisplayOrder)
+            (@setting ColoredHelp)
+        )
+        (@subcommand claim =>
+            (about: "Proves claims about masked stacks")
+            (@setting DeriveDisplayOrder)
+            (@setting ColoredHelp)
+            (@setting SubcommandRequiredElseHelp)
+            (@setting VersionlessSubcommands)
+            (@setting DisableHelpSubcommand)
+            (@subcommand distinct =>
+                (about: "Checks with all players that a stack holds no duplicate tokens, without revealing them; run again for each step")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg STACK: +required "The name or identifier of the stack")
+            )
+            (@subcommand equal_deck =>
+                (name: "equal-deck")
+                (about: "Creates a face-down stack from an open reference stack, with a proof that it holds exactly the same tokens")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg HIDDEN: +required "The name for the face-down stack")
+                (@arg REFERENCE: +required "The name or identifier of the open reference stack")
+            )
+            (@subcommand greater =>
+                (about: "Proves that a token hides a greater value than another, without revealing them; both must be visible to you and come from a deck template")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg GREATER: +required "The name or identifier of the stack with the greater token")
+                (@arg LESSER: +required "The name or identifier of the stack with the lesser token")
+                (@arg MAX: -m --max +takes_value "The bound on the values, which proofs grow quadratically with (default: one past the highest configured token)")
+            )
+            (@subcommand peek =>
+                (about: "Opens the commitment of an earlier peek at a stack, revealing it to everyone")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg STACK: +required "The name or identifier of the peeked stack")
+            )
+            (@subcommand list =>
+                (about: "Lists all claims in the chain and whether they verified")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+            )
+            (@subcommand verify =>
+                (about: "Takes the next step in checking a stack for duplicates, or lists the step each pending check needs from you")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg STACK: "The name or identifier of the stack to take the next step for")
+            )
+        )
+        (@subcommand audit =>
+            (about: "Replays the whole chain and lists every problem found")
+            (@setting DeriveDisplayOrder)
+            (@setting ColoredHelp)
         )
         (@subcommand message =>
             (about: "Adds a message to the current block")
@@ -96,13 +216,17 @@ fn main() {
                 (@setting DeriveDisplayOrder)
                 (@setting ColoredHelp)
                 (@arg TOKENS: +multiple +use_delimiter "The tokens in the stack")
+                (@arg TEMPLATE: -t --template +takes_value conflicts_with[TOKENS] possible_values(DeckTemplate::names()) "Uses a built-in deck template")
                 (@arg NAME: -n --name +takes_value "Sets the name of the stack")
+                (@arg DECK: -d --deck +takes_value requires[NAME] "Places the stack in a deck")
             )
             (@subcommand list =>
                 (about: "Lists existing stacks")
                 (@setting DeriveDisplayOrder)
                 (@setting ColoredHelp)
                 (@arg ALL: -a --all "Also includes unnamed stacks")
+                (@arg DECK: -d --deck +takes_value conflicts_with[ALL] "Lists only the stacks in a deck")
+                (@arg TAG: -t --tag +takes_value "Lists only the stacks with a tag")
             )
             (@subcommand show =>
                 (about: "Shows a stack's details")
@@ -111,12 +235,22 @@ fn main() {
                 (@arg STACK: "The name or identifier of the stack (shows all named stacks if none given)")
                 (@arg ALL: -a --all "Include unnamed stacks")
                 (@arg VERBOSE: -v --verbose "Includes more details, e.g. encrypted data")
+                (@arg DIFF: -d --diff +takes_value requires[STACK] "Compares the stack with another one")
+                (@arg COUNT: -c --count requires[STACK] conflicts_with[DIFF] "Shows only how many tokens the stack has, and how many are open, hidden, or revealed to everyone")
+                (@arg FORMAT: -f --format +takes_value possible_values(StackFormat::NAMES) "How to display the tokens (default: from the config)")
             )
             (@subcommand reveal =>
                 (about: "Reveals the secret share of a stack to others")
                 (@setting DeriveDisplayOrder)
                 (@setting ColoredHelp)
                 (@arg STACK: +required "The name or identifier of the stack")
+                (@arg INDICES: -i --indices +takes_value +multiple +use_delimiter "Reveals only the tokens at the given indices")
+            )
+            (@subcommand peek =>
+                (about: "Privately looks at a stack and publishes a commitment to its contents")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg STACK: +required "The name or identifier of the stack")
             )
             (@subcommand name =>
                 (about: "Names a stack")
@@ -125,6 +259,13 @@ fn main() {
                 (@arg ID: +required "The stack ID")
                 (@arg NAME: +required "The name of the stack")
             )
+            (@subcommand tag =>
+                (about: "Adds tags to a stack")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg STACK: +required "The name or identifier of the stack")
+                (@arg TAGS: +required +multiple +use_delimiter "The tags to add")
+            )
             (@subcommand mask =>
                 (about: "Remasks a stack")
                 (@setting DeriveDisplayOrder)
@@ -138,12 +279,21 @@ fn main() {
                 (@arg STACK: +multiple +required "The name or identifier of the stack")
                 (@arg ORDER: -o --order +takes_value "Chooses a specific order instead of randomizing")
             )
+            (@subcommand reshuffle_from =>
+                (name: "reshuffle-from")
+                (about: "Shuffles a stack, proving it is still a permutation of a reference stack")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg REF: +required "The name or identifier of the reference stack")
+                (@arg STACK: +required "The name or identifier of the stack to shuffle")
+            )
             (@subcommand cut =>
                 (about: "Cuts a stack")
                 (@setting DeriveDisplayOrder)
                 (@setting ColoredHelp)
                 (@arg STACK: +multiple +required "The name or identifier of the stack")
-                (@arg N: -n +takes_value "Chooses a specific cut size instead of randomizing")
+                (@arg N: -n +takes_value conflicts_with[REVEAL_TOP] "Chooses a specific cut size instead of randomizing")
+                (@arg REVEAL_TOP: -r --("reveal-top") "Reveals the secret share of the new top token")
             )
             (@subcommand take =>
                 (about: "Takes some tokens from an existing stack into another")
@@ -156,6 +306,24 @@ fn main() {
                 (@arg UNDER: -u --under +takes_value conflicts_with[OVER] conflicts_with[TARGET] "Piles the tokens at the bottom of this stack")
                 (@arg REMOVE: -r --remove conflicts_with[CLONE] "Remove the tokens from the source stack (default)")
                 (@arg CLONE: -c --clone conflicts_with[REMOVE] "Clones the tokens into the target stack")
+                (@arg FACE_UP: -f --("face-up") "Deals the tokens face up, publishing this party's secrets for them")
+            )
+            (@subcommand move =>
+                (about: "Moves some tokens from an existing stack to the top of another")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg SOURCE: +required "The name or identifier of the source stack")
+                (@arg INDICES: +required +use_delimiter "The indices of the tokens to move")
+                (@arg TARGET: +required "The name or identifier of the target stack")
+            )
+            (@subcommand redraw =>
+                (about: "Discards some tokens from a stack and draws as many replacements from another")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg HAND: +required "The name or identifier of the stack to replace tokens in")
+                (@arg INDICES: +required +use_delimiter "The indices of the tokens to discard")
+                (@arg DECK: +required "The name or identifier of the stack to draw from")
+                (@arg DISCARD: +required "The name or identifier of the stack to discard to")
             )
             (@subcommand pile =>
                 (about: "Piles several stacks together")
@@ -166,6 +334,28 @@ fn main() {
                 (@arg REMOVE: -r --remove conflicts_with[CLONE] "Remove the tokens from the source stacks (default)")
                 (@arg CLONE: -c --clone conflicts_with[REMOVE] "Clones the tokens into the target stack")
             )
+            (@subcommand burn =>
+                (about: "Burns some tokens from an existing stack")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg SOURCE: +required "The name or identifier of the source stack")
+                (@arg INDICES: +required +multiple +use_delimiter "The indices of the tokens to burn")
+            )
+            (@subcommand insert =>
+                (about: "Inserts the tokens of a stack into another at a position picked by the revealed random number generator named insert:<STACK ID>:<TOKENS ID>, whose value modulo one more than the stack's size is the insertion index")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg STACK: +required "The name or identifier of the stack to insert into")
+                (@arg TOKENS: +required "The name or identifier of the stack with the tokens to insert")
+            )
+            (@subcommand return_tokens =>
+                (name: "return")
+                (about: "Returns the tokens of a stack to a deck and reshuffles it")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg TOKENS: +required "The name or identifier of the stack with the tokens to return")
+                (@arg DECK: +required "The name or identifier of the deck to reshuffle them into")
+            )
         )
         (@subcommand rng =>
             (about: "Random number generation")
@@ -179,7 +369,8 @@ fn main() {
                 (@setting DeriveDisplayOrder)
                 (@setting ColoredHelp)
                 (@arg NAME: +required "The name of the generator")
-                (@arg SPEC: +required "The generator specification (e.g. 1d6+2)")
+                (@arg SPEC: +required "The generator specification (e.g. 1d6+2, 1d6,beacon:1234 to mix in an external beacon round, or sum:100 for a sum of secret values)")
+                (@arg VALUE: -v --value +takes_value "Your secret value for a sum generator")
             )
             (@subcommand list =>
                 (about: "Lists existing generators")
@@ -192,6 +383,15 @@ fn main() {
                 (@setting DeriveDisplayOrder)
                 (@setting ColoredHelp)
                 (@arg NAME: +required "The name of the generator")
+                (@arg VALUE: -v --value +takes_value "Your secret value for a sum generator")
+            )
+            (@subcommand beacon =>
+                (about: "Records the output of the external beacon a generator mixes in")
+                (@setting DeriveDisplayOrder)
+                (@setting ColoredHelp)
+                (@arg NAME: +required "The name of the generator")
+                (@arg VALUE: +required "The beacon output for the generator's round, in hex")
+                (@arg PROOF: -p --proof +takes_value "The beacon's signature for that round, in hex")
             )
             (@subcommand reveal =>
                 (about: "Reveals the secret share of a generator to others")
@@ -204,22 +404,49 @@ fn main() {
                 (@setting DeriveDisplayOrder)
                 (@setting ColoredHelp)
                 (@arg NAME: +required "The name of the generator")
+                (@arg RAW: -r --raw "Prints raw shared random bytes in hex instead, identical for every player but depending on the byte count")
+                (@arg BYTES: -b --bytes +takes_value requires[RAW] "The number of raw bytes to print (default 32)")
             )
         )
+        (@subcommand vote =>
+            (about: "Casts a secret ballot or reveals a vote tally")
+            (@setting DeriveDisplayOrder)
+            (@setting ColoredHelp)
+            (@arg TOPIC: +required "The topic of the vote")
+            (@arg CHOICE: possible_values(&["yes", "no"]) conflicts_with[REVEAL] "The ballot to cast")
+            (@arg REVEAL: -r --reveal "Reveals the secret share of the tally to others")
+        )
     )
     .get_matches();
+    let mut cfg = Config::read(
+        value_t!(matches, "DATA_DIR", PathBuf).ok(),
+        value_t!(matches, "GAME", String).ok(),
+    )
+    .unwrap_or_else(|e| e.exit());
+    cfg.dry_run = matches.is_present("DRY_RUN");
+    let init = matches.subcommand_name() == Some("init");
+    cfg.enter_game_dir(init).unwrap_or_else(|e| e.exit());
 
     match matches.subcommand() {
-        ("init", Some(sub_m)) => init::run(sub_m, &cfg),
-        ("reset", Some(sub_m)) => reset::run(sub_m, &cfg),
-        ("issue", Some(sub_m)) => issue::run(sub_m, &cfg),
-        ("join", Some(sub_m)) => join::run(sub_m, &cfg),
-        ("status", Some(sub_m)) => status::run(sub_m, &cfg),
-        ("log", Some(sub_m)) => log::run(sub_m, &cfg),
-        ("bin", Some(sub_m)) => bin::run(sub_m, &cfg),
-        ("message", Some(sub_m)) => message::run(sub_m, &cfg),
-        ("stack", Some(sub_m)) => stack::run(sub_m, &cfg),
-        ("rng", Some(sub_m)) => rng::run(sub_m, &cfg),
+        ("init", Some(sub_m)) => init::run(sub_m, &cfg, &FileStorage),
+        ("reset", Some(sub_m)) => reset::run(sub_m, &cfg, &FileStorage),
+        ("issue", Some(sub_m)) => issue::run(sub_m, &cfg, &FileStorage),
+        ("join", Some(sub_m)) => join::run(sub_m, &cfg, &FileStorage),
+        ("rename", Some(sub_m)) => rename::run(sub_m, &cfg, &FileStorage),
+        ("status", Some(sub_m)) => status::run(sub_m, &cfg, &FileStorage),
+        ("players", Some(sub_m)) => players::run(sub_m, &cfg, &FileStorage),
+        ("whoami", Some(sub_m)) => whoami::run(sub_m, &cfg, &FileStorage),
+        ("key", Some(sub_m)) => key::run(sub_m, &cfg, &FileStorage),
+        ("log", Some(sub_m)) => log::run(sub_m, &cfg, &FileStorage),
+        ("export", Some(sub_m)) => export::run(sub_m, &cfg, &FileStorage),
+        ("bin", Some(sub_m)) => bin::run(sub_m, &cfg, &FileStorage),
+        ("message", Some(sub_m)) => message::run(sub_m, &cfg, &FileStorage),
+        ("checkpoint", Some(sub_m)) => checkpoint::run(sub_m, &cfg, &FileStorage),
+        ("claim", Some(sub_m)) => claim::run(sub_m, &cfg, &FileStorage),
+        ("audit", Some(sub_m)) => audit::run(sub_m, &cfg, &FileStorage),
+        ("stack", Some(sub_m)) => stack::run(sub_m, &cfg, &FileStorage),
+        ("rng", Some(sub_m)) => rng::run(sub_m, &cfg, &FileStorage),
+        ("vote", Some(sub_m)) => vote::run(sub_m, &cfg, &FileStorage),
         _ => Err(Error::InvalidSubcommand),
     }
     .unwrap_or_else(|e| e.exit());
@@ -1,159 +1,154 @@
-use crate::Config;
-use pbmx_kit::{
-    crypto::{
-        keys::Fingerprint,
-        map,
-        vtmf::{Mask, Stack, Vtmf},
-    },
-    state::{PrivateSecretMap, SecretMap},
+use crate::{Config, Error};
+use pbmx_kit::{crypto::vtmf::Stack, state::State};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
 };
-use std::fmt::{self, Display, Formatter};
+
+/// Renders the contents of a stack
+pub trait StackFormatter {
+    /// Writes an open token
+    fn fmt_token(&self, f: &mut Formatter, token: u64) -> fmt::Result;
+
+    /// Writes a run of consecutive hidden masks
+    fn fmt_hidden(&self, f: &mut Formatter, count: usize) -> fmt::Result {
+        write!(f, "?{}", count)
+    }
+
+    /// Tests whether runs of consecutive tokens are written as ranges
+    fn collapses_runs(&self) -> bool {
+        false
+    }
+
+    /// Writes a range of consecutive open tokens
+    fn fmt_run(&self, f: &mut Formatter, first: u64, last: u64) -> fmt::Result {
+        self.fmt_token(f, first)?;
+        write!(f, "-")?;
+        self.fmt_token(f, last)
+    }
+}
+
+/// Writes tokens as plain numbers, collapsing runs into ranges
+pub struct NumericFormatter;
+
+impl StackFormatter for NumericFormatter {
+    fn fmt_token(&self, f: &mut Formatter, token: u64) -> fmt::Result {
+        write!(f, "{}", token)
+    }
+
+    fn collapses_runs(&self) -> bool {
+        true
+    }
+}
+
+/// Writes tokens by their configured names, or as numbers if they have none
+pub struct NamedFormatter<'a>(pub &'a HashMap<u64, String>);
+
+impl<'a> StackFormatter for NamedFormatter<'a> {
+    fn fmt_token(&self, f: &mut Formatter, token: u64) -> fmt::Result {
+        match self.0.get(&token) {
+            Some(s) => write!(f, "{}", s),
+            None => write!(f, "{}", token),
+        }
+    }
+}
+
+/// The built-in stack formats
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StackFormat {
+    /// Plain numbers
+    Numeric,
+    /// Token names
+    Names,
+}
+
+impl StackFormat {
+    /// The names of all the formats
+    pub const NAMES: &'static [&'static str] = &["numeric", "names"];
+
+    /// Gets a formatter for this format
+    pub fn formatter<'a>(self, cfg: &'a Config) -> Box<dyn StackFormatter + 'a> {
+        match self {
+            StackFormat::Numeric => Box::new(NumericFormatter),
+            StackFormat::Names => Box::new(NamedFormatter(&cfg.tokens)),
+        }
+    }
+}
+
+impl FromStr for StackFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "numeric" => Ok(StackFormat::Numeric),
+            "names" => Ok(StackFormat::Names),
+            _ => Err(Error::Config(format!("unknown stack format {:?}", s))),
+        }
+    }
+}
 
 struct DisplayStackContents<'a> {
     stack: &'a Stack,
-    secrets: &'a SecretMap,
-    private_secrets: &'a PrivateSecretMap,
-    vtmf: &'a Vtmf,
-    config: &'a Config,
+    state: &'a State,
+    formatter: Box<dyn StackFormatter + 'a>,
 }
+
+/// Displays a stack with the formatter chosen in the configuration
 pub fn display_stack_contents<'a>(
     stack: &'a Stack,
-    secrets: &'a SecretMap,
-    private_secrets: &'a PrivateSecretMap,
-    vtmf: &'a Vtmf,
+    state: &'a State,
     config: &'a Config,
 ) -> impl Display + 'a {
-    DisplayStackContents {
-        stack,
-        secrets,
-        private_secrets,
-        vtmf,
-        config,
-    }
+    display_stack_with(stack, state, config.stack_format().formatter(config))
 }
 
-fn unmask_with_public_secrets(
-    mut m: Mask,
-    secrets: &SecretMap,
-    vtmf: &Vtmf,
-    my_fp: &Fingerprint,
-) -> Option<Mask> {
-    if let Some((d, fp)) = secrets.get(&m) {
-        m = vtmf.unmask(&m, d);
-        if !fp.contains(my_fp) {
-            m = vtmf.unmask_private(&m);
-            if fp.len() + 1 == vtmf.parties() {
-                return Some(m);
-            }
-        } else {
-            if fp.len() == vtmf.parties() {
-                return Some(m);
-            }
-        }
-    } else {
-        if m.is_open() {
-            return Some(m);
-        }
+/// Displays a stack with the given formatter
+pub fn display_stack_with<'a>(
+    stack: &'a Stack,
+    state: &'a State,
+    formatter: Box<dyn StackFormatter + 'a>,
+) -> impl Display + 'a {
+    DisplayStackContents {
+        stack,
+        state,
+        formatter,
     }
-    None
 }
 
-fn unmask_with_private_secrets(
-    mut m: Mask,
-    secrets: &SecretMap,
-    private_secrets: &PrivateSecretMap,
-    vtmf: &Vtmf,
-    my_fp: &Fingerprint,
-) -> Option<Mask> {
-    while let Some(d) = private_secrets.get(&m) {
-        m -= d;
-        if m.is_open() {
-            return Some(m);
-        }
-        let unmasked = unmask_with_public_secrets(m, secrets, vtmf, my_fp);
-        if unmasked.is_some() {
-            return unmasked;
-        }
-    }
-    None
+enum Segment {
+    Hidden(usize),
+    Tokens(u64, u64),
 }
 
 impl<'a> Display for DisplayStackContents<'a> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let mut first = true;
-        let mut last_in_seq = None;
-        let mut unfinished_seq = false;
-        let mut count_encrypted = 0;
-        write!(f, "[")?;
-        let my_fp = &self.vtmf.private_key().fingerprint();
+        let mut segments = Vec::new();
         for m in self.stack.iter() {
-            let m =
-                unmask_with_public_secrets(*m, &self.secrets, &self.vtmf, &my_fp).or_else(|| {
-                    unmask_with_private_secrets(
-                        *m,
-                        &self.secrets,
-                        &self.private_secrets,
-                        &self.vtmf,
-                        &my_fp,
-                    )
-                });
-            if let Some(m) = m {
-                let u = self.vtmf.unmask_open(&m);
-                let token = map::from_curve(&u);
-                if count_encrypted > 0 {
-                    if !first {
-                        write!(f, " ")?;
-                    }
-                    write!(f, "?{}", count_encrypted)?;
-                    first = false;
-                    count_encrypted = 0;
-                }
-                if self.config.tokens.is_empty() {
-                    if let Some(last) = last_in_seq {
-                        if last + 1 == token {
-                            unfinished_seq = true;
-                        } else {
-                            if unfinished_seq {
-                                write!(f, "-{}", last)?;
-                                unfinished_seq = false;
-                            }
-                            write!(f, " {}", token)?;
-                        }
-                    } else {
-                        if !first {
-                            write!(f, " ")?;
-                        }
-                        write!(f, "{}", token)?;
-                    }
-                } else {
-                    if !first {
-                        write!(f, " ")?;
-                    }
-                    let s = self.config.tokens.get(&token);
-                    if let Some(s) = s {
-                        write!(f, "{}", s)?;
-                    } else {
-                        write!(f, "{}", token)?;
-                    }
+            let token = self.state.reveal_token(m).ok();
+            match (segments.last_mut(), token) {
+                (Some(Segment::Hidden(n)), None) => *n += 1,
+                (_, None) => segments.push(Segment::Hidden(1)),
+                (Some(Segment::Tokens(_, last)), Some(t))
+                    if self.formatter.collapses_runs() && *last + 1 == t =>
+                {
+                    *last = t
                 }
-                last_in_seq = Some(token);
-                first = false;
-            } else {
-                last_in_seq = None;
-                count_encrypted += 1;
+                (_, Some(t)) => segments.push(Segment::Tokens(t, t)),
             }
         }
-        if count_encrypted > 0 {
-            if !first {
+
+        write!(f, "[")?;
+        for (i, s) in segments.iter().enumerate() {
+            if i > 0 {
                 write!(f, " ")?;
             }
-            write!(f, "?{}", count_encrypted)?;
-        }
-        if unfinished_seq {
-            let last = last_in_seq.unwrap();
-            write!(f, "-{}", last)?;
+            match *s {
+                Segment::Hidden(n) => self.formatter.fmt_hidden(f, n)?,
+                Segment::Tokens(a, b) if a == b => self.formatter.fmt_token(f, a)?,
+                Segment::Tokens(a, b) => self.formatter.fmt_run(f, a, b)?,
+            }
         }
-        write!(f, "]")?;
-        Ok(())
+        write!(f, "]")
     }
 }
@@ -1,71 +1,50 @@
-use crate::{
-    constants::{
-        BLOCKS_FOLDER_NAME, BLOCK_EXTENSION, CURRENT_BLOCK_FILE_NAME, KEY_FILE_NAME,
-        SECRETS_FOLDER_NAME, SECRET_EXTENSION,
-    },
-    Error, Result,
-};
+use crate::{storage::Storage, Config, Result};
+use colored::Colorize;
 use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_POINT, scalar::Scalar};
 use pbmx_kit::{
-    chain::{Block, Chain, Payload},
+    chain::{Id, Payload},
     crypto::{
-        keys::PrivateKey,
+        hash::HashAlgorithm,
+        keys::Fingerprint,
         vtmf::{Mask, Stack},
     },
-    serde::Message,
     state::{PrivateSecretMap, State as BaseState},
 };
-use std::{ffi::OsStr, fs, path::PathBuf};
 
-#[derive(Debug)]
-pub struct State {
+pub struct State<'a> {
     pub base: BaseState,
     pub payloads: Vec<Payload>,
+    saved: usize,
+    dry_run: bool,
+    storage: &'a dyn Storage,
 }
 
-impl State {
-    pub fn read(include_temp: bool) -> Result<State> {
-        let mut path = PathBuf::from(SECRETS_FOLDER_NAME);
-        path.push(KEY_FILE_NAME);
-        let sk = PrivateKey::decode(&fs::read(&path)?)?;
-
-        let mut chain = Chain::new();
-        for entry in fs::read_dir(BLOCKS_FOLDER_NAME)? {
-            let entry = entry?;
-            if !entry.file_type()?.is_file() {
-                continue;
-            }
-            let block_extension = OsStr::new(BLOCK_EXTENSION);
-            if let Some(ext) = entry.path().extension() {
-                if ext != block_extension {
-                    continue;
-                }
-                let block = Block::decode(&fs::read(&entry.path())?)?;
-                chain.add_block(block);
-            }
-        }
+impl<'a> State<'a> {
+    pub fn read(include_temp: bool, cfg: &Config, storage: &'a dyn Storage) -> Result<Self> {
+        let sk = storage.load_key()?;
+        let chain = storage.load_chain()?;
 
         let mut base = BaseState::new(sk.clone());
+        if !cfg.tokens.is_empty() {
+            base.declare_tokens(cfg.tokens.keys().cloned());
+        }
+        if let Some(cache) = storage.load_cache() {
+            base.verified = cache;
+        }
         for block in chain.blocks() {
-            base.add_block(&block).map_err(|_| Error::InvalidBlock)?;
+            base.add_block(&block)?;
         }
-        for entry in fs::read_dir(SECRETS_FOLDER_NAME)? {
-            let entry = entry?;
-            if !entry.file_type()?.is_file() {
-                continue;
-            }
-            let secret_extension = OsStr::new(SECRET_EXTENSION);
-            if let Some(ext) = entry.path().extension() {
-                if ext != secret_extension {
-                    continue;
-                }
-                let secrets = PrivateSecretMap::decode(&fs::read(&entry.path())?)?;
-                base.add_secrets(secrets.into_iter())
-                    .map_err(|_| Error::InvalidBlock)?;
-            }
+        for (id, e) in base.take_pending_errors() {
+            println!("{} {:16}: {}", " ! Dropped block".yellow().bold(), id, e);
+        }
+        if !cfg.dry_run {
+            storage.save_cache(&base.verified)?;
+        }
+        for secrets in storage.load_secrets()? {
+            base.add_secrets(secrets.into_iter())?;
         }
 
-        let payloads = Vec::decode(&fs::read(CURRENT_BLOCK_FILE_NAME)?)?;
+        let payloads = storage.load_payloads()?;
 
         if include_temp {
             let mut builder = base.chain.build_block();
@@ -73,10 +52,32 @@ impl State {
                 builder.add_payload(p);
             }
             let block = builder.build(&sk);
-            base.add_block(&block).map_err(|_| Error::InvalidBlock)?;
+            base.add_block(&block)?;
         }
 
-        Ok(State { base, payloads })
+        Ok(State {
+            base,
+            saved: payloads.len(),
+            payloads,
+            dry_run: cfg.dry_run,
+            storage,
+        })
+    }
+
+    // a genesis block still being built may have chosen it already
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        let pending = self.payloads.iter().find_map(|p| match p {
+            Payload::SelectHash(algorithm) if self.base.chain.is_empty() => Some(*algorithm),
+            _ => None,
+        });
+        pending.unwrap_or_else(|| self.base.vtmf.hash_algorithm())
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.base
+            .vtmf
+            .private_key()
+            .fingerprint_with(self.hash_algorithm())
     }
 
     pub fn clear_payloads(&mut self) {
@@ -84,6 +85,10 @@ impl State {
     }
 
     pub fn save_secrets(&self, stack: &Stack, secrets: Vec<Scalar>) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+
         let base_mask = Mask(
             RISTRETTO_BASEPOINT_POINT,
             self.base.vtmf.shared_key().point(),
@@ -95,15 +100,31 @@ impl State {
             .map(|(m, r)| (m, r * base_mask))
             .collect();
 
-        let secret_file = format!("{}.{}", stack.id(), SECRET_EXTENSION);
-        let mut path = PathBuf::from(SECRETS_FOLDER_NAME);
-        path.push(secret_file);
-        fs::write(path, &map.encode()?)?;
-        Ok(())
+        self.storage.save_secrets(stack.id(), &map)
+    }
+
+    pub fn save_peek_nonce(&self, id: Id, nonce: &Id) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        self.storage.save_peek_nonce(id, nonce)
     }
 
     pub fn save_payloads(&self) -> Result<()> {
-        fs::write(CURRENT_BLOCK_FILE_NAME, &self.payloads.encode()?)?;
-        Ok(())
+        if self.dry_run {
+            println!("{}", " ~ Dry run, current block not saved".yellow().bold());
+            let hash = self.hash_algorithm();
+            let new = self.payloads.iter().enumerate().skip(self.saved);
+            for (i, p) in new {
+                match p.result_stack() {
+                    Some(id) => println!("   {:3} {} ↬ {:16}", i, p.display_short_with(hash), id),
+                    None => println!("   {:3} {}", i, p.display_short_with(hash)),
+                }
+            }
+            return Ok(());
+        }
+
+        self.storage.save_payloads(&self.payloads)
     }
 }
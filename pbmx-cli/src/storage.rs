@@ -0,0 +1,165 @@
+use crate::{
+    constants::{
+        BLOCKS_FOLDER_NAME, BLOCK_EXTENSION, CURRENT_BLOCK_FILE_NAME, KEY_FILE_NAME,
+        PEEK_EXTENSION, SECRETS_FOLDER_NAME, SECRET_EXTENSION, VERIFICATION_CACHE_FILE_NAME,
+    },
+    file, Result,
+};
+use pbmx_kit::{
+    chain::{Block, Chain, Id, Payload},
+    crypto::keys::PrivateKey,
+    serde::Message,
+    state::{PrivateSecretMap, VerificationCache},
+};
+use std::{convert::TryFrom, ffi::OsStr, fs, path::PathBuf};
+
+/// Persists the data of a game
+///
+/// Commands go through this instead of touching files, so games can be kept
+/// somewhere other than a folder, e.g. in a database or in memory.
+pub trait Storage {
+    /// Reads this player's private key
+    fn load_key(&self) -> Result<PrivateKey>;
+
+    /// Replaces this player's private key
+    fn save_key(&self, sk: &PrivateKey) -> Result<()>;
+
+    /// Reads all the issued blocks
+    fn load_chain(&self) -> Result<Chain>;
+
+    /// Stores a newly issued block
+    ///
+    /// Fails if the block was already stored.
+    fn append_block(&self, block: &Block) -> Result<()>;
+
+    /// Reads the payloads staged for the next block
+    fn load_payloads(&self) -> Result<Vec<Payload>>;
+
+    /// Replaces the payloads staged for the next block
+    fn save_payloads(&self, payloads: &[Payload]) -> Result<()>;
+
+    /// Reads the private secrets of all the stacks this player masked
+    fn load_secrets(&self) -> Result<Vec<PrivateSecretMap>>;
+
+    /// Stores the private secrets of a stack this player masked
+    fn save_secrets(&self, id: Id, secrets: &PrivateSecretMap) -> Result<()>;
+
+    /// Reads the nonce of this player's last peek at a stack
+    fn load_peek_nonce(&self, id: Id) -> Result<Id>;
+
+    /// Stores the nonce of a peek at a stack, to open its commitment later
+    fn save_peek_nonce(&self, id: Id, nonce: &Id) -> Result<()>;
+
+    /// Reads the cache of blocks already known to be valid, if any
+    fn load_cache(&self) -> Option<VerificationCache>;
+
+    /// Replaces the cache of blocks already known to be valid
+    fn save_cache(&self, cache: &VerificationCache) -> Result<()>;
+}
+
+/// Keeps a game in the files of the current folder
+pub struct FileStorage;
+
+impl FileStorage {
+    fn key_path() -> PathBuf {
+        let mut path = PathBuf::from(SECRETS_FOLDER_NAME);
+        path.push(KEY_FILE_NAME);
+        path
+    }
+
+    fn peek_path(id: Id) -> PathBuf {
+        let mut path = PathBuf::from(SECRETS_FOLDER_NAME);
+        path.push(format!("{}.{}", id, PEEK_EXTENSION));
+        path
+    }
+
+    fn cache_path() -> PathBuf {
+        let mut path = PathBuf::from(SECRETS_FOLDER_NAME);
+        path.push(VERIFICATION_CACHE_FILE_NAME);
+        path
+    }
+
+    fn read_all(folder: &str, extension: &str) -> Result<Vec<Vec<u8>>> {
+        let extension = OsStr::new(extension);
+        let mut contents = Vec::new();
+        for entry in fs::read_dir(folder)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if entry.path().extension() == Some(extension) {
+                contents.push(fs::read(entry.path())?);
+            }
+        }
+        Ok(contents)
+    }
+}
+
+impl Storage for FileStorage {
+    fn load_key(&self) -> Result<PrivateKey> {
+        Ok(PrivateKey::decode(&fs::read(Self::key_path())?)?)
+    }
+
+    fn save_key(&self, sk: &PrivateKey) -> Result<()> {
+        fs::write(Self::key_path(), &sk.encode()?)?;
+        Ok(())
+    }
+
+    fn load_chain(&self) -> Result<Chain> {
+        let mut chain = Chain::new();
+        for bytes in Self::read_all(BLOCKS_FOLDER_NAME, BLOCK_EXTENSION)? {
+            chain.add_block(Block::decode(&bytes)?);
+        }
+        Ok(chain)
+    }
+
+    fn append_block(&self, block: &Block) -> Result<()> {
+        let block_file = format!("{}.{}", block.id(), BLOCK_EXTENSION);
+        let mut path = PathBuf::from(BLOCKS_FOLDER_NAME);
+        path.push(block_file);
+        file::write_new(path, block.encode()?)
+    }
+
+    fn load_payloads(&self) -> Result<Vec<Payload>> {
+        Ok(Vec::decode(&fs::read(CURRENT_BLOCK_FILE_NAME)?)?)
+    }
+
+    fn save_payloads(&self, payloads: &[Payload]) -> Result<()> {
+        fs::write(CURRENT_BLOCK_FILE_NAME, &payloads.to_vec().encode()?)?;
+        Ok(())
+    }
+
+    fn load_secrets(&self) -> Result<Vec<PrivateSecretMap>> {
+        Self::read_all(SECRETS_FOLDER_NAME, SECRET_EXTENSION)?
+            .iter()
+            .map(|bytes| Ok(PrivateSecretMap::decode(bytes)?))
+            .collect()
+    }
+
+    fn save_secrets(&self, id: Id, secrets: &PrivateSecretMap) -> Result<()> {
+        let secret_file = format!("{}.{}", id, SECRET_EXTENSION);
+        let mut path = PathBuf::from(SECRETS_FOLDER_NAME);
+        path.push(secret_file);
+        fs::write(path, &secrets.encode()?)?;
+        Ok(())
+    }
+
+    fn load_peek_nonce(&self, id: Id) -> Result<Id> {
+        Ok(Id::try_from(&fs::read(Self::peek_path(id))?)?)
+    }
+
+    fn save_peek_nonce(&self, id: Id, nonce: &Id) -> Result<()> {
+        fs::write(Self::peek_path(id), &nonce[..])?;
+        Ok(())
+    }
+
+    fn load_cache(&self) -> Option<VerificationCache> {
+        let bytes = fs::read(Self::cache_path()).ok()?;
+        Some(VerificationCache::decode(&bytes).unwrap_or_default())
+    }
+
+    fn save_cache(&self, cache: &VerificationCache) -> Result<()> {
+        fs::write(Self::cache_path(), &cache.encode()?)?;
+        Ok(())
+    }
+}
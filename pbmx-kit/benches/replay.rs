@@ -0,0 +1,75 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use pbmx_kit::{
+    chain::{Block, Payload},
+    crypto::{
+        keys::PrivateKey,
+        map,
+        perm::Shuffles,
+        vtmf::{Mask, Stack},
+    },
+    state::{State, VerificationCache},
+};
+use rand::{thread_rng, Rng};
+
+const BLOCKS: usize = 200;
+
+fn build_chain(sk: &PrivateKey) -> Vec<Block> {
+    let mut rng = thread_rng();
+    let mut state = State::new(sk.clone());
+    let mut blocks = Vec::with_capacity(BLOCKS);
+
+    let mut stack: Stack = (0..8).map(|i| Mask::open(map::to_curve(i))).collect();
+    let mut b = state.chain.build_block();
+    b.add_payload(Payload::PublishKey("bench".into(), sk.public_key()));
+    b.add_payload(Payload::OpenStack(stack.clone()));
+    blocks.push(b.build(sk));
+    state.add_block(&blocks[0]).unwrap();
+
+    while blocks.len() < BLOCKS {
+        let pi = rng.sample(Shuffles(stack.len()));
+        let (shuffled, _, proof) = state.vtmf.mask_shuffle(&stack, &pi);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShuffleStack(stack.id(), shuffled.clone(), proof));
+        let block = b.build(sk);
+        state.add_block(&block).unwrap();
+        blocks.push(block);
+        stack = shuffled;
+    }
+    blocks
+}
+
+fn replay(sk: &PrivateKey, blocks: &[Block], cache: VerificationCache) -> State {
+    let mut state = State::new(sk.clone());
+    state.verified = cache;
+    for b in blocks {
+        state.add_block(b).unwrap();
+    }
+    state
+}
+
+fn bench_replay(c: &mut Criterion) {
+    let sk = PrivateKey::random(&mut thread_rng());
+    let blocks = build_chain(&sk);
+    let cache = replay(&sk, &blocks, VerificationCache::new()).verified;
+
+    let mut group = c.benchmark_group("replay 200 blocks");
+    group.sample_size(10);
+    group.bench_function("uncached", |b| {
+        b.iter_batched(
+            VerificationCache::new,
+            |cache| replay(&sk, &blocks, cache),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("cached", |b| {
+        b.iter_batched(
+            || cache.clone(),
+            |cache| replay(&sk, &blocks, cache),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_replay);
+criterion_main!(benches);
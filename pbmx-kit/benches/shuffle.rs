@@ -0,0 +1,36 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use pbmx_kit::crypto::{
+    keys::PrivateKey,
+    map,
+    perm::Shuffles,
+    vtmf::{CompressedStack, Mask, Stack, Vtmf},
+};
+use rand::{thread_rng, Rng};
+
+const DECK_SIZE: u64 = 52;
+
+fn bench_verify_shuffle(c: &mut Criterion) {
+    let mut rng = thread_rng();
+    let vtmf = Vtmf::new(PrivateKey::random(&mut rng));
+    let stack: Stack = (0..DECK_SIZE)
+        .map(|i| Mask::open(map::to_curve(i)))
+        .collect();
+    let pi = rng.sample(Shuffles(stack.len()));
+    let (shuffled, _, proof) = vtmf.mask_shuffle(&stack, &pi);
+
+    c.bench_function("verify shuffle of 52 tokens", |b| {
+        b.iter(|| vtmf.verify_mask_shuffle(&stack, &shuffled, &proof).unwrap())
+    });
+
+    let stack = CompressedStack::new(stack);
+    let shuffled = CompressedStack::new(shuffled);
+    c.bench_function("verify shuffle of 52 compressed tokens", |b| {
+        b.iter(|| {
+            vtmf.verify_mask_shuffle_compressed(&stack, &shuffled, &proof)
+                .unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, bench_verify_shuffle);
+criterion_main!(benches);
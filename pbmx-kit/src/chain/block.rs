@@ -6,11 +6,12 @@ use crate::{
         Id,
     },
     crypto::{
-        hash::{Transcribe, TranscriptAppend},
+        hash::{HashAlgorithm, Transcribe, TranscriptAppend, MESSAGE_ID_DOMAIN},
         keys::{Fingerprint, PrivateKey, PublicKey},
+        vtmf::Vtmf,
     },
     proto,
-    serde::{vec_from_proto, vec_to_proto, Proto},
+    serde::{check_payloads, vec_from_proto, vec_to_proto, Proto},
     Error, Result,
 };
 use merlin::Transcript;
@@ -26,12 +27,21 @@ pub struct Block {
     payload_order: Vec<Id>,
     fp: Fingerprint,
     sig: Signature,
+    difficulty: u32,
+    nonce: u64,
 }
 
 impl Transcribe for Block {
     fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
         b"block".append_to_transcript(t, label);
-        transcribe_unsigned_block(t, self.acks.iter(), self.payloads(), &self.fp);
+        transcribe_unsigned_block(
+            t,
+            self.acks.iter(),
+            self.payloads(),
+            &self.fp,
+            self.difficulty,
+            self.nonce,
+        );
         self.sig.to_bytes().append_to_transcript(t, b"signature");
     }
 }
@@ -42,6 +52,8 @@ impl Block {
         payloads: Vec<Payload>,
         fp: Fingerprint,
         sig: Signature,
+        difficulty: u32,
+        nonce: u64,
     ) -> Block {
         let payload_order = payloads.iter().map(|p| p.id()).collect();
         Block {
@@ -50,6 +62,8 @@ impl Block {
             fp,
             payload_order,
             payloads: payloads.into_iter().map(|p| (p.id(), p)).collect(),
+            difficulty,
+            nonce,
         }
     }
 
@@ -63,15 +77,59 @@ impl Block {
         self.fp
     }
 
+    /// Gets the number of leading zero bits this block's ID is required to
+    /// have
+    pub fn difficulty(&self) -> u32 {
+        self.difficulty
+    }
+
+    /// Checks whether this block's ID has the leading zero bits required by
+    /// its difficulty
+    pub fn meets_difficulty(&self) -> bool {
+        leading_zero_bits(&self.id()) >= self.difficulty
+    }
+
     /// Checks whether this block's signature is valid
     pub fn is_valid(&self, pk: &HashMap<Fingerprint, PublicKey>) -> Tribool {
         pk.get(&self.fp).map_or(Tribool::Indeterminate, |pk| {
-            let mut t = Transcript::new(BLOCK_SIGNATURE_DOMAIN);
-            transcribe_unsigned_block(&mut t, self.acks.iter(), self.payloads(), &self.fp);
+            let mut t = self.signature_transcript();
             pk.verify(&mut t, &self.sig).is_ok().into()
         })
     }
 
+    fn signature_transcript(&self) -> Transcript {
+        let mut t = Transcript::new(BLOCK_SIGNATURE_DOMAIN);
+        transcribe_unsigned_block(
+            &mut t,
+            self.acks.iter(),
+            self.payloads(),
+            &self.fp,
+            self.difficulty,
+            self.nonce,
+        );
+        t
+    }
+
+    /// Verifies this block's signature against the keys of the parties in a
+    /// VTMF instance
+    ///
+    /// Fails with [`Error::UnknownSigner`] if the signer is not one of the
+    /// parties, with [`Error::BadSignature`] if the signature does not
+    /// match, or with [`Error::InsufficientWork`] if the block's ID does not
+    /// meet its difficulty.
+    pub fn verify(&self, vtmf: &Vtmf) -> Result<()> {
+        let pki: HashMap<_, _> = vtmf
+            .public_keys()
+            .map(|pk| (vtmf.fingerprint_of(&pk), pk))
+            .collect();
+        match self.is_valid(&pki) {
+            Tribool::True if !self.meets_difficulty() => Err(Error::InsufficientWork),
+            Tribool::True => Ok(()),
+            Tribool::False => Err(Error::BadSignature),
+            Tribool::Indeterminate => Err(Error::UnknownSigner),
+        }
+    }
+
     /// Gets this block's parent IDs
     pub fn parent_ids(&self) -> &[Id] {
         &self.acks
@@ -115,6 +173,9 @@ impl<'a> ExactSizeIterator for PayloadIter<'a> {
 pub struct BlockBuilder {
     acks: Vec<Id>,
     payloads: Vec<Payload>,
+    merged: Vec<Vec<Payload>>,
+    difficulty: u32,
+    hash: HashAlgorithm,
 }
 
 impl BlockBuilder {
@@ -130,24 +191,91 @@ impl BlockBuilder {
     }
 
     /// Adds a payload to the block
+    ///
+    /// Adding a [`Payload::SelectHash`] also makes the block's signer
+    /// fingerprint use the selected hash function.
     pub fn add_payload(&mut self, payload: Payload) -> &mut BlockBuilder {
+        if let Payload::SelectHash(algorithm) = payload {
+            self.hash = algorithm;
+        }
         self.payloads.push(payload);
         self
     }
 
+    /// Sets the hash function for the block's signer fingerprint
+    ///
+    /// This must be the hash function chosen for the game the block goes
+    /// into; see [`Chain::hash_algorithm`](crate::chain::Chain::hash_algorithm).
+    pub fn with_hash_algorithm(&mut self, algorithm: HashAlgorithm) -> &mut BlockBuilder {
+        self.hash = algorithm;
+        self
+    }
+
+    /// Requires the block's ID to have a number of leading zero bits
+    ///
+    /// Building the block then searches for a nonce that meets this
+    /// difficulty, which takes about `2^bits` signatures. A difficulty of
+    /// zero, the default, requires no search.
+    pub fn with_difficulty(&mut self, bits: u32) -> &mut BlockBuilder {
+        self.difficulty = bits;
+        self
+    }
+
+    /// Merges the acknowledgements and payloads staged in another builder
+    /// into this one
+    ///
+    /// The payloads staged in each builder are kept together and in order, so
+    /// payloads that depend on each other still apply. In the resulting
+    /// block, these groups are ordered by the hash of their payload ids, so
+    /// the block does not depend on the order in which builders were
+    /// combined.
+    pub fn merge(&mut self, other: BlockBuilder) -> &mut BlockBuilder {
+        self.acks.extend(other.acks);
+        self.acks.sort();
+        self.acks.dedup();
+        self.merged.push(other.payloads);
+        self.merged.extend(other.merged);
+        self.difficulty = self.difficulty.max(other.difficulty);
+        if self.hash == HashAlgorithm::default() {
+            self.hash = other.hash;
+        }
+        self
+    }
+
+    fn into_payloads(self) -> Vec<Payload> {
+        let mut chunks = self.merged;
+        chunks.push(self.payloads);
+        chunks.retain(|c| !c.is_empty());
+        chunks.sort_by_cached_key(|c| {
+            let ids: Vec<_> = c.iter().map(Payload::id).collect();
+            Id::of(&ids, MESSAGE_ID_DOMAIN)
+        });
+        chunks.into_iter().flatten().collect()
+    }
+
     /// Builds the block, consuming the builder
     pub fn build(self, sk: &PrivateKey) -> Block {
-        let fp = sk.fingerprint();
+        let acks = self.acks.clone();
+        let difficulty = self.difficulty;
+        let fp = sk.fingerprint_with(self.hash);
+        let payloads = self.into_payloads();
         let mut t = Transcript::new(BLOCK_SIGNATURE_DOMAIN);
-        transcribe_unsigned_block(&mut t, self.acks.iter(), self.payloads.iter(), &fp);
+        transcribe_unsigned_block(&mut t, acks.iter(), payloads.iter(), &fp, difficulty, 0);
         let sig = sk.sign(&mut t);
-        Block {
-            acks: self.acks,
-            payload_order: self.payloads.iter().map(Payload::id).collect(),
-            payloads: self.payloads.into_iter().map(|p| (p.id(), p)).collect(),
+        let mut block = Block {
+            acks,
+            payload_order: payloads.iter().map(Payload::id).collect(),
+            payloads: payloads.into_iter().map(|p| (p.id(), p)).collect(),
             fp,
             sig,
+            difficulty,
+            nonce: 0,
+        };
+        while !block.meets_difficulty() {
+            block.nonce += 1;
+            block.sig = sk.sign(&mut block.signature_transcript());
         }
+        block
     }
 }
 
@@ -156,6 +284,8 @@ fn transcribe_unsigned_block<'a, T: TranscriptAppend, AckIt, PayloadIt>(
     acks: AckIt,
     payloads: PayloadIt,
     fp: &Fingerprint,
+    difficulty: u32,
+    nonce: u64,
 ) where
     AckIt: Iterator<Item = &'a Id> + 'a,
     PayloadIt: Iterator<Item = &'a Payload> + 'a,
@@ -165,6 +295,21 @@ fn transcribe_unsigned_block<'a, T: TranscriptAppend, AckIt, PayloadIt>(
         .collect::<Vec<_>>()
         .append_to_transcript(t, b"payloads");
     fp.append_to_transcript(t, b"signer");
+    if difficulty > 0 {
+        (difficulty as usize).append_to_transcript(t, b"difficulty");
+        nonce.append_to_transcript(t, b"nonce");
+    }
+}
+
+fn leading_zero_bits(id: &Id) -> u32 {
+    let mut bits = 0;
+    for &b in id.iter() {
+        bits += b.leading_zeros();
+        if b != 0 {
+            break;
+        }
+    }
+    bits
 }
 
 const BLOCK_SIGNATURE_DOMAIN: &'static [u8] = b"pbmx-block-sig";
@@ -174,6 +319,8 @@ struct BlockRaw {
     payloads: Vec<Payload>,
     fp: Fingerprint,
     sig: Signature,
+    difficulty: u32,
+    nonce: u64,
 }
 
 impl BlockRaw {
@@ -187,11 +334,20 @@ impl BlockRaw {
                 .collect(),
             fp: b.fp,
             sig: b.sig,
+            difficulty: b.difficulty,
+            nonce: b.nonce,
         }
     }
 
     fn into(self) -> Block {
-        Block::new_unchecked(self.acks, self.payloads, self.fp, self.sig)
+        Block::new_unchecked(
+            self.acks,
+            self.payloads,
+            self.fp,
+            self.sig,
+            self.difficulty,
+            self.nonce,
+        )
     }
 }
 
@@ -218,10 +374,13 @@ impl Proto for BlockRaw {
             payloads: vec_to_proto(&self.payloads)?,
             fingerprint: self.fp.to_vec(),
             signature: self.sig.to_bytes().to_vec(),
+            difficulty: self.difficulty,
+            nonce: self.nonce,
         })
     }
 
     fn from_proto(m: &proto::Block) -> Result<Self> {
+        check_payloads(m.payloads.len())?;
         Ok(Self {
             acks: m
                 .acks
@@ -231,6 +390,8 @@ impl Proto for BlockRaw {
             payloads: vec_from_proto(&m.payloads)?,
             fp: Fingerprint::try_from(&m.fingerprint)?,
             sig: Signature::from_bytes(&m.signature).map_err(|_| Error::Decoding)?,
+            difficulty: m.difficulty,
+            nonce: m.nonce,
         })
     }
 }
@@ -262,8 +423,14 @@ mod tests {
     use super::{Block, BlockBuilder};
     use crate::{
         chain::payload::Payload,
-        crypto::keys::PrivateKey,
-        serde::{FromBase64, ToBase64},
+        crypto::{
+            keys::PrivateKey,
+            map,
+            vtmf::{Mask, Stack, Vtmf},
+        },
+        proto,
+        serde::{FromBase64, Limits, Message, Proto, ToBase64},
+        Error,
     };
     use rand::thread_rng;
     use std::collections::HashMap;
@@ -280,6 +447,49 @@ mod tests {
         assert!(block.is_valid(&ring).is_true());
     }
 
+    #[test]
+    fn blocks_verify_against_vtmf_keys() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let sk2 = PrivateKey::random(&mut rng);
+        let mut vtmf = Vtmf::new(sk0.clone());
+        vtmf.add_key(sk1.public_key());
+
+        let block = BlockBuilder::new().build(&sk1);
+        assert_eq!(block.verify(&vtmf), Ok(()));
+
+        let block = BlockBuilder::new().build(&sk2);
+        assert_eq!(block.verify(&vtmf), Err(Error::UnknownSigner));
+    }
+
+    #[test]
+    fn blocks_below_their_difficulty_are_rejected() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let vtmf = Vtmf::new(sk.clone());
+
+        let mut builder = BlockBuilder::new();
+        builder.with_difficulty(8);
+        builder.add_payload(Payload::Text("spam".into()));
+        let block = builder.build(&sk);
+        assert_eq!(block.id()[0], 0);
+        assert_eq!(block.verify(&vtmf), Ok(()));
+
+        let mut m = block.to_proto().unwrap();
+        m.difficulty = 16;
+        let mut block = Block::from_proto(&m).unwrap();
+        loop {
+            block.nonce += 1;
+            block.sig = sk.sign(&mut block.signature_transcript());
+            if block.id()[1] != 0 {
+                break;
+            }
+        }
+        assert!(!block.meets_difficulty());
+        assert_eq!(block.verify(&vtmf), Err(Error::InsufficientWork));
+    }
+
     #[test]
     fn block_payload_order_is_preserved() {
         let mut rng = thread_rng();
@@ -303,6 +513,47 @@ mod tests {
         assert_eq!(payloads, expected);
     }
 
+    #[test]
+    fn merged_builders_produce_the_same_block() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let pk = sk.public_key();
+        let ring: HashMap<_, _> = vec![pk].into_iter().map(|k| (k.fingerprint(), k)).collect();
+        let partial = |bytes: &[u8]| {
+            let mut builder = BlockBuilder::new();
+            for &b in bytes {
+                builder.add_payload(Payload::Bytes(vec![b]));
+            }
+            builder
+        };
+
+        let mut first = partial(&[0, 1]);
+        let mut inner = partial(&[4]);
+        inner.merge(partial(&[3, 2]));
+        first.merge(inner);
+        let mut second = partial(&[3, 2]);
+        second.merge(partial(&[4]));
+        second.merge(partial(&[0, 1]));
+        let first = first.build(&sk);
+        let second = second.build(&sk);
+
+        assert!(first.is_valid(&ring).is_true());
+        assert_eq!(first.payload_order, second.payload_order);
+
+        // each builder's payloads stay together and in order
+        let bytes: Vec<_> = first
+            .payloads()
+            .map(|p| match p {
+                Payload::Bytes(b) => b[0],
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(bytes.len(), 5);
+        for chunk in [&[0, 1][..], &[3, 2], &[4]].iter() {
+            assert!(bytes.windows(chunk.len()).any(|w| w == *chunk));
+        }
+    }
+
     #[test]
     fn block_roundtrips_via_base64() {
         let mut rng = thread_rng();
@@ -327,4 +578,75 @@ mod tests {
         assert_eq!(original.fp, recovered.fp);
         assert_eq!(original.sig, recovered.sig);
     }
+
+    #[test]
+    fn oversized_blocks_are_rejected_on_decode() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let limits = Limits::default();
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let mut builder = BlockBuilder::new();
+        builder.add_payload(Payload::OpenStack(stack));
+        let block = builder.build(&sk);
+        let msg = block.to_proto().unwrap();
+        assert!(Block::from_proto(&msg).is_ok());
+
+        let mut big_stack = msg.clone();
+        if let Some(proto::payload::PayloadKind::OpenStack(p)) =
+            big_stack.payloads[0].payload_kind.as_mut()
+        {
+            let stack = p.stack.as_mut().unwrap();
+            let mask = stack.masks[0].clone();
+            stack.masks = vec![mask; limits.max_stack_len + 1];
+        }
+        assert_eq!(Block::from_proto(&big_stack).err(), Some(Error::Decoding));
+
+        let mut many_payloads = msg;
+        let payload = many_payloads.payloads[0].clone();
+        many_payloads.payloads = vec![payload; limits.max_payloads + 1];
+        assert_eq!(
+            Block::from_proto(&many_payloads).err(),
+            Some(Error::Decoding)
+        );
+
+        let huge = vec![0u8; limits.max_bytes + 1];
+        assert_eq!(Block::decode(&huge).err(), Some(Error::Decoding));
+    }
+
+    #[test]
+    fn limits_only_apply_to_their_own_decode() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let mut builder = BlockBuilder::new();
+        builder.add_payload(Payload::OpenStack(stack.clone()));
+        builder.add_payload(Payload::OpenStack(stack));
+        let encoded = builder.build(&sk).encode().unwrap();
+
+        let short = Limits {
+            max_stack_len: 2,
+            ..Limits::default()
+        };
+        let few = Limits {
+            max_payloads: 1,
+            ..Limits::default()
+        };
+        let small = Limits {
+            max_bytes: encoded.len() - 1,
+            ..Limits::default()
+        };
+        for limits in [short, few, small].iter() {
+            assert_eq!(
+                Block::decode_with_limits(&encoded, limits).err(),
+                Some(Error::Decoding)
+            );
+            assert!(Block::decode(&encoded).is_ok());
+        }
+        let exact = Limits {
+            max_stack_len: 3,
+            max_payloads: 2,
+            max_bytes: encoded.len(),
+        };
+        assert!(Block::decode_with_limits(&encoded, &exact).is_ok());
+    }
 }
@@ -7,7 +7,49 @@ pub use self::payload::{Payload, PayloadVisitor};
 
 pub use crate::crypto::keys::Fingerprint as Id;
 
-use std::collections::HashMap;
+use crate::crypto::hash::HashAlgorithm;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+    vec,
+};
+use tribool::Tribool;
+
+/// A structural problem in a chain
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChainError {
+    /// A block acknowledges a parent that is not in the chain
+    MissingParent {
+        /// The acknowledging block
+        block: Id,
+        /// The missing parent
+        parent: Id,
+    },
+    /// A block is part of a cycle
+    Cycle(Id),
+    /// A block is signed by a key not published in the chain
+    UnknownSigner(Id),
+    /// A block's signature does not match its signer's key
+    BadSignature(Id),
+    /// A block's ID does not meet its difficulty
+    InsufficientWork(Id),
+}
+
+impl Display for ChainError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ChainError::MissingParent { block, parent } => {
+                write!(f, "block {} acknowledges missing block {}", block, parent)
+            }
+            ChainError::Cycle(id) => write!(f, "block {} is part of a cycle", id),
+            ChainError::UnknownSigner(id) => write!(f, "block {} has an unknown signer", id),
+            ChainError::BadSignature(id) => write!(f, "block {} has a bad signature", id),
+            ChainError::InsufficientWork(id) => {
+                write!(f, "block {} does not meet its difficulty", id)
+            }
+        }
+    }
+}
 
 /// A blockchain
 #[derive(Default, Debug)]
@@ -54,18 +96,42 @@ impl Chain {
         self.count() == 0
     }
 
+    /// Tests whether a block is in this chain
+    pub fn contains(&self, id: &Id) -> bool {
+        self.blocks.contains_key(id)
+    }
+
     /// Tests whether this chain is incomplete (i.e. there are unknown blocks
     /// acknowledged)
     pub fn is_incomplete(&self) -> bool {
         !self.links.keys().all(|id| self.blocks.contains_key(id))
     }
 
+    /// Gets the hash function chosen in the genesis block
+    ///
+    /// This is the default one if the genesis block does not choose any, or
+    /// if the chain is empty.
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.roots
+            .iter()
+            .flat_map(|id| self.blocks[id].payloads())
+            .find_map(|p| match p {
+                Payload::SelectHash(algorithm) => Some(*algorithm),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
     /// Starts building a new block that acknowledges all blocks in this chain
+    ///
+    /// The block's signer fingerprint uses the chain's
+    /// [hash function](Chain::hash_algorithm).
     pub fn build_block(&self) -> BlockBuilder {
         let mut builder = BlockBuilder::new();
         for &h in self.heads.iter() {
             builder.acknowledge(h);
         }
+        builder.with_hash_algorithm(self.hash_algorithm());
         builder
     }
 
@@ -87,26 +153,202 @@ impl Chain {
         self.blocks.insert(id, block);
     }
 
-    /// An iterator over the blocks in this chain
-    pub fn blocks(&self) -> impl ExactSizeIterator<Item = &Block> {
+    /// An iterator over the blocks in this chain, in topological order
+    pub fn blocks(&self) -> impl DoubleEndedIterator<Item = &Block> + ExactSizeIterator {
         Blocks::new(self)
     }
+
+    /// Gets the blocks missing from a peer whose chain has the given heads, in
+    /// topological order
+    ///
+    /// If any of the heads is unknown to this chain, or none are given, all
+    /// blocks are returned.
+    pub fn blocks_since(&self, known_heads: &[Id]) -> Vec<&Block> {
+        if known_heads.is_empty() || known_heads.iter().any(|id| !self.blocks.contains_key(id)) {
+            return self.blocks().collect();
+        }
+        let mut known = HashSet::new();
+        let mut pending = known_heads.to_vec();
+        while let Some(id) = pending.pop() {
+            if known.insert(id) {
+                pending.extend(self.blocks[&id].parent_ids().iter().copied());
+            }
+        }
+        self.blocks().filter(|b| !known.contains(&b.id())).collect()
+    }
+
+    /// Gets the blocks descending from a given block, in topological order
+    ///
+    /// The given block itself is not included. If it is unknown to this chain,
+    /// no blocks are returned.
+    pub fn descendants(&self, id: &Id) -> Vec<&Block> {
+        let mut found = HashSet::new();
+        let mut pending = vec![*id];
+        while let Some(id) = pending.pop() {
+            if let Some(children) = self.links.get(&id) {
+                for &child in children.iter() {
+                    if found.insert(child) {
+                        pending.push(child);
+                    }
+                }
+            }
+        }
+        self.blocks().filter(|b| found.contains(&b.id())).collect()
+    }
+
+    /// Checks that this chain is a well-formed DAG of properly signed blocks
+    ///
+    /// Every acknowledged parent must be in the chain, there must be no
+    /// cycles, and every block must be signed by a key published in the chain
+    /// and meet its difficulty. Proofs in payloads are not verified; this is a
+    /// cheap check to run before building a [`State`](crate::state::State).
+    /// Blocks are checked in ID order, and the first problem found is
+    /// reported.
+    pub fn validate_structure(&self) -> Result<(), ChainError> {
+        let mut ids: Vec<_> = self.blocks.keys().copied().collect();
+        ids.sort();
+
+        for id in ids.iter() {
+            for parent in self.blocks[id].parent_ids() {
+                if !self.blocks.contains_key(parent) {
+                    return Err(ChainError::MissingParent {
+                        block: *id,
+                        parent: *parent,
+                    });
+                }
+            }
+        }
+
+        let mut incoming: HashMap<_, _> = ids
+            .iter()
+            .map(|id| (*id, self.blocks[id].parent_ids().len()))
+            .collect();
+        let mut children: HashMap<Id, Vec<Id>> = HashMap::new();
+        for id in ids.iter() {
+            for parent in self.blocks[id].parent_ids() {
+                children.entry(*parent).or_default().push(*id);
+            }
+        }
+        let mut ready: Vec<_> = ids
+            .iter()
+            .filter(|id| incoming[*id] == 0)
+            .copied()
+            .collect();
+        while let Some(id) = ready.pop() {
+            incoming.remove(&id);
+            for child in children.get(&id).into_iter().flatten() {
+                let inc = incoming.get_mut(child).unwrap();
+                *inc -= 1;
+                if *inc == 0 {
+                    ready.push(*child);
+                }
+            }
+        }
+        if let Some(id) = ids.iter().find(|id| incoming.contains_key(*id)) {
+            return Err(ChainError::Cycle(*id));
+        }
+
+        let hash = self.hash_algorithm();
+        let keys: HashMap<_, _> = ids
+            .iter()
+            .flat_map(|id| self.blocks[id].payloads())
+            .filter_map(|p| match p {
+                Payload::PublishKey(_, pk) => Some((pk.fingerprint_with(hash), pk.clone())),
+                _ => None,
+            })
+            .collect();
+        for id in ids.iter() {
+            let block = &self.blocks[id];
+            match block.is_valid(&keys) {
+                Tribool::True if !block.meets_difficulty() => {
+                    return Err(ChainError::InsufficientWork(*id))
+                }
+                Tribool::True => {}
+                Tribool::False => return Err(ChainError::BadSignature(*id)),
+                Tribool::Indeterminate => return Err(ChainError::UnknownSigner(*id)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the height of a block, i.e. the length of the longest path from it
+    /// back to a root
+    ///
+    /// Parents missing from this chain are ignored. If the block itself is
+    /// unknown, `None` is returned.
+    pub fn height(&self, id: &Id) -> Option<usize> {
+        if !self.blocks.contains_key(id) {
+            return None;
+        }
+        let mut heights = HashMap::new();
+        let mut pending = vec![*id];
+        while let Some(&top) = pending.last() {
+            let parents = self.blocks[&top].parent_ids();
+            let missing: Vec<_> = parents
+                .iter()
+                .filter(|&p| self.blocks.contains_key(p) && !heights.contains_key(p))
+                .copied()
+                .collect();
+            if missing.is_empty() {
+                let h = parents
+                    .iter()
+                    .filter_map(|p| heights.get(p))
+                    .map(|h| h + 1)
+                    .max()
+                    .unwrap_or(0);
+                heights.insert(top, h);
+                pending.pop();
+            } else {
+                pending.extend(missing);
+            }
+        }
+        heights.get(id).copied()
+    }
+
+    /// Searches the text payloads in this chain for a case-insensitive
+    /// substring, in topological order
+    pub fn search_text<'a>(&'a self, query: &str) -> impl Iterator<Item = (Id, &'a str)> + 'a {
+        let query = query.to_lowercase();
+        self.blocks().flat_map(move |b| {
+            let id = b.id();
+            let query = query.clone();
+            b.payloads().filter_map(move |p| match p {
+                Payload::Text(text) if text.to_lowercase().contains(&query) => {
+                    Some((id, text.as_str()))
+                }
+                _ => None,
+            })
+        })
+    }
 }
 
 struct Blocks<'a> {
-    roots: Vec<Id>,
     chain: &'a Chain,
-    incoming: HashMap<Id, usize>,
-    current: Option<Id>,
+    order: vec::IntoIter<Id>,
 }
 
 impl<'a> Blocks<'a> {
     fn new(chain: &Chain) -> Blocks {
+        let blocks = &chain.blocks;
+        let mut order = Vec::with_capacity(blocks.len());
+        let mut roots = chain.roots.clone();
+        let mut incoming = HashMap::new();
+        while let Some(n) = roots.pop() {
+            order.push(n);
+            if let Some(links) = chain.links.get(&n) {
+                for &m in links.iter() {
+                    let entry = incoming.entry(m);
+                    let inc = entry.or_insert_with(|| blocks.get(&m).unwrap().parent_ids().len());
+                    *inc -= 1;
+                    if *inc == 0 {
+                        roots.push(m);
+                    }
+                }
+            }
+        }
         Blocks {
-            roots: chain.roots.clone(),
-            chain: &chain,
-            incoming: HashMap::new(),
-            current: None,
+            chain,
+            order: order.into_iter(),
         }
     }
 }
@@ -115,38 +357,24 @@ impl<'a> Iterator for Blocks<'a> {
     type Item = &'a Block;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let blocks = &self.chain.blocks;
-        loop {
-            match self.current.take() {
-                None => {
-                    let n = self.roots.pop()?;
-                    self.current = Some(n);
-                    return blocks.get(&n);
-                }
-                Some(n) => {
-                    if let Some(links) = self.chain.links.get(&n) {
-                        for &m in links.iter() {
-                            let entry = self.incoming.entry(m);
-                            let inc =
-                                entry.or_insert_with(|| blocks.get(&m).unwrap().parent_ids().len());
-                            *inc -= 1;
-                            if *inc == 0 {
-                                self.roots.push(m);
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        let id = self.order.next()?;
+        self.chain.blocks.get(&id)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.order.size_hint()
     }
 }
 
-impl<'a> ExactSizeIterator for Blocks<'a> {
-    fn len(&self) -> usize {
-        self.chain.blocks.len()
+impl<'a> DoubleEndedIterator for Blocks<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let id = self.order.next_back()?;
+        self.chain.blocks.get(&id)
     }
 }
 
+impl<'a> ExactSizeIterator for Blocks<'a> {}
+
 /// A visitor for chains
 pub trait ChainVisitor: BlockVisitor {
     /// Visits a chain
@@ -159,7 +387,7 @@ pub trait ChainVisitor: BlockVisitor {
 
 #[cfg(test)]
 mod test {
-    use super::Chain;
+    use super::{Block, BlockBuilder, Chain, ChainError};
     use crate::{chain::payload::Payload, crypto::keys::PrivateKey};
     use rand::thread_rng;
 
@@ -191,6 +419,165 @@ mod test {
         chain.add_block(b2.clone());
 
         let blocks: Vec<_> = chain.blocks().map(|b| b.id()).collect();
-        assert_eq!(blocks, vec![gid, b1.id(), b0.id(), b2.id()])
+        assert_eq!(blocks, vec![gid, b1.id(), b0.id(), b2.id()]);
+        assert_eq!(chain.blocks().len(), 4);
+
+        let mut rev: Vec<_> = chain.blocks().rev().map(|b| b.id()).collect();
+        rev.reverse();
+        assert_eq!(rev, blocks);
+    }
+
+    #[test]
+    fn chain_blocks_since_works() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut chain = Chain::new();
+        let g = chain.build_block().build(&sk);
+        chain.add_block(g.clone());
+        let mut b0 = chain.build_block();
+        b0.add_payload(Payload::Bytes(vec![0]));
+        let b0 = b0.build(&sk);
+        let mut b1 = chain.build_block();
+        b1.add_payload(Payload::Bytes(vec![1]));
+        let b1 = b1.build(&sk);
+        chain.add_block(b0.clone());
+        chain.add_block(b1.clone());
+        let b2 = chain.build_block().build(&sk);
+        chain.add_block(b2.clone());
+
+        let ids = |v: Vec<&Block>| v.iter().map(|b| b.id()).collect::<Vec<_>>();
+        assert_eq!(ids(chain.blocks_since(&[b2.id()])), vec![]);
+        assert_eq!(ids(chain.blocks_since(&[b0.id()])), vec![b1.id(), b2.id()]);
+        assert_eq!(ids(chain.blocks_since(&[b0.id(), b1.id()])), vec![b2.id()]);
+        let all: Vec<_> = chain.blocks().map(|b| b.id()).collect();
+        assert_eq!(ids(chain.blocks_since(&[])), all);
+        let unknown = PrivateKey::random(&mut rng).fingerprint();
+        assert_eq!(ids(chain.blocks_since(&[b0.id(), unknown])), all);
+    }
+
+    #[test]
+    fn chain_structure_validation_works() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut chain = Chain::new();
+        let mut g = chain.build_block();
+        g.add_payload(Payload::PublishKey("foo".into(), sk.public_key()));
+        let g = g.build(&sk);
+        chain.add_block(g.clone());
+        let b0 = chain.build_block().build(&sk);
+        chain.add_block(b0.clone());
+        assert_eq!(chain.validate_structure(), Ok(()));
+
+        let stranger = PrivateKey::random(&mut rng);
+        let b1 = chain.build_block().build(&stranger);
+        chain.add_block(b1.clone());
+        assert_eq!(
+            chain.validate_structure(),
+            Err(ChainError::UnknownSigner(b1.id()))
+        );
+        chain.blocks.remove(&b1.id());
+
+        let unknown = PrivateKey::random(&mut rng).fingerprint();
+        let mut b2 = BlockBuilder::new();
+        b2.acknowledge(unknown);
+        let b2 = b2.build(&sk);
+        chain.add_block(b2.clone());
+        assert_eq!(
+            chain.validate_structure(),
+            Err(ChainError::MissingParent {
+                block: b2.id(),
+                parent: unknown
+            })
+        );
+        chain.blocks.remove(&b2.id());
+
+        let mut looped = BlockBuilder::new();
+        looped.acknowledge(b0.id());
+        chain.blocks.insert(g.id(), looped.build(&sk));
+        let mut cycle = [g.id(), b0.id()];
+        cycle.sort();
+        assert_eq!(chain.validate_structure(), Err(ChainError::Cycle(cycle[0])));
+    }
+
+    #[test]
+    fn chain_heights_work() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut chain = Chain::new();
+        let g = chain.build_block().build(&sk);
+        chain.add_block(g.clone());
+        let mut b0 = chain.build_block();
+        b0.add_payload(Payload::Bytes(vec![0]));
+        let b0 = b0.build(&sk);
+        chain.add_block(b0.clone());
+        let mut b1 = chain.build_block();
+        b1.add_payload(Payload::Bytes(vec![1]));
+        let b1 = b1.build(&sk);
+        let mut b2 = BlockBuilder::new();
+        b2.acknowledge(g.id());
+        let b2 = b2.build(&sk);
+        chain.add_block(b1.clone());
+        chain.add_block(b2.clone());
+        let b3 = chain.build_block().build(&sk);
+        chain.add_block(b3.clone());
+
+        assert_eq!(chain.height(&g.id()), Some(0));
+        assert_eq!(chain.height(&b0.id()), Some(1));
+        assert_eq!(chain.height(&b1.id()), Some(2));
+        assert_eq!(chain.height(&b2.id()), Some(1));
+        assert_eq!(chain.height(&b3.id()), Some(3));
+        let unknown = PrivateKey::random(&mut rng).fingerprint();
+        assert_eq!(chain.height(&unknown), None);
+    }
+
+    #[test]
+    fn chain_descendants_works() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut chain = Chain::new();
+        let g = chain.build_block().build(&sk);
+        chain.add_block(g.clone());
+        let mut b0 = chain.build_block();
+        b0.add_payload(Payload::Bytes(vec![0]));
+        let b0 = b0.build(&sk);
+        let mut b1 = chain.build_block();
+        b1.add_payload(Payload::Bytes(vec![1]));
+        let b1 = b1.build(&sk);
+        chain.add_block(b0.clone());
+        chain.add_block(b1.clone());
+        let b2 = chain.build_block().build(&sk);
+        chain.add_block(b2.clone());
+
+        let ids = |v: Vec<&Block>| v.iter().map(|b| b.id()).collect::<Vec<_>>();
+        assert_eq!(ids(chain.descendants(&b2.id())), vec![]);
+        assert_eq!(ids(chain.descendants(&b0.id())), vec![b2.id()]);
+        let all: Vec<_> = chain.blocks().skip(1).map(|b| b.id()).collect();
+        assert_eq!(ids(chain.descendants(&g.id())), all);
+        let unknown = PrivateKey::random(&mut rng).fingerprint();
+        assert_eq!(ids(chain.descendants(&unknown)), vec![]);
+    }
+
+    #[test]
+    fn chain_text_search_works() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut chain = Chain::new();
+        let mut b0 = chain.build_block();
+        b0.add_payload(Payload::Text("Hello there".into()));
+        b0.add_payload(Payload::Bytes(b"hello".to_vec()));
+        let b0 = b0.build(&sk);
+        chain.add_block(b0.clone());
+        let mut b1 = chain.build_block();
+        b1.add_payload(Payload::Text("goodbye".into()));
+        b1.add_payload(Payload::Text("oh, HELLO again".into()));
+        let b1 = b1.build(&sk);
+        chain.add_block(b1.clone());
+
+        let found: Vec<_> = chain.search_text("hello").collect();
+        assert_eq!(found, vec![
+            (b0.id(), "Hello there"),
+            (b1.id(), "oh, HELLO again")
+        ]);
+        assert_eq!(chain.search_text("nothing").count(), 0);
     }
 }
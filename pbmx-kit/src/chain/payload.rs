@@ -3,17 +3,20 @@
 use crate::{
     chain::{block::Block, Id},
     crypto::{
-        hash::{Transcribe, TranscriptAppend},
-        keys::PublicKey,
+        hash::{HashAlgorithm, Transcribe, TranscriptAppend, MESSAGE_ID_DOMAIN},
+        keys::{Fingerprint, PublicKey},
         vtmf::{
-            EntanglementProof, Mask, MaskProof, SecretShare, SecretShareProof, ShiftProof,
-            ShuffleProof, Stack,
+            DistinctProof, EntanglementProof, Mask, MaskProof, PairedProof, PairedStack,
+            PrivateMaskProof, SecretShare, SecretShareProof, ShiftProof, ShuffleProof, Stack,
         },
     },
     proto,
-    serde::{vec_from_proto, vec_to_proto, Proto},
+    serde::{
+        check_stack_len, scalars_from_proto, scalars_to_proto, vec_from_proto, vec_to_proto, Proto,
+    },
     Error, Result,
 };
+use curve25519_dalek::scalar::Scalar;
 use std::{
     convert::TryFrom,
     fmt::{self, Display, Formatter},
@@ -27,6 +30,8 @@ pub enum Payload {
     PublishKey(String, PublicKey),
     /// An open stack payload
     OpenStack(Stack),
+    /// A built-in deck template payload
+    DeckTemplate(String),
     /// A stack mask payload
     MaskStack(Id, Stack, Vec<MaskProof>),
     /// A stack shuffle payload
@@ -35,12 +40,16 @@ pub enum Payload {
     ShiftStack(Id, Stack, ShiftProof),
     /// A stack name payload
     NameStack(Id, String),
+    /// A stack tag payload
+    TagStack(Id, Vec<String>),
     /// A substack payload
     TakeStack(Id, Vec<usize>, Id),
     /// A stack pile payload
     PileStacks(Vec<Id>, Id),
     /// A secret share payload
     PublishShares(Id, Vec<SecretShare>, Vec<SecretShareProof>),
+    /// A partial secret share payload
+    PublishSharesAt(Id, Vec<usize>, Vec<SecretShare>, Vec<SecretShareProof>),
     /// An rng specification payload
     RandomSpec(String, String),
     /// An rng entropy payload
@@ -49,6 +58,48 @@ pub enum Payload {
     RandomReveal(String, SecretShare, SecretShareProof),
     /// An entanglement proof payload
     ProveEntanglement(Vec<Id>, Vec<Id>, EntanglementProof),
+    /// A burned substack payload
+    BurnStack(Id, Vec<usize>, Id),
+    /// A ballot payload
+    CastBallot(String, Mask, PrivateMaskProof),
+    /// A tally reveal payload
+    RevealTally(String, SecretShare, SecretShareProof),
+    /// A private peek payload
+    Peek(Id, Id),
+    /// A cut commitment payload
+    CommitShift(Id, Id),
+    /// A cut reveal payload
+    RevealShift(Id, usize, Id, Vec<Scalar>),
+    /// A greater-than proof payload
+    ProveGreater(Mask, Mask, usize, PrivateMaskProof),
+    /// A field disclosure proof payload
+    ProveField(Mask, u64, u64, u64, PrivateMaskProof),
+    /// A state checkpoint payload
+    Checkpoint(Id),
+    /// A face-up deal payload
+    DealFaceUp(Id, Vec<usize>, Id),
+    /// An external beacon entropy payload
+    BeaconEntropy(String, u64, Vec<u8>, Vec<u8>),
+    /// A distinct tokens proof blinding payload
+    ProveDistinct(Id, Stack, DistinctProof),
+    /// A random insertion payload
+    InsertStack(Id, Id, String, Id),
+    /// A hash function choice payload
+    SelectHash(HashAlgorithm),
+    /// An equal multiset proof payload
+    ProveEqualMultiset(Id, Id, ShuffleProof),
+    /// A player rename payload
+    RenamePlayer(Fingerprint, String),
+    /// A multi-round reveal share commitment payload
+    CommitShares(Id, u64, u64, Id),
+    /// A multi-round reveal share opening payload
+    OpenShares(Id, u64, Id),
+    /// A distinct tokens proof secret share payload
+    RevealDistinct(Id, Vec<SecretShare>, Vec<SecretShareProof>),
+    /// A stack shuffle payload that keeps track of a reference stack
+    ReshuffleFrom(Id, Id, Stack, ShuffleProof),
+    /// A peek commitment opening payload
+    OpenPeek(Id, Id),
     /// Raw text payload
     Text(String),
     /// Raw byte payload
@@ -58,34 +109,117 @@ pub enum Payload {
 impl Payload {
     /// Gets the id of this payload
     pub fn id(&self) -> Id {
-        Id::of(self, b"pbmx-payload-id")
+        Id::of(self, MESSAGE_ID_DOMAIN)
     }
 
     /// Gets a short string description of this payload
     pub fn display_short<'a>(&'a self) -> impl Display + 'a {
-        DisplayShort(self)
+        self.display_short_with(HashAlgorithm::default())
+    }
+
+    /// Gets a short string description of this payload, showing key
+    /// fingerprints with the given hash function
+    pub fn display_short_with<'a>(&'a self, algorithm: HashAlgorithm) -> impl Display + 'a {
+        DisplayShort(self, algorithm)
+    }
+
+    /// Gets the id of the stack this payload creates, if any
+    pub fn result_stack(&self) -> Option<Id> {
+        use Payload::*;
+        match self {
+            OpenStack(s) => Some(s.id()),
+            MaskStack(_, s, _) | ShuffleStack(_, s, _) | ShiftStack(_, s, _) => Some(s.id()),
+            ReshuffleFrom(_, _, s, _) => Some(s.id()),
+            TakeStack(_, _, id) | BurnStack(_, _, id) | DealFaceUp(_, _, id) => Some(*id),
+            PileStacks(_, id) | InsertStack(_, _, _, id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Builds the payloads that publish a paired shuffle
+    pub fn paired_shuffle(
+        m: &PairedStack,
+        c: &PairedStack,
+        proof: PairedProof<ShuffleProof>,
+    ) -> Vec<Payload> {
+        vec![
+            Payload::ShuffleStack(m.first().id(), c.first().clone(), proof.first),
+            Payload::ShuffleStack(m.second().id(), c.second().clone(), proof.second),
+            Payload::ProveEntanglement(m.ids(), c.ids(), proof.entanglement),
+        ]
+    }
+
+    /// Builds the payloads that publish a paired shift
+    pub fn paired_shift(
+        m: &PairedStack,
+        c: &PairedStack,
+        proof: PairedProof<ShiftProof>,
+    ) -> Vec<Payload> {
+        vec![
+            Payload::ShiftStack(m.first().id(), c.first().clone(), proof.first),
+            Payload::ShiftStack(m.second().id(), c.second().clone(), proof.second),
+            Payload::ProveEntanglement(m.ids(), c.ids(), proof.entanglement),
+        ]
     }
 }
 
-struct DisplayShort<'a>(&'a Payload);
+struct DisplayShort<'a>(&'a Payload, HashAlgorithm);
 
 impl<'a> Display for DisplayShort<'a> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         use Payload::*;
         match self.0 {
-            PublishKey(name, pk) => write!(f, "publish key {} {:16}", name, pk.fingerprint()),
+            PublishKey(name, pk) => {
+                write!(f, "publish key {} {:16}", name, pk.fingerprint_with(self.1))
+            }
             OpenStack(stk) => write!(f, "open stack {:16}", stk.id()),
             NameStack(id, name) => write!(f, "name {:16} {}", id, name),
+            TagStack(id, tags) => write!(f, "tag {:16} {}", id, tags.join(" ")),
+            DeckTemplate(name) => write!(f, "deck {}", name),
             MaskStack(id, stk, _) => write!(f, "mask {1:16} \u{21AC} {0:16}", id, stk.id()),
             ShuffleStack(id, stk, _) => write!(f, "shuffle {1:16} \u{224B} {0:16}", id, stk.id()),
             ShiftStack(id, stk, _) => write!(f, "cut {1:16} \u{21CB} {0:16}", id, stk.id()),
             TakeStack(id1, idxs, id2) => write!(f, "take {:16}{:?} {:16}", id1, idxs, id2),
             PileStacks(ids, id2) => write!(f, "pile {:16?} {:16}", ids, id2),
             PublishShares(id, ..) => write!(f, "reveal {:16}", id),
+            PublishSharesAt(id, idxs, ..) => write!(f, "reveal {:16}{:?}", id, idxs),
             RandomSpec(id, ..) => write!(f, "new rng {}", id),
             RandomEntropy(id, ..) => write!(f, "add entropy {}", id),
             RandomReveal(id, ..) => write!(f, "open rng {}", id),
             ProveEntanglement(ids1, ids2, ..) => write!(f, "entangled {:?} {:?}", ids1, ids2),
+            BurnStack(id1, idxs, id2) => write!(f, "burn {:16}{:?} {:16}", id1, idxs, id2),
+            CastBallot(topic, ..) => write!(f, "vote {}", topic),
+            RevealTally(topic, ..) => write!(f, "open tally {}", topic),
+            Peek(id, commit) => write!(f, "peek {:16} {:16}", id, commit),
+            CommitShift(id, commit) => write!(f, "commit cut {:16} {:16}", id, commit),
+            RevealShift(id, k, ..) => write!(f, "open cut {:16} {}", id, k),
+            ProveGreater(_, _, max, _) => write!(f, "greater below {}", max),
+            ProveField(_, field_mask, field_value, ..) => {
+                write!(f, "field {:#x}={:#x}", field_mask, field_value)
+            }
+            Checkpoint(state) => write!(f, "checkpoint {:16}", state),
+            DealFaceUp(id1, idxs, id2) => write!(f, "deal up {:16}{:?} {:16}", id1, idxs, id2),
+            BeaconEntropy(id, round, ..) => write!(f, "add beacon {} #{}", id, round),
+            ProveDistinct(id, ..) => write!(f, "distinct {:16}", id),
+            InsertStack(id1, id2, rng, id3) => {
+                write!(f, "insert {:16} {:16} @{} {:16}", id1, id2, rng, id3)
+            }
+            SelectHash(algorithm) => write!(f, "hash {}", algorithm),
+            ProveEqualMultiset(id1, id2, _) => write!(f, "equal {:16} {:16}", id1, id2),
+            RenamePlayer(fp, name) => write!(f, "rename {:16} {}", fp, name),
+            CommitShares(id, rounds, round, _) => {
+                write!(f, "commit shares {:16} {}/{}", id, round + 1, rounds)
+            }
+            OpenShares(id, round, _) => write!(f, "open shares {:16} {}", id, round + 1),
+            RevealDistinct(id, ..) => write!(f, "reveal distinct {:16}", id),
+            ReshuffleFrom(id1, id2, stk, _) => write!(
+                f,
+                "reshuffle {:16} \u{224B} {:16} from {:16}",
+                id2,
+                stk.id(),
+                id1
+            ),
+            OpenPeek(id, _) => write!(f, "open peek {:16}", id),
             Text(text) => write!(f, "text {}", text),
             Bytes(bytes) => write!(
                 f,
@@ -108,6 +242,9 @@ pub trait PayloadVisitor {
             OpenStack(stk) => {
                 self.visit_open_stack(block, stk);
             }
+            DeckTemplate(name) => {
+                self.visit_deck_template(block, name);
+            }
             MaskStack(id, stk, proof) => {
                 self.visit_mask_stack(block, *id, stk, proof);
             }
@@ -120,6 +257,9 @@ pub trait PayloadVisitor {
             NameStack(id, name) => {
                 self.visit_name_stack(block, *id, name);
             }
+            TagStack(id, tags) => {
+                self.visit_tag_stack(block, *id, tags);
+            }
             TakeStack(id1, idxs, id2) => {
                 self.visit_take_stack(block, *id1, idxs, *id2);
             }
@@ -129,6 +269,9 @@ pub trait PayloadVisitor {
             PublishShares(id, shares, proof) => {
                 self.visit_publish_shares(block, *id, shares, proof);
             }
+            PublishSharesAt(id, idxs, shares, proof) => {
+                self.visit_publish_shares_at(block, *id, idxs, shares, proof);
+            }
             RandomSpec(id, spec) => {
                 self.visit_random_spec(block, id, spec);
             }
@@ -141,6 +284,69 @@ pub trait PayloadVisitor {
             ProveEntanglement(ids1, ids2, proof) => {
                 self.visit_prove_entanglement(block, ids1, ids2, proof);
             }
+            BurnStack(id1, idxs, id2) => {
+                self.visit_burn_stack(block, *id1, idxs, *id2);
+            }
+            CastBallot(topic, ballot, proof) => {
+                self.visit_cast_ballot(block, topic, ballot, proof);
+            }
+            RevealTally(topic, share, proof) => {
+                self.visit_reveal_tally(block, topic, share, proof);
+            }
+            Peek(id, commit) => {
+                self.visit_peek(block, *id, *commit);
+            }
+            CommitShift(id, commit) => {
+                self.visit_commit_shift(block, *id, *commit);
+            }
+            RevealShift(id, k, nonce, secrets) => {
+                self.visit_reveal_shift(block, *id, *k, *nonce, secrets);
+            }
+            ProveGreater(a, b, max, proof) => {
+                self.visit_prove_greater(block, a, b, *max, proof);
+            }
+            ProveField(mask, field_mask, field_value, max, proof) => {
+                self.visit_prove_field(block, mask, *field_mask, *field_value, *max, proof);
+            }
+            Checkpoint(state) => {
+                self.visit_checkpoint(block, *state);
+            }
+            DealFaceUp(id1, idxs, id2) => {
+                self.visit_deal_face_up(block, *id1, idxs, *id2);
+            }
+            BeaconEntropy(id, round, value, proof) => {
+                self.visit_beacon_entropy(block, id, *round, value, proof);
+            }
+            ProveDistinct(id, stk, proof) => {
+                self.visit_prove_distinct(block, *id, stk, proof);
+            }
+            InsertStack(id1, id2, rng, id3) => {
+                self.visit_insert_stack(block, *id1, *id2, rng, *id3);
+            }
+            SelectHash(algorithm) => {
+                self.visit_select_hash(block, *algorithm);
+            }
+            ProveEqualMultiset(id1, id2, proof) => {
+                self.visit_prove_equal_multiset(block, *id1, *id2, proof);
+            }
+            RenamePlayer(fp, name) => {
+                self.visit_rename_player(block, *fp, name);
+            }
+            CommitShares(id, rounds, round, commit) => {
+                self.visit_commit_shares(block, *id, *rounds, *round, *commit);
+            }
+            OpenShares(id, round, share) => {
+                self.visit_open_shares(block, *id, *round, *share);
+            }
+            RevealDistinct(id, shares, proof) => {
+                self.visit_reveal_distinct(block, *id, shares, proof);
+            }
+            ReshuffleFrom(id1, id2, stk, proof) => {
+                self.visit_reshuffle_from(block, *id1, *id2, stk, proof);
+            }
+            OpenPeek(id, nonce) => {
+                self.visit_open_peek(block, *id, *nonce);
+            }
             Text(text) => {
                 self.visit_text(block, text);
             }
@@ -153,6 +359,8 @@ pub trait PayloadVisitor {
     fn visit_publish_key(&mut self, _block: &Block, _name: &str, _key: &PublicKey) {}
     /// Visits a OpenStack payload
     fn visit_open_stack(&mut self, _block: &Block, _stack: &Stack) {}
+    /// Visits a DeckTemplate payload
+    fn visit_deck_template(&mut self, _block: &Block, _name: &str) {}
     /// Visits a MaskStack payload
     fn visit_mask_stack(
         &mut self,
@@ -179,6 +387,8 @@ pub trait PayloadVisitor {
     fn visit_pile_stack(&mut self, _block: &Block, _ids: &[Id], _id2: Id) {}
     /// Visits a NameStack payload
     fn visit_name_stack(&mut self, _block: &Block, _id: Id, _name: &str) {}
+    /// Visits a TagStack payload
+    fn visit_tag_stack(&mut self, _block: &Block, _id: Id, _tags: &[String]) {}
     /// Visits a PublishShares payload
     fn visit_publish_shares(
         &mut self,
@@ -188,6 +398,16 @@ pub trait PayloadVisitor {
         _proof: &[SecretShareProof],
     ) {
     }
+    /// Visits a PublishSharesAt payload
+    fn visit_publish_shares_at(
+        &mut self,
+        _block: &Block,
+        _id: Id,
+        _idxs: &[usize],
+        _shares: &[SecretShare],
+        _proof: &[SecretShareProof],
+    ) {
+    }
     /// Visits a RandomSpec payload
     fn visit_random_spec(&mut self, _block: &Block, _name: &str, _spec: &str) {}
     /// Visits a RandomEntropy payload
@@ -210,6 +430,133 @@ pub trait PayloadVisitor {
         _proof: &EntanglementProof,
     ) {
     }
+    /// Visits a BurnStack payload
+    fn visit_burn_stack(&mut self, _block: &Block, _id1: Id, _idxs: &[usize], _id2: Id) {}
+    /// Visits a CastBallot payload
+    fn visit_cast_ballot(
+        &mut self,
+        _block: &Block,
+        _topic: &str,
+        _ballot: &Mask,
+        _proof: &PrivateMaskProof,
+    ) {
+    }
+    /// Visits a RevealTally payload
+    fn visit_reveal_tally(
+        &mut self,
+        _block: &Block,
+        _topic: &str,
+        _share: &SecretShare,
+        _proof: &SecretShareProof,
+    ) {
+    }
+    /// Visits a Peek payload
+    fn visit_peek(&mut self, _block: &Block, _id: Id, _commit: Id) {}
+    /// Visits a CommitShift payload
+    fn visit_commit_shift(&mut self, _block: &Block, _id: Id, _commit: Id) {}
+    /// Visits a RevealShift payload
+    fn visit_reveal_shift(
+        &mut self,
+        _block: &Block,
+        _id: Id,
+        _k: usize,
+        _nonce: Id,
+        _secrets: &[Scalar],
+    ) {
+    }
+    /// Visits a ProveGreater payload
+    fn visit_prove_greater(
+        &mut self,
+        _block: &Block,
+        _a: &Mask,
+        _b: &Mask,
+        _max: usize,
+        _proof: &PrivateMaskProof,
+    ) {
+    }
+    /// Visits a ProveField payload
+    fn visit_prove_field(
+        &mut self,
+        _block: &Block,
+        _mask: &Mask,
+        _field_mask: u64,
+        _field_value: u64,
+        _max: u64,
+        _proof: &PrivateMaskProof,
+    ) {
+    }
+    /// Visits a Checkpoint payload
+    fn visit_checkpoint(&mut self, _block: &Block, _state: Id) {}
+    /// Visits a DealFaceUp payload
+    fn visit_deal_face_up(&mut self, _block: &Block, _id1: Id, _idxs: &[usize], _id2: Id) {}
+    /// Visits a BeaconEntropy payload
+    fn visit_beacon_entropy(
+        &mut self,
+        _block: &Block,
+        _id: &str,
+        _round: u64,
+        _value: &[u8],
+        _proof: &[u8],
+    ) {
+    }
+    /// Visits a ProveDistinct payload
+    fn visit_prove_distinct(
+        &mut self,
+        _block: &Block,
+        _id: Id,
+        _blinded: &Stack,
+        _proof: &DistinctProof,
+    ) {
+    }
+    /// Visits an InsertStack payload
+    fn visit_insert_stack(&mut self, _block: &Block, _id1: Id, _id2: Id, _rng: &str, _id3: Id) {}
+    /// Visits a SelectHash payload
+    fn visit_select_hash(&mut self, _block: &Block, _algorithm: HashAlgorithm) {}
+    /// Visits a ProveEqualMultiset payload
+    fn visit_prove_equal_multiset(
+        &mut self,
+        _block: &Block,
+        _hidden: Id,
+        _reference: Id,
+        _proof: &ShuffleProof,
+    ) {
+    }
+    /// Visits a RenamePlayer payload
+    fn visit_rename_player(&mut self, _block: &Block, _fp: Fingerprint, _name: &str) {}
+    /// Visits a CommitShares payload
+    fn visit_commit_shares(
+        &mut self,
+        _block: &Block,
+        _id: Id,
+        _rounds: u64,
+        _round: u64,
+        _commit: Id,
+    ) {
+    }
+    /// Visits an OpenShares payload
+    fn visit_open_shares(&mut self, _block: &Block, _id: Id, _round: u64, _share: Id) {}
+    /// Visits a RevealDistinct payload
+    fn visit_reveal_distinct(
+        &mut self,
+        _block: &Block,
+        _id: Id,
+        _shares: &[SecretShare],
+        _proof: &[SecretShareProof],
+    ) {
+    }
+    /// Visits a ReshuffleFrom payload
+    fn visit_reshuffle_from(
+        &mut self,
+        _block: &Block,
+        _reference: Id,
+        _source: Id,
+        _stack: &Stack,
+        _proof: &ShuffleProof,
+    ) {
+    }
+    /// Visits an OpenPeek payload
+    fn visit_open_peek(&mut self, _block: &Block, _id: Id, _nonce: Id) {}
+
     /// Visits a Text payload
     fn visit_text(&mut self, _block: &Block, _text: &str) {}
 
@@ -231,10 +578,30 @@ impl Proto for Payload {
             Payload::OpenStack(stk) => PayloadKind::OpenStack(proto::OpenStack {
                 stack: Some(stk.to_proto()?),
             }),
+            Payload::DeckTemplate(name) => {
+                PayloadKind::DeckTemplate(proto::DeckTemplate { name: name.clone() })
+            }
             Payload::NameStack(id, name) => PayloadKind::NameStack(proto::NameStack {
                 id: id.to_vec(),
                 name: name.clone(),
             }),
+            Payload::CommitShares(id, rounds, round, commit) => {
+                PayloadKind::CommitShares(proto::CommitShares {
+                    id: id.to_vec(),
+                    rounds: *rounds,
+                    round: *round,
+                    commit: commit.to_vec(),
+                })
+            }
+            Payload::OpenShares(id, round, share) => PayloadKind::OpenShares(proto::OpenShares {
+                id: id.to_vec(),
+                round: *round,
+                share: share.to_vec(),
+            }),
+            Payload::TagStack(id, tags) => PayloadKind::TagStack(proto::TagStack {
+                id: id.to_vec(),
+                tags: tags.clone(),
+            }),
             Payload::MaskStack(id, stk, proof) => PayloadKind::MaskStack(proto::MaskStack {
                 id: id.to_vec(),
                 stack: Some(stk.to_proto()?),
@@ -268,6 +635,14 @@ impl Proto for Payload {
                     proofs: vec_to_proto(&proof)?,
                 })
             }
+            Payload::PublishSharesAt(id, idxs, shares, proof) => {
+                PayloadKind::PublishSharesAt(proto::PublishSharesAt {
+                    id: id.to_vec(),
+                    indices: idxs.iter().map(|&i| i as i64).collect(),
+                    shares: vec_to_proto(shares)?,
+                    proofs: vec_to_proto(proof)?,
+                })
+            }
             Payload::RandomSpec(name, spec) => PayloadKind::RandomSpec(proto::RandomSpec {
                 name: name.clone(),
                 spec: spec.clone(),
@@ -292,6 +667,122 @@ impl Proto for Payload {
                     proof: Some(proof.to_proto()?),
                 })
             }
+            Payload::BurnStack(id1, idxs, id2) => PayloadKind::BurnStack(proto::BurnStack {
+                source_id: id1.to_vec(),
+                indices: idxs.iter().map(|&i| i as i64).collect(),
+                result_id: id2.to_vec(),
+            }),
+            Payload::CastBallot(topic, ballot, proof) => {
+                PayloadKind::CastBallot(proto::CastBallot {
+                    topic: topic.clone(),
+                    ballot: Some(ballot.to_proto()?),
+                    proof: Some(proof.to_proto()?),
+                })
+            }
+            Payload::RevealTally(topic, share, proof) => {
+                PayloadKind::RevealTally(proto::RevealTally {
+                    topic: topic.clone(),
+                    share: Some(share.to_proto()?),
+                    proof: Some(proof.to_proto()?),
+                })
+            }
+            Payload::Peek(id, commit) => PayloadKind::Peek(proto::Peek {
+                id: id.to_vec(),
+                commitment: commit.to_vec(),
+            }),
+            Payload::CommitShift(id, commit) => PayloadKind::CommitShift(proto::CommitShift {
+                id: id.to_vec(),
+                commitment: commit.to_vec(),
+            }),
+            Payload::RevealShift(id, k, nonce, secrets) => {
+                PayloadKind::RevealShift(proto::RevealShift {
+                    id: id.to_vec(),
+                    shift: *k as i64,
+                    nonce: nonce.to_vec(),
+                    secrets: scalars_to_proto(secrets)?,
+                })
+            }
+            Payload::ProveGreater(a, b, max, proof) => {
+                PayloadKind::ProveGreater(proto::ProveGreater {
+                    greater: Some(a.to_proto()?),
+                    lesser: Some(b.to_proto()?),
+                    max: *max as i64,
+                    proof: Some(proof.to_proto()?),
+                })
+            }
+            Payload::ProveField(mask, field_mask, field_value, max, proof) => {
+                PayloadKind::ProveField(proto::ProveField {
+                    mask: Some(mask.to_proto()?),
+                    field_mask: *field_mask,
+                    field_value: *field_value,
+                    max: *max,
+                    proof: Some(proof.to_proto()?),
+                })
+            }
+            Payload::Checkpoint(state) => PayloadKind::Checkpoint(proto::Checkpoint {
+                state: state.to_vec(),
+            }),
+            Payload::DealFaceUp(id1, idxs, id2) => PayloadKind::DealFaceUp(proto::DealFaceUp {
+                source_id: id1.to_vec(),
+                indices: idxs.iter().map(|&i| i as i64).collect(),
+                result_id: id2.to_vec(),
+            }),
+            Payload::BeaconEntropy(id, round, value, proof) => {
+                PayloadKind::BeaconEntropy(proto::BeaconEntropy {
+                    name: id.clone(),
+                    round: *round,
+                    value: value.clone(),
+                    proof: proof.clone(),
+                })
+            }
+            Payload::ProveDistinct(id, stk, proof) => {
+                PayloadKind::ProveDistinct(proto::ProveDistinct {
+                    id: id.to_vec(),
+                    proof: Some(proof.to_proto()?),
+                    blinded: Some(stk.to_proto()?),
+                })
+            }
+            Payload::RevealDistinct(id, shares, proof) => {
+                PayloadKind::RevealDistinct(proto::RevealDistinct {
+                    id: id.to_vec(),
+                    shares: vec_to_proto(shares)?,
+                    proofs: vec_to_proto(proof)?,
+                })
+            }
+            Payload::ReshuffleFrom(id1, id2, stk, proof) => {
+                PayloadKind::ReshuffleFrom(proto::ReshuffleFrom {
+                    reference_id: id1.to_vec(),
+                    source_id: id2.to_vec(),
+                    shuffle: Some(stk.to_proto()?),
+                    proof: Some(proof.to_proto()?),
+                })
+            }
+            Payload::OpenPeek(id, nonce) => PayloadKind::OpenPeek(proto::OpenPeek {
+                id: id.to_vec(),
+                nonce: nonce.to_vec(),
+            }),
+            Payload::InsertStack(id1, id2, rng, id3) => {
+                PayloadKind::InsertStack(proto::InsertStack {
+                    source_id: id1.to_vec(),
+                    inserted_id: id2.to_vec(),
+                    rng: rng.clone(),
+                    result_id: id3.to_vec(),
+                })
+            }
+            Payload::SelectHash(algorithm) => PayloadKind::SelectHash(proto::SelectHash {
+                algorithm: algorithm.to_string(),
+            }),
+            Payload::ProveEqualMultiset(id1, id2, proof) => {
+                PayloadKind::ProveEqualMultiset(proto::ProveEqualMultiset {
+                    hidden_id: id1.to_vec(),
+                    reference_id: id2.to_vec(),
+                    proof: Some(proof.to_proto()?),
+                })
+            }
+            Payload::RenamePlayer(fp, name) => PayloadKind::RenamePlayer(proto::RenamePlayer {
+                fingerprint: fp.to_vec(),
+                name: name.clone(),
+            }),
             Payload::Text(text) => PayloadKind::Text(text.clone()),
             Payload::Bytes(bytes) => PayloadKind::Raw(bytes.clone()),
         };
@@ -301,6 +792,10 @@ impl Proto for Payload {
     }
 
     fn from_proto(m: &proto::Payload) -> Result<Self> {
+        fn bounded<T>(v: &[T]) -> Option<&[T]> {
+            check_stack_len(v.len()).ok().map(|_| v)
+        }
+
         fn do_it(m: &proto::Payload) -> Option<Payload> {
             use proto::payload::PayloadKind;
 
@@ -312,10 +807,11 @@ impl Proto for Payload {
                 PayloadKind::OpenStack(p) => {
                     Payload::OpenStack(Stack::from_proto(p.stack.as_ref()?).ok()?)
                 }
+                PayloadKind::DeckTemplate(p) => Payload::DeckTemplate(p.name.clone()),
                 PayloadKind::MaskStack(p) => Payload::MaskStack(
                     Id::try_from(&p.id).ok()?,
                     Stack::from_proto(p.stack.as_ref()?).ok()?,
-                    vec_from_proto(&p.proofs).ok()?,
+                    vec_from_proto(bounded(&p.proofs)?).ok()?,
                 ),
                 PayloadKind::ShuffleStack(p) => Payload::ShuffleStack(
                     Id::try_from(&p.id).ok()?,
@@ -330,13 +826,16 @@ impl Proto for Payload {
                 PayloadKind::NameStack(p) => {
                     Payload::NameStack(Id::try_from(&p.id).ok()?, p.name.clone())
                 }
+                PayloadKind::TagStack(p) => {
+                    Payload::TagStack(Id::try_from(&p.id).ok()?, bounded(&p.tags)?.to_vec())
+                }
                 PayloadKind::TakeStack(p) => Payload::TakeStack(
                     Id::try_from(&p.source_id).ok()?,
-                    p.indices.iter().map(|&i| i as usize).collect(),
+                    bounded(&p.indices)?.iter().map(|&i| i as usize).collect(),
                     Id::try_from(&p.result_id).ok()?,
                 ),
                 PayloadKind::PileStacks(p) => Payload::PileStacks(
-                    p.source_ids
+                    bounded(&p.source_ids)?
                         .iter()
                         .map(|id| Id::try_from(id))
                         .collect::<Result<_>>()
@@ -345,8 +844,14 @@ impl Proto for Payload {
                 ),
                 PayloadKind::PublishShares(p) => Payload::PublishShares(
                     Id::try_from(&p.id).ok()?,
-                    vec_from_proto(&p.shares).ok()?,
-                    vec_from_proto(&p.proofs).ok()?,
+                    vec_from_proto(bounded(&p.shares)?).ok()?,
+                    vec_from_proto(bounded(&p.proofs)?).ok()?,
+                ),
+                PayloadKind::PublishSharesAt(p) => Payload::PublishSharesAt(
+                    Id::try_from(&p.id).ok()?,
+                    bounded(&p.indices)?.iter().map(|&i| i as usize).collect(),
+                    vec_from_proto(bounded(&p.shares)?).ok()?,
+                    vec_from_proto(bounded(&p.proofs)?).ok()?,
                 ),
                 PayloadKind::RandomSpec(p) => Payload::RandomSpec(p.name.clone(), p.spec.clone()),
                 PayloadKind::RandomEntropy(p) => Payload::RandomEntropy(
@@ -359,18 +864,119 @@ impl Proto for Payload {
                     SecretShareProof::from_proto(p.proof.as_ref()?).ok()?,
                 ),
                 PayloadKind::ProveEntanglement(p) => Payload::ProveEntanglement(
-                    p.source_ids
+                    bounded(&p.source_ids)?
                         .iter()
                         .map(|id| Id::try_from(id))
                         .collect::<Result<_>>()
                         .ok()?,
-                    p.shuffle_ids
+                    bounded(&p.shuffle_ids)?
                         .iter()
                         .map(|id| Id::try_from(id))
                         .collect::<Result<_>>()
                         .ok()?,
                     EntanglementProof::from_proto(p.proof.as_ref()?).ok()?,
                 ),
+                PayloadKind::BurnStack(p) => Payload::BurnStack(
+                    Id::try_from(&p.source_id).ok()?,
+                    bounded(&p.indices)?.iter().map(|&i| i as usize).collect(),
+                    Id::try_from(&p.result_id).ok()?,
+                ),
+                PayloadKind::CastBallot(p) => Payload::CastBallot(
+                    p.topic.clone(),
+                    Mask::from_proto(p.ballot.as_ref()?).ok()?,
+                    PrivateMaskProof::from_proto(p.proof.as_ref()?).ok()?,
+                ),
+                PayloadKind::RevealTally(p) => Payload::RevealTally(
+                    p.topic.clone(),
+                    SecretShare::from_proto(p.share.as_ref()?).ok()?,
+                    SecretShareProof::from_proto(p.proof.as_ref()?).ok()?,
+                ),
+                PayloadKind::Peek(p) => {
+                    Payload::Peek(Id::try_from(&p.id).ok()?, Id::try_from(&p.commitment).ok()?)
+                }
+                PayloadKind::CommitShift(p) => Payload::CommitShift(
+                    Id::try_from(&p.id).ok()?,
+                    Id::try_from(&p.commitment).ok()?,
+                ),
+                PayloadKind::RevealShift(p) => Payload::RevealShift(
+                    Id::try_from(&p.id).ok()?,
+                    p.shift as usize,
+                    Id::try_from(&p.nonce).ok()?,
+                    scalars_from_proto(bounded(&p.secrets)?).ok()?,
+                ),
+                PayloadKind::ProveGreater(p) => Payload::ProveGreater(
+                    Mask::from_proto(p.greater.as_ref()?).ok()?,
+                    Mask::from_proto(p.lesser.as_ref()?).ok()?,
+                    check_stack_len(p.max as usize)
+                        .ok()
+                        .map(|_| p.max as usize)?,
+                    PrivateMaskProof::from_proto(p.proof.as_ref()?).ok()?,
+                ),
+                PayloadKind::ProveField(p) => Payload::ProveField(
+                    Mask::from_proto(p.mask.as_ref()?).ok()?,
+                    p.field_mask,
+                    p.field_value,
+                    check_stack_len(p.max as usize).ok().map(|_| p.max)?,
+                    PrivateMaskProof::from_proto(p.proof.as_ref()?).ok()?,
+                ),
+                PayloadKind::Checkpoint(p) => Payload::Checkpoint(Id::try_from(&p.state).ok()?),
+                PayloadKind::DealFaceUp(p) => Payload::DealFaceUp(
+                    Id::try_from(&p.source_id).ok()?,
+                    bounded(&p.indices)?.iter().map(|&i| i as usize).collect(),
+                    Id::try_from(&p.result_id).ok()?,
+                ),
+                PayloadKind::BeaconEntropy(p) => Payload::BeaconEntropy(
+                    p.name.clone(),
+                    p.round,
+                    p.value.clone(),
+                    p.proof.clone(),
+                ),
+                PayloadKind::ProveDistinct(p) => Payload::ProveDistinct(
+                    Id::try_from(&p.id).ok()?,
+                    Stack::from_proto(p.blinded.as_ref()?).ok()?,
+                    DistinctProof::from_proto(p.proof.as_ref()?).ok()?,
+                ),
+                PayloadKind::RevealDistinct(p) => Payload::RevealDistinct(
+                    Id::try_from(&p.id).ok()?,
+                    vec_from_proto(bounded(&p.shares)?).ok()?,
+                    vec_from_proto(bounded(&p.proofs)?).ok()?,
+                ),
+                PayloadKind::ReshuffleFrom(p) => Payload::ReshuffleFrom(
+                    Id::try_from(&p.reference_id).ok()?,
+                    Id::try_from(&p.source_id).ok()?,
+                    Stack::from_proto(p.shuffle.as_ref()?).ok()?,
+                    ShuffleProof::from_proto(p.proof.as_ref()?).ok()?,
+                ),
+                PayloadKind::OpenPeek(p) => {
+                    Payload::OpenPeek(Id::try_from(&p.id).ok()?, Id::try_from(&p.nonce).ok()?)
+                }
+                PayloadKind::InsertStack(p) => Payload::InsertStack(
+                    Id::try_from(&p.source_id).ok()?,
+                    Id::try_from(&p.inserted_id).ok()?,
+                    p.rng.clone(),
+                    Id::try_from(&p.result_id).ok()?,
+                ),
+                PayloadKind::SelectHash(p) => Payload::SelectHash(p.algorithm.parse().ok()?),
+                PayloadKind::ProveEqualMultiset(p) => Payload::ProveEqualMultiset(
+                    Id::try_from(&p.hidden_id).ok()?,
+                    Id::try_from(&p.reference_id).ok()?,
+                    ShuffleProof::from_proto(p.proof.as_ref()?).ok()?,
+                ),
+                PayloadKind::RenamePlayer(p) => Payload::RenamePlayer(
+                    Fingerprint::try_from(&p.fingerprint).ok()?,
+                    p.name.clone(),
+                ),
+                PayloadKind::CommitShares(p) => Payload::CommitShares(
+                    Id::try_from(&p.id).ok()?,
+                    p.rounds,
+                    p.round,
+                    Id::try_from(&p.commit).ok()?,
+                ),
+                PayloadKind::OpenShares(p) => Payload::OpenShares(
+                    Id::try_from(&p.id).ok()?,
+                    p.round,
+                    Id::try_from(&p.share).ok()?,
+                ),
                 PayloadKind::Text(s) => Payload::Text(s.clone()),
                 PayloadKind::Raw(p) => Payload::Bytes(p.clone()),
             })
@@ -393,11 +999,33 @@ impl Transcribe for Payload {
                 b"open-stack".append_to_transcript(t, b"type");
                 stk.append_to_transcript(t, b"stack");
             }
+            DeckTemplate(name) => {
+                b"deck-template".append_to_transcript(t, b"type");
+                name.append_to_transcript(t, b"name");
+            }
+            CommitShares(id, rounds, round, commit) => {
+                b"commit-shares".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"id");
+                rounds.append_to_transcript(t, b"rounds");
+                round.append_to_transcript(t, b"round");
+                commit.append_to_transcript(t, b"commit");
+            }
+            OpenShares(id, round, share) => {
+                b"open-shares".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"id");
+                round.append_to_transcript(t, b"round");
+                share.append_to_transcript(t, b"share");
+            }
             NameStack(id, name) => {
                 b"name-stack".append_to_transcript(t, b"type");
                 id.append_to_transcript(t, b"stack");
                 name.append_to_transcript(t, b"name");
             }
+            TagStack(id, tags) => {
+                b"tag-stack".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"stack");
+                tags.append_to_transcript(t, b"tags");
+            }
             MaskStack(id, stk, proof) => {
                 b"mask-stack".append_to_transcript(t, b"type");
                 id.append_to_transcript(t, b"input");
@@ -433,6 +1061,13 @@ impl Transcribe for Payload {
                 shares.append_to_transcript(t, b"shares");
                 proof.append_to_transcript(t, b"proof");
             }
+            PublishSharesAt(id, idxs, shares, proof) => {
+                b"publish-shares-at".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"stack");
+                idxs.append_to_transcript(t, b"indices");
+                shares.append_to_transcript(t, b"shares");
+                proof.append_to_transcript(t, b"proof");
+            }
             RandomSpec(id, spec) => {
                 b"random-spec".append_to_transcript(t, b"type");
                 id.append_to_transcript(t, b"id");
@@ -455,6 +1090,119 @@ impl Transcribe for Payload {
                 ids2.append_to_transcript(t, b"output");
                 proof.append_to_transcript(t, b"proof");
             }
+            BurnStack(id1, idxs, id2) => {
+                b"burn-stack".append_to_transcript(t, b"type");
+                id1.append_to_transcript(t, b"input");
+                idxs.append_to_transcript(t, b"indices");
+                id2.append_to_transcript(t, b"output");
+            }
+            CastBallot(topic, ballot, proof) => {
+                b"cast-ballot".append_to_transcript(t, b"type");
+                topic.append_to_transcript(t, b"topic");
+                ballot.append_to_transcript(t, b"ballot");
+                proof.append_to_transcript(t, b"proof");
+            }
+            RevealTally(topic, share, proof) => {
+                b"reveal-tally".append_to_transcript(t, b"type");
+                topic.append_to_transcript(t, b"topic");
+                share.append_to_transcript(t, b"share");
+                proof.append_to_transcript(t, b"proof");
+            }
+            Peek(id, commit) => {
+                b"peek".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"stack");
+                commit.append_to_transcript(t, b"commitment");
+            }
+            CommitShift(id, commit) => {
+                b"commit-shift".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"stack");
+                commit.append_to_transcript(t, b"commitment");
+            }
+            RevealShift(id, k, nonce, secrets) => {
+                b"reveal-shift".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"stack");
+                k.append_to_transcript(t, b"shift");
+                nonce.append_to_transcript(t, b"nonce");
+                secrets.append_to_transcript(t, b"secrets");
+            }
+            ProveGreater(a, b, max, proof) => {
+                b"prove-greater".append_to_transcript(t, b"type");
+                a.append_to_transcript(t, b"greater");
+                b.append_to_transcript(t, b"lesser");
+                max.append_to_transcript(t, b"max");
+                proof.append_to_transcript(t, b"proof");
+            }
+            ProveField(mask, field_mask, field_value, max, proof) => {
+                b"prove-field".append_to_transcript(t, b"type");
+                mask.append_to_transcript(t, b"mask");
+                field_mask.append_to_transcript(t, b"field_mask");
+                field_value.append_to_transcript(t, b"field_value");
+                max.append_to_transcript(t, b"max");
+                proof.append_to_transcript(t, b"proof");
+            }
+            Checkpoint(state) => {
+                b"checkpoint".append_to_transcript(t, b"type");
+                state.append_to_transcript(t, b"state");
+            }
+            DealFaceUp(id1, idxs, id2) => {
+                b"deal-face-up".append_to_transcript(t, b"type");
+                id1.append_to_transcript(t, b"input");
+                idxs.append_to_transcript(t, b"indices");
+                id2.append_to_transcript(t, b"output");
+            }
+            BeaconEntropy(id, round, value, proof) => {
+                b"beacon-entropy".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"id");
+                round.append_to_transcript(t, b"round");
+                value.append_to_transcript(t, b"value");
+                proof.append_to_transcript(t, b"proof");
+            }
+            ProveDistinct(id, stk, proof) => {
+                b"prove-distinct".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"stack");
+                stk.append_to_transcript(t, b"blinded");
+                proof.append_to_transcript(t, b"proof");
+            }
+            RevealDistinct(id, shares, proof) => {
+                b"reveal-distinct".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"stack");
+                shares.append_to_transcript(t, b"shares");
+                proof.append_to_transcript(t, b"proof");
+            }
+            ReshuffleFrom(id1, id2, stk, proof) => {
+                b"reshuffle-from".append_to_transcript(t, b"type");
+                id1.append_to_transcript(t, b"reference");
+                id2.append_to_transcript(t, b"input");
+                stk.append_to_transcript(t, b"output");
+                proof.append_to_transcript(t, b"proof");
+            }
+            OpenPeek(id, nonce) => {
+                b"open-peek".append_to_transcript(t, b"type");
+                id.append_to_transcript(t, b"stack");
+                nonce.append_to_transcript(t, b"nonce");
+            }
+            InsertStack(id1, id2, rng, id3) => {
+                b"insert-stack".append_to_transcript(t, b"type");
+                id1.append_to_transcript(t, b"input");
+                id2.append_to_transcript(t, b"inserted");
+                rng.append_to_transcript(t, b"rng");
+                id3.append_to_transcript(t, b"output");
+            }
+            SelectHash(algorithm) => {
+                b"select-hash".append_to_transcript(t, b"type");
+                algorithm.to_string().append_to_transcript(t, b"algorithm");
+            }
+            ProveEqualMultiset(id1, id2, proof) => {
+                b"prove-equal-multiset".append_to_transcript(t, b"type");
+                id1.append_to_transcript(t, b"hidden");
+                id2.append_to_transcript(t, b"reference");
+                proof.append_to_transcript(t, b"proof");
+            }
+            RenamePlayer(fp, name) => {
+                b"rename-player".append_to_transcript(t, b"type");
+                fp.append_to_transcript(t, b"fingerprint");
+                name.append_to_transcript(t, b"name");
+            }
             Text(text) => {
                 b"text".append_to_transcript(t, b"type");
                 text.append_to_transcript(t, b"text");
@@ -1,12 +1,20 @@
 //! Cryptographic hash functions
 
+use crate::{chain::Id, serde::Message, Error, Result};
 use curve25519_dalek::{
-    constants::RISTRETTO_BASEPOINT_TABLE, ristretto::RistrettoPoint, scalar::Scalar,
+    constants::RISTRETTO_BASEPOINT_TABLE,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
 };
 use digest::XofReader;
-use merlin::{Transcript};
-
-use std::iter;
+use merlin::Transcript;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Read},
+    iter,
+    str::FromStr,
+};
+use tiny_keccak::{Hasher, IntoXof, KangarooTwelve, KangarooTwelveXof, Shake, Xof as KeccakXof};
 
 /// A transcript that can be appended to.
 pub trait TranscriptAppend {
@@ -22,17 +30,87 @@ impl TranscriptAppend for Transcript {
 
 impl TranscriptAppend for TranscriptHash {
     fn append_message(&mut self, label: &'static [u8], message: &[u8]) {
-        self.0.append_message(label, message);
+        match &mut self.0 {
+            HashState::Strobe(t) => t.append_message(label, message),
+            HashState::Shake(h) => absorb_framed(h, label, message),
+            HashState::K12(h) => absorb_framed(h, label, message),
+        }
+    }
+}
+
+/// The hash functions available for player fingerprints and random
+/// generators
+///
+/// All parties in a game must use the same one, so it is chosen in the
+/// genesis block.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// STROBE-128, as used by merlin transcripts (the default)
+    #[default]
+    Strobe,
+    /// SHAKE256, as specified in FIPS 202
+    Shake256,
+    /// KangarooTwelve
+    KangarooTwelve,
+}
+
+impl HashAlgorithm {
+    /// The names of the available hash functions
+    pub const NAMES: &'static [&'static str] = &["strobe", "shake256", "k12"];
+}
+
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            HashAlgorithm::Strobe => write!(f, "strobe"),
+            HashAlgorithm::Shake256 => write!(f, "shake256"),
+            HashAlgorithm::KangarooTwelve => write!(f, "k12"),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "strobe" => Ok(HashAlgorithm::Strobe),
+            "shake256" => Ok(HashAlgorithm::Shake256),
+            "k12" => Ok(HashAlgorithm::KangarooTwelve),
+            _ => Err(Error::Decoding),
+        }
     }
 }
 
-/// A STROBE-based hash function
-pub struct TranscriptHash(Transcript);
+/// A hash function over framed transcripts
+///
+/// This is a STROBE unless created with another [`HashAlgorithm`].
+pub struct TranscriptHash(HashState);
+
+#[allow(clippy::large_enum_variant)]
+enum HashState {
+    Strobe(Transcript),
+    Shake(Shake),
+    K12(KangarooTwelve<&'static [u8]>),
+}
 
 impl TranscriptHash {
     /// Creates a new hash object
     pub fn new(protocol: &'static [u8]) -> Self {
-        Self(Transcript::new(protocol))
+        Self::with_algorithm(protocol, HashAlgorithm::Strobe)
+    }
+
+    /// Creates a new hash object using a given hash function
+    pub fn with_algorithm(protocol: &'static [u8], algorithm: HashAlgorithm) -> Self {
+        Self(match algorithm {
+            HashAlgorithm::Strobe => HashState::Strobe(Transcript::new(protocol)),
+            HashAlgorithm::Shake256 => {
+                let mut h = Shake::v256();
+                absorb_framed(&mut h, b"dom-sep", protocol);
+                HashState::Shake(h)
+            }
+            HashAlgorithm::KangarooTwelve => HashState::K12(KangarooTwelve::new(protocol)),
+        })
     }
 
     /// Appends an object for hashing, with a given label for framing
@@ -42,24 +120,82 @@ impl TranscriptHash {
 
     /// Produces a fixed-size hash from all the data that was appended
     pub fn finish(mut self, buffer: &mut [u8]) {
-        self.0.challenge_bytes(b"$hash", buffer);
+        if let HashState::Strobe(t) = &mut self.0 {
+            t.challenge_bytes(b"$hash", buffer);
+        } else {
+            self.append_message(b"$hash", &[]);
+            self.into_xof().read(buffer);
+        }
     }
 
     /// Creates a XOF reader object to produce a variable-size hash
     pub fn into_xof(self) -> impl XofReader {
-        TranscriptXof(self.0)
+        match self.0 {
+            HashState::Strobe(t) => TranscriptXof::Strobe(t),
+            HashState::Shake(h) => TranscriptXof::Shake(h),
+            HashState::K12(h) => TranscriptXof::K12(h.into_xof()),
+        }
     }
 }
 
-struct TranscriptXof(Transcript);
+/// An adapter to read the output of a XOF through [`std::io::Read`]
+///
+/// A XOF never runs out, so reads always fill the whole buffer. Each read is
+/// passed on as one read from the XOF; with STROBE-based XOFs, reading the
+/// same bytes in different chunks gives different output.
+#[derive(Debug)]
+pub struct XofRead<X>(pub X);
+
+impl<X: XofReader> Read for XofRead<X> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf);
+        Ok(buf.len())
+    }
+}
+
+/// Absorbs a message with its label and both their lengths, so that
+/// different sequences of messages cannot produce the same input
+fn absorb_framed<H: Hasher>(h: &mut H, label: &[u8], message: &[u8]) {
+    h.update(&(label.len() as u64).to_le_bytes());
+    h.update(label);
+    h.update(&(message.len() as u64).to_le_bytes());
+    h.update(message);
+}
+
+/// Hashes a protocol value as it would be read back from its canonical
+/// encoding
+///
+/// This can be used to commit to arbitrary values off-chain, e.g. a planned
+/// move. Values with the same encoding have the same hash, and for payloads
+/// this is the same as [`Payload::id`](crate::chain::Payload::id).
+pub fn hash_message<M: Message + Transcribe>(m: &M) -> Result<Id> {
+    Ok(Id::of(&M::decode(&m.encode()?)?, MESSAGE_ID_DOMAIN))
+}
+
+pub(crate) const MESSAGE_ID_DOMAIN: &[u8] = b"pbmx-payload-id";
+
+enum TranscriptXof {
+    Strobe(Transcript),
+    Shake(Shake),
+    K12(KangarooTwelveXof),
+}
 
 impl XofReader for TranscriptXof {
     fn read(&mut self, buffer: &mut [u8]) {
-        self.0.challenge_bytes(b"$xof", buffer);
+        match self {
+            TranscriptXof::Strobe(t) => t.challenge_bytes(b"$xof", buffer),
+            TranscriptXof::Shake(h) => h.squeeze(buffer),
+            TranscriptXof::K12(h) => h.squeeze(buffer),
+        }
     }
 }
 
 /// A type that can be hashed using a STROBE
+///
+/// Integers are always transcribed as 8 big-endian bytes, with `usize` widened
+/// to `u64` first, so indices and lengths hash the same on every platform and
+/// whatever type holds them. Proofs use these impls both for their challenges
+/// and for rekeying their nonce generators, so the two always agree.
 pub trait Transcribe {
     /// Appends this object to a transcript, with a given label for framing
     fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]);
@@ -107,12 +243,19 @@ impl Transcribe for bool {
     }
 }
 
+// big-endian, as for u64
 impl Transcribe for usize {
     fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
         t.append_message(label, &(*self as u64).to_be_bytes());
     }
 }
 
+impl Transcribe for u64 {
+    fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
+        t.append_message(label, &self.to_be_bytes());
+    }
+}
+
 impl Transcribe for [u8] {
     fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
         t.append_message(label, &self);
@@ -138,9 +281,15 @@ impl Transcribe for Scalar {
 }
 
 impl Transcribe for RistrettoPoint {
+    fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
+        self.compress().append_to_transcript(t, label);
+    }
+}
+
+impl Transcribe for CompressedRistretto {
     fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
         b"point".append_to_transcript(t, label);
-        self.compress().as_bytes().append_to_transcript(t, b"bytes");
+        self.as_bytes().append_to_transcript(t, b"bytes");
     }
 }
 
@@ -170,3 +319,90 @@ impl Challenge for RistrettoPoint {
         &RISTRETTO_BASEPOINT_TABLE * &s
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        hash_message, HashAlgorithm, Transcribe, TranscriptAppend, TranscriptHash, XofRead,
+    };
+    use crate::{chain::Payload, serde::Message};
+    use digest::XofReader;
+    use std::io::Read;
+
+    #[test]
+    fn hash_message_depends_only_on_encoding() {
+        let a = Payload::Bytes(vec![1, 2, 3]);
+        let b = Payload::decode(&a.encode().unwrap()).unwrap();
+        let c = Payload::Bytes(vec![1, 2, 4]);
+
+        assert_eq!(hash_message(&a).unwrap(), hash_message(&b).unwrap());
+        assert_ne!(hash_message(&a).unwrap(), hash_message(&c).unwrap());
+        assert_eq!(hash_message(&a).unwrap(), a.id());
+    }
+
+    #[derive(Default)]
+    struct Recorder(Vec<(&'static [u8], Vec<u8>)>);
+
+    impl TranscriptAppend for Recorder {
+        fn append_message(&mut self, label: &'static [u8], message: &[u8]) {
+            self.0.push((label, message.to_vec()));
+        }
+    }
+
+    #[test]
+    fn integers_are_transcribed_big_endian() {
+        let mut from_usize = Recorder::default();
+        vec![3usize, 258].append_to_transcript(&mut from_usize, b"v");
+        let mut from_u64 = Recorder::default();
+        vec![3u64, 258].append_to_transcript(&mut from_u64, b"v");
+
+        assert_eq!(from_usize.0, from_u64.0);
+        assert_eq!(from_usize.0, vec![
+            (&b"v"[..], b"vec".to_vec()),
+            (&b"$len"[..], vec![0, 0, 0, 0, 0, 0, 0, 2]),
+            (&b"element"[..], vec![0, 0, 0, 0, 0, 0, 0, 3]),
+            (&b"element"[..], vec![0, 0, 0, 0, 0, 0, 1, 2]),
+        ]);
+    }
+
+    #[test]
+    fn hash_algorithms_are_distinct_and_named() {
+        let algorithms = [
+            HashAlgorithm::Strobe,
+            HashAlgorithm::Shake256,
+            HashAlgorithm::KangarooTwelve,
+        ];
+        let hashes: Vec<_> = algorithms
+            .iter()
+            .map(|&a| {
+                assert_eq!(a.to_string().parse::<HashAlgorithm>(), Ok(a));
+
+                let mut h = TranscriptHash::with_algorithm(b"test", a);
+                h.append(b"data", &42u64);
+                let mut buf = [0; 32];
+                h.finish(&mut buf);
+                buf
+            })
+            .collect();
+        assert_ne!(hashes[0], hashes[1]);
+        assert_ne!(hashes[0], hashes[2]);
+        assert_ne!(hashes[1], hashes[2]);
+        assert!("blake3".parse::<HashAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn xof_reads_match_the_xof_output() {
+        let xof = || {
+            let mut h = TranscriptHash::new(b"test");
+            h.append(b"data", &42u64);
+            h.into_xof()
+        };
+
+        let mut expected = [0; 100];
+        xof().read(&mut expected);
+
+        let mut actual = [0; 100];
+        XofRead(xof()).read_exact(&mut actual).unwrap();
+        assert_eq!(&actual[..], &expected[..]);
+    }
+}
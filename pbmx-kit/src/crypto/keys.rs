@@ -1,12 +1,16 @@
 //! ElGamal encryption scheme for elliptic curves
 
 use crate::{
-    crypto::hash::{Transcribe, TranscriptAppend, TranscriptHash},
+    crypto::hash::{HashAlgorithm, Transcribe, TranscriptAppend, TranscriptHash},
     proto,
     random::thread_rng,
     serde::{point_from_proto, point_to_proto, Proto},
     Error, Result,
 };
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Nonce,
+};
 use curve25519_dalek::{
     constants::RISTRETTO_BASEPOINT_TABLE,
     ristretto::{RistrettoBasepointTable, RistrettoPoint},
@@ -23,11 +27,27 @@ use std::{
     ops::Deref,
     str::{self, FromStr},
 };
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 /// A private key
+///
+/// The secret key material is wiped from memory when the key is dropped, and
+/// every clone wipes its own copy. Scalars obtained through
+/// [`exponent`](PrivateKey::exponent) are plain copies and are not covered.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PrivateKey(schnorrkel::SecretKey);
 
+/// A private key encrypted under a passphrase
+///
+/// The passphrase is stretched with Argon2id into a key for
+/// ChaCha20-Poly1305, which encrypts and authenticates the private key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SealedKey {
+    salt: [u8; 32],
+    nonce: [u8; 12],
+    data: Vec<u8>,
+}
+
 /// A public key
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PublicKey(RistrettoPoint);
@@ -77,6 +97,11 @@ impl PrivateKey {
         self.public_key().fingerprint()
     }
 
+    /// Gets the public key fingerprint under a given hash function
+    pub fn fingerprint_with(&self, algorithm: HashAlgorithm) -> Fingerprint {
+        self.public_key().fingerprint_with(algorithm)
+    }
+
     /// Decrypts a given ciphertext
     pub fn decrypt(&self, c: &(RistrettoPoint, RistrettoPoint)) -> RistrettoPoint {
         c.1 - c.0 * self.exponent()
@@ -87,8 +112,36 @@ impl PrivateKey {
         let pk = self.0.to_public();
         self.0.sign(attach_rng(t, &mut thread_rng()), &pk)
     }
+
+    /// Encrypts this key under a passphrase, e.g. for backups
+    pub fn seal(&self, passphrase: &str) -> SealedKey {
+        let mut salt = [0u8; 32];
+        thread_rng().fill(&mut salt);
+        let mut nonce = [0u8; 12];
+        thread_rng().fill(&mut nonce);
+        let mut key = SealedKey::derive_key(passphrase, &salt);
+        let mut raw = self.0.to_bytes();
+        let data = SealedKey::cipher(&key)
+            .encrypt(Nonce::from_slice(&nonce), Payload {
+                msg: &raw,
+                aad: SEAL_CONTEXT,
+            })
+            .expect("private keys are small enough to encrypt");
+        key.zeroize();
+        raw.zeroize();
+        SealedKey { salt, nonce, data }
+    }
+}
+
+impl Zeroize for PrivateKey {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
 }
 
+// the inner schnorrkel key zeroizes itself on drop
+impl ZeroizeOnDrop for PrivateKey {}
+
 impl Proto for PrivateKey {
     type Message = proto::PrivateKey;
 
@@ -105,6 +158,72 @@ impl Proto for PrivateKey {
     }
 }
 
+impl SealedKey {
+    /// Decrypts this key with a passphrase
+    ///
+    /// Fails with [`Error::WrongPassphrase`] if the passphrase does not match
+    /// or the data was tampered with.
+    pub fn open(&self, passphrase: &str) -> Result<PrivateKey> {
+        let mut key = Self::derive_key(passphrase, &self.salt);
+        let raw = Self::cipher(&key).decrypt(Nonce::from_slice(&self.nonce), Payload {
+            msg: &self.data,
+            aad: SEAL_CONTEXT,
+        });
+        key.zeroize();
+        let mut raw = raw.map_err(|_| Error::WrongPassphrase)?;
+        let sk = schnorrkel::SecretKey::from_bytes(&raw).map_err(|_| Error::Decoding);
+        raw.zeroize();
+        Ok(PrivateKey(sk?))
+    }
+
+    fn derive_key(passphrase: &str, salt: &[u8; 32]) -> [u8; 32] {
+        let params = argon2::Params::new(SEAL_MEMORY, SEAL_ITERATIONS, SEAL_LANES, None)
+            .expect("sealing parameters are valid");
+        let kdf = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+        let mut key = [0u8; 32];
+        kdf.hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .expect("sealing parameters are valid");
+        key
+    }
+
+    fn cipher(key: &[u8; 32]) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key))
+    }
+}
+
+// Argon2id costs as recommended by OWASP: 19 MiB of memory, two passes
+const SEAL_MEMORY: u32 = 19 * 1024;
+const SEAL_ITERATIONS: u32 = 2;
+const SEAL_LANES: u32 = 1;
+const SEAL_CONTEXT: &[u8] = b"pbmx-sealed-key";
+
+impl Proto for SealedKey {
+    type Message = proto::SealedKey;
+
+    fn to_proto(&self) -> Result<proto::SealedKey> {
+        Ok(proto::SealedKey {
+            salt: self.salt.to_vec(),
+            nonce: self.nonce.to_vec(),
+            data: self.data.clone(),
+        })
+    }
+
+    fn from_proto(m: &proto::SealedKey) -> Result<Self> {
+        if m.salt.len() != 32 || m.nonce.len() != 12 {
+            return Err(Error::Decoding);
+        }
+        let mut salt = [0u8; 32];
+        salt.copy_from_slice(&m.salt);
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&m.nonce);
+        Ok(SealedKey {
+            salt,
+            nonce,
+            data: m.data.clone(),
+        })
+    }
+}
+
 impl PublicKey {
     /// Gets this key's public value
     pub fn point(&self) -> RistrettoPoint {
@@ -113,7 +232,15 @@ impl PublicKey {
 
     /// Gets this key's fingerprint
     pub fn fingerprint(&self) -> Fingerprint {
-        Fingerprint::of(self, b"pbmx-key-fp")
+        self.fingerprint_with(HashAlgorithm::default())
+    }
+
+    /// Gets this key's fingerprint under a given hash function
+    ///
+    /// Games identify players by their fingerprint under the hash function
+    /// chosen in the genesis block.
+    pub fn fingerprint_with(&self, algorithm: HashAlgorithm) -> Fingerprint {
+        Fingerprint::of_with(self, b"pbmx-key-fp", algorithm)
     }
 
     /// Combines this public key with another one to form a shared key
@@ -145,6 +272,12 @@ impl PublicKey {
     }
 }
 
+impl Identity for PublicKey {
+    fn identity() -> Self {
+        PublicKey(RistrettoPoint::identity())
+    }
+}
+
 impl Transcribe for PublicKey {
     fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
         b"public-key".append_to_transcript(t, label);
@@ -176,7 +309,17 @@ impl Fingerprint {
 
     /// Extracts a fingerprint from a given transcript
     pub fn of<M: Transcribe>(m: &M, domain: &'static [u8]) -> Fingerprint {
-        let mut h = TranscriptHash::new(domain);
+        Self::of_with(m, domain, HashAlgorithm::default())
+    }
+
+    /// Extracts a fingerprint from a given transcript under a given hash
+    /// function
+    pub fn of_with<M: Transcribe>(
+        m: &M,
+        domain: &'static [u8],
+        algorithm: HashAlgorithm,
+    ) -> Fingerprint {
+        let mut h = TranscriptHash::with_algorithm(domain, algorithm);
         m.append_to_transcript(&mut h, b"public-key");
         let mut buf = [0; 32];
         h.finish(&mut buf);
@@ -275,15 +418,16 @@ const FINGERPRINT_SIZE: usize = 32;
 
 #[cfg(test)]
 mod tests {
-    use super::{Fingerprint, PrivateKey, PublicKey, G};
+    use super::{Fingerprint, PrivateKey, PublicKey, SealedKey, G};
     use crate::{
-        serde::{FromBase64, ToBase64},
+        serde::{FromBase64, Message, ToBase64},
         Error,
     };
     use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
     use rand::thread_rng;
     use schnorrkel::signing_context;
     use std::str::FromStr;
+    use zeroize::{Zeroize, ZeroizeOnDrop};
 
     #[test]
     fn keys_produces_valid_keys() {
@@ -356,6 +500,24 @@ mod tests {
         assert_eq!(r, Err(Error::BadSignature));
     }
 
+    #[test]
+    fn sealed_keys_open_only_with_the_passphrase() {
+        let original = PrivateKey::random(&mut thread_rng());
+
+        let sealed = original.seal("correct horse");
+        let decoded = SealedKey::decode(&sealed.encode().unwrap()).unwrap();
+        assert_eq!(decoded, sealed);
+
+        let recovered = decoded.open("correct horse").unwrap();
+        assert_eq!(recovered, original);
+        assert_eq!(decoded.open("battery staple"), Err(Error::WrongPassphrase));
+
+        let mut tampered = sealed.clone();
+        tampered.data[0] ^= 1;
+        assert_eq!(tampered.open("correct horse"), Err(Error::WrongPassphrase));
+        assert_ne!(original.seal("correct horse"), sealed);
+    }
+
     #[test]
     fn fingerprint_roundtrips_via_string() {
         let original = Fingerprint::random(&mut thread_rng());
@@ -366,4 +528,16 @@ mod tests {
 
         assert_eq!(original.0, recovered.0);
     }
+
+    #[test]
+    fn private_keys_can_be_zeroized() {
+        fn assert_zeroize_on_drop<T: ZeroizeOnDrop>() {}
+        assert_zeroize_on_drop::<PrivateKey>();
+        assert_zeroize_on_drop::<crate::crypto::vtmf::Vtmf>();
+
+        let mut sk = PrivateKey::random(&mut thread_rng());
+        assert_ne!(sk.exponent(), Scalar::zero());
+        sk.zeroize();
+        assert_eq!(sk.exponent(), Scalar::zero());
+    }
 }
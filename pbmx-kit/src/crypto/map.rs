@@ -1,17 +1,28 @@
 //! Mapping integers to/from the elliptic curve
 
-use crate::random::thread_rng;
-use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use crate::{
+    crypto::hash::{TranscriptAppend, TranscriptHash},
+    random::thread_rng,
+};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_POINT,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::Identity,
+};
+use digest::XofReader;
 use rand::Rng;
+use std::collections::{hash_map::Entry, HashMap};
 
 const START_BYTE: usize = 12;
 const END_BYTE: usize = START_BYTE + 8;
 
 /// Maps an integer to the curve
 ///
-/// Each 8-byte integer is mapped into a point whose compressed encoding has bytes [12..20] equal
-/// to the integer's bytes in little-endian order, and the other 24 bytes are random. This means
-/// each integer can be mapped into many different points.
+/// Each 8-byte integer is mapped into a point whose compressed encoding has
+/// bytes [12..20] equal to the integer's bytes in little-endian order, and the
+/// other 24 bytes are random. This means each integer can be mapped into many
+/// different points.
 pub fn to_curve(x: u64) -> RistrettoPoint {
     let mut rng = thread_rng();
     let mut buf = [0u8; 32];
@@ -25,30 +36,217 @@ pub fn to_curve(x: u64) -> RistrettoPoint {
     }
 }
 
+/// Maps an integer to the curve deterministically
+///
+/// Like [`to_curve`], bytes [12..20] of the point's compressed encoding hold
+/// the integer, so [`from_curve`] inverts this mapping; but the other bytes are
+/// derived from the integer instead of being random, so all parties map the
+/// same integer to the same point.
+pub fn to_curve_fixed(x: u64) -> RistrettoPoint {
+    let mut h = TranscriptHash::new(FIXED_TOKEN_DOMAIN);
+    // little-endian like the bytes embedded in the point, unlike transcribed
+    // integers; changing it would change every fixed token
+    h.append_message(b"token", &x.to_le_bytes());
+    let mut xof = h.into_xof();
+    let mut buf = [0u8; 32];
+    buf[START_BYTE..END_BYTE].copy_from_slice(&x.to_le_bytes());
+    loop {
+        xof.read(&mut buf[..START_BYTE]);
+        xof.read(&mut buf[END_BYTE..]);
+        if let Some(p) = CompressedRistretto::from_slice(&buf).decompress() {
+            break p;
+        }
+    }
+}
+
+const FIXED_TOKEN_DOMAIN: &[u8] = b"pbmx-fixed-token";
+
+/// Tests whether a point is the one [`to_curve_fixed`] maps its integer to
+pub fn is_fixed(point: &RistrettoPoint) -> bool {
+    from_curve(point)
+        .map(|x| to_curve_fixed(x) == *point)
+        .unwrap_or(false)
+}
+
 /// Maps a curve point to an integer
 ///
-/// Each curve point is mapped into an 8-byte integer whose bytes in little-endian order are bytes
-/// [12..20] of the point's compressed encoding. This means that many different points can be
-/// mapped into the same integer.
-pub fn from_curve(point: &RistrettoPoint) -> u64 {
+/// Each curve point is mapped into an 8-byte integer whose bytes in
+/// little-endian order are bytes [12..20] of the point's compressed encoding.
+/// This means that many different points can be mapped into the same integer.
+///
+/// Returns `None` for the identity, which [`to_curve`] never produces. Other
+/// points not produced by [`to_curve`] cannot be told apart from mapped ones
+/// and still yield some integer.
+pub fn from_curve(point: &RistrettoPoint) -> Option<u64> {
+    let bytes = point.compress().0;
+    if bytes == [0u8; 32] {
+        return None;
+    }
     let mut buf = [0u8; 8];
-    buf.copy_from_slice(&point.compress().0[START_BYTE..END_BYTE]);
-    u64::from_le_bytes(buf)
+    buf.copy_from_slice(&bytes[START_BYTE..END_BYTE]);
+    Some(u64::from_le_bytes(buf))
+}
+
+/// Maps a token id to the curve deterministically
+///
+/// Each id is hashed to a single, fixed point, so all parties map the same id
+/// to the same point. Unlike [`to_curve`], the id cannot be read back from the
+/// point, so the inverse mapping requires a [`TokenRegistry`].
+pub fn to_curve_id(id: u64) -> RistrettoPoint {
+    let mut h = TranscriptHash::new(TOKEN_ID_DOMAIN);
+    // little-endian like to_curve_fixed; changing it would change every id
+    h.append_message(b"id", &id.to_le_bytes());
+    let mut buf = [0u8; 64];
+    h.into_xof().read(&mut buf);
+    RistrettoPoint::from_uniform_bytes(&buf)
+}
+
+const TOKEN_ID_DOMAIN: &[u8] = b"pbmx-token-id";
+
+/// A registry of the token ids in use, for mapping the points produced by
+/// [`to_curve_id`] back into ids
+///
+/// Every id that may appear in the game must be registered before its tokens
+/// are revealed; points for unregistered ids cannot be mapped back.
+#[derive(Clone, Debug, Default)]
+pub struct TokenRegistry {
+    ids: HashMap<CompressedRistretto, u64>,
+}
+
+impl TokenRegistry {
+    /// Creates a new empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a token id and returns its point
+    ///
+    /// Two distinct ids hashing to the same point is computationally
+    /// infeasible, but such a collision is still detected: the id already
+    /// registered for that point is returned as an error and the registry is
+    /// left unchanged.
+    pub fn register(&mut self, id: u64) -> Result<RistrettoPoint, u64> {
+        let p = to_curve_id(id);
+        match self.ids.entry(p.compress()) {
+            Entry::Occupied(e) if *e.get() != id => Err(*e.get()),
+            Entry::Occupied(_) => Ok(p),
+            Entry::Vacant(e) => {
+                e.insert(id);
+                Ok(p)
+            }
+        }
+    }
+
+    /// Maps a curve point back into a registered token id
+    pub fn lookup(&self, point: &RistrettoPoint) -> Option<u64> {
+        self.ids.get(&point.compress()).cloned()
+    }
+
+    /// Gets the number of registered ids
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Tests whether the registry is empty
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+/// Maps an integer to the curve homomorphically
+///
+/// Each integer *x* is mapped into the point *xG*, so adding points adds the
+/// integers. Unlike [`to_curve`], this mapping can only be inverted by search,
+/// so it is only suitable for small values such as counts and tallies.
+pub fn to_curve_additive(x: u64) -> RistrettoPoint {
+    RISTRETTO_BASEPOINT_POINT * Scalar::from(x)
+}
+
+/// Maps a curve point to an integer no greater than `max`, as mapped by
+/// [`to_curve_additive`]
+pub fn from_curve_additive(point: &RistrettoPoint, max: u64) -> Option<u64> {
+    let mut p = RistrettoPoint::identity();
+    for x in 0..=max {
+        if p == *point {
+            return Some(x);
+        }
+        p += RISTRETTO_BASEPOINT_POINT;
+    }
+    None
 }
 
 #[cfg(test)]
 mod test {
-    use super::{from_curve, to_curve};
+    use super::{
+        from_curve, from_curve_additive, to_curve, to_curve_additive, to_curve_fixed, to_curve_id,
+        TokenRegistry,
+    };
+    use crate::random::thread_rng;
+    use curve25519_dalek::{
+        ristretto::{CompressedRistretto, RistrettoPoint},
+        traits::Identity,
+    };
+    use rand::Rng;
 
     #[test]
     fn curve_mapping_is_invertible() {
         for i in 0..32 {
             let p = to_curve(i);
-            assert_eq!(from_curve(&p), i);
+            assert_eq!(from_curve(&p), Some(i));
         }
         for i in (std::u64::MAX - 32)..std::u64::MAX {
             let p = to_curve(i);
-            assert_eq!(from_curve(&p), i);
+            assert_eq!(from_curve(&p), Some(i));
+        }
+    }
+
+    #[test]
+    fn fixed_curve_mapping_is_deterministic_and_invertible() {
+        for i in (0..32).chain((u64::MAX - 32)..u64::MAX) {
+            let p = to_curve_fixed(i);
+            assert_eq!(p, to_curve_fixed(i));
+            assert_eq!(from_curve(&p), Some(i));
+        }
+    }
+
+    #[test]
+    fn curve_unmapping_never_panics() {
+        let mut rng = thread_rng();
+        assert_eq!(from_curve(&RistrettoPoint::identity()), None);
+        for _ in 0..256 {
+            let p = RistrettoPoint::random(&mut rng);
+            assert!(from_curve(&p).is_some());
+        }
+        for _ in 0..256 {
+            let mut buf = [0u8; 32];
+            rng.fill(&mut buf);
+            if let Some(p) = CompressedRistretto(buf).decompress() {
+                assert!(from_curve(&p).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_token_ids_map_back_through_registry() {
+        let mut registry = TokenRegistry::new();
+        for &id in [3, 7, 19, u64::MAX].iter() {
+            assert_eq!(registry.register(id), Ok(to_curve_id(id)));
+        }
+        assert_eq!(registry.register(7), Ok(to_curve_id(7)));
+        assert_eq!(registry.len(), 4);
+
+        assert_eq!(to_curve_id(7), to_curve_id(7));
+        assert_eq!(registry.lookup(&to_curve_id(19)), Some(19));
+        assert_eq!(registry.lookup(&to_curve_id(8)), None);
+    }
+
+    #[test]
+    fn additive_curve_mapping_is_homomorphic() {
+        for i in 0..32 {
+            let p = to_curve_additive(i) + to_curve_additive(2 * i);
+            assert_eq!(from_curve_additive(&p, 96), Some(3 * i));
         }
+        let p = to_curve_additive(33);
+        assert_eq!(from_curve_additive(&p, 32), None);
     }
 }
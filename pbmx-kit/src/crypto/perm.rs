@@ -31,6 +31,24 @@ impl Permutation {
         Self(v)
     }
 
+    /// Creates a permutation close to the identity by applying `swaps` random
+    /// transpositions to it
+    ///
+    /// The result is *not* uniformly distributed and must not be used for
+    /// cryptographic shuffling; use [`Shuffles`] for that. This is meant for
+    /// building nearly-ordered decks, e.g. in tests or simulations.
+    pub fn near_identity<R: Rng + ?Sized>(n: usize, swaps: usize, rng: &mut R) -> Self {
+        let mut v: Vec<_> = (0..n).collect();
+        if n > 1 {
+            for _ in 0..swaps {
+                let i = rng.gen_range(0..n);
+                let j = rng.gen_range(0..n);
+                v.swap(i, j);
+            }
+        }
+        Self(v)
+    }
+
     /// Creates a permutation equivalent to applying this permutation after
     /// another
     pub fn after(&self, other: &Self) -> Self {
@@ -53,6 +71,20 @@ impl Permutation {
         Self(v)
     }
 
+    /// Tests whether this permutation leaves every element in place
+    pub fn is_identity(&self) -> bool {
+        self.iter().enumerate().all(|(i, &p)| i == p)
+    }
+
+    /// Gets the positions of the elements this permutation leaves in place
+    pub fn fixed_points(&self) -> Vec<usize> {
+        self.iter()
+            .enumerate()
+            .filter(|(i, &p)| *i == p)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
     /// Applies a permutation to a slice
     pub fn apply_to<T>(&self, slice: &mut [T]) {
         let mut placed = Vec::new();
@@ -126,6 +158,7 @@ impl Distribution<Permutation> for Shifts {
 #[cfg(test)]
 mod tests {
     use super::{Permutation, Shifts, Shuffles};
+    use crate::Error;
     use rand::{thread_rng, Rng};
     use std::convert::TryFrom;
 
@@ -138,6 +171,22 @@ mod tests {
         assert_eq!(p.0, expected);
     }
 
+    #[test]
+    fn permutation_fixed_points_are_found() {
+        assert!(Permutation::identity(5).is_identity());
+        assert_eq!(Permutation::identity(3).fixed_points(), vec![0, 1, 2]);
+        assert!(Permutation::identity(0).is_identity());
+
+        let p = Permutation::try_from(vec![0, 2, 1, 3]).unwrap();
+        assert!(!p.is_identity());
+        assert_eq!(p.fixed_points(), vec![0, 3]);
+
+        let p = Permutation::shift(4, 1);
+        assert!(!p.is_identity());
+        assert!(p.fixed_points().is_empty());
+        assert!(Permutation::shift(4, 0).is_identity());
+    }
+
     #[test]
     fn permutation_shifts_are_generated_correctly() {
         let mut expected = Vec::new();
@@ -175,8 +224,8 @@ mod tests {
 
         let r = Permutation::try_from(invalid1);
         assert!(r.is_err());
-        let r = Permutation::try_from(invalid2);
-        assert!(r.is_err());
+        let r = Permutation::try_from(invalid2).map_err(Error::from);
+        assert_eq!(r.err(), Some(Error::InvalidPermutation));
     }
 
     #[test]
@@ -190,6 +239,22 @@ mod tests {
         assert_eq!(p.0, expected);
     }
 
+    #[test]
+    fn permutation_near_identity_moves_few_elements() {
+        let mut rng = thread_rng();
+
+        let p = Permutation::near_identity(10, 0, &mut rng);
+        assert_eq!(p, Permutation::identity(10));
+
+        let p = Permutation::near_identity(52, 3, &mut rng);
+        assert!(Permutation::try_from(p.0.clone()).is_ok());
+        let moved = p.iter().enumerate().filter(|&(i, &x)| i != x).count();
+        assert!(moved <= 6);
+
+        let p = Permutation::near_identity(0, 5, &mut rng);
+        assert_eq!(p.len(), 0);
+    }
+
     #[test]
     fn permutation_inverse_is_correct() {
         let original = Permutation::try_from(vec![3, 2, 4, 6, 9, 1, 7, 5, 8, 0]).unwrap();
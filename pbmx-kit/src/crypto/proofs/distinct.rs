@@ -0,0 +1,194 @@
+//! Proof that a masked stack was blinded by a secret exponent
+//!
+//! Blinding multiplies both points of every token by the same secret scalar
+//! `z`, so the blinded stack masks `z` times each of the original tokens.
+//! When every party blinds a stack in turn, nobody knows the combined
+//! exponent, and once the blinded stack is unmasked its points are equal
+//! exactly when the original tokens are. This reveals whether a stack holds
+//! duplicate tokens without revealing any of them, and works on stacks
+//! nobody alone can unmask. Tokens are compared as points, so each value must
+//! have a single point, as with
+//! [`to_curve_fixed`](crate::crypto::map::to_curve_fixed).
+//!
+//! The prover publishes `zG` and proves it has the same discrete logarithm
+//! as a random linear combination of the blinded points over the source
+//! ones, with the coefficients drawn from the transcript. Both proving and
+//! verifying time grow linearly with the size of the stack, while the proof
+//! has a constant size.
+
+use super::{dlog_eq, fmt_summary, TranscriptProtocol};
+use crate::{
+    crypto::{
+        hash::{Transcribe, TranscriptAppend},
+        vtmf::Mask,
+    },
+    proto,
+    serde::{point_from_proto, point_to_proto, Proto},
+    Error, Result,
+};
+use curve25519_dalek::{
+    constants::RISTRETTO_BASEPOINT_TABLE,
+    ristretto::{RistrettoBasepointTable, RistrettoPoint},
+    scalar::Scalar,
+    traits::{IsIdentity, VartimeMultiscalarMul},
+};
+use merlin::Transcript;
+use std::fmt::{self, Display, Formatter};
+
+const G: &RistrettoBasepointTable = &RISTRETTO_BASEPOINT_TABLE;
+
+/// Non-interactive proof
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof {
+    key: RistrettoPoint,
+    proof: dlog_eq::Proof,
+}
+
+impl Proto for Proof {
+    type Message = proto::DistinctProof;
+
+    fn to_proto(&self) -> Result<proto::DistinctProof> {
+        Ok(proto::DistinctProof {
+            key: point_to_proto(&self.key)?,
+            proof: Some(self.proof.to_proto()?),
+        })
+    }
+
+    fn from_proto(m: &proto::DistinctProof) -> Result<Self> {
+        Ok(Proof {
+            key: point_from_proto(&m.key)?,
+            proof: dlog_eq::Proof::from_proto(m.proof.as_ref().ok_or(Error::Decoding)?)?,
+        })
+    }
+}
+
+impl Transcribe for Proof {
+    fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
+        b"distinct-proof".append_to_transcript(t, label);
+        self.key.append_to_transcript(t, b"key");
+        self.proof.append_to_transcript(t, b"proof");
+    }
+}
+
+impl Display for Proof {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_summary(self, "DistinctProof", f)
+    }
+}
+
+/// Public parameters
+#[derive(Copy, Clone)]
+pub struct Publics<'a> {
+    /// Source stack
+    pub e0: &'a [Mask],
+    /// Blinded stack
+    pub e1: &'a [Mask],
+}
+
+/// Secret parameters
+#[derive(Copy, Clone)]
+pub struct Secrets<'a> {
+    /// Blinding exponent
+    pub z: &'a Scalar,
+}
+
+impl Proof {
+    /// Generates a non-interactive zero-knowledge proof that a masked stack
+    /// was blinded by a secret exponent
+    pub fn create(transcript: &mut Transcript, publics: Publics, secrets: Secrets) -> Self {
+        let key = G * secrets.z;
+        let (g, a) = combine(transcript, publics, &key);
+
+        let proof = dlog_eq::Proof::create(
+            transcript,
+            dlog_eq::Publics {
+                a: &a,
+                b: &key,
+                g: &g,
+                h: &G.basepoint(),
+            },
+            dlog_eq::Secrets { x: secrets.z },
+        );
+        Self { key, proof }
+    }
+
+    /// Verifies a non-interactive zero-knowledge proof that a masked stack
+    /// was blinded by a secret exponent
+    pub fn verify(&self, transcript: &mut Transcript, publics: Publics) -> Result<()> {
+        if publics.e0.len() != publics.e1.len() || self.key.is_identity() {
+            return Err(Error::BadProof);
+        }
+        let (g, a) = combine(transcript, publics, &self.key);
+
+        self.proof.verify(transcript, dlog_eq::Publics {
+            a: &a,
+            b: &self.key,
+            g: &g,
+            h: &G.basepoint(),
+        })
+    }
+}
+
+fn combine(
+    transcript: &mut Transcript,
+    publics: Publics,
+    key: &RistrettoPoint,
+) -> (RistrettoPoint, RistrettoPoint) {
+    transcript.domain_sep(b"distinct");
+
+    transcript.commit(b"e0", publics.e0);
+    transcript.commit(b"e1", publics.e1);
+    transcript.commit(b"key", key);
+
+    let n = publics.e0.len();
+    let t: Vec<Scalar> = transcript.challenge_sized(b"t", 2 * n);
+    let points = |e: &[Mask]| e.iter().flat_map(|m| vec![m.0, m.1]).collect::<Vec<_>>();
+    let g = RistrettoPoint::vartime_multiscalar_mul(&t, points(publics.e0));
+    let a = RistrettoPoint::vartime_multiscalar_mul(&t, points(publics.e1));
+    (g, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Proof, Publics, Secrets};
+    use crate::{crypto::vtmf::Mask, Error};
+    use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
+    use merlin::Transcript;
+    use rand::thread_rng;
+
+    #[test]
+    fn prove_and_verify_agree() {
+        let mut rng = thread_rng();
+        let e0: Vec<_> = (0..8)
+            .map(|_| {
+                Mask(
+                    RistrettoPoint::random(&mut rng),
+                    RistrettoPoint::random(&mut rng),
+                )
+            })
+            .collect();
+        let z = Scalar::random(&mut rng);
+        let e1: Vec<_> = e0.iter().map(|m| Mask(m.0 * z, m.1 * z)).collect();
+
+        let publics = Publics { e0: &e0, e1: &e1 };
+        let secrets = Secrets { z: &z };
+
+        let proof = Proof::create(&mut Transcript::new(b"test"), publics, secrets);
+        let verified = proof.verify(&mut Transcript::new(b"test"), publics);
+        assert_eq!(verified, Ok(()));
+
+        let mut mixed = e1.clone();
+        mixed[3] = Mask(e0[3].0 * (z + Scalar::one()), e0[3].1 * (z + Scalar::one()));
+        let invalid = proof.verify(&mut Transcript::new(b"test"), Publics {
+            e0: &e0,
+            e1: &mixed,
+        });
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let invalid = proof.verify(&mut Transcript::new(b"test"), Publics {
+            e0: &e0,
+            e1: &e1[1..],
+        });
+        assert_eq!(invalid, Err(Error::BadProof));
+    }
+}
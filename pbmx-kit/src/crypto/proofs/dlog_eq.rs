@@ -3,7 +3,7 @@
 // [CS97] Jan Camenisch, Markus Stadler:
 //          'Proof Systems for General Statements about Discrete Logarithms',
 //          Technical Report, 1997.
-use super::{TranscriptProtocol, TranscriptRngProtocol};
+use super::{fmt_summary, TranscriptProtocol, TranscriptRngProtocol};
 use crate::{
     crypto::hash::{Transcribe, TranscriptAppend},
     proto,
@@ -13,6 +13,7 @@ use crate::{
 };
 use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
 use merlin::Transcript;
+use std::fmt::{self, Display, Formatter};
 
 /// Non-interactive proof
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -47,6 +48,12 @@ impl Transcribe for Proof {
     }
 }
 
+impl Display for Proof {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_summary(self, "MaskProof", f)
+    }
+}
+
 /// Public parameters
 #[derive(Copy, Clone)]
 pub struct Publics<'a> {
@@ -156,4 +163,25 @@ mod tests {
         let verified = proof.verify(&mut Transcript::new(b"test"), publics);
         assert_eq!(verified, Err(Error::BadProof));
     }
+    #[test]
+    fn proofs_display_a_short_summary() {
+        let mut rng = thread_rng();
+
+        let g = &RistrettoPoint::random(&mut rng);
+        let h = &RistrettoPoint::random(&mut rng);
+        let x = &Scalar::random(&mut rng);
+        let publics = Publics {
+            a: &(g * x),
+            b: &(h * x),
+            g,
+            h,
+        };
+        let proof = Proof::create(&mut Transcript::new(b"test"), publics, Secrets { x });
+
+        let summary = proof.to_string();
+        assert!(summary.starts_with("MaskProof["));
+        assert!(summary.ends_with("\u{2026}]"));
+        assert_eq!(summary.len(), "MaskProof[".len() + 6 + "\u{2026}]".len());
+        assert_eq!(summary, proof.clone().to_string());
+    }
 }
@@ -1,6 +1,6 @@
 //! Proof of entanglement of stacks
 
-use super::TranscriptProtocol;
+use super::{fmt_summary, TranscriptProtocol};
 use crate::{
     crypto::{
         hash::{Transcribe, TranscriptAppend},
@@ -14,7 +14,10 @@ use crate::{
 };
 use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
 use merlin::Transcript;
-use std::ops::{Add, Mul};
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::{Add, Mul},
+};
 
 /// Non-interactive proof
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -45,6 +48,12 @@ impl Transcribe for Proof {
     }
 }
 
+impl Display for Proof {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_summary(self, "EntanglementProof", f)
+    }
+}
+
 /// Public parameters
 #[derive(Copy, Clone)]
 pub struct Publics<'a> {
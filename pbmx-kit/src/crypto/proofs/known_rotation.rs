@@ -79,6 +79,15 @@ pub struct Secrets<'a> {
 }
 
 impl Proof {
+    /// Gets the trivial proof for an empty sequence
+    pub(super) fn empty() -> Self {
+        Self {
+            f: Vec::new(),
+            l: Vec::new(),
+            t: Vec::new(),
+        }
+    }
+
     /// Generates a non-interactive rotation of known content argument
     pub fn create(transcript: &mut Transcript, publics: Publics, secrets: Secrets) -> Self {
         transcript.domain_sep(b"known_rotation");
@@ -18,7 +18,7 @@ use crate::{
     },
     Error, Result,
 };
-use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
+use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar, traits::Identity};
 use merlin::Transcript;
 use std::iter;
 
@@ -96,6 +96,19 @@ pub struct Secrets<'a> {
 }
 
 impl Proof {
+    /// Gets the trivial proof for an empty sequence
+    pub(super) fn empty() -> Self {
+        Self {
+            cd: RistrettoPoint::identity(),
+            cdd: RistrettoPoint::identity(),
+            cda: RistrettoPoint::identity(),
+            f: Vec::new(),
+            z: Scalar::zero(),
+            fd: Vec::new(),
+            zd: Scalar::zero(),
+        }
+    }
+
     /// Generates a non-interactive shuffle of known content argument
     pub fn create(transcript: &mut Transcript, publics: Publics, secrets: Secrets) -> Self {
         transcript.domain_sep(b"known_shuffle");
@@ -209,9 +222,10 @@ impl Proof {
         publics.com.open(&ceca, &self.fd, &self.zd)?;
 
         let ex = e * x;
+        let e_inv = e.invert();
         let mut ff = self.f[0] - ex;
         for i in 1..n {
-            ff = (ff * (self.f[i] - ex) + self.fd[i - 1]) * e.invert();
+            ff = (ff * (self.f[i] - ex) + self.fd[i - 1]) * e_inv;
         }
         let prod = publics.m.iter().map(|m| m - x).product::<Scalar>();
         if ff == e * prod {
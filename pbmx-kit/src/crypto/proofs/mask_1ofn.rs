@@ -0,0 +1,208 @@
+//! Zero-knowledge proof that a mask hides one of a known set of points
+
+// [CDS94] Ronald Cramer, Ivan Damgård, Berry Schoenmakers:
+//          'Proofs of Partial Knowledge and Simplified Design of Witness Hiding
+//          Protocols', CRYPTO 1994, LNCS 839, pp. 174--187, 1994.
+use super::{fmt_summary, random_scalars, TranscriptProtocol, TranscriptRngProtocol};
+use crate::{
+    crypto::{
+        hash::{Transcribe, TranscriptAppend},
+        vtmf::Mask,
+    },
+    proto,
+    random::thread_rng,
+    serde::{scalars_from_proto, scalars_to_proto, Proto},
+    Error, Result,
+};
+use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
+use merlin::Transcript;
+use std::fmt::{self, Display, Formatter};
+
+/// Non-interactive proof
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof {
+    c: Vec<Scalar>,
+    r: Vec<Scalar>,
+}
+
+impl Proto for Proof {
+    type Message = proto::Mask1OfNProof;
+
+    fn to_proto(&self) -> Result<proto::Mask1OfNProof> {
+        Ok(proto::Mask1OfNProof {
+            c: scalars_to_proto(&self.c)?,
+            r: scalars_to_proto(&self.r)?,
+        })
+    }
+
+    fn from_proto(m: &proto::Mask1OfNProof) -> Result<Self> {
+        Ok(Proof {
+            c: scalars_from_proto(&m.c)?,
+            r: scalars_from_proto(&m.r)?,
+        })
+    }
+}
+
+impl Transcribe for Proof {
+    fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
+        b"mask-1ofn-proof".append_to_transcript(t, label);
+        self.c.append_to_transcript(t, b"c");
+        self.r.append_to_transcript(t, b"r");
+    }
+}
+
+impl Display for Proof {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_summary(self, "PrivateMaskProof", f)
+    }
+}
+
+/// Public parameters
+#[derive(Copy, Clone)]
+pub struct Publics<'a> {
+    /// Masking base point
+    pub g: &'a RistrettoPoint,
+    /// Public key
+    pub h: &'a RistrettoPoint,
+    /// Mask
+    pub c: &'a Mask,
+    /// Candidate points
+    pub m: &'a [RistrettoPoint],
+}
+
+/// Secret parameters
+#[derive(Copy, Clone)]
+pub struct Secrets<'a> {
+    /// Index of the masked point among the candidates
+    pub index: usize,
+    /// Masking factor
+    pub r: &'a Scalar,
+}
+
+impl Proof {
+    /// Generates a non-interactive zero-knowledge proof that a mask hides one
+    /// of a set of points
+    pub fn create(transcript: &mut Transcript, publics: Publics, secrets: Secrets) -> Self {
+        let n = publics.m.len();
+        assert!(secrets.index < n);
+
+        transcript.domain_sep(b"mask_1ofn");
+
+        transcript.commit(b"g", publics.g);
+        transcript.commit(b"h", publics.h);
+        transcript.commit(b"c", publics.c);
+        transcript.commit(b"m", publics.m);
+
+        let mut rng = transcript
+            .build_rng()
+            .rekey(b"r", secrets.r)
+            .finalize(&mut thread_rng());
+
+        let w = Scalar::random(&mut rng);
+        let mut c = random_scalars(n, &mut rng);
+        let mut r = random_scalars(n, &mut rng);
+
+        let (t1, t2): (Vec<_>, Vec<_>) = publics
+            .m
+            .iter()
+            .enumerate()
+            .map(|(i, m)| {
+                if i == secrets.index {
+                    (publics.g * w, publics.h * w)
+                } else {
+                    (
+                        publics.c.0 * c[i] + publics.g * r[i],
+                        (publics.c.1 - m) * c[i] + publics.h * r[i],
+                    )
+                }
+            })
+            .unzip();
+
+        transcript.commit(b"t1", &t1);
+        transcript.commit(b"t2", &t2);
+
+        let cc: Scalar = transcript.challenge(b"c");
+        c[secrets.index] = Scalar::zero();
+        c[secrets.index] = cc - c.iter().sum::<Scalar>();
+        r[secrets.index] = w - c[secrets.index] * secrets.r;
+
+        Self { c, r }
+    }
+
+    /// Verifies a non-interactive zero-knowledge proof that a mask hides one
+    /// of a set of points
+    pub fn verify(&self, transcript: &mut Transcript, publics: Publics) -> Result<()> {
+        let n = publics.m.len();
+        if self.c.len() != n || self.r.len() != n {
+            return Err(Error::BadProof);
+        }
+
+        transcript.domain_sep(b"mask_1ofn");
+
+        transcript.commit(b"g", publics.g);
+        transcript.commit(b"h", publics.h);
+        transcript.commit(b"c", publics.c);
+        transcript.commit(b"m", publics.m);
+
+        let (t1, t2): (Vec<_>, Vec<_>) = publics
+            .m
+            .iter()
+            .zip(self.c.iter().zip(self.r.iter()))
+            .map(|(m, (c, r))| {
+                (
+                    publics.c.0 * c + publics.g * r,
+                    (publics.c.1 - m) * c + publics.h * r,
+                )
+            })
+            .unzip();
+
+        transcript.commit(b"t1", &t1);
+        transcript.commit(b"t2", &t2);
+
+        let c: Scalar = transcript.challenge(b"c");
+
+        if c == self.c.iter().sum() {
+            Ok(())
+        } else {
+            Err(Error::BadProof)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Proof, Publics, Secrets};
+    use crate::{crypto::vtmf::Mask, Error};
+    use curve25519_dalek::{ristretto::RistrettoPoint, scalar::Scalar};
+    use merlin::Transcript;
+    use rand::{thread_rng, Rng};
+    use std::iter;
+
+    #[test]
+    fn prove_and_verify_agree() {
+        let mut rng = thread_rng();
+
+        let g = &RistrettoPoint::random(&mut rng);
+        let h = &RistrettoPoint::random(&mut rng);
+        let m: Vec<_> = iter::repeat_with(|| RistrettoPoint::random(&mut rng))
+            .take(8)
+            .collect();
+        let index = rng.gen_range(0..m.len());
+        let r = &Scalar::random(&mut rng);
+        let c = &Mask(g * r, h * r + m[index]);
+
+        let publics = Publics { g, h, c, m: &m };
+        let secrets = Secrets { index, r };
+
+        let proof = Proof::create(&mut Transcript::new(b"test"), publics, secrets);
+
+        let verified = proof.verify(&mut Transcript::new(b"test"), publics);
+        assert_eq!(verified, Ok(()));
+
+        // break the proof
+        let index = (index + 1) % m.len();
+        let proof = Proof::create(&mut Transcript::new(b"test"), publics, Secrets { index, r });
+        let verified = proof.verify(&mut Transcript::new(b"test"), publics);
+        assert_eq!(verified, Err(Error::BadProof));
+    }
+}
@@ -2,20 +2,26 @@
 
 #![allow(clippy::many_single_char_names)]
 
+pub mod distinct;
 pub mod dlog_eq;
 pub mod entanglement;
 mod known_rotation;
 mod known_shuffle;
+pub mod mask_1ofn;
 pub mod secret_rotation;
 pub mod secret_shuffle;
 
 use crate::crypto::{
     hash::{Challenge, Transcribe, TranscriptAppend},
+    keys::Fingerprint,
 };
 use curve25519_dalek::scalar::Scalar;
 use merlin::{Transcript, TranscriptRngBuilder};
 use rand::{CryptoRng, Rng};
-use std::iter;
+use std::{
+    fmt::{self, Formatter},
+    iter,
+};
 
 trait TranscriptProtocol {
     fn domain_sep(&mut self, domain: &'static [u8]);
@@ -74,3 +80,40 @@ impl TranscriptAppend for AppendableTranscriptRngBuilder {
 fn random_scalars<R: Rng + CryptoRng>(n: usize, rng: &mut R) -> Vec<Scalar> {
     iter::repeat_with(|| Scalar::random(rng)).take(n).collect()
 }
+
+fn fmt_summary<P: Transcribe>(proof: &P, name: &str, f: &mut Formatter) -> fmt::Result {
+    let fp = Fingerprint::of(proof, b"pbmx-proof-summary");
+    write!(f, "{}[{:6}\u{2026}]", name, fp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TranscriptProtocol, TranscriptRngProtocol};
+    use merlin::Transcript;
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    #[test]
+    fn challenges_and_nonces_encode_indices_alike() {
+        let mut committed = Transcript::new(b"test");
+        committed.commit(b"n", &7usize);
+        let mut raw = Transcript::new(b"test");
+        raw.append_message(b"n", &7u64.to_be_bytes());
+
+        let mut c1 = [0; 32];
+        committed.challenge_bytes(b"c", &mut c1);
+        let mut c2 = [0; 32];
+        raw.challenge_bytes(b"c", &mut c2);
+        assert_eq!(c1, c2);
+
+        let t = Transcript::new(b"test");
+        let mut rekeyed = t
+            .build_rng()
+            .rekey(b"n", &7usize)
+            .finalize(&mut StdRng::seed_from_u64(0));
+        let mut raw = t
+            .build_rng()
+            .rekey_with_witness_bytes(b"n", &7u64.to_be_bytes())
+            .finalize(&mut StdRng::seed_from_u64(0));
+        assert_eq!(rekeyed.next_u64(), raw.next_u64());
+    }
+}
@@ -5,7 +5,7 @@
 //              Public Key Cryptography 2009, LNCS 5443, pp. 393--410, Springer
 // 2009.
 
-use super::{random_scalars, TranscriptProtocol, TranscriptRngProtocol};
+use super::{fmt_summary, random_scalars, TranscriptProtocol, TranscriptRngProtocol};
 use crate::{
     crypto::{
         commit::Pedersen,
@@ -28,6 +28,7 @@ use curve25519_dalek::{
     scalar::Scalar,
 };
 use merlin::Transcript;
+use std::fmt::{self, Display, Formatter};
 
 const G: &RistrettoBasepointTable = &RISTRETTO_BASEPOINT_TABLE;
 
@@ -92,6 +93,12 @@ impl Transcribe for Proof {
     }
 }
 
+impl Display for Proof {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_summary(self, "ShiftProof", f)
+    }
+}
+
 /// Public parameters
 #[derive(Copy, Clone)]
 pub struct Publics<'a> {
@@ -113,6 +120,21 @@ pub struct Secrets<'a> {
 }
 
 impl Proof {
+    /// Gets the trivial proof for shifting an empty stack
+    fn empty() -> Self {
+        Self {
+            rkc: known_rotation::Proof::empty(),
+            h: Vec::new(),
+            z: Vec::new(),
+            v: Scalar::zero(),
+            f: Vec::new(),
+            ff: Vec::new(),
+            tau: Vec::new(),
+            rho: Vec::new(),
+            mu: Vec::new(),
+        }
+    }
+
     /// Generates a non-interactive zero-knowledge proof of a shuffle of known
     /// content
     pub fn create(transcript: &mut Transcript, publics: Publics, secrets: Secrets) -> Self {
@@ -132,6 +154,9 @@ impl Proof {
         };
 
         let n = publics.e0.len();
+        if n == 0 {
+            return Self::empty();
+        }
         let gh = Mask(G.basepoint(), *publics.h);
 
         let a: Vec<Scalar> = transcript.challenge_sized(b"a", n);
@@ -232,6 +257,13 @@ impl Proof {
         let com: Pedersen = transcript.challenge_sized(b"com", 1);
 
         let n = publics.e0.len();
+        if n == 0 {
+            return if publics.e1.is_empty() && *self == Self::empty() {
+                Ok(())
+            } else {
+                Err(Error::BadProof)
+            };
+        }
         let gh = Mask(G.basepoint(), *publics.h);
 
         let a: Vec<Scalar> = transcript.challenge_sized(b"a", n);
@@ -3,7 +3,7 @@
 // [Gr05] Jens Groth: 'A Verifiable Secret Shuffle of Homomorphic Encryptions',
 //          Cryptology ePrint Archive, Report 2005/246, 2005.
 
-use super::{TranscriptProtocol, TranscriptRngProtocol};
+use super::{fmt_summary, TranscriptProtocol, TranscriptRngProtocol};
 use crate::{
     crypto::{
         commit::Pedersen,
@@ -24,9 +24,13 @@ use curve25519_dalek::{
     constants::RISTRETTO_BASEPOINT_TABLE,
     ristretto::{RistrettoBasepointTable, RistrettoPoint},
     scalar::Scalar,
+    traits::{Identity, IsIdentity, VartimeMultiscalarMul},
 };
 use merlin::Transcript;
-use std::iter;
+use std::{
+    fmt::{self, Display, Formatter},
+    iter,
+};
 
 const G: &RistrettoBasepointTable = &RISTRETTO_BASEPOINT_TABLE;
 
@@ -79,6 +83,12 @@ impl Transcribe for Proof {
     }
 }
 
+impl Display for Proof {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_summary(self, "ShuffleProof", f)
+    }
+}
+
 /// Public parameters
 #[derive(Copy, Clone)]
 pub struct Publics<'a> {
@@ -100,6 +110,18 @@ pub struct Secrets<'a> {
 }
 
 impl Proof {
+    /// Gets the trivial proof for shuffling an empty stack
+    fn empty() -> Self {
+        Self {
+            skc: known_shuffle::Proof::empty(),
+            c: RistrettoPoint::identity(),
+            cd: RistrettoPoint::identity(),
+            ed: Mask::identity(),
+            f: Vec::new(),
+            z: Scalar::zero(),
+        }
+    }
+
     /// Generates a non-interactive zero-knowledge proof of a secret shuffle
     pub fn create(transcript: &mut Transcript, publics: Publics, secrets: Secrets) -> Self {
         transcript.domain_sep(b"secret_shuffle");
@@ -109,6 +131,9 @@ impl Proof {
         transcript.commit(b"e1", publics.e1);
 
         let n = publics.e0.len();
+        if n == 0 {
+            return Self::empty();
+        }
         let com: Pedersen = transcript.challenge_sized(b"com", n);
 
         let rekey_rng = |t: &Transcript| {
@@ -197,13 +222,39 @@ impl Proof {
 
     /// Verifies a non-interactive zero-knowledge proof of a secret shuffle
     pub fn verify(&self, transcript: &mut Transcript, publics: Publics) -> Result<()> {
+        self.verify_with(transcript, publics, publics.e0, publics.e1)
+    }
+
+    /// Verifies a proof, committing to values that transcribe the same as
+    /// the original and shuffled stacks
+    pub(crate) fn verify_with<E0, E1>(
+        &self,
+        transcript: &mut Transcript,
+        publics: Publics,
+        e0: &E0,
+        e1: &E1,
+    ) -> Result<()>
+    where
+        E0: Transcribe + ?Sized,
+        E1: Transcribe + ?Sized,
+    {
         transcript.domain_sep(b"secret_shuffle");
 
         transcript.commit(b"h", publics.h);
-        transcript.commit(b"e0", publics.e0);
-        transcript.commit(b"e1", publics.e1);
+        transcript.commit(b"e0", e0);
+        transcript.commit(b"e1", e1);
 
         let n = publics.e0.len();
+        if n == 0 {
+            return if publics.e1.is_empty() && *self == Self::empty() {
+                Ok(())
+            } else {
+                Err(Error::BadProof)
+            };
+        }
+        if publics.e1.len() != n || self.f.len() != n {
+            return Err(Error::BadProof);
+        }
         let com: Pedersen = transcript.challenge_sized(b"com", n);
 
         let gh = Mask(G.basepoint(), *publics.h);
@@ -230,24 +281,24 @@ impl Proof {
             m: &m,
         })?;
 
-        let efed = self.ed
-            + publics
-                .e1
-                .iter()
-                .zip(self.f.iter())
-                .map(|(e, f)| e * f)
-                .sum::<Mask>();
-        let etfd = efed
-            + publics
-                .e0
-                .iter()
-                .zip(t.iter())
-                .map(|(e, t)| e * -t)
-                .sum::<Mask>();
-
-        let ez = gh * self.z;
+        // checks ed + e1*f - e0*t - (g, h)*z is the identity, one mask
+        // component at a time
+        let scalars = || {
+            iter::once(Scalar::one())
+                .chain(self.f.iter().cloned())
+                .chain(t.iter().map(|t| -t))
+                .chain(iter::once(-self.z))
+        };
+        let points = |component: fn(&Mask) -> RistrettoPoint| {
+            iter::once(component(&self.ed))
+                .chain(publics.e1.iter().map(component))
+                .chain(publics.e0.iter().map(component))
+                .chain(iter::once(component(&gh)))
+        };
+        let c0 = RistrettoPoint::vartime_multiscalar_mul(scalars(), points(|m| m.0));
+        let c1 = RistrettoPoint::vartime_multiscalar_mul(scalars(), points(|m| m.1));
 
-        if etfd == ez {
+        if c0.is_identity() && c1.is_identity() {
             Ok(())
         } else {
             Err(Error::BadProof)
@@ -309,4 +360,65 @@ mod tests {
         let verified = proof.verify(&mut Transcript::new(b"test"), publics);
         assert_eq!(verified, Err(Error::BadProof));
     }
+
+    #[test]
+    fn verify_rejects_tampered_masks() {
+        let mut rng = thread_rng();
+
+        let h = &RistrettoPoint::random(&mut rng);
+        let gh = Mask(G.basepoint(), *h);
+
+        let e0: Vec<_> = random_scalars(8, &mut rng)
+            .iter()
+            .map(|m| gh * Scalar::random(&mut rng) + Mask::open(G * m))
+            .collect();
+        let (mut e1, mut r): (Vec<_>, Vec<_>) = e0
+            .iter()
+            .map(|e| {
+                let r = Scalar::random(&mut rng);
+                (gh * r + e, r)
+            })
+            .unzip();
+        let pi = &rng.sample(Shuffles(8));
+        pi.apply_to(&mut e1);
+        pi.apply_to(&mut r);
+
+        let publics = Publics {
+            h,
+            e0: &e0,
+            e1: &e1,
+        };
+        let proof = Proof::create(&mut Transcript::new(b"test"), publics, Secrets {
+            pi,
+            r: &r,
+        });
+
+        let mut tampered = e1.clone();
+        tampered[3].1 += G.basepoint();
+        let publics = Publics {
+            h,
+            e0: &e0,
+            e1: &tampered,
+        };
+        let verified = proof.verify(&mut Transcript::new(b"test"), publics);
+        assert_eq!(verified, Err(Error::BadProof));
+
+        let publics = Publics {
+            h,
+            e0: &e0,
+            e1: &e1[1..],
+        };
+        let verified = proof.verify(&mut Transcript::new(b"test"), publics);
+        assert_eq!(verified, Err(Error::BadProof));
+
+        let mut short = proof.clone();
+        short.f.pop();
+        let publics = Publics {
+            h,
+            e0: &e0,
+            e1: &e1,
+        };
+        let verified = short.verify(&mut Transcript::new(b"test"), publics);
+        assert_eq!(verified, Err(Error::BadProof));
+    }
 }
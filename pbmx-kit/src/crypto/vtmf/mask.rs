@@ -53,6 +53,17 @@ impl Mask {
     pub fn is_open(&self) -> bool {
         self.0.is_identity()
     }
+
+    /// Adds up several masks
+    ///
+    /// The result masks the sum of the masked points. Only points obtained
+    /// with [`map::to_curve_additive`](crate::crypto::map::to_curve_additive)
+    /// add up to a meaningful value; tokens mapped with
+    /// [`map::to_curve`](crate::crypto::map::to_curve) do not, and their
+    /// individual values cannot be recovered from the sum.
+    pub fn sum<I: IntoIterator<Item = Mask>>(it: I) -> Mask {
+        it.into_iter().fold(Mask::identity(), |acc, m| acc + m)
+    }
 }
 
 impl Identity for Mask {
@@ -5,10 +5,11 @@
 //          Cryptography and Coding 2003, LNCS 2898, pp. 370--383, 2003
 use crate::{
     crypto::{
-        hash::{Transcribe, TranscriptAppend, TranscriptHash},
+        hash::{Challenge, HashAlgorithm, Transcribe, TranscriptAppend, TranscriptHash},
         keys::{Fingerprint, PrivateKey, PublicKey},
+        map,
         perm::Permutation,
-        proofs::{dlog_eq, entanglement, secret_rotation, secret_shuffle},
+        proofs::{distinct, dlog_eq, entanglement, mask_1ofn, secret_rotation, secret_shuffle},
     },
     proto,
     random::thread_rng,
@@ -19,14 +20,21 @@ use curve25519_dalek::{
     constants::RISTRETTO_BASEPOINT_TABLE,
     ristretto::{RistrettoBasepointTable, RistrettoPoint},
     scalar::Scalar,
+    traits::Identity,
 };
 use digest::XofReader;
 use merlin::Transcript;
 use rand::{CryptoRng, Rng};
-use std::{collections::HashMap, iter};
+use std::{
+    collections::HashMap,
+    iter,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use zeroize::ZeroizeOnDrop;
 
 pub use crate::crypto::proofs::{
-    dlog_eq::Proof as MaskProof, entanglement::Proof as EntanglementProof,
+    distinct::Proof as DistinctProof, dlog_eq::Proof as MaskProof,
+    entanglement::Proof as EntanglementProof, mask_1ofn::Proof as PrivateMaskProof,
     secret_rotation::Proof as ShiftProof, secret_shuffle::Proof as ShuffleProof,
 };
 
@@ -34,15 +42,74 @@ mod mask;
 pub use mask::*;
 mod stack;
 pub use stack::*;
+mod paired;
+pub use paired::*;
 
 const G: &RistrettoBasepointTable = &RISTRETTO_BASEPOINT_TABLE;
 
+fn transcript(domain: &[u8; 32], label: &'static [u8]) -> Transcript {
+    let mut t = Transcript::new(label);
+    if *domain != [0; 32] {
+        t.append_message(b"domain", domain);
+    }
+    t
+}
+
+fn greater_pairs(max: u64) -> impl Iterator<Item = (u64, u64)> {
+    (1..max).flat_map(|x| (0..x).map(move |y| (x, y)))
+}
+
+// Combines two private views a = (a0, sk·a0 + A) and b = (b0, sk·b0 + B)
+// with a challenge t into a single mask (sk·G, sk·(a0 + t·b0) + A + t·B) of
+// one of the pairs of greater fixed tokens, masked with the private key.
+fn greater_publics(
+    transcript: &mut Transcript,
+    a: &Mask,
+    b: &Mask,
+    pk: &PublicKey,
+    max: u64,
+) -> (RistrettoPoint, Mask, Vec<RistrettoPoint>) {
+    a.append_to_transcript(transcript, b"a");
+    b.append_to_transcript(transcript, b"b");
+    max.append_to_transcript(transcript, b"max");
+    let t = Scalar::read_from_transcript(transcript, b"t");
+
+    let h = a.0 + b.0 * t;
+    let c = Mask(pk.point(), a.1 + b.1 * t);
+    let p = greater_pairs(max)
+        .map(|(x, y)| map::to_curve_fixed(x) + map::to_curve_fixed(y) * t)
+        .collect();
+    (h, c, p)
+}
+
+fn field_tokens(field_mask: u64, field_value: u64, max: u64) -> impl Iterator<Item = u64> {
+    (0..max).filter(move |t| t & field_mask == field_value)
+}
+
 /// A verifiable *k*-out-of-*k* threshold masking function
+///
+/// The private key is wiped from memory when the VTMF is dropped.
 #[derive(Debug)]
 pub struct Vtmf {
     sk: PrivateKey,
     pk: PublicKey,
     pki: HashMap<Fingerprint, PublicKey>,
+    domain: [u8; 32],
+    hash: HashAlgorithm,
+    masked: AtomicBool,
+}
+
+impl ZeroizeOnDrop for Vtmf {}
+
+/// The outcome of adding a public key to a VTMF
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KeyAdded {
+    /// The key was already known
+    Known,
+    /// The key was new
+    New,
+    /// The key was new, but added after masking had already happened
+    Late,
 }
 
 /// One party's share of a secret
@@ -76,15 +143,110 @@ pub type SecretShareProof = MaskProof;
 impl Vtmf {
     /// Creates a new VTMF with the given private key
     pub fn new(sk: PrivateKey) -> Self {
+        Self::with_domain(sk, [0; 32])
+    }
+
+    /// Creates a new VTMF with the given private key, whose proofs only
+    /// verify under the given domain (e.g. a game's genesis block id)
+    pub fn with_domain(sk: PrivateKey, domain: [u8; 32]) -> Self {
+        Self::with_hash_algorithm(sk, domain, HashAlgorithm::default())
+    }
+
+    /// Creates a new VTMF with the given private key and domain, whose
+    /// fingerprints and random generators use the given hash function
+    pub fn with_hash_algorithm(sk: PrivateKey, domain: [u8; 32], hash: HashAlgorithm) -> Self {
         let pk = sk.public_key();
         Self {
             sk,
             pk: pk.clone(),
-            pki: vec![(pk.fingerprint(), pk)].into_iter().collect(),
+            pki: vec![(pk.fingerprint_with(hash), pk)].into_iter().collect(),
+            domain,
+            hash,
+            masked: AtomicBool::new(false),
         }
     }
 
+    /// Creates a new VTMF with the given private key, whose owner only
+    /// becomes a party once its public key is added
+    ///
+    /// This allows verifying masks created by other parties before this one
+    /// joined.
+    pub fn unjoined(sk: PrivateKey) -> Self {
+        Self {
+            sk,
+            pk: PublicKey::identity(),
+            pki: HashMap::new(),
+            domain: [0; 32],
+            hash: HashAlgorithm::default(),
+            masked: AtomicBool::new(false),
+        }
+    }
+
+    /// Creates a group of VTMFs with random keys that all know each other's
+    /// public keys
+    ///
+    /// This is a shortcut for tests with several parties masking and
+    /// unmasking together. Outside this crate it needs the `test-utils`
+    /// feature.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn test_group<R: Rng + CryptoRng>(n: usize, rng: &mut R) -> Vec<Vtmf> {
+        let sks: Vec<_> = (0..n).map(|_| PrivateKey::random(rng)).collect();
+        let pks: Vec<_> = sks.iter().map(PrivateKey::public_key).collect();
+        sks.into_iter()
+            .map(|sk| {
+                let mut vtmf = Vtmf::new(sk);
+                for pk in pks.iter() {
+                    vtmf.add_key(pk.clone());
+                }
+                vtmf
+            })
+            .collect()
+    }
+
+    /// Gets the domain this VTMF's proofs are bound to
+    pub fn domain(&self) -> &[u8; 32] {
+        &self.domain
+    }
+
+    fn transcript(&self, label: &'static [u8]) -> Transcript {
+        transcript(&self.domain, label)
+    }
+
+    /// Gets the hash function for this VTMF's fingerprints and random
+    /// generators
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash
+    }
+
+    /// Gets this party's fingerprint under this VTMF's hash function
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.sk.fingerprint_with(self.hash)
+    }
+
+    /// Gets a key's fingerprint under this VTMF's hash function
+    ///
+    /// Parties are identified by these fingerprints, and blocks in a game
+    /// are signed with them.
+    pub fn fingerprint_of(&self, pk: &PublicKey) -> Fingerprint {
+        pk.fingerprint_with(self.hash)
+    }
+
+    pub(crate) fn set_domain(&mut self, domain: [u8; 32]) {
+        self.domain = domain;
+    }
+
+    pub(crate) fn set_hash_algorithm(&mut self, hash: HashAlgorithm) {
+        self.hash = hash;
+        self.pki = self
+            .pki
+            .drain()
+            .map(|(_, pk)| (pk.fingerprint_with(hash), pk))
+            .collect();
+    }
+
     /// Gets the private key
+    ///
+    /// The returned copy is wiped from memory when dropped.
     pub fn private_key(&self) -> PrivateKey {
         self.sk.clone()
     }
@@ -100,13 +262,33 @@ impl Vtmf {
     }
 
     /// Add a public key to the VTMF
-    pub fn add_key(&mut self, pk: PublicKey) {
-        let fp = pk.fingerprint();
+    ///
+    /// Adding a key again leaves the shared key unchanged. A new key is
+    /// always added, but if any masking has already happened the join is
+    /// [`KeyAdded::Late`], since masks created before the new party joined
+    /// cannot be unmasked by the full set of parties.
+    pub fn add_key(&mut self, pk: PublicKey) -> KeyAdded {
+        let fp = self.fingerprint_of(&pk);
         if self.pki.contains_key(&fp) {
-            return;
+            return KeyAdded::Known;
         }
         self.pk.combine(&pk);
         self.pki.insert(fp, pk);
+        if self.has_masked() {
+            KeyAdded::Late
+        } else {
+            KeyAdded::New
+        }
+    }
+
+    /// Tests whether any masking has happened under this VTMF
+    pub fn has_masked(&self) -> bool {
+        self.masked.load(Ordering::Relaxed)
+    }
+
+    /// Records that masking has happened under this VTMF
+    pub(crate) fn mark_masked(&self) {
+        self.masked.store(true, Ordering::Relaxed);
     }
 }
 
@@ -130,13 +312,14 @@ impl Vtmf {
 impl Vtmf {
     /// Applies the verifiable masking protocol
     pub fn mask(&self, p: &RistrettoPoint) -> (Mask, Scalar, MaskProof) {
+        self.mark_masked();
         let h = self.pk.point();
         let r = Scalar::random(&mut thread_rng());
         let c0 = G * &r;
         let hr = h * r;
         let c1 = hr + p;
         let proof = MaskProof::create(
-            &mut Transcript::new(b"mask"),
+            &mut self.transcript(b"mask"),
             dlog_eq::Publics {
                 a: &c0,
                 b: &hr,
@@ -150,22 +333,278 @@ impl Vtmf {
 
     /// Verifies the application of the masking protocol
     pub fn verify_mask(&self, p: &RistrettoPoint, c: &Mask, proof: &MaskProof) -> Result<()> {
-        proof.verify(&mut Transcript::new(b"mask"), dlog_eq::Publics {
+        proof.verify(&mut self.transcript(b"mask"), dlog_eq::Publics {
+            a: &c.0,
+            b: &(c.1 - p),
+            g: &G.basepoint(),
+            h: &self.pk.point(),
+        })
+    }
+
+    /// Applies the verifiable masking protocol with another generator
+    ///
+    /// The mask is `(r·base, p + r·H)` instead of `(r·G, p + r·H)`, and the
+    /// proof commits to `base`, so it only verifies with the same generator.
+    /// Such masks are meant for custom proofs: the standard unmasking methods
+    /// assume `G` and do not open them.
+    pub fn mask_with_base(
+        &self,
+        p: &RistrettoPoint,
+        base: &RistrettoPoint,
+    ) -> (Mask, Scalar, MaskProof) {
+        self.mark_masked();
+        let h = self.pk.point();
+        let r = Scalar::random(&mut thread_rng());
+        let c0 = base * r;
+        let hr = h * r;
+        let c1 = hr + p;
+        let proof = MaskProof::create(
+            &mut self.base_transcript(base),
+            dlog_eq::Publics {
+                a: &c0,
+                b: &hr,
+                g: base,
+                h: &h,
+            },
+            dlog_eq::Secrets { x: &r },
+        );
+        (Mask(c0, c1), r, proof)
+    }
+
+    /// Verifies the application of the masking protocol with another
+    /// generator
+    pub fn verify_mask_with_base(
+        &self,
+        p: &RistrettoPoint,
+        base: &RistrettoPoint,
+        c: &Mask,
+        proof: &MaskProof,
+    ) -> Result<()> {
+        proof.verify(&mut self.base_transcript(base), dlog_eq::Publics {
             a: &c.0,
             b: &(c.1 - p),
+            g: base,
+            h: &self.pk.point(),
+        })
+    }
+
+    fn base_transcript(&self, base: &RistrettoPoint) -> Transcript {
+        let mut t = self.transcript(b"mask_with_base");
+        base.append_to_transcript(&mut t, b"base");
+        t
+    }
+
+    /// Applies the verifiable masking protocol to one of a set of points,
+    /// without revealing which
+    pub fn mask_private(
+        &self,
+        p: &[RistrettoPoint],
+        idx: usize,
+    ) -> (Mask, Scalar, PrivateMaskProof) {
+        self.mask_private_in(&mut self.transcript(b"mask_private"), p, idx)
+    }
+
+    /// Verifies the application of the private masking protocol
+    pub fn verify_private_mask(
+        &self,
+        p: &[RistrettoPoint],
+        c: &Mask,
+        proof: &PrivateMaskProof,
+    ) -> Result<()> {
+        self.verify_private_mask_in(&mut self.transcript(b"mask_private"), p, c, proof)
+    }
+
+    /// Applies the private masking protocol to a ballot in a vote
+    ///
+    /// The proof is bound to the voter and the topic, so the ballot cannot be
+    /// cast again by another party or in another vote.
+    pub fn mask_ballot(
+        &self,
+        topic: &str,
+        p: &[RistrettoPoint],
+        idx: usize,
+    ) -> (Mask, Scalar, PrivateMaskProof) {
+        let fp = self.fingerprint();
+        self.mask_private_in(&mut self.ballot_transcript(topic, &fp), p, idx)
+    }
+
+    /// Verifies the application of the private masking protocol to a ballot
+    /// cast by a voter in a vote
+    pub fn verify_ballot(
+        &self,
+        topic: &str,
+        voter: &Fingerprint,
+        p: &[RistrettoPoint],
+        c: &Mask,
+        proof: &PrivateMaskProof,
+    ) -> Result<()> {
+        self.verify_private_mask_in(&mut self.ballot_transcript(topic, voter), p, c, proof)
+    }
+
+    fn ballot_transcript(&self, topic: &str, voter: &Fingerprint) -> Transcript {
+        let mut t = self.party_transcript(b"mask_ballot", voter);
+        topic.append_to_transcript(&mut t, b"topic");
+        t
+    }
+
+    fn mask_private_in(
+        &self,
+        transcript: &mut Transcript,
+        p: &[RistrettoPoint],
+        idx: usize,
+    ) -> (Mask, Scalar, PrivateMaskProof) {
+        self.mark_masked();
+        let h = self.pk.point();
+        let r = Scalar::random(&mut thread_rng());
+        let c = Mask(G * &r, h * r + p[idx]);
+        let proof = PrivateMaskProof::create(
+            transcript,
+            mask_1ofn::Publics {
+                g: &G.basepoint(),
+                h: &h,
+                c: &c,
+                m: p,
+            },
+            mask_1ofn::Secrets { index: idx, r: &r },
+        );
+        (c, r, proof)
+    }
+
+    fn verify_private_mask_in(
+        &self,
+        transcript: &mut Transcript,
+        p: &[RistrettoPoint],
+        c: &Mask,
+        proof: &PrivateMaskProof,
+    ) -> Result<()> {
+        proof.verify(transcript, mask_1ofn::Publics {
+            g: &G.basepoint(),
+            h: &self.pk.point(),
+            c,
+            m: p,
+        })
+    }
+
+    /// Proves that a token hides a greater value than another
+    ///
+    /// Both tokens must be mapped with [`map::to_curve_fixed`] and hold values
+    /// below `max`; proofs grow quadratically with `max`. `a` and `b` must be
+    /// this party's private views of the tokens, i.e. the tokens with the
+    /// secret shares of all other parties removed, and `x` and `y` the values
+    /// they hide. Open tokens are their own private views. No masking factors
+    /// are needed, so any party that can see both tokens privately can prove
+    /// this.
+    pub fn prove_greater(&self, a: &Mask, b: &Mask, x: u64, y: u64, max: u64) -> PrivateMaskProof {
+        assert!(y < x && x < max);
+
+        let mut transcript = self.party_transcript(b"greater", &self.fingerprint());
+        let (h, c, p) = greater_publics(&mut transcript, a, b, &self.sk.public_key(), max);
+        let index = greater_pairs(max).position(|pair| pair == (x, y)).unwrap();
+        PrivateMaskProof::create(
+            &mut transcript,
+            mask_1ofn::Publics {
+                g: &G.basepoint(),
+                h: &h,
+                c: &c,
+                m: &p,
+            },
+            mask_1ofn::Secrets {
+                index,
+                r: &self.sk.exponent(),
+            },
+        )
+    }
+
+    /// Verifies a proof that a token hides a greater value than another,
+    /// given the private views of the party with the given fingerprint
+    pub fn verify_greater(
+        &self,
+        a: &Mask,
+        b: &Mask,
+        pk_fp: &Fingerprint,
+        max: u64,
+        proof: &PrivateMaskProof,
+    ) -> Result<()> {
+        let pk = self.pki.get(pk_fp).ok_or(Error::BadProof)?;
+        let mut transcript = self.party_transcript(b"greater", pk_fp);
+        let (h, c, p) = greater_publics(&mut transcript, a, b, pk, max);
+        proof.verify(&mut transcript, mask_1ofn::Publics {
+            g: &G.basepoint(),
+            h: &h,
+            c: &c,
+            m: &p,
+        })
+    }
+
+    /// Proves that a masked token has a given value in some bit field
+    ///
+    /// The token must be mapped with [`map::to_curve_fixed`] and `r` must be
+    /// its masking factor, so only the party that masked it can prove this.
+    /// The proof shows that the token is one of those below `max` whose
+    /// bits under `field_mask` equal `field_value`, without revealing which
+    /// one; proofs grow linearly with the number of matching tokens.
+    pub fn prove_field(
+        &self,
+        mask: &Mask,
+        value: u64,
+        r: &Scalar,
+        field_mask: u64,
+        field_value: u64,
+        max: u64,
+    ) -> PrivateMaskProof {
+        assert!(value < max && value & field_mask == field_value);
+
+        let index = field_tokens(field_mask, field_value, max)
+            .position(|t| t == value)
+            .unwrap();
+        let p: Vec<_> = field_tokens(field_mask, field_value, max)
+            .map(map::to_curve_fixed)
+            .collect();
+        PrivateMaskProof::create(
+            &mut self.transcript(b"field"),
+            mask_1ofn::Publics {
+                g: &G.basepoint(),
+                h: &self.pk.point(),
+                c: mask,
+                m: &p,
+            },
+            mask_1ofn::Secrets { index, r },
+        )
+    }
+
+    /// Verifies a proof that a masked token below `max` has a given value in
+    /// some bit field
+    pub fn verify_field(
+        &self,
+        mask: &Mask,
+        field_mask: u64,
+        field_value: u64,
+        max: u64,
+        proof: &PrivateMaskProof,
+    ) -> Result<()> {
+        let p: Vec<_> = field_tokens(field_mask, field_value, max)
+            .map(map::to_curve_fixed)
+            .collect();
+        if p.is_empty() {
+            return Err(Error::BadProof);
+        }
+        proof.verify(&mut self.transcript(b"field"), mask_1ofn::Publics {
             g: &G.basepoint(),
             h: &self.pk.point(),
+            c: mask,
+            m: &p,
         })
     }
 
     /// Applies the verifiable re-masking protocol
     pub fn remask(&self, c: &Mask) -> (Mask, Scalar, MaskProof) {
+        self.mark_masked();
         let h = self.pk.point();
         let r = Scalar::random(&mut thread_rng());
         let gr = G * &r;
         let hr = h * r;
         let proof = MaskProof::create(
-            &mut Transcript::new(b"remask"),
+            &mut self.transcript(b"remask"),
             dlog_eq::Publics {
                 a: &gr,
                 b: &hr,
@@ -185,13 +624,97 @@ impl Vtmf {
         let h = self.pk.point();
         let gr = c.0 - m.0;
         let hr = c.1 - m.1;
-        proof.verify(&mut Transcript::new(b"remask"), dlog_eq::Publics {
+        proof.verify(&mut self.transcript(b"remask"), dlog_eq::Publics {
             a: &gr,
             b: &hr,
             g: &G.basepoint(),
             h: &h,
         })
     }
+
+    /// Proves that a mask hides the sum of the values hidden by two others
+    ///
+    /// `r` is the combined randomness, i.e. the masking factor of `out`
+    /// minus those of `a` and `b`; it is zero when `out` is exactly `a + b`,
+    /// and the re-masking factor when `out` is a re-masking of it.
+    pub fn prove_sum(&self, a: &Mask, b: &Mask, out: &Mask, r: &Scalar) -> MaskProof {
+        let h = self.pk.point();
+        let c = out - (a + b);
+        MaskProof::create(
+            &mut self.transcript(b"sum"),
+            dlog_eq::Publics {
+                a: &c.0,
+                b: &c.1,
+                g: &G.basepoint(),
+                h: &h,
+            },
+            dlog_eq::Secrets { x: r },
+        )
+    }
+
+    /// Verifies a proof that a mask hides the sum of the values hidden by
+    /// two others
+    pub fn verify_sum(&self, a: &Mask, b: &Mask, out: &Mask, proof: &MaskProof) -> Result<()> {
+        let h = self.pk.point();
+        let c = out - (a + b);
+        proof.verify(&mut self.transcript(b"sum"), dlog_eq::Publics {
+            a: &c.0,
+            b: &c.1,
+            g: &G.basepoint(),
+            h: &h,
+        })
+    }
+
+    /// Masks a whole stack of points at once
+    ///
+    /// Each proof is a re-masking proof from the corresponding open mask, so
+    /// the result can be published as a `MaskStack` of the open stack.
+    pub fn mask_all(&self, points: &[RistrettoPoint]) -> (Stack, Vec<Scalar>, Vec<MaskProof>) {
+        self.mark_masked();
+        let mut rng = thread_rng();
+        let h = self.pk.point();
+        let g = G.basepoint();
+        let base = self.transcript(b"remask");
+
+        let mut stack = Vec::with_capacity(points.len());
+        let mut secrets = Vec::with_capacity(points.len());
+        let mut proofs = Vec::with_capacity(points.len());
+        for p in points {
+            let r = Scalar::random(&mut rng);
+            let gr = G * &r;
+            let hr = h * r;
+            proofs.push(MaskProof::create(
+                &mut base.clone(),
+                dlog_eq::Publics {
+                    a: &gr,
+                    b: &hr,
+                    g: &g,
+                    h: &h,
+                },
+                dlog_eq::Secrets { x: &r },
+            ));
+            stack.push(Mask(gr, hr + p));
+            secrets.push(r);
+        }
+        (stack.into(), secrets, proofs)
+    }
+
+    /// Verifies a stack masked with [`Vtmf::mask_all`]
+    pub fn verify_mask_all(
+        &self,
+        points: &[RistrettoPoint],
+        stack: &Stack,
+        proofs: &[MaskProof],
+    ) -> Result<()> {
+        if points.len() != stack.len() || points.len() != proofs.len() {
+            return Err(Error::BadProof);
+        }
+        points
+            .iter()
+            .zip(stack.iter())
+            .zip(proofs.iter())
+            .try_for_each(|((p, c), proof)| self.verify_remask(&Mask::open(*p), c, proof))
+    }
 }
 
 impl Vtmf {
@@ -201,7 +724,7 @@ impl Vtmf {
 
         let d = c.0 * x;
         let proof = MaskProof::create(
-            &mut Transcript::new(b"mask_share"),
+            &mut self.transcript(b"mask_share"),
             dlog_eq::Publics {
                 a: &d,
                 b: &(G * x),
@@ -229,7 +752,7 @@ impl Vtmf {
             }
             Some(pk) => pk,
         };
-        proof.verify(&mut Transcript::new(b"mask_share"), dlog_eq::Publics {
+        proof.verify(&mut self.transcript(b"mask_share"), dlog_eq::Publics {
             a: &d.0,
             b: &pk.point(),
             g: &c.0,
@@ -252,11 +775,27 @@ impl Vtmf {
     pub fn unmask_open(&self, m: &Mask) -> RistrettoPoint {
         m.1
     }
+
+    /// Recovers the value of an unmasked aggregate of additively mapped
+    /// values
+    ///
+    /// Only sums no greater than `max` can be recovered, and the cost of
+    /// recovery grows linearly with `max`.
+    pub fn unmask_sum(&self, m: &Mask, max: u64) -> Option<u64> {
+        map::from_curve_additive(&self.unmask_open(m), max)
+    }
 }
 
 impl Vtmf {
     /// Applies the mask-shuffle protocol for a given permutation
     pub fn mask_shuffle(&self, m: &Stack, pi: &Permutation) -> (Stack, Vec<Scalar>, ShuffleProof) {
+        let (rm, r) = self.remask_permuted(m, pi);
+        let proof = ShuffleProof::create_with_key(&self.domain, &self.pk.point(), m, &rm, pi, &r);
+        (rm, r, proof)
+    }
+
+    fn remask_permuted(&self, m: &Stack, pi: &Permutation) -> (Stack, Vec<Scalar>) {
+        self.mark_masked();
         let mut rng = thread_rng();
 
         let h = self.pk.point();
@@ -276,23 +815,13 @@ impl Vtmf {
             .unzip();
         pi.apply_to(&mut rm);
         pi.apply_to(&mut r);
-
-        let proof = ShuffleProof::create(
-            &mut Transcript::new(b"mask_shuffle"),
-            secret_shuffle::Publics {
-                h: &h,
-                e0: m,
-                e1: &rm,
-            },
-            secret_shuffle::Secrets { pi, r: &r },
-        );
-        (rm, r, proof)
+        (rm, r)
     }
 
     /// Verifies the application of the mask-shuffling protocol
     pub fn verify_mask_shuffle(&self, m: &Stack, c: &Stack, proof: &ShuffleProof) -> Result<()> {
         proof.verify(
-            &mut Transcript::new(b"mask_shuffle"),
+            &mut self.transcript(b"mask_shuffle"),
             secret_shuffle::Publics {
                 h: &self.pk.point(),
                 e0: m,
@@ -300,33 +829,196 @@ impl Vtmf {
             },
         )
     }
+
+    /// Verifies a masked shuffle between stacks with cached compressed masks
+    ///
+    /// This is the same check as
+    /// [`verify_mask_shuffle`](Vtmf::verify_mask_shuffle), but it does not
+    /// compress the masks again.
+    pub fn verify_mask_shuffle_compressed(
+        &self,
+        m: &CompressedStack,
+        c: &CompressedStack,
+        proof: &ShuffleProof,
+    ) -> Result<()> {
+        proof.verify_with(
+            &mut self.transcript(b"mask_shuffle"),
+            secret_shuffle::Publics {
+                h: &self.pk.point(),
+                e0: m.stack(),
+                e1: c.stack(),
+            },
+            m.compressed(),
+            c.compressed(),
+        )
+    }
+
+    /// Proves that a stack is a remasking of a permutation of another stack
+    pub fn prove_same_multiset(
+        &self,
+        a: &Stack,
+        b: &Stack,
+        pi: &Permutation,
+        secrets: &[Scalar],
+    ) -> ShuffleProof {
+        ShuffleProof::create(
+            &mut self.transcript(b"same_multiset"),
+            secret_shuffle::Publics {
+                h: &self.pk.point(),
+                e0: a,
+                e1: b,
+            },
+            secret_shuffle::Secrets { pi, r: secrets },
+        )
+    }
+
+    /// Verifies that a stack is a remasking of a permutation of another stack
+    pub fn verify_same_multiset(&self, a: &Stack, b: &Stack, proof: &ShuffleProof) -> Result<()> {
+        proof.verify(
+            &mut self.transcript(b"same_multiset"),
+            secret_shuffle::Publics {
+                h: &self.pk.point(),
+                e0: a,
+                e1: b,
+            },
+        )
+    }
+
+    /// Shuffles a stack that is a permutation of a reference stack
+    ///
+    /// The proof shows that the shuffled stack is a remasked permutation of
+    /// the source. It is bound to the reference, and verifiers check that the
+    /// source itself is a permutation of it, so that no token can have been
+    /// added or dropped along the way.
+    pub fn prove_permutation_of(
+        &self,
+        reference: &Fingerprint,
+        source: &Stack,
+        pi: &Permutation,
+    ) -> (Stack, Vec<Scalar>, ShuffleProof) {
+        let (shuffled, secrets) = self.remask_permuted(source, pi);
+        let mut transcript = self.transcript(b"permutation_of");
+        reference.append_to_transcript(&mut transcript, b"reference");
+        let proof = ShuffleProof::create(
+            &mut transcript,
+            secret_shuffle::Publics {
+                h: &self.pk.point(),
+                e0: source,
+                e1: &shuffled,
+            },
+            secret_shuffle::Secrets { pi, r: &secrets },
+        );
+        (shuffled, secrets, proof)
+    }
+
+    /// Verifies that a stack is a shuffle of a source stack, bound to a
+    /// reference
+    ///
+    /// This does not check that the source is a permutation of the reference.
+    pub fn verify_permutation_of(
+        &self,
+        reference: &Fingerprint,
+        source: &Stack,
+        shuffled: &Stack,
+        proof: &ShuffleProof,
+    ) -> Result<()> {
+        if source.len() != shuffled.len() {
+            return Err(Error::BadProof);
+        }
+        let mut transcript = self.transcript(b"permutation_of");
+        reference.append_to_transcript(&mut transcript, b"reference");
+        proof.verify(&mut transcript, secret_shuffle::Publics {
+            h: &self.pk.point(),
+            e0: source,
+            e1: shuffled,
+        })
+    }
+
+    /// Masks and shuffles an open reference stack, with a proof that the
+    /// result holds exactly the same tokens
+    ///
+    /// This is [`prove_permutation_of`](Vtmf::prove_permutation_of) with the
+    /// reference as its own source.
+    pub fn prove_equal_multiset(
+        &self,
+        reference: &Stack,
+        pi: &Permutation,
+    ) -> (Stack, Vec<Scalar>, ShuffleProof) {
+        self.prove_permutation_of(&reference.id(), reference, pi)
+    }
+
+    /// Verifies that a masked stack holds exactly the tokens of an open
+    /// reference stack
+    ///
+    /// Fails with [`Error::BadProof`] if the reference is not open, besides
+    /// when [`verify_permutation_of`](Vtmf::verify_permutation_of) fails.
+    pub fn verify_equal_multiset(
+        &self,
+        hidden: &Stack,
+        reference: &Stack,
+        proof: &ShuffleProof,
+    ) -> Result<()> {
+        if !reference.iter().all(Mask::is_open) {
+            return Err(Error::BadProof);
+        }
+        self.verify_permutation_of(&reference.id(), reference, hidden, proof)
+    }
+
+    /// Blinds a stack for a check that it holds no duplicate tokens
+    ///
+    /// Every party blinds the stack in turn, and then unmasks the result;
+    /// see [`DistinctProof`] for the approach and its cost.
+    pub fn prove_distinct(&self, m: &Stack) -> (Stack, DistinctProof) {
+        let z = Scalar::random(&mut thread_rng());
+        let c: Stack = m.iter().map(|e| Mask(e.0 * z, e.1 * z)).collect();
+        let proof = DistinctProof::create(
+            &mut self.party_transcript(b"distinct", &self.fingerprint()),
+            distinct::Publics { e0: m, e1: &c },
+            distinct::Secrets { z: &z },
+        );
+        (c, proof)
+    }
+
+    /// Verifies a party's blinding of a stack for a check that it holds no
+    /// duplicate tokens
+    pub fn verify_distinct(
+        &self,
+        m: &Stack,
+        c: &Stack,
+        pk_fp: &Fingerprint,
+        proof: &DistinctProof,
+    ) -> Result<()> {
+        proof.verify(
+            &mut self.party_transcript(b"distinct", pk_fp),
+            distinct::Publics { e0: m, e1: c },
+        )
+    }
+
+    fn party_transcript(&self, label: &'static [u8], pk_fp: &Fingerprint) -> Transcript {
+        let mut t = self.transcript(label);
+        pk_fp.append_to_transcript(&mut t, b"party");
+        t
+    }
 }
 
 impl Vtmf {
     /// Applies the mask-shift protocol for a given permutation
     pub fn mask_shift(&self, m: &Stack, k: usize) -> (Stack, Vec<Scalar>, ShiftProof) {
+        self.mark_masked();
         let mut rng = thread_rng();
 
         let h = self.pk.point();
 
         let (rm, r) = self.do_shift(m, k, &mut rng);
 
-        let proof = ShiftProof::create(
-            &mut Transcript::new(b"mask_shift"),
-            secret_rotation::Publics {
-                h: &h,
-                e0: m,
-                e1: &rm,
-            },
-            secret_rotation::Secrets { k, r: &r },
-        );
+        let proof = ShiftProof::create_with_key(&self.domain, &h, m, &rm, k, &r);
         (rm, r, proof)
     }
 
     /// Verifies the application of the mask-shifting protocol
     pub fn verify_mask_shift(&self, m: &Stack, c: &Stack, proof: &ShiftProof) -> Result<()> {
         proof.verify(
-            &mut Transcript::new(b"mask_shift"),
+            &mut self.transcript(b"mask_shift"),
             secret_rotation::Publics {
                 h: &self.pk.point(),
                 e0: m,
@@ -335,6 +1027,25 @@ impl Vtmf {
         )
     }
 
+    /// Recomputes a shifted stack from its revealed cut point and secrets
+    ///
+    /// Returns `None` if the cut point is out of range or the number of
+    /// secrets does not match the stack.
+    pub fn apply_shift(&self, m: &Stack, k: usize, r: &[Scalar]) -> Option<Stack> {
+        if k >= m.len().max(1) || r.len() != m.len() {
+            return None;
+        }
+        let h = self.pk.point();
+        let mut rm = m.clone();
+        Permutation::shift(m.len(), k).apply_to(&mut rm);
+        Some(
+            rm.iter()
+                .zip(r.iter())
+                .map(|(c, r)| Mask(G * r + c.0, h * r + c.1))
+                .collect(),
+        )
+    }
+
     fn do_shift<R: Rng + CryptoRng>(
         &self,
         m: &Stack,
@@ -363,14 +1074,26 @@ impl Vtmf {
 impl Vtmf {
     /// Applies a random mask
     pub fn mask_random<R: Rng + CryptoRng>(&self, rng: &mut R) -> Mask {
+        self.mark_masked();
         let p = RistrettoPoint::random(rng);
         self.mask(&p).0
     }
 
     /// Undoes a random mask
     pub fn unmask_random(&self, m: &Mask) -> impl XofReader {
-        let mut h = TranscriptHash::new(b"pbmx-random");
+        self.unmask_random_mixed(m, None)
+    }
+
+    /// Undoes a random mask, mixing in some public entropy
+    ///
+    /// Without public entropy this is the same as
+    /// [`unmask_random`](Vtmf::unmask_random).
+    pub fn unmask_random_mixed(&self, m: &Mask, public: Option<&[u8]>) -> impl XofReader {
+        let mut h = TranscriptHash::with_algorithm(b"pbmx-random", self.hash);
         m.append_to_transcript(&mut h, b"entropy");
+        if let Some(public) = public {
+            public.append_to_transcript(&mut h, b"public");
+        }
         h.into_xof()
     }
 }
@@ -390,21 +1113,8 @@ impl Vtmf {
         It2: Iterator<Item = &'a Stack>,
         It3: Iterator<Item = &'a [Scalar]>,
     {
-        let h = self.pk.point();
-
-        let m: Vec<_> = m.map(|s| &s[..]).collect();
-        let c: Vec<_> = c.map(|s| &s[..]).collect();
-        let r: Vec<_> = secrets.collect();
-        entanglement::Proof::create(
-            &mut Transcript::new(b"entanglement"),
-            entanglement::Publics {
-                h: &h,
-                e0: &m,
-                e1: &c,
-            },
-            entanglement::Secrets { pi, r: &r },
-        )
-    }
+        EntanglementProof::create_with_key(&self.domain, &self.pk.point(), m, c, pi, secrets)
+    }
 
     /// Proves that multiple stacks have been reordered according to the same
     /// permutation
@@ -423,7 +1133,7 @@ impl Vtmf {
         let m: Vec<_> = m.map(|s| &s[..]).collect();
         let c: Vec<_> = c.map(|s| &s[..]).collect();
         proof.verify(
-            &mut Transcript::new(b"entanglement"),
+            &mut self.transcript(b"entanglement"),
             entanglement::Publics {
                 h: &h,
                 e0: &m,
@@ -431,11 +1141,168 @@ impl Vtmf {
             },
         )
     }
+
+    /// Masks two aligned lists of points as a paired stack
+    pub fn mask_paired(
+        &self,
+        first: &[RistrettoPoint],
+        second: &[RistrettoPoint],
+    ) -> (PairedStack, [Vec<Scalar>; 2], [Vec<MaskProof>; 2]) {
+        assert_eq!(first.len(), second.len());
+
+        let (m0, r0, proofs0) = self.mask_all(first);
+        let (m1, r1, proofs1) = self.mask_all(second);
+        let m = PairedStack::new(m0, m1).unwrap();
+        (m, [r0, r1], [proofs0, proofs1])
+    }
+
+    /// Applies the mask-shuffle protocol to both stacks of a paired stack
+    /// under the same permutation
+    pub fn mask_shuffle_paired(
+        &self,
+        m: &PairedStack,
+        pi: &Permutation,
+    ) -> (PairedStack, [Vec<Scalar>; 2], PairedProof<ShuffleProof>) {
+        let (c0, r0, proof0) = self.mask_shuffle(m.first(), pi);
+        let (c1, r1, proof1) = self.mask_shuffle(m.second(), pi);
+        let c = PairedStack::new(c0, c1).unwrap();
+        let entanglement =
+            self.prove_entanglement(m.iter(), c.iter(), pi, [&r0[..], &r1[..]].iter().cloned());
+        let proof = PairedProof {
+            first: proof0,
+            second: proof1,
+            entanglement,
+        };
+        (c, [r0, r1], proof)
+    }
+
+    /// Verifies the application of the mask-shuffle protocol to a paired
+    /// stack
+    pub fn verify_mask_shuffle_paired(
+        &self,
+        m: &PairedStack,
+        c: &PairedStack,
+        proof: &PairedProof<ShuffleProof>,
+    ) -> Result<()> {
+        self.verify_mask_shuffle(m.first(), c.first(), &proof.first)?;
+        self.verify_mask_shuffle(m.second(), c.second(), &proof.second)?;
+        self.verify_entanglement(m.iter(), c.iter(), &proof.entanglement)
+    }
+
+    /// Applies the mask-shift protocol to both stacks of a paired stack by
+    /// the same amount
+    pub fn mask_shift_paired(
+        &self,
+        m: &PairedStack,
+        k: usize,
+    ) -> (PairedStack, [Vec<Scalar>; 2], PairedProof<ShiftProof>) {
+        let (c0, r0, proof0) = self.mask_shift(m.first(), k);
+        let (c1, r1, proof1) = self.mask_shift(m.second(), k);
+        let c = PairedStack::new(c0, c1).unwrap();
+        let entanglement = self.prove_entanglement(
+            m.iter(),
+            c.iter(),
+            &Permutation::shift(m.len(), k),
+            [&r0[..], &r1[..]].iter().cloned(),
+        );
+        let proof = PairedProof {
+            first: proof0,
+            second: proof1,
+            entanglement,
+        };
+        (c, [r0, r1], proof)
+    }
+
+    /// Verifies the application of the mask-shift protocol to a paired stack
+    pub fn verify_mask_shift_paired(
+        &self,
+        m: &PairedStack,
+        c: &PairedStack,
+        proof: &PairedProof<ShiftProof>,
+    ) -> Result<()> {
+        self.verify_mask_shift(m.first(), c.first(), &proof.first)?;
+        self.verify_mask_shift(m.second(), c.second(), &proof.second)?;
+        self.verify_entanglement(m.iter(), c.iter(), &proof.entanglement)
+    }
+}
+
+impl ShuffleProof {
+    /// Proves a mask-shuffle with only the shared public key
+    ///
+    /// The proof verifies with [`Vtmf::verify_mask_shuffle`] for any VTMF
+    /// with shared key `h` and the given domain.
+    pub fn create_with_key(
+        domain: &[u8; 32],
+        h: &RistrettoPoint,
+        m: &Stack,
+        c: &Stack,
+        pi: &Permutation,
+        secrets: &[Scalar],
+    ) -> Self {
+        Self::create(
+            &mut transcript(domain, b"mask_shuffle"),
+            secret_shuffle::Publics { h, e0: m, e1: c },
+            secret_shuffle::Secrets { pi, r: secrets },
+        )
+    }
+}
+
+impl ShiftProof {
+    /// Proves a mask-shift with only the shared public key
+    ///
+    /// The proof verifies with [`Vtmf::verify_mask_shift`] for any VTMF with
+    /// shared key `h` and the given domain.
+    pub fn create_with_key(
+        domain: &[u8; 32],
+        h: &RistrettoPoint,
+        m: &Stack,
+        c: &Stack,
+        k: usize,
+        secrets: &[Scalar],
+    ) -> Self {
+        Self::create(
+            &mut transcript(domain, b"mask_shift"),
+            secret_rotation::Publics { h, e0: m, e1: c },
+            secret_rotation::Secrets { k, r: secrets },
+        )
+    }
+}
+
+impl EntanglementProof {
+    /// Proves an entanglement with only the shared public key
+    ///
+    /// The proof verifies with [`Vtmf::verify_entanglement`] for any VTMF
+    /// with shared key `h` and the given domain.
+    pub fn create_with_key<'a, It1, It2, It3>(
+        domain: &[u8; 32],
+        h: &RistrettoPoint,
+        m: It1,
+        c: It2,
+        pi: &Permutation,
+        secrets: It3,
+    ) -> Self
+    where
+        It1: Iterator<Item = &'a Stack>,
+        It2: Iterator<Item = &'a Stack>,
+        It3: Iterator<Item = &'a [Scalar]>,
+    {
+        let m: Vec<_> = m.map(|s| &s[..]).collect();
+        let c: Vec<_> = c.map(|s| &s[..]).collect();
+        let r: Vec<_> = secrets.collect();
+        Self::create(
+            &mut transcript(domain, b"entanglement"),
+            entanglement::Publics { h, e0: &m, e1: &c },
+            entanglement::Secrets { pi, r: &r },
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Mask, Stack, Vtmf};
+    use super::{
+        CompressedStack, EntanglementProof, KeyAdded, Mask, PairedStack, ShiftProof, ShuffleProof,
+        Stack, Vtmf, G,
+    };
     use crate::{
         crypto::{
             keys::PrivateKey,
@@ -444,23 +1311,17 @@ mod tests {
         },
         Error,
     };
+    use curve25519_dalek::ristretto::RistrettoPoint;
     use digest::XofReader;
     use rand::{thread_rng, Rng};
 
     #[test]
     fn vtmf_masking_remasking_and_unmasking_work() {
         let mut rng = thread_rng();
-        let sk0 = PrivateKey::random(&mut rng);
-        let sk1 = PrivateKey::random(&mut rng);
-        let pk0 = sk0.public_key();
-        let pk1 = sk1.public_key();
-
-        let mut vtmf0 = Vtmf::new(sk0);
-        let mut vtmf1 = Vtmf::new(sk1);
-        let fp0 = pk0.fingerprint();
-        let fp1 = pk1.fingerprint();
-        vtmf0.add_key(pk1);
-        vtmf1.add_key(pk0);
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+        let fp0 = vtmf0.private_key().fingerprint();
+        let fp1 = vtmf1.private_key().fingerprint();
 
         let x = rng.gen_range(0..16);
         let p = map::to_curve(x);
@@ -488,7 +1349,7 @@ mod tests {
         let mask0 = vtmf0.unmask(&mask, &d1);
         let mask0 = vtmf0.unmask_private(&mask0);
         let r = vtmf0.unmask_open(&mask0);
-        let r = map::from_curve(&r);
+        let r = map::from_curve(&r).unwrap();
         assert_eq!(r, x);
 
         let verified = vtmf1.verify_unmask(&mask, &fp0, &d0, &proof0);
@@ -498,31 +1359,204 @@ mod tests {
         let mask1 = vtmf1.unmask(&mask, &d0);
         let mask1 = vtmf1.unmask_private(&mask1);
         let r = vtmf1.unmask_open(&mask1);
-        let r = map::from_curve(&r);
+        let r = map::from_curve(&r).unwrap();
         assert_eq!(r, x);
     }
 
     #[test]
-    fn vtmf_open_masking_works() {
+    fn vtmf_batch_masking_works() {
         let mut rng = thread_rng();
-        let sk0 = PrivateKey::random(&mut rng);
-        let sk1 = PrivateKey::random(&mut rng);
-        let pk0 = sk0.public_key();
-        let pk1 = sk1.public_key();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+
+        let points: Vec<_> = (0..8).map(map::to_curve).collect();
+        let (stack, secrets, proofs) = vtmf0.mask_all(&points);
+        assert_eq!(stack.len(), 8);
+        assert_eq!(secrets.len(), 8);
+        let verified = vtmf1.verify_mask_all(&points, &stack, &proofs);
+        assert_eq!(verified, Ok(()));
+        let open: Stack = points.iter().cloned().map(Mask::open).collect();
+        for ((m, c), proof) in open.iter().zip(stack.iter()).zip(proofs.iter()) {
+            assert_eq!(vtmf1.verify_remask(m, c, proof), Ok(()));
+        }
 
-        let mut vtmf0 = Vtmf::new(sk0);
-        let mut vtmf1 = Vtmf::new(sk1);
-        let fp0 = pk0.fingerprint();
-        let fp1 = pk1.fingerprint();
-        vtmf0.add_key(pk1);
-        vtmf1.add_key(pk0);
+        let invalid = vtmf1.verify_mask_all(&points[1..], &stack, &proofs);
+        assert_eq!(invalid, Err(Error::BadProof));
+        let mut swapped = proofs.clone();
+        swapped.swap(0, 1);
+        let invalid = vtmf1.verify_mask_all(&points, &stack, &swapped);
+        assert_eq!(invalid, Err(Error::BadProof));
+    }
+
+    #[test]
+    fn vtmf_private_masking_works() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+        let fp0 = vtmf0.private_key().fingerprint();
+
+        let stack: Stack = (0..8).map(|i| Mask::open(map::to_curve(i))).collect();
+        let points: Vec<_> = stack.iter().map(|m| vtmf0.unmask_open(m)).collect();
+        let x = rng.gen_range(0..points.len());
+        let (mask, _, proof) = vtmf0.mask_private(&points, x);
+        let verified = vtmf1.verify_private_mask(&points, &mask, &proof);
+        assert_eq!(verified, Ok(()));
+        let invalid = vtmf1.verify_private_mask(&points[1..], &mask, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+        let mut others = points.clone();
+        others.remove(x);
+        others.push(map::to_curve(8));
+        let invalid = vtmf1.verify_private_mask(&others, &mask, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let (d0, proof0) = vtmf0.unmask_share(&mask);
+        let verified = vtmf1.verify_unmask(&mask, &fp0, &d0, &proof0);
+        assert_eq!(verified, Ok(()));
+        let mask1 = vtmf1.unmask(&mask, &d0);
+        let mask1 = vtmf1.unmask_private(&mask1);
+        let r = vtmf1.unmask_open(&mask1);
+        let r = map::from_curve(&r).unwrap();
+        assert_eq!(r, x as u64);
+    }
+
+    #[test]
+    fn vtmf_greater_proofs_work() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+        let fp0 = vtmf0.public_key().fingerprint();
+        let fp1 = vtmf1.public_key().fingerprint();
+        let view0 = |m: &Mask| vtmf0.unmask(m, &vtmf1.unmask_share(m).0);
+
+        let (a, ..) = vtmf1.mask(&map::to_curve_fixed(9));
+        let b = Mask::open(map::to_curve_fixed(6));
+        let (a0, b0) = (view0(&a), view0(&b));
+        let proof = vtmf0.prove_greater(&a0, &b0, 9, 6, 12);
+        let verified = vtmf1.verify_greater(&a0, &b0, &fp0, 12, &proof);
+        assert_eq!(verified, Ok(()));
+        let invalid = vtmf1.verify_greater(&a0, &b0, &fp1, 12, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+        let invalid = vtmf1.verify_greater(&b0, &a0, &fp0, 12, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+        let invalid = vtmf1.verify_greater(&a0, &b0, &fp0, 10, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+        let invalid = vtmf1.verify_greater(&a, &b, &fp0, 12, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let (c, ..) = vtmf0.mask(&map::to_curve_fixed(6));
+        let c0 = view0(&c);
+        let proof = vtmf0.prove_greater(&c0, &b0, 7, 6, 12);
+        let invalid = vtmf1.verify_greater(&c0, &b0, &fp0, 12, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+    }
+
+    #[test]
+    fn vtmf_sum_proofs_work() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+
+        let (a, ra, _) = vtmf0.mask(&map::to_curve_additive(3));
+        let (b, rb, _) = vtmf1.mask(&map::to_curve_additive(5));
+        let (out, r, _) = vtmf0.remask(&(a + b));
+        let proof = vtmf0.prove_sum(&a, &b, &out, &r);
+        let verified = vtmf1.verify_sum(&a, &b, &out, &proof);
+        assert_eq!(verified, Ok(()));
+        let verified = vtmf1.verify_sum(&b, &a, &out, &proof);
+        assert_eq!(verified, Ok(()));
+        let invalid = vtmf1.verify_sum(&a, &a, &out, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let (fresh, rf, _) = vtmf0.mask(&map::to_curve_additive(8));
+        let proof = vtmf0.prove_sum(&a, &b, &fresh, &(rf - ra - rb));
+        let verified = vtmf1.verify_sum(&a, &b, &fresh, &proof);
+        assert_eq!(verified, Ok(()));
+
+        let (wrong, rw, _) = vtmf0.mask(&map::to_curve_additive(9));
+        let proof = vtmf0.prove_sum(&a, &b, &wrong, &(rw - ra - rb));
+        let invalid = vtmf1.verify_sum(&a, &b, &wrong, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+    }
+
+    #[test]
+    fn vtmf_field_proofs_work() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+
+        let (mask, r, _) = vtmf0.mask(&map::to_curve_fixed(0b10_1101));
+        let proof = vtmf0.prove_field(&mask, 0b10_1101, &r, 0b11_0000, 0b10_0000, 52);
+        let verified = vtmf1.verify_field(&mask, 0b11_0000, 0b10_0000, 52, &proof);
+        assert_eq!(verified, Ok(()));
+        let invalid = vtmf1.verify_field(&mask, 0b11_0000, 0b01_0000, 52, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+        let invalid = vtmf1.verify_field(&mask, 0b11_0000, 0b10_0000, 40, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let (other, ro, _) = vtmf0.mask(&map::to_curve_fixed(0b01_1101));
+        let proof = vtmf0.prove_field(&other, 0b10_1101, &ro, 0b11_0000, 0b10_0000, 52);
+        let invalid = vtmf1.verify_field(&other, 0b11_0000, 0b10_0000, 52, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+    }
+
+    #[test]
+    fn vtmf_masking_with_another_base_works() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+
+        let base = RistrettoPoint::random(&mut rng);
+        let p = map::to_curve(7);
+        let (mask, r, proof) = vtmf0.mask_with_base(&p, &base);
+        assert_eq!(mask.0, base * r);
+        let verified = vtmf1.verify_mask_with_base(&p, &base, &mask, &proof);
+        assert_eq!(verified, Ok(()));
+        let other = RistrettoPoint::random(&mut rng);
+        let invalid = vtmf1.verify_mask_with_base(&p, &other, &mask, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+        let invalid = vtmf1.verify_mask(&p, &mask, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let (mask, _, proof) = vtmf0.mask(&p);
+        let invalid = vtmf1.verify_mask_with_base(&p, &G.basepoint(), &mask, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+    }
+
+    #[test]
+    fn vtmf_masked_sums_work() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+        let fp1 = vtmf1.private_key().fingerprint();
+
+        let x0 = rng.gen_range(0..16);
+        let x1 = rng.gen_range(0..16);
+        let (m0, ..) = vtmf0.mask(&map::to_curve_additive(x0));
+        let (m1, ..) = vtmf1.mask(&map::to_curve_additive(x1));
+        let sum = Mask::sum(vec![m0, m1]);
+
+        let (d1, proof1) = vtmf1.unmask_share(&sum);
+        let verified = vtmf0.verify_unmask(&sum, &fp1, &d1, &proof1);
+        assert_eq!(verified, Ok(()));
+        let open = vtmf0.unmask(&sum, &d1);
+        let open = vtmf0.unmask_private(&open);
+        assert_eq!(vtmf0.unmask_sum(&open, 32), Some(x0 + x1));
+    }
+
+    #[test]
+    fn vtmf_open_masking_works() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+        let fp0 = vtmf0.private_key().fingerprint();
+        let fp1 = vtmf1.private_key().fingerprint();
 
         let x = rng.gen_range(0..16);
         let p = map::to_curve(x);
         let mask = Mask::open(p);
 
         let open = vtmf1.unmask_open(&mask);
-        let open = map::from_curve(&open);
+        let open = map::from_curve(&open).unwrap();
         assert_eq!(open, x);
 
         let (d0, proof0) = vtmf0.unmask_share(&mask);
@@ -533,7 +1567,7 @@ mod tests {
         let mask0 = vtmf0.unmask(&mask, &d1);
         let mask0 = vtmf0.unmask_private(&mask0);
         let r = vtmf0.unmask_open(&mask0);
-        let r = map::from_curve(&r);
+        let r = map::from_curve(&r).unwrap();
         assert_eq!(r, x);
 
         let verified = vtmf1.verify_unmask(&mask, &fp0, &d0, &proof0);
@@ -541,23 +1575,16 @@ mod tests {
         let mask1 = vtmf1.unmask(&mask, &d0);
         let mask1 = vtmf1.unmask_private(&mask1);
         let r = vtmf1.unmask_open(&mask1);
-        let r = map::from_curve(&r);
+        let r = map::from_curve(&r).unwrap();
         assert_eq!(r, x);
     }
 
     #[test]
     fn vtmf_mask_shuffling_works() {
         let mut rng = thread_rng();
-        let sk0 = PrivateKey::random(&mut rng);
-        let sk1 = PrivateKey::random(&mut rng);
-        let pk0 = sk0.public_key();
-        let pk1 = sk1.public_key();
-
-        let mut vtmf0 = Vtmf::new(sk0);
-        let mut vtmf1 = Vtmf::new(sk1);
-        let fp0 = pk0.fingerprint();
-        vtmf0.add_key(pk1);
-        vtmf1.add_key(pk0);
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+        let fp0 = vtmf0.private_key().fingerprint();
 
         let m: Stack = (0u64..8)
             .map(map::to_curve)
@@ -571,6 +1598,15 @@ mod tests {
         m2[0] = vtmf0.mask(&map::to_curve(8)).0;
         let invalid = vtmf1.verify_mask_shuffle(&m2, &shuffle, &proof);
         assert_eq!(invalid, Err(Error::BadProof));
+        let (cm, cshuffle) = (
+            CompressedStack::new(m),
+            CompressedStack::new(shuffle.clone()),
+        );
+        let verified = vtmf1.verify_mask_shuffle_compressed(&cm, &cshuffle, &proof);
+        assert_eq!(verified, Ok(()));
+        let invalid =
+            vtmf1.verify_mask_shuffle_compressed(&CompressedStack::new(m2), &cshuffle, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
 
         let open: Vec<_> = shuffle
             .iter()
@@ -581,7 +1617,7 @@ mod tests {
                 let mask1 = vtmf1.unmask(m, &d0);
                 let mask1 = vtmf1.unmask_private(&mask1);
                 let r = vtmf1.unmask_open(&mask1);
-                map::from_curve(&r)
+                map::from_curve(&r).unwrap()
             })
             .collect();
         let mut expected: Vec<_> = (0u64..8).collect();
@@ -590,18 +1626,184 @@ mod tests {
     }
 
     #[test]
-    fn vtmf_mask_shifting_works() {
+    fn vtmf_same_multiset_works() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+
+        let a: Stack = (0u64..8)
+            .map(map::to_curve)
+            .map(|p| vtmf0.mask(&p).0)
+            .collect();
+        let pi = thread_rng().sample(Shuffles(a.len()));
+        let (b, r, _) = vtmf0.mask_shuffle(&a, &pi);
+        let proof = vtmf0.prove_same_multiset(&a, &b, &pi, &r);
+        let verified = vtmf1.verify_same_multiset(&a, &b, &proof);
+        assert_eq!(verified, Ok(()));
+        let invalid = vtmf1.verify_mask_shuffle(&a, &b, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+        let mut b2 = b.clone();
+        b2[0] = vtmf0.mask(&map::to_curve(8)).0;
+        let invalid = vtmf1.verify_same_multiset(&a, &b2, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+    }
+
+    #[test]
+    fn vtmf_keys_are_added_once() {
         let mut rng = thread_rng();
         let sk0 = PrivateKey::random(&mut rng);
         let sk1 = PrivateKey::random(&mut rng);
-        let pk0 = sk0.public_key();
-        let pk1 = sk1.public_key();
-
+        let (pk0, pk1) = (sk0.public_key(), sk1.public_key());
         let mut vtmf0 = Vtmf::new(sk0);
         let mut vtmf1 = Vtmf::new(sk1);
-        let fp0 = pk0.fingerprint();
-        vtmf0.add_key(pk1);
-        vtmf1.add_key(pk0);
+        assert_eq!(vtmf0.add_key(pk1.clone()), KeyAdded::New);
+        assert_eq!(vtmf1.add_key(pk0.clone()), KeyAdded::New);
+
+        let shared = vtmf0.shared_key().fingerprint();
+        assert_eq!(vtmf0.add_key(pk1), KeyAdded::Known);
+        assert_eq!(vtmf0.add_key(pk0), KeyAdded::Known);
+        assert_eq!(vtmf0.shared_key().fingerprint(), shared);
+        assert_eq!(vtmf1.shared_key().fingerprint(), shared);
+        assert_eq!(vtmf0.parties(), 2);
+
+        let x = map::to_curve(7);
+        let (c, _, proof) = vtmf0.mask(&x);
+        assert_eq!(vtmf1.verify_mask(&x, &c, &proof), Ok(()));
+        let (d1, proof1) = vtmf1.unmask_share(&c);
+        let fp1 = vtmf1.private_key().fingerprint();
+        assert_eq!(vtmf0.verify_unmask(&c, &fp1, &d1, &proof1), Ok(()));
+        let c = vtmf0.unmask(&c, &d1);
+        assert_eq!(vtmf0.unmask_open(&vtmf0.unmask_private(&c)), x);
+    }
+
+    #[test]
+    fn vtmf_reports_late_joins() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let pk1 = PrivateKey::random(&mut rng).public_key();
+        let pk2 = PrivateKey::random(&mut rng).public_key();
+
+        let mut vtmf = Vtmf::new(sk0);
+        assert_eq!(vtmf.add_key(pk1.clone()), KeyAdded::New);
+        assert!(!vtmf.has_masked());
+        let _ = vtmf.mask(&map::to_curve(0));
+        assert!(vtmf.has_masked());
+        assert_eq!(vtmf.add_key(pk1), KeyAdded::Known);
+        assert_eq!(vtmf.add_key(pk2), KeyAdded::Late);
+        assert_eq!(vtmf.parties(), 3);
+    }
+
+    #[test]
+    fn vtmf_proofs_are_bound_to_domain() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let vtmf_a = Vtmf::with_domain(sk.clone(), [1; 32]);
+        let vtmf_b = Vtmf::with_domain(sk.clone(), [2; 32]);
+        let vtmf = Vtmf::new(sk);
+
+        let p = map::to_curve(42);
+        let (c, _, proof) = vtmf_a.mask(&p);
+        assert_eq!(vtmf_a.verify_mask(&p, &c, &proof), Ok(()));
+        assert_eq!(vtmf_b.verify_mask(&p, &c, &proof), Err(Error::BadProof));
+        assert_eq!(vtmf.verify_mask(&p, &c, &proof), Err(Error::BadProof));
+
+        let (c, _, proof) = vtmf.mask(&p);
+        assert_eq!(vtmf.verify_mask(&p, &c, &proof), Ok(()));
+        assert_eq!(vtmf_a.verify_mask(&p, &c, &proof), Err(Error::BadProof));
+    }
+
+    #[test]
+    fn vtmf_permutation_of_works() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+
+        let reference: Stack = (0u64..8).map(|i| Mask::open(map::to_curve(i))).collect();
+        let pi = thread_rng().sample(Shuffles(reference.len()));
+        let (source, ..) = vtmf0.mask_shuffle(&reference, &pi);
+        let (shuffled, _, proof) = vtmf0.prove_permutation_of(&reference.id(), &source, &pi);
+        let verified = vtmf1.verify_permutation_of(&reference.id(), &source, &shuffled, &proof);
+        assert_eq!(verified, Ok(()));
+
+        let invalid = vtmf1.verify_permutation_of(&source.id(), &source, &shuffled, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+        let dropped: Stack = shuffled.iter().skip(1).cloned().collect();
+        let invalid = vtmf1.verify_permutation_of(&reference.id(), &source, &dropped, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+    }
+
+    #[test]
+    fn vtmf_equal_multiset_works() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+
+        let reference: Stack = (0u64..8).map(|i| Mask::open(map::to_curve(i))).collect();
+        let pi = thread_rng().sample(Shuffles(reference.len()));
+        let (hidden, _, proof) = vtmf0.prove_equal_multiset(&reference, &pi);
+        let verified = vtmf1.verify_equal_multiset(&hidden, &reference, &proof);
+        assert_eq!(verified, Ok(()));
+
+        let dropped: Stack = hidden.iter().skip(1).cloned().collect();
+        let invalid = vtmf1.verify_equal_multiset(&dropped, &reference, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let (rehidden, _, masked_ref) = vtmf0.prove_equal_multiset(&hidden, &pi);
+        let invalid = vtmf1.verify_equal_multiset(&rehidden, &hidden, &masked_ref);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let (other, _, other_proof) = vtmf0.prove_permutation_of(&hidden.id(), &reference, &pi);
+        let invalid = vtmf1.verify_equal_multiset(&other, &reference, &other_proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+    }
+
+    #[test]
+    fn vtmf_distinct_proofs_work() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+        let fp0 = vtmf0.private_key().fingerprint();
+        let fp1 = vtmf1.private_key().fingerprint();
+
+        let open: Stack = (0u64..8).map(|i| Mask::open(map::to_curve(i))).collect();
+        let pi = thread_rng().sample(Shuffles(open.len()));
+        let (stack, ..) = vtmf0.mask_shuffle(&open, &pi);
+        let (blinded0, proof0) = vtmf0.prove_distinct(&stack);
+        assert_eq!(
+            vtmf1.verify_distinct(&stack, &blinded0, &fp0, &proof0),
+            Ok(())
+        );
+        let invalid = vtmf1.verify_distinct(&stack, &blinded0, &fp1, &proof0);
+        assert_eq!(invalid, Err(Error::BadProof));
+        let (other, ..) = vtmf0.mask_shuffle(&open, &pi);
+        let invalid = vtmf1.verify_distinct(&other, &blinded0, &fp0, &proof0);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let (blinded1, proof1) = vtmf1.prove_distinct(&blinded0);
+        assert_eq!(
+            vtmf0.verify_distinct(&blinded0, &blinded1, &fp1, &proof1),
+            Ok(())
+        );
+        let tags: Vec<_> = blinded1
+            .iter()
+            .map(|m| {
+                let d0 = vtmf0.unmask_share(m).0;
+                let d1 = vtmf1.unmask_share(m).0;
+                vtmf0.unmask_open(&vtmf0.unmask(&vtmf0.unmask(m, &d0), &d1))
+            })
+            .collect();
+        assert!(tags.iter().all(|t| !open.iter().any(|m| m.1 == *t)));
+        for (i, t) in tags.iter().enumerate() {
+            assert!(tags[i + 1..].iter().all(|u| u != t));
+        }
+    }
+
+    #[test]
+    fn vtmf_mask_shifting_works() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+        let fp0 = vtmf0.private_key().fingerprint();
 
         let m: Stack = (0u64..8)
             .map(map::to_curve)
@@ -625,7 +1827,7 @@ mod tests {
                 let mask1 = vtmf1.unmask(m, &d0);
                 let mask1 = vtmf1.unmask_private(&mask1);
                 let r = vtmf1.unmask_open(&mask1);
-                map::from_curve(&r)
+                map::from_curve(&r).unwrap()
             })
             .collect();
         let mut expected: Vec<_> = (0u64..8).collect();
@@ -637,17 +1839,10 @@ mod tests {
     #[test]
     fn vtmf_random_masking_works() {
         let mut rng = thread_rng();
-        let sk0 = PrivateKey::random(&mut rng);
-        let sk1 = PrivateKey::random(&mut rng);
-        let pk0 = sk0.public_key();
-        let pk1 = sk1.public_key();
-
-        let mut vtmf0 = Vtmf::new(sk0);
-        let mut vtmf1 = Vtmf::new(sk1);
-        let fp0 = pk0.fingerprint();
-        let fp1 = pk1.fingerprint();
-        vtmf0.add_key(pk1);
-        vtmf1.add_key(pk0);
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+        let fp0 = vtmf0.private_key().fingerprint();
+        let fp1 = vtmf1.private_key().fingerprint();
 
         let mask0 = vtmf0.mask_random(&mut rng);
         let mask1 = vtmf1.mask_random(&mut rng);
@@ -678,17 +1873,57 @@ mod tests {
     }
 
     #[test]
-    fn vtmf_entangled_mask_shuffling_works() {
+    fn proofs_can_be_created_with_only_the_shared_key() {
         let mut rng = thread_rng();
         let sk0 = PrivateKey::random(&mut rng);
         let sk1 = PrivateKey::random(&mut rng);
         let pk0 = sk0.public_key();
         let pk1 = sk1.public_key();
+        let domain = [7; 32];
 
-        let mut vtmf0 = Vtmf::new(sk0);
-        let mut vtmf1 = Vtmf::new(sk1);
+        let mut vtmf0 = Vtmf::with_domain(sk0, domain);
+        let mut vtmf1 = Vtmf::with_domain(sk1, domain);
         vtmf0.add_key(pk1);
         vtmf1.add_key(pk0);
+        let h = vtmf0.shared_key().point();
+
+        let points: Vec<_> = (0..8).map(map::to_curve).collect();
+        let (m, ..) = vtmf0.mask_all(&points);
+        let pi = rng.sample(Shuffles(m.len()));
+        let (c, r, _) = vtmf0.mask_shuffle(&m, &pi);
+        let proof = ShuffleProof::create_with_key(&domain, &h, &m, &c, &pi, &r);
+        assert_eq!(vtmf1.verify_mask_shuffle(&m, &c, &proof), Ok(()));
+        let proof = ShuffleProof::create_with_key(&[0; 32], &h, &m, &c, &pi, &r);
+        assert_eq!(
+            vtmf1.verify_mask_shuffle(&m, &c, &proof),
+            Err(Error::BadProof)
+        );
+
+        let (c, r, _) = vtmf0.mask_shift(&m, 3);
+        let proof = ShiftProof::create_with_key(&domain, &h, &m, &c, 3, &r);
+        assert_eq!(vtmf1.verify_mask_shift(&m, &c, &proof), Ok(()));
+
+        let (n, ..) = vtmf0.mask_all(&points);
+        let (c, r, _) = vtmf0.mask_shuffle(&m, &pi);
+        let (d, s, _) = vtmf0.mask_shuffle(&n, &pi);
+        let proof = EntanglementProof::create_with_key(
+            &domain,
+            &h,
+            [&m, &n].iter().cloned(),
+            [&c, &d].iter().cloned(),
+            &pi,
+            [&r[..], &s[..]].iter().cloned(),
+        );
+        let verified =
+            vtmf1.verify_entanglement([&m, &n].iter().cloned(), [&c, &d].iter().cloned(), &proof);
+        assert_eq!(verified, Ok(()));
+    }
+
+    #[test]
+    fn vtmf_entangled_mask_shuffling_works() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
 
         let m0: Stack = (0u64..8)
             .map(map::to_curve)
@@ -727,4 +1962,73 @@ mod tests {
         let invalid = vtmf1.verify_entanglement(m.iter(), bad_shuffles.iter(), &proof);
         assert_eq!(invalid, Err(Error::BadProof));
     }
+
+    #[test]
+    fn vtmf_paired_stacks_move_together() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+
+        let cards: Vec<_> = (0u64..6).map(map::to_curve).collect();
+        let enchantments: Vec<_> = (10u64..16).map(map::to_curve).collect();
+        let (m, ..) = vtmf0.mask_paired(&cards, &enchantments);
+        assert_eq!(m.len(), 6);
+
+        let pi = rng.sample(Shuffles(m.len()));
+        let (c, _, proof) = vtmf0.mask_shuffle_paired(&m, &pi);
+        let verified = vtmf1.verify_mask_shuffle_paired(&m, &c, &proof);
+        assert_eq!(verified, Ok(()));
+        let pi2 = rng.sample(Shuffles(m.len()));
+        let (misaligned, ..) = vtmf0.mask_shuffle(m.second(), &pi2);
+        let misaligned = PairedStack::new(c.first().clone(), misaligned).unwrap();
+        let invalid = vtmf1.verify_mask_shuffle_paired(&m, &misaligned, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let (s, _, proof) = vtmf0.mask_shift_paired(&c, 4);
+        let verified = vtmf1.verify_mask_shift_paired(&c, &s, &proof);
+        assert_eq!(verified, Ok(()));
+        let invalid = vtmf1.verify_mask_shift_paired(&m, &s, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let short: Stack = c.first().iter().take(5).cloned().collect();
+        assert_eq!(PairedStack::new(short, c.second().clone()), None);
+    }
+
+    #[test]
+    fn vtmf_empty_stacks_work() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let (vtmf0, vtmf1) = (&vtmfs[0], &vtmfs[1]);
+
+        let empty = Stack::default();
+        let one: Stack = vec![vtmf0.mask(&map::to_curve(0)).0].into();
+
+        let pi = Permutation::identity(0);
+        let (shuffle, secrets, proof) = vtmf0.mask_shuffle(&empty, &pi);
+        assert!(shuffle.is_empty());
+        assert!(secrets.is_empty());
+        let verified = vtmf1.verify_mask_shuffle(&empty, &shuffle, &proof);
+        assert_eq!(verified, Ok(()));
+        let invalid = vtmf1.verify_mask_shuffle(&empty, &one, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let (shift, secrets, proof) = vtmf0.mask_shift(&empty, 0);
+        assert!(shift.is_empty());
+        assert_eq!(vtmf1.apply_shift(&empty, 0, &secrets), Some(empty.clone()));
+        let verified = vtmf1.verify_mask_shift(&empty, &shift, &proof);
+        assert_eq!(verified, Ok(()));
+        let invalid = vtmf1.verify_mask_shift(&empty, &one, &proof);
+        assert_eq!(invalid, Err(Error::BadProof));
+
+        let m = [empty.clone(), empty.clone()];
+        let c = [empty.clone(), empty];
+        let proof = vtmf0.prove_entanglement(
+            m.iter(),
+            c.iter(),
+            &pi,
+            [&secrets[..], &secrets[..]].iter().cloned(),
+        );
+        let verified = vtmf1.verify_entanglement(m.iter(), c.iter(), &proof);
+        assert_eq!(verified, Ok(()));
+    }
 }
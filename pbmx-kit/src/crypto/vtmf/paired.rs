@@ -0,0 +1,71 @@
+use crate::{
+    chain::Id,
+    crypto::vtmf::{EntanglementProof, Stack},
+};
+
+/// A pair of aligned stacks whose tokens always move together
+///
+/// The first stack usually holds the tokens proper, and the second some
+/// hidden attribute of each one. Shuffles and shifts apply the same
+/// permutation to both, with an entanglement proof linking them.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct PairedStack {
+    first: Stack,
+    second: Stack,
+}
+
+impl PairedStack {
+    /// Pairs two stacks, if they have the same length
+    pub fn new(first: Stack, second: Stack) -> Option<Self> {
+        if first.len() != second.len() {
+            return None;
+        }
+        Some(Self { first, second })
+    }
+
+    /// Gets the first stack
+    pub fn first(&self) -> &Stack {
+        &self.first
+    }
+
+    /// Gets the second stack
+    pub fn second(&self) -> &Stack {
+        &self.second
+    }
+
+    /// Gets the number of paired tokens
+    pub fn len(&self) -> usize {
+        self.first.len()
+    }
+
+    /// Tests whether there are no paired tokens
+    pub fn is_empty(&self) -> bool {
+        self.first.is_empty()
+    }
+
+    /// Gets the IDs of both stacks
+    pub fn ids(&self) -> Vec<Id> {
+        vec![self.first.id(), self.second.id()]
+    }
+
+    /// Iterates over both stacks, first then second
+    pub fn iter(&self) -> impl Iterator<Item = &Stack> {
+        vec![&self.first, &self.second].into_iter()
+    }
+
+    /// Splits into the two stacks
+    pub fn into_inner(self) -> (Stack, Stack) {
+        (self.first, self.second)
+    }
+}
+
+/// The proofs of an operation applied to both stacks of a [`PairedStack`]
+#[derive(Clone, Debug)]
+pub struct PairedProof<P> {
+    /// The proof for the first stack
+    pub first: P,
+    /// The proof for the second stack
+    pub second: P,
+    /// The proof that both stacks were permuted alike
+    pub entanglement: EntanglementProof,
+}
@@ -5,9 +5,10 @@ use crate::{
         vtmf::Mask,
     },
     proto,
-    serde::{vec_from_proto, vec_to_proto, Proto},
+    serde::{check_stack_len, vec_from_proto, vec_to_proto, Proto},
     Result,
 };
+use curve25519_dalek::ristretto::CompressedRistretto;
 use std::{
     borrow::{Borrow, BorrowMut},
     iter::FromIterator,
@@ -28,6 +29,7 @@ impl Proto for Stack {
     }
 
     fn from_proto(m: &proto::Stack) -> Result<Self> {
+        check_stack_len(m.masks.len())?;
         Ok(Stack(vec_from_proto(&m.masks)?))
     }
 }
@@ -37,6 +39,15 @@ impl Stack {
     pub fn id(&self) -> Id {
         Id::of(self, b"pbmx-stack-id")
     }
+
+    /// Gets an ID for each token in this stack, in order
+    ///
+    /// These IDs fingerprint the masks themselves, so they change whenever the
+    /// stack is remasked or shuffled and are only stable within one version of
+    /// a stack.
+    pub fn positional_ids(&self) -> Vec<Id> {
+        self.iter().map(|m| Id::of(m, b"pbmx-mask-id")).collect()
+    }
 }
 
 impl Transcribe for Stack {
@@ -46,6 +57,94 @@ impl Transcribe for Stack {
     }
 }
 
+/// A mask with both points compressed
+///
+/// This transcribes exactly like the [`Mask`] it was made from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CompressedMask(pub CompressedRistretto, pub CompressedRistretto);
+
+impl From<&Mask> for CompressedMask {
+    fn from(m: &Mask) -> Self {
+        Self(m.0.compress(), m.1.compress())
+    }
+}
+
+impl Transcribe for CompressedMask {
+    fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
+        b"mask".append_to_transcript(t, label);
+        self.0.append_to_transcript(t, b"c1");
+        self.1.append_to_transcript(t, b"c2");
+    }
+}
+
+/// A stack that keeps the compressed form of its masks
+///
+/// Hashing, serializing and verifying proofs about a stack compress every
+/// mask each time. This caches the compressed masks, at the cost of twice
+/// the memory, for stacks that are used many times.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedStack {
+    stack: Stack,
+    compressed: Vec<CompressedMask>,
+}
+
+impl CompressedStack {
+    /// Compresses all the masks in a stack
+    pub fn new(stack: Stack) -> Self {
+        let compressed = stack.iter().map(CompressedMask::from).collect();
+        Self { stack, compressed }
+    }
+
+    /// Gets the uncompressed stack
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    /// Gets the compressed masks
+    pub fn compressed(&self) -> &[CompressedMask] {
+        &self.compressed
+    }
+
+    /// Gets an ID for this stack, equal to [`Stack::id`]
+    pub fn id(&self) -> Id {
+        Id::of(self, b"pbmx-stack-id")
+    }
+}
+
+impl From<Stack> for CompressedStack {
+    fn from(stack: Stack) -> Self {
+        Self::new(stack)
+    }
+}
+
+impl Transcribe for CompressedStack {
+    fn append_to_transcript<T: TranscriptAppend>(&self, t: &mut T, label: &'static [u8]) {
+        b"stack".append_to_transcript(t, label);
+        self.compressed.append_to_transcript(t, b"$");
+    }
+}
+
+impl Proto for CompressedStack {
+    type Message = proto::Stack;
+
+    fn to_proto(&self) -> Result<proto::Stack> {
+        Ok(proto::Stack {
+            masks: self
+                .compressed
+                .iter()
+                .map(|m| proto::Mask {
+                    c1: m.0.as_bytes().to_vec(),
+                    c2: m.1.as_bytes().to_vec(),
+                })
+                .collect(),
+        })
+    }
+
+    fn from_proto(m: &proto::Stack) -> Result<Self> {
+        Stack::from_proto(m).map(Self::new)
+    }
+}
+
 impl<T> From<T> for Stack
 where
     Vec<Mask>: From<T>,
@@ -157,3 +256,80 @@ where
         self.0.index_mut(index)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{CompressedStack, Stack};
+    use crate::{
+        crypto::{keys::PrivateKey, map, vtmf::Vtmf},
+        serde::{Message, Proto},
+        Error,
+    };
+    use rand::thread_rng;
+
+    #[test]
+    fn positional_ids_change_on_remask() {
+        let vtmf = Vtmf::new(PrivateKey::random(&mut thread_rng()));
+        let stack: Stack = (0..4).map(|i| vtmf.mask(&map::to_curve(i)).0).collect();
+
+        let ids = stack.positional_ids();
+        assert_eq!(ids.len(), stack.len());
+        assert_eq!(ids, stack.clone().positional_ids());
+        let mut sorted = ids.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), ids.len());
+
+        let remasked: Stack = stack.iter().map(|m| vtmf.remask(m).0).collect();
+        let new_ids = remasked.positional_ids();
+        assert!(ids.iter().zip(new_ids.iter()).all(|(a, b)| a != b));
+    }
+
+    #[test]
+    fn compressed_stacks_match_stacks() {
+        let vtmf = Vtmf::new(PrivateKey::random(&mut thread_rng()));
+        let stack: Stack = (0..4).map(|i| vtmf.mask(&map::to_curve(i)).0).collect();
+        let compressed = CompressedStack::new(stack.clone());
+
+        assert_eq!(compressed.id(), stack.id());
+        assert_eq!(compressed.encode().unwrap(), stack.encode().unwrap());
+        let decoded = CompressedStack::decode(&stack.encode().unwrap()).unwrap();
+        assert_eq!(decoded.stack(), &stack);
+    }
+
+    #[test]
+    fn ids_are_order_sensitive_and_survive_encoding() {
+        let vtmf = Vtmf::new(PrivateKey::random(&mut thread_rng()));
+        let stack: Stack = (0..4).map(|i| vtmf.mask(&map::to_curve(i)).0).collect();
+
+        let mut swapped = stack.clone();
+        swapped.swap(0, 1);
+        assert_ne!(stack.id(), swapped.id());
+        let mut reversed = stack.clone();
+        reversed.reverse();
+        assert_ne!(stack.id(), reversed.id());
+
+        let encoded = stack.encode().unwrap();
+        let decoded = Stack::decode(&encoded).unwrap();
+        assert_eq!(decoded.id(), stack.id());
+        assert_eq!(decoded.encode().unwrap(), encoded);
+        assert_eq!(
+            Stack::decode(&swapped.encode().unwrap()).unwrap().id(),
+            swapped.id()
+        );
+    }
+
+    #[test]
+    fn non_canonical_masks_are_rejected() {
+        let vtmf = Vtmf::new(PrivateKey::random(&mut thread_rng()));
+        let stack: Stack = (0..2).map(|i| vtmf.mask(&map::to_curve(i)).0).collect();
+
+        let mut proto = stack.to_proto().unwrap();
+        proto.masks[1].c2[31] |= 0x80;
+        assert_eq!(Stack::from_proto(&proto), Err(Error::Decoding));
+
+        let mut proto = stack.to_proto().unwrap();
+        proto.masks[0].c1.push(0);
+        assert_eq!(Stack::from_proto(&proto), Err(Error::Decoding));
+    }
+}
@@ -4,7 +4,7 @@ use std::fmt::{self, Display, Formatter};
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Error type
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Error {
     /// An encoding error
     Encoding,
@@ -14,6 +14,51 @@ pub enum Error {
     BadSignature,
     /// A proof verification failure
     BadProof,
+    /// A block was signed by a key that is not known
+    UnknownSigner,
+    /// A block's ID does not meet its proof-of-work difficulty
+    InsufficientWork,
+    /// A sealed key could not be opened with the given passphrase
+    WrongPassphrase,
+    /// A payload is not valid in the current state
+    InvalidPayload,
+    /// A checkpoint does not match the current state
+    Diverged,
+    /// A permutation is not valid
+    InvalidPermutation,
+    /// A stack is not known
+    UnknownStack,
+    /// Some parties have not published their secret shares yet
+    MissingShares,
+    /// An unmasked token does not map to a value
+    Unmapped,
+    /// Too many blocks are waiting for the blocks they refer to
+    TooManyPending,
+}
+
+impl Error {
+    /// Gets a stable numeric code for this error
+    ///
+    /// Codes are never reused or renumbered, so bindings can expose them to
+    /// callers that cannot match on the variants.
+    pub fn code(self) -> u32 {
+        match self {
+            Error::Encoding => 1,
+            Error::Decoding => 2,
+            Error::BadSignature => 3,
+            Error::BadProof => 4,
+            Error::UnknownSigner => 5,
+            Error::InsufficientWork => 6,
+            Error::WrongPassphrase => 7,
+            Error::InvalidPayload => 8,
+            Error::Diverged => 9,
+            Error::InvalidPermutation => 10,
+            Error::UnknownStack => 11,
+            Error::MissingShares => 12,
+            Error::Unmapped => 13,
+            Error::TooManyPending => 14,
+        }
+    }
 }
 
 impl Display for Error {
@@ -23,6 +68,16 @@ impl Display for Error {
             Error::Decoding => write!(f, "decoding failure"),
             Error::BadSignature => write!(f, "invalid signature"),
             Error::BadProof => write!(f, "invalid proof"),
+            Error::UnknownSigner => write!(f, "unknown signing key"),
+            Error::InsufficientWork => write!(f, "insufficient proof of work"),
+            Error::WrongPassphrase => write!(f, "wrong passphrase or corrupted key"),
+            Error::InvalidPayload => write!(f, "invalid payload"),
+            Error::Diverged => write!(f, "state does not match checkpoint"),
+            Error::InvalidPermutation => write!(f, "invalid permutation"),
+            Error::UnknownStack => write!(f, "unknown stack"),
+            Error::MissingShares => write!(f, "missing secret shares"),
+            Error::Unmapped => write!(f, "token does not map to a value"),
+            Error::TooManyPending => write!(f, "too many pending blocks"),
         }
     }
 }
@@ -40,3 +95,38 @@ impl Display for InvalidPermutationError {
         write!(f, "invalid permutation")
     }
 }
+
+impl From<InvalidPermutationError> for Error {
+    fn from(_: InvalidPermutationError) -> Self {
+        Error::InvalidPermutation
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Error;
+    use std::collections::HashSet;
+
+    #[test]
+    fn error_codes_are_distinct_and_nonzero() {
+        let all = [
+            Error::Encoding,
+            Error::Decoding,
+            Error::BadSignature,
+            Error::BadProof,
+            Error::UnknownSigner,
+            Error::InsufficientWork,
+            Error::WrongPassphrase,
+            Error::InvalidPayload,
+            Error::Diverged,
+            Error::InvalidPermutation,
+            Error::UnknownStack,
+            Error::MissingShares,
+            Error::Unmapped,
+            Error::TooManyPending,
+        ];
+        let codes: HashSet<_> = all.iter().map(|e| e.code()).collect();
+        assert_eq!(codes.len(), all.len());
+        assert!(!codes.contains(&0));
+    }
+}
@@ -0,0 +1,75 @@
+//! Limits on decoded messages
+
+use crate::{Error, Result};
+use std::cell::Cell;
+
+const DEFAULT_MAX_STACK_LEN: usize = 100_000;
+const DEFAULT_MAX_PAYLOADS: usize = 10_000;
+const DEFAULT_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+thread_local! {
+    // only changed for the duration of a single decode call
+    static LIMITS: Cell<Limits> = Cell::new(Limits::default());
+}
+
+/// Limits enforced when decoding messages
+///
+/// These are passed to
+/// [`Message::decode_with_limits`](crate::serde::Message::decode_with_limits);
+/// plain decoding uses the default ones.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum number of elements in a stack or in any list in a payload
+    pub max_stack_len: usize,
+    /// The maximum number of payloads in a block
+    pub max_payloads: usize,
+    /// The maximum size of an encoded message, in bytes
+    pub max_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_stack_len: DEFAULT_MAX_STACK_LEN,
+            max_payloads: DEFAULT_MAX_PAYLOADS,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+/// Runs a decoding function with the given limits in effect
+pub(crate) fn with_limits<T>(limits: &Limits, f: impl FnOnce() -> T) -> T {
+    struct Restore(Limits);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            LIMITS.with(|l| l.set(self.0));
+        }
+    }
+
+    let _restore = Restore(LIMITS.with(|l| l.replace(*limits)));
+    f()
+}
+
+/// Checks the length of a decoded stack or list
+pub(crate) fn check_stack_len(len: usize) -> Result<()> {
+    check(len, |l| l.max_stack_len)
+}
+
+/// Checks the number of payloads in a decoded block
+pub(crate) fn check_payloads(len: usize) -> Result<()> {
+    check(len, |l| l.max_payloads)
+}
+
+/// Checks the size of an encoded message
+pub(crate) fn check_bytes(len: usize) -> Result<()> {
+    check(len, |l| l.max_bytes)
+}
+
+fn check(len: usize, max: impl FnOnce(Limits) -> usize) -> Result<()> {
+    if len > LIMITS.with(|l| max(l.get())) {
+        Err(Error::Decoding)
+    } else {
+        Ok(())
+    }
+}
@@ -2,9 +2,12 @@
 
 #[macro_use]
 mod bytes;
+mod limits;
 mod protobuf;
+pub(crate) use self::limits::{check_payloads, check_stack_len};
 pub use self::{
     bytes::{FromBase64, ToBase64},
+    limits::Limits,
     protobuf::Proto,
 };
 
@@ -27,8 +30,18 @@ pub trait Message: Sized {
         ))
     }
 
-    /// Decodes a PBMX message into a value
-    fn decode(buf: &[u8]) -> Result<Self, Error>;
+    /// Decodes a PBMX message into a value, within the default limits
+    fn decode(buf: &[u8]) -> Result<Self, Error> {
+        Self::decode_with_limits(buf, &Limits::default())
+    }
+
+    /// Decodes a PBMX message into a value, within the given limits
+    fn decode_with_limits(buf: &[u8], limits: &Limits) -> Result<Self, Error>;
+
+    /// Gets the wire format version of encoded messages
+    fn format_version() -> usize {
+        FORMAT_NUMBER
+    }
 
     /// Decodes a base64 PBMX message into a value
     fn decode_base64(string: &str) -> Result<Self, Error> {
@@ -45,7 +58,7 @@ where
     fn encode(&self) -> Result<Vec<u8>, Error> {
         use prost::Message;
         let msg = self.to_proto()?;
-        let raw_len = self.to_proto()?.encoded_len();
+        let raw_len = msg.encoded_len();
         let delim_len = prost::length_delimiter_len(raw_len);
         let format_len = prost::length_delimiter_len(FORMAT_NUMBER);
         let mut buf = Vec::with_capacity(raw_len + delim_len + format_len);
@@ -55,16 +68,19 @@ where
         Ok(buf)
     }
 
-    fn decode(buf: &[u8]) -> Result<Self, Error> {
+    fn decode_with_limits(buf: &[u8], limits: &Limits) -> Result<Self, Error> {
         use prost::Message;
-        let format = prost::decode_length_delimiter(buf).map_err(|_| Error::Decoding)?;
-        if format != FORMAT_NUMBER {
-            return Err(Error::Decoding);
-        }
-        let format_len = prost::length_delimiter_len(format);
-        let msg = <Self as Proto>::Message::decode_length_delimited(&buf[format_len..])
-            .map_err(|_| Error::Decoding)?;
-        Self::from_proto(&msg)
+        limits::with_limits(limits, || {
+            limits::check_bytes(buf.len())?;
+            let format = prost::decode_length_delimiter(buf).map_err(|_| Error::Decoding)?;
+            if format != FORMAT_NUMBER {
+                return Err(Error::Decoding);
+            }
+            let format_len = prost::length_delimiter_len(format);
+            let msg = <Self as Proto>::Message::decode_length_delimited(&buf[format_len..])
+                .map_err(|_| Error::Decoding)?;
+            Self::from_proto(&msg)
+        })
     }
 }
 
@@ -129,3 +145,61 @@ pub(crate) fn points_from_proto(v: &[Vec<u8>]) -> Result<Vec<RistrettoPoint>, Er
 pub(crate) fn points_to_proto(p: &[RistrettoPoint]) -> Result<Vec<Vec<u8>>, Error> {
     p.iter().map(point_to_proto).collect()
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Message, Proto, FORMAT_NUMBER};
+    use crate::{
+        chain::{Block, Payload},
+        crypto::{
+            keys::{PrivateKey, PublicKey},
+            map,
+            vtmf::{
+                EntanglementProof, Mask, MaskProof, PrivateMaskProof, ShiftProof, ShuffleProof,
+                Stack,
+            },
+        },
+        state::State,
+    };
+    use std::fmt::Debug;
+
+    #[test]
+    fn encoding_is_format_number_then_delimited_message() {
+        let stack: Stack = (0..8).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let encoded = stack.encode().unwrap();
+
+        let mut expected = Vec::new();
+        prost::encode_length_delimiter(FORMAT_NUMBER, &mut expected).unwrap();
+        prost::Message::encode_length_delimited(&stack.to_proto().unwrap(), &mut expected).unwrap();
+        assert_eq!(encoded, expected);
+        assert_eq!(Stack::decode(&encoded).unwrap(), stack);
+        assert_eq!(encoded[0] as usize, Stack::format_version());
+    }
+
+    fn round_trip<T: Message + Debug>(bytes: &[u8]) -> T {
+        assert_eq!(bytes[0] as usize, T::format_version());
+        let value = T::decode(bytes).unwrap();
+        assert_eq!(value.encode().unwrap(), bytes);
+        value
+    }
+
+    #[test]
+    fn golden_vectors_decode() {
+        let sk: PrivateKey = round_trip(include_bytes!("vectors/private_key.bin"));
+        let pk: PublicKey = round_trip(include_bytes!("vectors/public_key.bin"));
+        assert_eq!(sk.public_key(), pk);
+        round_trip::<Stack>(include_bytes!("vectors/stack.bin"));
+        round_trip::<Payload>(include_bytes!("vectors/payload.bin"));
+        round_trip::<MaskProof>(include_bytes!("vectors/mask_proof.bin"));
+        round_trip::<PrivateMaskProof>(include_bytes!("vectors/private_mask_proof.bin"));
+        round_trip::<ShuffleProof>(include_bytes!("vectors/shuffle_proof.bin"));
+        round_trip::<ShiftProof>(include_bytes!("vectors/shift_proof.bin"));
+        round_trip::<EntanglementProof>(include_bytes!("vectors/entanglement_proof.bin"));
+
+        let block: Block = round_trip(include_bytes!("vectors/block.bin"));
+        let mut state = State::new(sk);
+        assert_eq!(state.add_block(&block), Ok(()));
+        assert_eq!(state.stacks.len(), 6);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{chain::Id, crypto::keys::Fingerprint, proto, serde::Proto, Error};
+use std::{collections::HashMap, convert::TryFrom};
+
+/// A cache of blocks whose proofs have already been verified
+///
+/// Each block is recorded along with the shared key it was verified against,
+/// so that entries become stale when the key set changes.
+#[derive(Clone, Default, Debug)]
+pub struct VerificationCache(HashMap<Id, Fingerprint>);
+
+impl VerificationCache {
+    /// Creates a new empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the number of blocks in the cache
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Tests whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Records a block as verified against a given shared key
+    pub fn insert(&mut self, id: Id, key: Fingerprint) {
+        self.0.insert(id, key);
+    }
+
+    /// Tests whether a block has been verified against a given shared key
+    pub fn contains(&self, id: &Id, key: &Fingerprint) -> bool {
+        self.0.get(id) == Some(key)
+    }
+
+    /// Removes all blocks from the cache
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+impl Proto for VerificationCache {
+    type Message = proto::VerificationCache;
+
+    fn to_proto(&self) -> Result<Self::Message, Error> {
+        Ok(proto::VerificationCache {
+            blocks: self
+                .0
+                .iter()
+                .map(|(id, key)| proto::VerifiedBlock {
+                    id: id.to_vec(),
+                    key: key.to_vec(),
+                })
+                .collect(),
+        })
+    }
+
+    fn from_proto(m: &Self::Message) -> Result<Self, Error> {
+        m.blocks
+            .iter()
+            .map(|b| Ok((Id::try_from(&b.id)?, Fingerprint::try_from(&b.key)?)))
+            .collect::<Result<_, Error>>()
+            .map(VerificationCache)
+    }
+}
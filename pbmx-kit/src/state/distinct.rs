@@ -0,0 +1,145 @@
+use crate::{
+    crypto::{
+        keys::Fingerprint,
+        vtmf::{SecretShare, Stack, Vtmf},
+    },
+    Error,
+};
+use curve25519_dalek::{ristretto::RistrettoPoint, traits::Identity};
+use std::collections::HashSet;
+
+/// The next step a party has to take in a distinct check
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DistinctStep {
+    /// The party has to blind the stack
+    Blind,
+    /// The party has to publish its secret shares of the blinded stack
+    Reveal,
+    /// The party has to wait for the other parties
+    Wait,
+    /// The check is complete
+    Done,
+}
+
+/// A check that a masked stack holds no duplicate tokens
+///
+/// Every party blinds the stack in turn with a secret exponent, and then
+/// publishes its secret shares of the fully blinded stack. The unmasked
+/// points are equal exactly when the original tokens are the same points,
+/// and reveal nothing else about them.
+#[derive(Debug, Clone)]
+pub struct DistinctCheck {
+    parties: Vec<Fingerprint>,
+    blinded: Stack,
+    blinders: Vec<Fingerprint>,
+    secrets: Vec<SecretShare>,
+    secret_fp: Vec<Fingerprint>,
+}
+
+impl DistinctCheck {
+    /// Creates a new check of a stack distributed over the given parties
+    pub fn new(parties: Vec<Fingerprint>, stack: Stack) -> Self {
+        let secrets = vec![SecretShare(RistrettoPoint::identity()); stack.len()];
+        Self {
+            parties,
+            blinded: stack,
+            blinders: Vec::new(),
+            secrets,
+            secret_fp: Vec::new(),
+        }
+    }
+
+    /// Gets the stack as blinded so far
+    pub fn blinded(&self) -> &Stack {
+        &self.blinded
+    }
+
+    /// Gets a list of parties that have blinded the stack
+    pub fn blinders(&self) -> &[Fingerprint] {
+        &self.blinders
+    }
+
+    /// Gets a list of parties that have revealed secrets
+    pub fn secret_parties(&self) -> &[Fingerprint] {
+        &self.secret_fp
+    }
+
+    /// Tests whether all parties have blinded the stack
+    pub fn is_blinded(&self) -> bool {
+        self.blinders.len() == self.parties.len()
+    }
+
+    /// Tests whether all secrets for unmasking the blinded stack have been
+    /// collected
+    pub fn is_revealed(&self) -> bool {
+        self.secret_fp.len() == self.parties.len()
+    }
+
+    /// Gets the next step the given party has to take in this check
+    pub fn next_step(&self, party: &Fingerprint) -> DistinctStep {
+        if self.is_revealed() {
+            DistinctStep::Done
+        } else if !self.is_blinded() {
+            if self.blinders.contains(party) {
+                DistinctStep::Wait
+            } else {
+                DistinctStep::Blind
+            }
+        } else if self.secret_fp.contains(party) {
+            DistinctStep::Wait
+        } else {
+            DistinctStep::Reveal
+        }
+    }
+
+    /// Adds a party's blinding of the stack
+    ///
+    /// Only the parties the check was created for can blind, once each, and
+    /// before any secrets are revealed.
+    pub fn add_blinding(&mut self, party: Fingerprint, blinded: Stack) -> Result<(), Error> {
+        if !self.parties.contains(&party)
+            || self.is_blinded()
+            || self.blinders.contains(&party)
+            || blinded.len() != self.blinded.len()
+        {
+            return Err(Error::InvalidPayload);
+        }
+        self.blinded = blinded;
+        self.blinders.push(party);
+        Ok(())
+    }
+
+    /// Adds a party's secret shares of the blinded stack
+    ///
+    /// Only the parties the check was created for can reveal, once each, and
+    /// only after everyone blinded the stack.
+    pub fn add_secrets(&mut self, party: Fingerprint, shares: &[SecretShare]) -> Result<(), Error> {
+        if !self.parties.contains(&party)
+            || !self.is_blinded()
+            || self.secret_fp.contains(&party)
+            || shares.len() != self.secrets.len()
+        {
+            return Err(Error::InvalidPayload);
+        }
+        for (secret, share) in self.secrets.iter_mut().zip(shares.iter()) {
+            secret.0 += share.0;
+        }
+        self.secret_fp.push(party);
+        Ok(())
+    }
+
+    /// Tests whether the stack holds no duplicate tokens, once revealed
+    pub fn result(&self, vtmf: &Vtmf) -> Option<bool> {
+        if !self.is_revealed() {
+            return None;
+        }
+        let mut seen = HashSet::new();
+        Some(
+            self.blinded
+                .iter()
+                .zip(self.secrets.iter())
+                .map(|(m, d)| vtmf.unmask_open(&vtmf.unmask(m, d)))
+                .all(|p| seen.insert(p.compress().to_bytes())),
+        )
+    }
+}
@@ -1,25 +1,235 @@
 //! PBMX state
 
 use crate::{
-    chain::{Block, BlockVisitor, Chain, Id, PayloadVisitor},
+    chain::{Block, BlockVisitor, Chain, Id, Payload, PayloadVisitor},
     crypto::{
+        hash::{HashAlgorithm, Transcribe, TranscriptHash},
         keys::{Fingerprint, PrivateKey, PublicKey},
+        map,
+        perm::Shuffles,
         vtmf::{
-            EntanglementProof, Mask, MaskProof, SecretShare, SecretShareProof, ShiftProof,
-            ShuffleProof, Stack, Vtmf,
+            DistinctProof, EntanglementProof, KeyAdded, Mask, MaskProof, PrivateMaskProof,
+            SecretShare, SecretShareProof, ShiftProof, ShuffleProof, Stack, Vtmf,
         },
     },
+    random::thread_rng,
+    Error,
+};
+use curve25519_dalek::scalar::Scalar;
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+    mem,
 };
-use std::collections::HashMap;
 
 mod stack_map;
-pub use stack_map::{PrivateSecretMap, SecretMap, StackMap};
+pub use stack_map::{NameRank, PrivateSecretMap, SecretMap, StackMap, DECK_SEPARATOR};
 
 mod rng;
-pub use rng::Rng;
+pub use rng::{Rng, DEFAULT_SUM_MAX};
+
+mod cache;
+pub use cache::VerificationCache;
+
+mod tally;
+pub use tally::Tally;
+
+mod reveal;
+pub use reveal::MultiRoundReveal;
+
+mod distinct;
+pub use distinct::{DistinctCheck, DistinctStep};
+
+mod template;
+pub use template::DeckTemplate;
+
+mod pending;
+pub use pending::{PendingBlock, Reference};
+
+mod observer;
+use observer::Observers;
+pub use observer::StateObserver;
 
 type PlayerMap = HashMap<Fingerprint, String>;
+type LateJoinSet = HashSet<Fingerprint>;
 type RngMap = HashMap<String, Rng>;
+type TallyMap = HashMap<String, Tally>;
+type RevealMap = HashMap<Id, MultiRoundReveal>;
+type PeekMap = HashMap<Id, Vec<(Fingerprint, Id)>>;
+type CutCommitMap = HashMap<(Id, Fingerprint), (Id, Option<Id>)>;
+type CutMap = HashMap<Id, usize>;
+type MaskPartyMap = HashMap<Mask, Vec<Fingerprint>>;
+type ShuffleMap = HashMap<Id, (Id, Fingerprint)>;
+type GreaterSet = HashSet<(Mask, Mask)>;
+type FieldSet = HashSet<(Mask, u64, u64)>;
+type DistinctSet = HashSet<Id>;
+type DistinctCheckMap = HashMap<Id, DistinctCheck>;
+type PileMap = HashMap<Id, Vec<Id>>;
+type EqualMultisetSet = HashSet<(Id, Id)>;
+type InsertRngSet = HashSet<String>;
+
+/// The most blocks a state keeps aside waiting for the blocks they refer to
+pub const MAX_PENDING_BLOCKS: usize = 64;
+
+/// An error revealing the values of masked tokens
+#[derive(Debug, Eq, PartialEq)]
+pub enum RevealError {
+    /// The stack is not known
+    UnknownStack,
+    /// Some parties have not published their secret shares yet
+    MissingShares(Vec<Fingerprint>),
+    /// The unmasked token does not map to a value
+    Unmapped,
+}
+
+impl Display for RevealError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RevealError::UnknownStack => write!(f, "unknown stack"),
+            RevealError::Unmapped => write!(f, "token does not map to a value"),
+            RevealError::MissingShares(fps) => {
+                write!(f, "missing secret shares from")?;
+                for fp in fps.iter() {
+                    write!(f, " {}", fp)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for RevealError {}
+
+impl From<RevealError> for Error {
+    fn from(e: RevealError) -> Self {
+        match e {
+            RevealError::UnknownStack => Error::UnknownStack,
+            RevealError::MissingShares(_) => Error::MissingShares,
+            RevealError::Unmapped => Error::Unmapped,
+        }
+    }
+}
+
+/// The kind of payload holding a proof
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProofKind {
+    /// A stack mask
+    Mask,
+    /// A stack shuffle
+    Shuffle,
+    /// A stack shift
+    Shift,
+    /// A set of secret shares of a stack
+    Shares,
+    /// A secret share of an rng
+    RandomReveal,
+    /// A ballot
+    Ballot,
+    /// A secret share of a vote tally
+    Tally,
+    /// An entanglement of stacks
+    Entanglement,
+    /// A comparison of masked values
+    Greater,
+    /// A disclosure of a field of a masked value
+    Field,
+    /// An absence of duplicate tokens in a stack
+    Distinct,
+    /// A blinding of a stack to check for duplicate tokens
+    Blinding,
+    /// A shuffle of stacks piled together, e.g. tokens returned to a deck
+    Reshuffle,
+    /// An equality of a masked stack and an open one as multisets
+    EqualMultiset,
+    /// A shuffle of a stack that is a permutation of a reference stack
+    PermutationOf,
+    /// An opening of a commitment to a peeked stack
+    Peek,
+}
+
+impl ProofKind {
+    /// Tests whether proofs of this kind are standing claims about masked
+    /// values or stacks, rather than steps of some other operation
+    pub fn is_claim(&self) -> bool {
+        matches!(
+            self,
+            ProofKind::Greater
+                | ProofKind::Field
+                | ProofKind::Distinct
+                | ProofKind::EqualMultiset
+                | ProofKind::Peek
+        )
+    }
+}
+
+impl Display for ProofKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            ProofKind::Mask => "mask",
+            ProofKind::Shuffle => "shuffle",
+            ProofKind::Shift => "shift",
+            ProofKind::Shares => "secret",
+            ProofKind::RandomReveal => "rng reveal",
+            ProofKind::Ballot => "ballot",
+            ProofKind::Tally => "tally reveal",
+            ProofKind::Entanglement => "entanglement",
+            ProofKind::Greater => "greater",
+            ProofKind::Field => "field",
+            ProofKind::Distinct => "distinct",
+            ProofKind::Blinding => "blinding",
+            ProofKind::Reshuffle => "reshuffle",
+            ProofKind::EqualMultiset => "equal multiset",
+            ProofKind::PermutationOf => "permutation",
+            ProofKind::Peek => "peek",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A record of a proof found while adding blocks
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProofRecord {
+    /// The block holding the proof
+    pub block: Id,
+    /// The kind of payload holding the proof
+    pub kind: ProofKind,
+    /// The stacks the proof refers to
+    pub stacks: Vec<Id>,
+    /// The party that signed the block
+    pub signer: Fingerprint,
+    /// Whether the payload and its proof verified
+    ///
+    /// Proofs in blocks found in the verification cache are not verified
+    /// again, but count as verified.
+    pub verified: bool,
+}
+
+/// A claim made in the chain, as shown to users
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ClaimView {
+    /// The block holding the claim
+    pub block: Id,
+    /// The kind of claim
+    pub kind: ProofKind,
+    /// The stacks the claim is about
+    pub stacks: Vec<Id>,
+    /// The parties that made the claim
+    pub parties: Vec<Fingerprint>,
+    /// Whether the claim verified
+    pub verified: bool,
+}
+
+/// A problem found while auditing a chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuditFinding {
+    /// The block with the problem
+    pub block: Id,
+    /// The invalid payload, or `None` if the problem is with the block itself
+    pub payload: Option<Id>,
+    /// The problem found
+    pub error: Error,
+}
 
 /// The end state of a chain
 #[derive(Debug)]
@@ -34,289 +244,4157 @@ pub struct State {
     pub stacks: StackMap,
     /// The RNGs
     pub rngs: RngMap,
+    /// The vote tallies
+    pub tallies: TallyMap,
+    /// The multi-round reveals
+    pub reveals: RevealMap,
+    /// The checks for duplicate tokens, by stack
+    pub distinct_checks: DistinctCheckMap,
+    /// The peeks made at each stack, with their commitments
+    pub peeks: PeekMap,
+    /// The pending cut commitments, by source stack and committer, with the
+    /// committer's shifted stack once it is published
+    pub cut_commits: CutCommitMap,
+    /// The revealed cut points, by shifted stack
+    pub cuts: CutMap,
+    /// The shuffles that produced each stack, with their source and shuffler
+    pub shuffles: ShuffleMap,
+    /// The blocks already known to be valid
+    pub verified: VerificationCache,
+    late_joins: LateJoinSet,
+    mask_parties: MaskPartyMap,
+    tokens: Option<HashSet<u64>>,
+    unfixed_tokens: bool,
+    proofs: Vec<ProofRecord>,
+    greater: GreaterSet,
+    fields: FieldSet,
+    distinct: DistinctSet,
+    piles: PileMap,
+    equal_multisets: EqualMultisetSet,
+    insert_rngs: InsertRngSet,
+    pending: Vec<Block>,
+    pending_errors: Vec<(Id, Error)>,
+    observers: Observers,
 }
 
 impl State {
     /// Creates a new blank state with a given private key
+    ///
+    /// The key's owner only becomes a party once its key is published in the
+    /// chain.
     pub fn new(sk: PrivateKey) -> Self {
         Self {
-            vtmf: Vtmf::new(sk),
+            vtmf: Vtmf::unjoined(sk),
             names: PlayerMap::new(),
             chain: Chain::new(),
             stacks: StackMap::new(),
             rngs: RngMap::new(),
+            tallies: TallyMap::new(),
+            reveals: RevealMap::new(),
+            distinct_checks: DistinctCheckMap::new(),
+            peeks: PeekMap::new(),
+            cut_commits: CutCommitMap::new(),
+            cuts: CutMap::new(),
+            shuffles: ShuffleMap::new(),
+            verified: VerificationCache::new(),
+            late_joins: LateJoinSet::new(),
+            mask_parties: MaskPartyMap::new(),
+            tokens: None,
+            unfixed_tokens: false,
+            proofs: Vec::new(),
+            greater: GreaterSet::new(),
+            fields: FieldSet::new(),
+            distinct: DistinctSet::new(),
+            piles: PileMap::new(),
+            equal_multisets: EqualMultisetSet::new(),
+            insert_rngs: InsertRngSet::new(),
+            pending: Vec::new(),
+            pending_errors: Vec::new(),
+            observers: Observers::default(),
+        }
+    }
+
+    /// Registers an observer to be notified of state transitions
+    pub fn add_observer(&mut self, observer: Box<dyn StateObserver>) {
+        self.observers.0.push(observer);
+    }
+
+    /// Declares the set of valid tokens
+    ///
+    /// Once declared, open stacks with tokens outside this set are rejected.
+    pub fn declare_tokens<I: IntoIterator<Item = u64>>(&mut self, tokens: I) {
+        self.tokens = Some(tokens.into_iter().collect());
+    }
+
+    /// Tests whether an open token is valid
+    pub fn is_valid_token(&self, m: &Mask) -> bool {
+        match &self.tokens {
+            Some(tokens) => match map::from_curve(&self.vtmf.unmask_open(m)) {
+                Some(t) => tokens.contains(&t),
+                None => false,
+            },
+            None => true,
         }
     }
 
+    /// Tests whether all open tokens so far were mapped with
+    /// [`map::to_curve_fixed`]
+    ///
+    /// Checks for duplicate tokens compare points rather than values, so
+    /// they can only start while each value has a single point.
+    pub fn has_only_fixed_tokens(&self) -> bool {
+        !self.unfixed_tokens
+    }
+
     /// Adds a block's payloads to this state
-    pub fn add_block(&mut self, b: &Block) -> Result<(), ()> {
+    ///
+    /// Proofs are not verified again for blocks found in the verification
+    /// cache. Fails with the error of the first proof that did not verify, or
+    /// with [`Error::InvalidPayload`] if a payload is otherwise not valid in
+    /// this state.
+    ///
+    /// A block that refers to stacks or generators this state does not know
+    /// yet, and whose parents have not all been added, is kept aside until
+    /// they arrive; see [`pending_blocks`](State::pending_blocks). At most
+    /// [`MAX_PENDING_BLOCKS`] are kept, and further ones fail with
+    /// [`Error::TooManyPending`]. Adding a block also adds any kept block it
+    /// unblocks; those that fail are dropped and reported by
+    /// [`take_pending_errors`](State::take_pending_errors) instead.
+    pub fn add_block(&mut self, b: &Block) -> Result<(), Error> {
+        if self.is_blocked(b) {
+            if self.pending.iter().all(|p| p.id() != b.id()) {
+                if self.pending.len() >= MAX_PENDING_BLOCKS {
+                    return Err(Error::TooManyPending);
+                }
+                self.pending.push(b.clone());
+            }
+            return Ok(());
+        }
+        self.add_ready_block(b)?;
+
+        while let Some(i) = self.pending.iter().position(|p| !self.is_blocked(p)) {
+            let p = self.pending.remove(i);
+            if let Err(e) = self.add_ready_block(&p) {
+                self.pending_errors.push((p.id(), e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the errors of kept blocks that failed once unblocked
+    ///
+    /// Each error comes with the ID of the dropped block, in the order they
+    /// were found.
+    pub fn take_pending_errors(&mut self) -> Vec<(Id, Error)> {
+        mem::take(&mut self.pending_errors)
+    }
+
+    /// Gets the blocks kept aside until the blocks they refer to are added
+    pub fn pending_blocks(&self) -> Vec<PendingBlock> {
+        self.pending
+            .iter()
+            .map(|b| PendingBlock {
+                block: b.id(),
+                missing: pending::missing_references(self, b),
+            })
+            .collect()
+    }
+
+    fn is_blocked(&self, b: &Block) -> bool {
+        let orphan = b.parent_ids().iter().any(|p| !self.chain.contains(p));
+        orphan && !pending::missing_references(self, b).is_empty()
+    }
+
+    fn add_ready_block(&mut self, b: &Block) -> Result<(), Error> {
+        let id = b.id();
+        let key = self.vtmf.shared_key().fingerprint();
+        let verify = !self.verified.contains(&id, &key);
+        self.apply_block(b, verify)?;
+        self.verified.insert(id, key);
+        Ok(())
+    }
+
+    /// Adds a block's payloads to this state without verifying any proofs
+    ///
+    /// This is only safe for blocks that are already known to be valid, e.g.
+    /// when replaying a chain that was fully verified before. It must never
+    /// be used for blocks from untrusted sources. Other validity checks still
+    /// apply, and the block is not recorded in the verification cache.
+    pub fn add_block_trusted(&mut self, b: &Block) -> Result<(), Error> {
+        self.apply_block(b, false)
+    }
+
+    fn apply_block(&mut self, b: &Block, verify: bool) -> Result<(), Error> {
         let mut adder = BlockAdder {
             state: self,
             valid: true,
+            verify,
+            error: Cell::new(None),
+            findings: None,
         };
         b.visit(&mut adder);
         if adder.valid {
             Ok(())
         } else {
-            Err(())
+            Err(adder.error.get().unwrap_or(Error::InvalidPayload))
         }
     }
 
-    /// Adds a stack's private secrets to this state
-    pub fn add_secrets<It>(&mut self, it: It) -> Result<(), ()>
-    where
-        It: Iterator<Item = (Mask, Mask)>,
-    {
-        self.stacks.add_private_secrets(it)
-    }
-}
-
-struct BlockAdder<'a> {
-    state: &'a mut State,
-    valid: bool,
-}
+    /// Replays a whole chain into this state, reporting every problem found
+    ///
+    /// Unlike [`add_block`](State::add_block), this does not stop at the
+    /// first failure. Invalid payloads are reported and skipped while the
+    /// valid ones are still applied, and every block is added to the chain
+    /// so the blocks after it can still be replayed. All proofs are
+    /// verified, whether or not their blocks are in the verification cache.
+    pub fn audit(&mut self, chain: &Chain) -> Vec<AuditFinding> {
+        let mut findings = Vec::new();
+        for b in chain.blocks() {
+            let mut adder = BlockAdder {
+                state: self,
+                valid: true,
+                verify: true,
+                error: Cell::new(None),
+                findings: Some(Vec::new()),
+            };
+            b.visit(&mut adder);
+            findings.extend(adder.findings.unwrap_or_default());
 
-impl<'a> BlockVisitor for BlockAdder<'a> {
-    fn visit_block(&mut self, block: &Block) {
-        for payload in block.payloads() {
-            self.visit_payload(block, payload);
-            if !self.valid {
-                return;
+            // the signer's key may be published by the block itself
+            if let Err(e) = b.verify(&self.vtmf) {
+                findings.push(AuditFinding {
+                    block: b.id(),
+                    payload: None,
+                    error: e,
+                });
             }
         }
-        if self.valid {
-            self.state.chain.add_block(block.clone());
-        }
+        findings
     }
-}
 
-impl<'a> PayloadVisitor for BlockAdder<'a> {
-    fn visit_publish_key(&mut self, block: &Block, name: &str, key: &PublicKey) {
-        self.valid = self.valid && block.signer() == key.fingerprint();
+    /// Gets the records of all proofs found while adding blocks
+    ///
+    /// A block that fails to be added contributes the records of its
+    /// payloads up to the first invalid one.
+    pub fn proof_log(&self) -> Vec<ProofRecord> {
+        self.proofs.clone()
+    }
 
-        if self.valid {
-            self.state.vtmf.add_key(key.clone());
-            self.state.names.insert(key.fingerprint(), name.to_string());
-        }
+    /// Gets all claims found while adding blocks, in chain order
+    ///
+    /// Claims that failed to verify are included, marked as such.
+    pub fn claims_view(&self) -> Vec<ClaimView> {
+        self.proofs
+            .iter()
+            .filter(|record| record.kind.is_claim())
+            .map(|record| ClaimView {
+                block: record.block,
+                kind: record.kind,
+                stacks: record.stacks.clone(),
+                parties: vec![record.signer],
+                verified: record.verified,
+            })
+            .collect()
     }
 
-    fn visit_open_stack(&mut self, _: &Block, stack: &Stack) {
-        self.valid = self.valid && stack.iter().all(Mask::is_open);
+    /// Tests whether a mask was proven to hide a greater value than another
+    pub fn is_proven_greater(&self, a: &Mask, b: &Mask) -> bool {
+        self.greater.contains(&(*a, *b))
+    }
 
-        if self.valid {
-            self.state.stacks.insert(stack.clone());
-        }
+    /// Tests whether a mask was proven to hide a value whose bits under
+    /// `field_mask` equal `field_value`
+    pub fn is_proven_field(&self, mask: &Mask, field_mask: u64, field_value: u64) -> bool {
+        self.fields.contains(&(*mask, field_mask, field_value))
     }
 
-    fn visit_mask_stack(&mut self, _: &Block, source: Id, stack: &Stack, proofs: &[MaskProof]) {
-        self.valid = self.valid
-            && self
-                .state
-                .stacks
-                .get_by_id(&source)
-                .map(|src| {
-                    src.iter()
-                        .zip(stack.iter())
-                        .zip(proofs.iter())
-                        .all(|((a, b), p)| self.state.vtmf.verify_remask(a, b, p).is_ok())
-                })
-                .unwrap_or(false);
+    /// Tests whether a stack was proven to hold no duplicate tokens
+    pub fn is_proven_distinct(&self, id: &Id) -> bool {
+        self.distinct.contains(id)
+    }
 
-        if self.valid {
-            self.state.stacks.insert(stack.clone());
-        }
+    /// Tests whether a masked stack was proven to hold exactly the tokens of
+    /// an open reference stack
+    pub fn is_proven_equal_multiset(&self, hidden: &Id, reference: &Id) -> bool {
+        self.equal_multisets.contains(&(*hidden, *reference))
     }
 
-    fn visit_shuffle_stack(&mut self, _: &Block, source: Id, stack: &Stack, proof: &ShuffleProof) {
-        self.valid = self.valid
-            && self
-                .state
-                .stacks
-                .get_by_id(&source)
-                .map(|src| {
-                    self.state
-                        .vtmf
-                        .verify_mask_shuffle(src, stack, proof)
-                        .is_ok()
-                })
-                .unwrap_or(false);
+    /// Computes the payloads that move some tokens from one stack to the top
+    /// of another
+    ///
+    /// Both stacks are given by name or ID, as in
+    /// [`StackMap::get_by_str`]. The payloads take the tokens at the given
+    /// indices and the remaining tokens from the source, pile the taken
+    /// tokens over the target, and rename the resulting stacks after the
+    /// source and target if those were given by name. All stack IDs are
+    /// computed as they will be when the payloads are applied.
+    ///
+    /// Returns `None` if either stack is unknown or burned, if both are the
+    /// same stack, or if any index is out of range or repeated.
+    pub fn move_tokens(&self, from: &str, indices: &[usize], to: &str) -> Option<Vec<Payload>> {
+        let source = self.stacks.get_by_str(from)?.id();
+        let target = self.stacks.get_by_str(to)?.id();
+        if source == target || !self.stacks.is_live(&source) || !self.stacks.is_live(&target) {
+            return None;
+        }
+        let len = self.stacks.get_by_id(&source)?.len();
+        let mut seen = HashSet::new();
+        if !indices.iter().all(|&i| i < len && seen.insert(i)) {
+            return None;
+        }
+        let rest: Vec<_> = (0..len).filter(|i| !seen.contains(i)).collect();
 
-        if self.valid {
-            self.state.stacks.insert(stack.clone());
+        let (taken_stack, taken) = self.stacks.preview_take(&source, indices)?;
+        let (_, remaining) = self.stacks.preview_take(&source, &rest)?;
+        let pile: Stack = taken_stack
+            .iter()
+            .chain(self.stacks.get_by_id(&target)?.iter())
+            .cloned()
+            .collect();
+        let piled = pile.id();
+
+        let mut payloads = vec![
+            Payload::TakeStack(source, indices.to_vec(), taken),
+            Payload::TakeStack(source, rest, remaining),
+            Payload::PileStacks(vec![taken, target], piled),
+        ];
+        if self.stacks.is_name(from) {
+            payloads.push(Payload::NameStack(remaining, from.into()));
+        }
+        if self.stacks.is_name(to) {
+            payloads.push(Payload::NameStack(piled, to.into()));
         }
+        Some(payloads)
     }
 
-    fn visit_shift_stack(&mut self, _: &Block, source: Id, stack: &Stack, proof: &ShiftProof) {
-        self.valid = self.valid
-            && self
-                .state
-                .stacks
-                .get_by_id(&source)
-                .map(|src| self.state.vtmf.verify_mask_shift(src, stack, proof).is_ok())
-                .unwrap_or(false);
+    /// Gets the name of the generator that picks the position for inserting
+    /// the tokens of one stack into another
+    ///
+    /// The name ties the generator to both stacks, so it has to be created
+    /// for this insertion before anyone knows its value, and no other
+    /// generator can be picked once values are known.
+    pub fn insert_rng_name(source: &Id, inserted: &Id) -> String {
+        format!("insert:{}:{}", source, inserted)
+    }
 
-        if self.valid {
-            self.state.stacks.insert(stack.clone());
+    /// Computes the position the generator for an insertion picks
+    ///
+    /// The position is the generated value modulo one more than the length
+    /// of the stack, so the generator should have a range much larger than
+    /// the stack to keep the bias small. Returns `None` if the stack or the
+    /// generator is unknown, if the generator has not been revealed yet, or
+    /// if it was already used. See [`insert_rng_name`](State::insert_rng_name)
+    /// for the generator used.
+    pub fn insert_position(&self, source: &Id, inserted: &Id) -> Option<usize> {
+        let len = self.stacks.get_by_id(source)?.len() as u64;
+        let name = Self::insert_rng_name(source, inserted);
+        if self.insert_rngs.contains(&name) {
+            return None;
         }
+        let rng = self.rngs.get(&name).filter(|rng| rng.is_revealed())?;
+        let value = rng.gen(&self.vtmf)?;
+        Some((value % (len + 1)) as usize)
     }
 
-    fn visit_take_stack(&mut self, _: &Block, source: Id, indices: &[usize], target: Id) {
-        let src = self.state.stacks.get_by_id(&source);
-        self.valid = self.valid
-            && src
-                .map(|src| indices.iter().all(|i| *i < src.len()))
-                .unwrap_or(false);
+    /// Computes the payloads that insert the tokens of one stack into another
+    /// at a position picked by the revealed generator for this insertion
+    ///
+    /// Both stacks are given by name or ID, as in [`StackMap::get_by_str`].
+    /// The target is renamed after the resulting stack, and the inserted
+    /// stack after an empty one, if they were given by name. See
+    /// [`insert_position`](State::insert_position) for how the position is
+    /// picked.
+    ///
+    /// Returns `None` if either stack is unknown or burned, if both are the
+    /// same stack, or if the generator is unknown, not revealed yet, or
+    /// already used.
+    pub fn insert_at_rng(&self, into: &str, tokens: &str) -> Option<Vec<Payload>> {
+        let source = self.stacks.get_by_str(into)?.id();
+        let inserted = self.stacks.get_by_str(tokens)?.id();
+        if source == inserted {
+            return None;
+        }
+        let at = self.insert_position(&source, &inserted)?;
+        let rng = Self::insert_rng_name(&source, &inserted);
+        let (_, result) = self.stacks.preview_insert(&source, &inserted, at)?;
 
-        if !self.valid {
-            return;
+        let (_, empty) = self.stacks.preview_take(&inserted, &[])?;
+
+        let mut payloads = vec![Payload::InsertStack(source, inserted, rng, result)];
+        if self.stacks.is_name(into) {
+            payloads.push(Payload::NameStack(result, into.into()));
+        }
+        if self.stacks.is_name(tokens) {
+            payloads.push(Payload::TakeStack(inserted, vec![], empty));
+            payloads.push(Payload::NameStack(empty, tokens.into()));
+        }
+        Some(payloads)
+    }
+
+    /// Computes the payloads that return some tokens to a deck and reshuffle
+    /// it
+    ///
+    /// Both stacks are given by name or ID, as in [`StackMap::get_by_str`].
+    /// The payloads pile the returned tokens over the deck and shuffle the
+    /// piled stack, so replaying them checks that the new deck is a
+    /// permutation of the old deck and the returned tokens. The pair is
+    /// recorded in the proof log as a [`ProofKind::Reshuffle`]; see
+    /// [`reshuffled_from`](State::reshuffled_from). The deck is renamed after
+    /// the new deck, and the returned stack after an empty one, if they were
+    /// given by name.
+    ///
+    /// Also returns the new deck and its masking secrets, which should be
+    /// kept as private secrets.
+    ///
+    /// Returns `None` if either stack is unknown or burned, if both are the
+    /// same stack, or if there are no tokens to return.
+    pub fn return_and_reshuffle(
+        &self,
+        tokens: &str,
+        deck: &str,
+    ) -> Option<(Vec<Payload>, Stack, Vec<Scalar>)> {
+        let returned = self.stacks.get_by_str(tokens)?;
+        let returned_id = returned.id();
+        let deck_id = self.stacks.get_by_str(deck)?.id();
+        if returned.is_empty()
+            || returned_id == deck_id
+            || !self.stacks.is_live(&returned_id)
+            || !self.stacks.is_live(&deck_id)
+        {
+            return None;
         }
+        let (piled, piled_id) = self.stacks.preview_pile(&[returned_id, deck_id])?;
+        let (_, empty) = self.stacks.preview_take(&returned_id, &[])?;
 
-        let src = src.unwrap();
-        let stack: Stack = indices.iter().map(|i| src[*i]).collect();
-        self.valid = self.valid && stack.id() == target;
+        let pi = rand::Rng::sample(&mut thread_rng(), Shuffles(piled.len()));
+        let (shuffled, secrets, proof) = self.vtmf.mask_shuffle(&piled, &pi);
 
-        if self.valid {
-            self.state.stacks.insert(stack);
+        let mut payloads = vec![
+            Payload::PileStacks(vec![returned_id, deck_id], piled_id),
+            Payload::ShuffleStack(piled_id, shuffled.clone(), proof),
+        ];
+        if self.stacks.is_name(deck) {
+            payloads.push(Payload::NameStack(shuffled.id(), deck.into()));
         }
+        if self.stacks.is_name(tokens) {
+            payloads.push(Payload::TakeStack(returned_id, vec![], empty));
+            payloads.push(Payload::NameStack(empty, tokens.into()));
+        }
+        Some((payloads, shuffled, secrets))
     }
 
-    fn visit_pile_stack(&mut self, _: &Block, sources: &[Id], target: Id) {
-        let stacks = &self.state.stacks;
-        let mut srcs = sources.iter().map(|id| stacks.get_by_id(&id));
-        self.valid = self.valid && srcs.all(|s| s.is_some());
+    /// Gets the stacks that were piled together and shuffled into a stack
+    ///
+    /// This is only known for stacks that were shuffled right from a pile,
+    /// e.g. by [`return_and_reshuffle`](State::return_and_reshuffle). The
+    /// verified pile and shuffle together establish that the stack is a
+    /// permutation of the tokens of all the returned stacks.
+    pub fn reshuffled_from(&self, id: &Id) -> Option<&[Id]> {
+        let (source, _) = self.shuffles.get(id)?;
+        self.piles.get(source).map(Vec::as_slice)
+    }
 
-        if !self.valid {
-            return;
+    /// Computes the payloads that discard some tokens from a hand and draw as
+    /// many replacements from the top of a deck
+    ///
+    /// All stacks are given by name or ID, as in [`StackMap::get_by_str`].
+    /// The discarded tokens are piled over the discard stack, in the order
+    /// given, and the drawn tokens take the positions of the discarded ones
+    /// in the hand, so the hand keeps its size. The payloads are only takes
+    /// and piles, so replaying them checks that every moved token ends up in
+    /// exactly one of the resulting stacks. Stacks given by name are renamed
+    /// after their new versions, and all stack IDs are computed as they will
+    /// be when the payloads are applied.
+    ///
+    /// Returns `None` if any stack is unknown or burned, if any two are the
+    /// same stack, if no index is given, if any index is out of range or
+    /// repeated, or if the deck has fewer tokens than are discarded.
+    pub fn discard_draw(
+        &self,
+        hand: &str,
+        indices: &[usize],
+        deck: &str,
+        discard: &str,
+    ) -> Option<Vec<Payload>> {
+        let hand_id = self.stacks.get_by_str(hand)?.id();
+        let deck_id = self.stacks.get_by_str(deck)?.id();
+        let discard_id = self.stacks.get_by_str(discard)?.id();
+        if hand_id == deck_id
+            || hand_id == discard_id
+            || deck_id == discard_id
+            || [hand_id, deck_id, discard_id]
+                .iter()
+                .any(|id| !self.stacks.is_live(id))
+        {
+            return None;
         }
+        let len = self.stacks.get_by_id(&hand_id)?.len();
+        let deck_len = self.stacks.get_by_id(&deck_id)?.len();
+        let mut seen = HashSet::new();
+        if indices.is_empty()
+            || indices.len() > deck_len
+            || !indices.iter().all(|&i| i < len && seen.insert(i))
+        {
+            return None;
+        }
+        let kept: Vec<_> = (0..len).filter(|i| !seen.contains(i)).collect();
+        let top: Vec<_> = (0..indices.len()).collect();
+        let bottom: Vec<_> = (indices.len()..deck_len).collect();
 
-        let stack: Stack = srcs
-            .map(Option::unwrap)
-            .flat_map(|stk| stk.iter())
+        let (discarded_stack, discarded) = self.stacks.preview_take(&hand_id, indices)?;
+        let (kept_stack, kept_id) = self.stacks.preview_take(&hand_id, &kept)?;
+        let new_discard: Stack = discarded_stack
+            .iter()
+            .chain(self.stacks.get_by_id(&discard_id)?.iter())
+            .cloned()
+            .collect();
+        let (drawn_stack, drawn) = self.stacks.preview_take(&deck_id, &top)?;
+        let (_, rest) = self.stacks.preview_take(&deck_id, &bottom)?;
+        let joined: Stack = kept_stack
+            .iter()
+            .chain(drawn_stack.iter())
             .cloned()
             .collect();
 
-        self.valid = self.valid && stack.id() == target;
+        // the kept tokens come first in the joined stack, then the drawn ones
+        let mut order = Vec::with_capacity(len);
+        let mut next_kept = 0;
+        for i in 0..len {
+            match indices.iter().position(|&d| d == i) {
+                Some(j) => order.push(kept.len() + j),
+                None => {
+                    order.push(next_kept);
+                    next_kept += 1;
+                }
+            }
+        }
+        let new_hand: Stack = order.iter().map(|&i| joined[i]).collect();
 
-        if self.valid {
-            self.state.stacks.insert(stack.clone());
+        let mut payloads = vec![
+            Payload::TakeStack(hand_id, indices.to_vec(), discarded),
+            Payload::TakeStack(hand_id, kept, kept_id),
+            Payload::PileStacks(vec![discarded, discard_id], new_discard.id()),
+            Payload::TakeStack(deck_id, top, drawn),
+            Payload::TakeStack(deck_id, bottom, rest),
+            Payload::PileStacks(vec![kept_id, drawn], joined.id()),
+            Payload::TakeStack(joined.id(), order, new_hand.id()),
+        ];
+        if self.stacks.is_name(hand) {
+            payloads.push(Payload::NameStack(new_hand.id(), hand.into()));
         }
+        if self.stacks.is_name(deck) {
+            payloads.push(Payload::NameStack(rest, deck.into()));
+        }
+        if self.stacks.is_name(discard) {
+            payloads.push(Payload::NameStack(new_discard.id(), discard.into()));
+        }
+        Some(payloads)
     }
 
-    fn visit_name_stack(&mut self, _: &Block, id: Id, name: &str) {
-        self.valid = self.valid && self.state.stacks.get_by_id(&id).is_some();
+    /// Computes the payloads that cut a stack by a hidden amount and reveal
+    /// this party's share of the new top token
+    ///
+    /// The stack is given by name or ID, as in [`StackMap::get_by_str`]. The
+    /// payloads are a [`Payload::ShiftStack`] of the stack, a
+    /// [`Payload::PublishSharesAt`] for index 0 of the shifted stack, and a
+    /// rename of the shifted stack if the stack was given by name. Since the
+    /// share refers to the shifted stack by ID and is verified against its
+    /// first token, replaying the chain checks that the revealed token is the
+    /// top of the cut stack. The other parties still need to publish their
+    /// shares of that token for it to be fully revealed.
+    ///
+    /// Also returns the shifted stack and its masking secrets, which should
+    /// be kept as private secrets.
+    ///
+    /// Returns `None` if the stack is unknown, burned, or empty.
+    pub fn cut_and_reveal_top(&self, stack: &str) -> Option<(Vec<Payload>, Stack, Vec<Scalar>)> {
+        let source = self.stacks.get_by_str(stack)?;
+        let id = source.id();
+        if source.is_empty() || !self.stacks.is_live(&id) {
+            return None;
+        }
+        let k = rand::Rng::gen_range(&mut thread_rng(), 0..source.len());
+        let (shifted, secrets, proof) = self.vtmf.mask_shift(source, k);
+        let (share, share_proof) = self.vtmf.unmask_share(&shifted[0]);
 
-        if self.valid {
-            self.state.stacks.set_name(id, name.to_string());
+        let shifted_id = shifted.id();
+        let mut payloads = vec![
+            Payload::ShiftStack(id, shifted.clone(), proof),
+            Payload::PublishSharesAt(shifted_id, vec![0], vec![share], vec![share_proof]),
+        ];
+        if self.stacks.is_name(stack) {
+            payloads.push(Payload::NameStack(shifted_id, stack.into()));
         }
+        Some((payloads, shifted, secrets))
     }
 
-    fn visit_publish_shares(
-        &mut self,
-        block: &Block,
-        id: Id,
-        shares: &[SecretShare],
-        proofs: &[SecretShareProof],
-    ) {
-        self.valid = self.valid
-            && self
-                .state
-                .stacks
-                .get_by_id(&id)
-                .map(|src| {
-                    src.iter()
-                        .zip(shares.iter())
-                        .zip(proofs.iter())
-                        .all(|((m, s), p)| {
-                            self.state
-                                .vtmf
-                                .verify_unmask(m, &block.signer(), s, p)
-                                .is_ok()
-                        })
-                })
-                .unwrap_or(false);
+    /// Gets the IDs of all burned stacks
+    pub fn burned_stacks(&self) -> impl Iterator<Item = &Id> {
+        self.stacks.burned()
+    }
 
-        if self.valid {
-            self.state
-                .stacks
-                .add_secret_share(id, block.signer(), shares.to_vec());
-        }
+    /// Gets the IDs of all stacks dealt face up
+    pub fn face_up_stacks(&self) -> impl Iterator<Item = &Id> {
+        self.stacks.face_up()
     }
 
-    fn visit_random_spec(&mut self, _: &Block, name: &str, spec: &str) {
-        let e = self.state.rngs.get(name);
-        self.valid = self.valid && e.map(|rng| rng.spec() == spec).unwrap_or(true);
+    /// Gets the parties that still owe secret shares for a stack dealt face
+    /// up
+    ///
+    /// Returns `None` if the stack is unknown or was not dealt face up. Once
+    /// this is empty, the stack is open to everyone.
+    pub fn awaiting_face_up_shares(&self, id: &Id) -> Option<Vec<Fingerprint>> {
+        if !self.stacks.is_face_up(id) {
+            return None;
+        }
+        let stack = self.stacks.get_by_id(id)?;
+        let mut missing = Vec::new();
+        for m in stack.iter() {
+            for fp in self.missing_shares(m) {
+                if !missing.contains(&fp) {
+                    missing.push(fp);
+                }
+            }
+        }
+        Some(missing)
+    }
 
-        if self.valid && e.is_none() {
-            let rng = Rng::new(self.state.vtmf.parties(), spec);
-            self.valid = self.valid && rng.is_ok();
-            self.state.rngs.insert(name.into(), rng.unwrap());
+    /// Gets the parties that have not published their secret share of a
+    /// token
+    ///
+    /// Once this is empty, the token is open to everyone. Open tokens need no
+    /// shares.
+    pub fn missing_shares(&self, m: &Mask) -> Vec<Fingerprint> {
+        if m.is_open() {
+            return Vec::new();
         }
+        let fps = self
+            .stacks
+            .secrets()
+            .get(m)
+            .map(|(_, fps)| &fps[..])
+            .unwrap_or(&[]);
+        self.parties_of(m)
+            .into_iter()
+            .filter(|fp| !fps.contains(fp))
+            .collect()
     }
 
-    fn visit_random_entropy(&mut self, block: &Block, name: &str, entropy: &Mask) {
-        let fp = block.signer();
-        let e = self.state.rngs.get_mut(name);
-        self.valid = self.valid
-            && e.as_ref()
-                .map(|rng| !rng.is_generated() && !rng.entropy_parties().contains(&fp))
-                .unwrap_or(false);
+    /// Privately peeks at a stack whose secret shares from all other parties
+    /// have been published
+    ///
+    /// Returns the open stack, a random nonce, and a commitment to both that
+    /// can be published in a [`Payload::Peek`](crate::chain::Payload::Peek).
+    /// The nonce must be kept to open the commitment later; see
+    /// [`open_peek`](State::open_peek).
+    pub fn peek(&self, id: &Id) -> Option<(Stack, Id, Id)> {
+        let stack = self.stacks.get_by_id(id)?;
+        let my_fp = self.vtmf.fingerprint();
+        let open = stack
+            .iter()
+            .map(|m| {
+                if m.is_open() {
+                    return Some(*m);
+                }
+                if self.missing_shares(m) != [my_fp] {
+                    return None;
+                }
+                let m = self.unmask_with_public_secrets(m, &my_fp).ok()?;
+                Some(Mask::open(self.vtmf.unmask_open(&m)))
+            })
+            .collect::<Option<Stack>>()?;
+        let nonce = Id::random(&mut thread_rng());
+        let commit = Self::peek_commitment(&open, &nonce);
+        Some((open, nonce, commit))
+    }
 
-        if self.valid {
-            e.unwrap().add_entropy(fp, entropy);
+    /// Computes the payloads that open this party's commitment to a peeked
+    /// stack
+    ///
+    /// The payloads publish this party's secret shares of the stack, if it
+    /// has not yet, so that everyone can unmask it and check the commitment
+    /// made with the given nonce.
+    ///
+    /// Returns `None` if the stack is unknown or burned.
+    pub fn open_peek(&self, id: &Id, nonce: &Id) -> Option<Vec<Payload>> {
+        let stack = self.stacks.get_live(id)?;
+        let my_fp = self.vtmf.fingerprint();
+        let mut payloads = Vec::new();
+        if stack
+            .iter()
+            .any(|m| self.missing_shares(m).contains(&my_fp))
+        {
+            let (shares, proofs) = stack.iter().map(|m| self.vtmf.unmask_share(m)).unzip();
+            payloads.push(Payload::PublishShares(*id, shares, proofs));
         }
+        payloads.push(Payload::OpenPeek(*id, *nonce));
+        Some(payloads)
     }
 
-    fn visit_random_reveal(
-        &mut self,
-        block: &Block,
-        name: &str,
-        share: &SecretShare,
-        proof: &SecretShareProof,
-    ) {
-        let fp = block.signer();
-        let vtmf = &self.state.vtmf;
-        let e = self.state.rngs.get_mut(name);
-        self.valid = self.valid
-            && e.as_ref()
-                .map(|rng| {
-                    !rng.is_revealed()
-                        && !rng.secret_parties().contains(&fp)
-                        && vtmf.verify_unmask(rng.mask(), &fp, share, proof).is_ok()
-                })
-                .unwrap_or(false);
+    /// Computes a proof that a token hides a greater value than another
+    ///
+    /// Both tokens must be visible to this party, either open or with the
+    /// secret shares of all other parties published, and mapped with
+    /// [`map::to_curve_fixed`]. The values must be below `max`.
+    ///
+    /// Returns `None` if any of these conditions does not hold.
+    pub fn prove_greater(&self, a: &Mask, b: &Mask, max: u64) -> Option<Payload> {
+        let my_fp = self.vtmf.fingerprint();
+        let va = self.private_view(a, &my_fp)?;
+        let vb = self.private_view(b, &my_fp)?;
+        let pa = self.vtmf.unmask_private(&va).1;
+        let pb = self.vtmf.unmask_private(&vb).1;
+        if !map::is_fixed(&pa) || !map::is_fixed(&pb) {
+            return None;
+        }
+        let (x, y) = (map::from_curve(&pa)?, map::from_curve(&pb)?);
+        if y >= x || x >= max {
+            return None;
+        }
+        let proof = self.vtmf.prove_greater(&va, &vb, x, y, max);
+        Some(Payload::ProveGreater(*a, *b, max as usize, proof))
+    }
 
-        if self.valid {
-            e.unwrap().add_secret(fp, share);
+    /// Reveals the values of all tokens in a stack to this party
+    ///
+    /// Applies all published secret shares, the local private key, and any
+    /// private secrets from local shuffles. Fails naming the parties whose
+    /// shares are still missing.
+    pub fn reveal_values(&self, id: &Id) -> Result<Vec<u64>, RevealError> {
+        let stack = self.stacks.get_by_id(id).ok_or(RevealError::UnknownStack)?;
+        self.reveal_stack(stack)
+    }
+
+    /// Computes the values in one stack that are not in another
+    ///
+    /// This is a multiset difference: each value in `partial` cancels out one
+    /// equal value in `full`. The remaining values are kept in the order they
+    /// appear in `full`. Fails if either stack cannot be fully revealed to
+    /// this party.
+    pub fn revealed_difference(
+        &self,
+        full: &Stack,
+        partial: &Stack,
+    ) -> Result<Vec<u64>, RevealError> {
+        let full = self.reveal_stack(full)?;
+        let mut counts = HashMap::new();
+        for x in self.reveal_stack(partial)? {
+            *counts.entry(x).or_insert(0usize) += 1;
         }
+        Ok(full
+            .into_iter()
+            .filter(|x| match counts.get_mut(x) {
+                Some(n) if *n > 0 => {
+                    *n -= 1;
+                    false
+                }
+                _ => true,
+            })
+            .collect())
     }
 
-    fn visit_prove_entanglement(
-        &mut self,
-        _block: &Block,
-        source_ids: &[Id],
-        shuffle_ids: &[Id],
-        proof: &EntanglementProof,
-    ) {
-        let stacks = &self.state.stacks;
-        let sources: Vec<_> = source_ids.iter().map(|id| stacks.get_by_id(id)).collect();
-        let shuffles: Vec<_> = shuffle_ids.iter().map(|id| stacks.get_by_id(id)).collect();
+    fn reveal_stack(&self, stack: &Stack) -> Result<Vec<u64>, RevealError> {
+        let mut values = Vec::with_capacity(stack.len());
+        let mut missing = Vec::new();
+        for m in stack.iter() {
+            match self.reveal_token(m) {
+                Ok(x) => values.push(x),
+                Err(RevealError::MissingShares(fps)) => {
+                    for fp in fps {
+                        if !missing.contains(&fp) {
+                            missing.push(fp);
+                        }
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if missing.is_empty() {
+            Ok(values)
+        } else {
+            Err(RevealError::MissingShares(missing))
+        }
+    }
 
-        self.valid = self.valid
-            && sources.iter().all(Option::is_some)
-            && shuffles.iter().all(Option::is_some);
+    /// Reveals the value of a single token to this party
+    ///
+    /// See [`reveal_values`](State::reveal_values).
+    pub fn reveal_token(&self, m: &Mask) -> Result<u64, RevealError> {
+        let my_fp = self.vtmf.fingerprint();
+        let unmasked = self
+            .unmask_with_public_secrets(m, &my_fp)
+            .or_else(|missing| {
+                let mut m = *m;
+                while let Some(d) = self.stacks.private_secrets().get(&m) {
+                    m -= d;
+                    if let Ok(u) = self.unmask_with_public_secrets(&m, &my_fp) {
+                        return Ok(u);
+                    }
+                }
+                Err(missing)
+            })?;
+        map::from_curve(&self.vtmf.unmask_open(&unmasked)).ok_or(RevealError::Unmapped)
+    }
 
-        if !self.valid {
-            return;
+    /// Tests whether a party published its key after masking had already
+    /// happened
+    ///
+    /// Such a party cannot help reveal anything masked before it joined.
+    pub fn is_late_join(&self, fp: &Fingerprint) -> bool {
+        self.late_joins.contains(fp)
+    }
+
+    /// Tests whether some token in a stack was masked before this party
+    /// joined
+    ///
+    /// Such tokens can only be revealed to this party once all parties that
+    /// masked them publish their secret shares.
+    pub fn masked_before_join(&self, id: &Id) -> bool {
+        let my_fp = self.vtmf.fingerprint();
+        self.stacks
+            .get_by_id(id)
+            .into_iter()
+            .flat_map(|stack| stack.iter())
+            .any(|m| !m.is_open() && !self.parties_of(m).contains(&my_fp))
+    }
+
+    /// Tests whether a stack results from a sequence of shuffles of another
+    /// in which every party took part
+    ///
+    /// Each step in the sequence was verified when its block was added, so a
+    /// positive result means no single party knows the final order.
+    pub fn is_jointly_shuffled(&self, result: &Id, source: &Id) -> bool {
+        match self.shufflers(result, source) {
+            Some(shufflers) => {
+                !shufflers.is_empty() && self.vtmf.fingerprints().all(|fp| shufflers.contains(&fp))
+            }
+            None => false,
         }
+    }
 
-        let sources = sources.iter().map(|s| s.unwrap());
-        let shuffles = shuffles.iter().map(|s| s.unwrap());
+    /// Tests whether a stack results from a sequence of shuffles of another,
+    /// or is that same stack
+    ///
+    /// Each step in the sequence was verified when its block was added, so a
+    /// positive result means the stack holds the same tokens as the
+    /// reference, in some order.
+    pub fn is_permutation_of(&self, result: &Id, reference: &Id) -> bool {
+        self.shufflers(result, reference).is_some()
+    }
 
+    fn shufflers(&self, result: &Id, source: &Id) -> Option<HashSet<Fingerprint>> {
+        let mut shufflers = HashSet::new();
+        let mut current = result;
+        let mut steps = 0;
+        while current != source {
+            match self.shuffles.get(current) {
+                Some((previous, fp)) if steps < self.shuffles.len() => {
+                    shufflers.insert(*fp);
+                    current = previous;
+                    steps += 1;
+                }
+                _ => return None,
+            }
+        }
+        Some(shufflers)
+    }
+
+    fn parties_of(&self, m: &Mask) -> Vec<Fingerprint> {
+        self.mask_parties
+            .get(m)
+            .cloned()
+            .unwrap_or_else(|| self.vtmf.fingerprints().collect())
+    }
+
+    fn unmask_publicly(&self, stack: &Stack) -> Option<Stack> {
+        stack
+            .iter()
+            .map(|m| {
+                if m.is_open() {
+                    return Some(*m);
+                }
+                if !self.missing_shares(m).is_empty() {
+                    return None;
+                }
+                let (d, _) = self.stacks.secrets().get(m)?;
+                Some(Mask::open(self.vtmf.unmask_open(&self.vtmf.unmask(m, d))))
+            })
+            .collect()
+    }
+
+    // The token with the secret shares of all parties but the given one
+    // removed; open and publicly unmasked tokens are seen as open tokens.
+    fn private_view(&self, m: &Mask, fp: &Fingerprint) -> Option<Mask> {
+        if m.is_open() {
+            return Some(*m);
+        }
+        let missing = self.missing_shares(m);
+        let view = match self.stacks.secrets().get(m) {
+            Some((d, _)) => self.vtmf.unmask(m, d),
+            None => *m,
+        };
+        if missing.is_empty() {
+            Some(Mask::open(self.vtmf.unmask_open(&view)))
+        } else if missing == [*fp] {
+            Some(view)
+        } else {
+            None
+        }
+    }
+
+    fn unmask_with_public_secrets(
+        &self,
+        m: &Mask,
+        my_fp: &Fingerprint,
+    ) -> Result<Mask, RevealError> {
+        if m.is_open() {
+            return Ok(*m);
+        }
+        let parties = self.parties_of(m);
+        let (m, fps) = match self.stacks.secrets().get(m) {
+            Some((d, fps)) => (self.vtmf.unmask(m, d), &fps[..]),
+            None => (*m, &[][..]),
+        };
+        let missing: Vec<_> = parties
+            .iter()
+            .filter(|fp| *fp != my_fp && !fps.contains(fp))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            return Err(RevealError::MissingShares(missing));
+        }
+        if fps.contains(my_fp) || !parties.contains(my_fp) {
+            Ok(m)
+        } else {
+            Ok(self.vtmf.unmask_private(&m))
+        }
+    }
+
+    /// Computes a hash of the public state
+    ///
+    /// This covers the players, the shared key, the stacks with their names,
+    /// tags, and burned and face-up status, the published secret shares, the
+    /// random number generators, the tallies, the multi-round reveals, the
+    /// peeks and cuts, and the proven claims along with the checks in
+    /// progress. Parties that added the same blocks get the same hash, whatever
+    /// their private secrets, so it can be committed to in a
+    /// [`Payload::Checkpoint`](crate::chain::Payload::Checkpoint).
+    pub fn digest(&self) -> Id {
+        let mut h = TranscriptHash::new(b"pbmx-state");
+
+        let mut players: Vec<_> = self.names.iter().collect();
+        players.sort();
+        h.append(b"players", &players.len());
+        for (fp, name) in players {
+            h.append(b"player", fp);
+            h.append(b"name", name);
+        }
+        h.append(b"key", &self.vtmf.shared_key().fingerprint());
+
+        let mut ids: Vec<_> = self.stacks.ids().collect();
+        ids.sort();
+        h.append(b"stacks", &ids.len());
+        for id in ids {
+            h.append(b"stack", id);
+            h.append(b"burned", &self.stacks.is_burned(id));
+            h.append(b"face_up", &self.stacks.is_face_up(id));
+            let mut tags: Vec<_> = self.stacks.tags(id).map(String::from).collect();
+            tags.sort();
+            h.append(b"tags", &tags);
+        }
+        let mut names: Vec<_> = self.stacks.names().collect();
+        names.sort_unstable();
+        h.append(b"names", &names.len());
+        for name in names {
+            if let Some(stack) = self.stacks.get_by_name(name) {
+                h.append(b"name", &name.to_string());
+                h.append(b"named", &stack.id());
+            }
+        }
+
+        let mut rngs: Vec<_> = self.rngs.iter().collect();
+        rngs.sort_by(|a, b| a.0.cmp(b.0));
+        h.append(b"rngs", &rngs.len());
+        for (name, rng) in rngs {
+            h.append(b"rng", name);
+            h.append(b"spec", &rng.spec());
+            h.append(b"entropy", rng.mask());
+            if let Some(beacon) = rng.beacon() {
+                beacon.append_to_transcript(&mut h, b"beacon");
+            }
+            let mut entropy = rng.entropy_parties().to_vec();
+            entropy.sort();
+            h.append(b"entropy_parties", &entropy);
+            let mut secrets = rng.secret_parties().to_vec();
+            secrets.sort();
+            h.append(b"secret_parties", &secrets);
+        }
+
+        let mut secrets: Vec<_> = self.stacks.secrets().iter().collect();
+        secrets.sort_by_key(|(m, _)| mask_key(m));
+        h.append(b"secrets", &secrets.len());
+        for (m, (share, owners)) in secrets {
+            let mut owners = owners.clone();
+            owners.sort();
+            h.append(b"token", m);
+            h.append(b"share", share);
+            h.append(b"owners", &owners);
+        }
+
+        let mut tallies: Vec<_> = self.tallies.iter().collect();
+        tallies.sort_by(|a, b| a.0.cmp(b.0));
+        h.append(b"tallies", &tallies.len());
+        for (topic, tally) in tallies {
+            let mut voters = tally.voters().to_vec();
+            voters.sort();
+            let mut secrets = tally.secret_parties().to_vec();
+            secrets.sort();
+            h.append(b"tally", topic);
+            h.append(b"ballots", tally.mask());
+            h.append(b"voters", &voters);
+            h.append(b"secret_parties", &secrets);
+        }
+
+        let mut parties: Vec<_> = self.vtmf.fingerprints().collect();
+        parties.sort();
+        let mut reveals: Vec<_> = self.reveals.iter().collect();
+        reveals.sort_by(|a, b| a.0.cmp(b.0));
+        h.append(b"reveals", &reveals.len());
+        for (id, reveal) in reveals {
+            h.append(b"reveal", id);
+            h.append(b"rounds", &reveal.rounds());
+            h.append(b"round", &reveal.round());
+            for fp in parties.iter() {
+                h.append(b"committed", &reveal.has_committed(fp));
+                h.append(b"opened", &reveal.has_opened(fp));
+            }
+            h.append(b"outputs", &reveal.outputs().to_vec());
+        }
+
+        let mut checks: Vec<_> = self.distinct_checks.iter().collect();
+        checks.sort_by(|a, b| a.0.cmp(b.0));
+        h.append(b"distinct_checks", &checks.len());
+        for (id, check) in checks {
+            let mut blinders = check.blinders().to_vec();
+            blinders.sort();
+            let mut secrets = check.secret_parties().to_vec();
+            secrets.sort();
+            h.append(b"check", id);
+            h.append(b"blinded", check.blinded());
+            h.append(b"blinders", &blinders);
+            h.append(b"secret_parties", &secrets);
+        }
+
+        let mut peeks: Vec<_> = self.peeks.iter().collect();
+        peeks.sort_by(|a, b| a.0.cmp(b.0));
+        h.append(b"peeks", &peeks.len());
+        for (id, commits) in peeks {
+            let mut commits = commits.clone();
+            commits.sort();
+            h.append(b"peeked", id);
+            h.append(b"commits", &commits.len());
+            for (fp, commit) in commits {
+                h.append(b"peeker", &fp);
+                h.append(b"commit", &commit);
+            }
+        }
+
+        let mut cut_commits: Vec<_> = self.cut_commits.iter().collect();
+        cut_commits.sort_by(|a, b| a.0.cmp(b.0));
+        h.append(b"cut_commits", &cut_commits.len());
+        for ((id, fp), (commit, target)) in cut_commits {
+            h.append(b"source", id);
+            h.append(b"committer", fp);
+            h.append(b"commit", commit);
+            h.append(b"shifted", &target.is_some());
+            if let Some(target) = target {
+                h.append(b"target", target);
+            }
+        }
+        let mut cuts: Vec<_> = self.cuts.iter().collect();
+        cuts.sort();
+        h.append(b"cuts", &cuts.len());
+        for (id, k) in cuts {
+            h.append(b"cut", id);
+            h.append(b"shift", k);
+        }
+
+        let mut greater: Vec<_> = self.greater.iter().collect();
+        greater.sort_by_key(|(a, b)| (mask_key(a), mask_key(b)));
+        h.append(b"greater", &greater.len());
+        for (a, b) in greater {
+            h.append(b"a", a);
+            h.append(b"b", b);
+        }
+        let mut fields: Vec<_> = self.fields.iter().collect();
+        fields.sort_by_key(|(m, field_mask, value)| (mask_key(m), *field_mask, *value));
+        h.append(b"fields", &fields.len());
+        for (m, field_mask, value) in fields {
+            h.append(b"token", m);
+            h.append(b"field_mask", field_mask);
+            h.append(b"field_value", value);
+        }
+        let mut distinct: Vec<_> = self.distinct.iter().collect();
+        distinct.sort();
+        h.append(b"distinct", &distinct);
+        let mut equal: Vec<_> = self.equal_multisets.iter().collect();
+        equal.sort();
+        h.append(b"equal_multisets", &equal.len());
+        for (a, b) in equal {
+            h.append(b"a", a);
+            h.append(b"b", b);
+        }
+
+        let mut buf = [0; 32];
+        h.finish(&mut buf);
+        buf.into()
+    }
+
+    /// Computes the commitment to a peeked stack
+    pub fn peek_commitment(open: &Stack, nonce: &Id) -> Id {
+        let mut h = TranscriptHash::new(b"peek");
+        h.append(b"stack", open);
+        h.append(b"nonce", nonce);
+        let mut buf = [0; 32];
+        h.finish(&mut buf);
+        buf.into()
+    }
+
+    /// Computes the commitment to a cut point
+    ///
+    /// The commitment can be published in a
+    /// [`Payload::CommitShift`](crate::chain::Payload::CommitShift) before the
+    /// stack is cut, and opened with a
+    /// [`Payload::RevealShift`](crate::chain::Payload::RevealShift) after.
+    pub fn cut_commitment(source: &Id, k: usize, nonce: &Id) -> Id {
+        let mut h = TranscriptHash::new(b"cut");
+        h.append(b"stack", source);
+        h.append(b"shift", &k);
+        h.append(b"nonce", nonce);
+        let mut buf = [0; 32];
+        h.finish(&mut buf);
+        buf.into()
+    }
+
+    /// Gets this party's next payload in a multi-round reveal
+    ///
+    /// Starts the reveal if it does not exist yet. Returns a
+    /// [`Payload::CommitShares`](crate::chain::Payload::CommitShares) until
+    /// this party has committed in the current round, then a
+    /// [`Payload::OpenShares`](crate::chain::Payload::OpenShares) once all
+    /// parties have. Returns `None` while waiting for others, once all rounds
+    /// are done, or if an existing reveal has a different number of rounds.
+    pub fn multi_round_reveal(&self, id: Id, rounds: u64) -> Option<Payload> {
+        let fp = self.vtmf.fingerprint();
+        let (round, open) = match self.reveals.get(&id) {
+            None => (0, false),
+            Some(r) if r.rounds() != rounds || r.is_complete() => return None,
+            Some(r) if !r.has_committed(&fp) => (r.round(), false),
+            Some(r) if r.is_committed() && !r.has_opened(&fp) => (r.round(), true),
+            Some(_) => return None,
+        };
+        if rounds == 0 {
+            return None;
+        }
+
+        // shares are derived from the private key, so nothing needs storing
+        // between committing and opening
+        let mut h = TranscriptHash::new(b"reveal-share");
+        h.append(b"key", &self.vtmf.private_key().exponent());
+        h.append(b"reveal", &id);
+        h.append(b"round", &round);
+        let mut buf = [0; 32];
+        h.finish(&mut buf);
+        let share = buf.into();
+
+        Some(if open {
+            Payload::OpenShares(id, round, share)
+        } else {
+            let commit = MultiRoundReveal::commitment(&id, round, &fp, &share);
+            Payload::CommitShares(id, rounds, round, commit)
+        })
+    }
+
+    /// Gets this party's next payload in checking that a stack holds no
+    /// duplicate tokens
+    ///
+    /// This is a [`Payload::ProveDistinct`](crate::chain::Payload::ProveDistinct)
+    /// until this party has blinded the stack, then a
+    /// [`Payload::RevealDistinct`](crate::chain::Payload::RevealDistinct)
+    /// once all parties have. Returns `None` while waiting for others, once
+    /// the check is done, if the stack is not known, or if a check cannot
+    /// start because some open tokens were not mapped with
+    /// [`map::to_curve_fixed`].
+    pub fn prove_distinct(&self, id: &Id) -> Option<Payload> {
+        let fp = self.vtmf.fingerprint();
+        let check = match self.distinct_checks.get(id) {
+            Some(check) => check.clone(),
+            None if self.unfixed_tokens => return None,
+            None => DistinctCheck::new(
+                self.vtmf.fingerprints().collect(),
+                self.stacks.get_by_id(id)?.clone(),
+            ),
+        };
+        match check.next_step(&fp) {
+            DistinctStep::Blind => {
+                let (blinded, proof) = self.vtmf.prove_distinct(check.blinded());
+                Some(Payload::ProveDistinct(*id, blinded, proof))
+            }
+            DistinctStep::Reveal => {
+                let (shares, proofs) = check
+                    .blinded()
+                    .iter()
+                    .map(|m| self.vtmf.unmask_share(m))
+                    .unzip();
+                Some(Payload::RevealDistinct(*id, shares, proofs))
+            }
+            DistinctStep::Wait | DistinctStep::Done => None,
+        }
+    }
+
+    /// Adds a stack's private secrets to this state
+    pub fn add_secrets<It>(&mut self, it: It) -> Result<(), Error>
+    where
+        It: Iterator<Item = (Mask, Mask)>,
+    {
+        self.stacks.add_private_secrets(it)
+    }
+
+    /// Adds published secrets exported from another state to this state
+    ///
+    /// This lets a substitute take over revealing stacks from a party.
+    pub fn add_published_secrets<It>(&mut self, it: It)
+    where
+        It: Iterator<Item = (Mask, (SecretShare, Vec<Fingerprint>))>,
+    {
+        self.stacks.add_published_secrets(it);
+    }
+}
+
+struct BlockAdder<'a> {
+    state: &'a mut State,
+    valid: bool,
+    verify: bool,
+    error: Cell<Option<Error>>,
+    findings: Option<Vec<AuditFinding>>,
+}
+
+impl<'a> BlockAdder<'a> {
+    fn check<F: FnOnce() -> crate::Result<()>>(&self, f: F) -> bool {
+        !self.verify || record_error(&self.error, f())
+    }
+
+    fn live_stack(&self, id: &Id) -> Option<&Stack> {
+        self.state.stacks.get_live(id)
+    }
+
+    fn record_mask_parties(&mut self, stack: &Stack) {
+        let parties: Vec<_> = self.state.vtmf.fingerprints().collect();
+        for m in stack.iter() {
+            self.state.mask_parties.insert(*m, parties.clone());
+        }
+    }
+
+    fn record_proof(&mut self, block: &Block, kind: ProofKind, stacks: Vec<Id>) {
+        self.state.proofs.push(ProofRecord {
+            block: block.id(),
+            kind,
+            stacks,
+            signer: block.signer(),
+            verified: self.valid,
+        });
+    }
+
+    fn notify<F: Fn(&mut dyn StateObserver)>(&mut self, f: F) {
+        for o in self.state.observers.0.iter_mut() {
+            f(o.as_mut());
+        }
+    }
+
+    fn take_stack(&self, source: Id, indices: &[usize], target: Id) -> Option<Stack> {
+        self.state
+            .stacks
+            .preview_take(&source, indices)
+            .filter(|(_, id)| *id == target)
+            .map(|(stack, _)| stack)
+    }
+}
+
+/// Records the first error seen while adding a block, and tests for success
+fn record_error(error: &Cell<Option<Error>>, r: crate::Result<()>) -> bool {
+    match r {
+        Ok(()) => true,
+        Err(e) => {
+            if error.get().is_none() {
+                error.set(Some(e));
+            }
+            false
+        }
+    }
+}
+
+/// Gets a key that orders masks by their compressed points
+fn mask_key(m: &Mask) -> ([u8; 32], [u8; 32]) {
+    (m.0.compress().to_bytes(), m.1.compress().to_bytes())
+}
+
+impl<'a> BlockVisitor for BlockAdder<'a> {
+    fn visit_block(&mut self, block: &Block) {
+        let genesis = self.state.chain.is_empty();
+        for payload in block.payloads() {
+            self.visit_payload(block, payload);
+            if self.valid {
+                continue;
+            }
+            match self.findings.as_mut() {
+                Some(findings) => {
+                    findings.push(AuditFinding {
+                        block: block.id(),
+                        payload: Some(payload.id()),
+                        error: self.error.take().unwrap_or(Error::InvalidPayload),
+                    });
+                    self.valid = true;
+                }
+                None => return,
+            }
+        }
+        if self.valid {
+            self.state.chain.add_block(block.clone());
+            // the genesis proofs cannot include its own id, but all later
+            // ones are bound to this game
+            if genesis {
+                let mut domain = [0; 32];
+                domain.copy_from_slice(&block.id());
+                self.state.vtmf.set_domain(domain);
+            }
+        }
+    }
+}
+
+impl<'a> PayloadVisitor for BlockAdder<'a> {
+    fn visit_publish_key(&mut self, block: &Block, name: &str, key: &PublicKey) {
+        let fp = self.state.vtmf.fingerprint_of(key);
+        self.valid = self.valid && block.signer() == fp;
+
+        if self.valid {
+            // a late join still counts, the new party just cannot help reveal
+            // anything masked before it
+            if self.state.vtmf.add_key(key.clone()) == KeyAdded::Late {
+                self.state.late_joins.insert(fp);
+            }
+            self.state.names.insert(fp, name.to_string());
+            self.notify(|o| o.on_key_published(name, fp));
+        }
+    }
+
+    fn visit_rename_player(&mut self, block: &Block, fp: Fingerprint, name: &str) {
+        // players can only rename themselves, and only after joining
+        self.valid = self.valid && block.signer() == fp && self.state.names.contains_key(&fp);
+
+        if self.valid {
+            self.state.names.insert(fp, name.to_string());
+            self.notify(|o| o.on_player_renamed(name, fp));
+        }
+    }
+
+    fn visit_commit_shares(&mut self, block: &Block, id: Id, rounds: u64, round: u64, commit: Id) {
+        let parties = self.state.vtmf.fingerprints().collect();
+        let mut reveal = self
+            .state
+            .reveals
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| MultiRoundReveal::new(parties, rounds));
+        self.valid = self.valid
+            && reveal.rounds() == rounds
+            && record_error(
+                &self.error,
+                reveal.add_commit(block.signer(), round, commit),
+            );
+
+        if self.valid {
+            self.state.reveals.insert(id, reveal);
+        }
+    }
+
+    fn visit_open_shares(&mut self, block: &Block, id: Id, round: u64, share: Id) {
+        let fp = block.signer();
+        let e = self.state.reveals.get_mut(&id);
+        self.valid = self.valid
+            && record_error(
+                &self.error,
+                e.ok_or(Error::InvalidPayload)
+                    .and_then(|r| r.add_share(&id, fp, round, share)),
+            );
+    }
+
+    fn visit_open_stack(&mut self, _: &Block, stack: &Stack) {
+        self.valid = self.valid
+            && stack
+                .iter()
+                .all(|m| m.is_open() && self.state.is_valid_token(m));
+
+        if self.valid {
+            self.state.unfixed_tokens =
+                self.state.unfixed_tokens || !stack.iter().all(|m| map::is_fixed(&m.1));
+            self.state.stacks.insert(stack.clone());
+            self.notify(|o| o.on_stack_changed(stack.id()));
+        }
+    }
+
+    fn visit_deck_template(&mut self, block: &Block, name: &str) {
+        match DeckTemplate::get(name) {
+            Some(template) => self.visit_open_stack(block, &template.stack()),
+            None => self.valid = false,
+        }
+    }
+
+    fn visit_mask_stack(&mut self, block: &Block, source: Id, stack: &Stack, proofs: &[MaskProof]) {
+        self.valid = self.valid
+            && self
+                .live_stack(&source)
+                .map(|src| {
+                    src.iter()
+                        .zip(stack.iter())
+                        .zip(proofs.iter())
+                        .all(|((a, b), p)| self.check(|| self.state.vtmf.verify_remask(a, b, p)))
+                })
+                .unwrap_or(false);
+        self.record_proof(block, ProofKind::Mask, vec![source, stack.id()]);
+
+        if self.valid {
+            self.state.vtmf.mark_masked();
+            self.record_mask_parties(stack);
+            self.state.stacks.insert(stack.clone());
+            self.notify(|o| o.on_stack_changed(stack.id()));
+        }
+    }
+
+    fn visit_shuffle_stack(
+        &mut self,
+        block: &Block,
+        source: Id,
+        stack: &Stack,
+        proof: &ShuffleProof,
+    ) {
+        self.valid = self.valid
+            && self
+                .live_stack(&source)
+                .map(|src| self.check(|| self.state.vtmf.verify_mask_shuffle(src, stack, proof)))
+                .unwrap_or(false);
+        self.record_proof(block, ProofKind::Shuffle, vec![source, stack.id()]);
+        if let Some(piled) = self.state.piles.get(&source) {
+            let mut stacks = piled.clone();
+            stacks.push(stack.id());
+            self.record_proof(block, ProofKind::Reshuffle, stacks);
+        }
+
+        if self.valid {
+            self.state.vtmf.mark_masked();
+            self.record_mask_parties(stack);
+            self.state
+                .shuffles
+                .insert(stack.id(), (source, block.signer()));
+            self.state.stacks.insert(stack.clone());
+            self.notify(|o| o.on_stack_changed(stack.id()));
+        }
+    }
+
+    fn visit_reshuffle_from(
+        &mut self,
+        block: &Block,
+        reference: Id,
+        source: Id,
+        stack: &Stack,
+        proof: &ShuffleProof,
+    ) {
         self.valid = self.valid
+            && self.state.is_permutation_of(&source, &reference)
             && self
+                .live_stack(&source)
+                .map(|src| {
+                    self.check(|| {
+                        self.state
+                            .vtmf
+                            .verify_permutation_of(&reference, src, stack, proof)
+                    })
+                })
+                .unwrap_or(false);
+        self.record_proof(block, ProofKind::PermutationOf, vec![reference, stack.id()]);
+
+        if self.valid {
+            self.state.vtmf.mark_masked();
+            self.record_mask_parties(stack);
+            self.state
+                .shuffles
+                .insert(stack.id(), (source, block.signer()));
+            self.state.stacks.insert(stack.clone());
+            self.notify(|o| o.on_stack_changed(stack.id()));
+        }
+    }
+
+    fn visit_shift_stack(&mut self, block: &Block, source: Id, stack: &Stack, proof: &ShiftProof) {
+        let key = (source, block.signer());
+        self.valid = self.valid
+            && self
+                .live_stack(&source)
+                .map(|src| self.check(|| self.state.vtmf.verify_mask_shift(src, stack, proof)))
+                .unwrap_or(false)
+            && self
+                .state
+                .cut_commits
+                .get(&key)
+                .map(|(_, target)| target.is_none())
+                .unwrap_or(true);
+        self.record_proof(block, ProofKind::Shift, vec![source, stack.id()]);
+
+        if self.valid {
+            if let Some((_, target)) = self.state.cut_commits.get_mut(&key) {
+                *target = Some(stack.id());
+            }
+            self.state.vtmf.mark_masked();
+            self.record_mask_parties(stack);
+            self.state.stacks.insert(stack.clone());
+            self.notify(|o| o.on_stack_changed(stack.id()));
+        }
+    }
+
+    fn visit_take_stack(&mut self, _: &Block, source: Id, indices: &[usize], target: Id) {
+        let stack = self.take_stack(source, indices, target);
+        self.valid = self.valid && stack.is_some();
+
+        if self.valid {
+            self.state.stacks.insert(stack.unwrap());
+            self.notify(|o| o.on_stack_changed(target));
+        }
+    }
+
+    fn visit_pile_stack(&mut self, _: &Block, sources: &[Id], target: Id) {
+        let stack = self
+            .state
+            .stacks
+            .preview_pile(sources)
+            .filter(|(_, id)| *id == target);
+        self.valid = self.valid && stack.is_some();
+
+        if self.valid {
+            self.state.stacks.insert(stack.unwrap().0);
+            self.state.piles.insert(target, sources.to_vec());
+            self.notify(|o| o.on_stack_changed(target));
+        }
+    }
+
+    fn visit_name_stack(&mut self, block: &Block, id: Id, name: &str) {
+        self.valid = self.valid && self.state.stacks.get_by_id(&id).is_some();
+
+        if self.valid {
+            let height = block
+                .parent_ids()
+                .iter()
+                .filter_map(|p| self.state.chain.height(p))
+                .map(|h| h + 1)
+                .max()
+                .unwrap_or(0);
+            let rank = (height, block.id());
+            if self
                 .state
-                .vtmf
-                .verify_entanglement(sources, shuffles, proof)
-                .is_ok();
+                .stacks
+                .set_name_ranked(id, name.to_string(), rank)
+            {
+                self.notify(|o| o.on_stack_changed(id));
+            }
+        }
+    }
+
+    fn visit_tag_stack(&mut self, _: &Block, id: Id, tags: &[String]) {
+        self.valid = self.valid && self.state.stacks.get_by_id(&id).is_some();
+
+        if self.valid {
+            self.state.stacks.add_tags(id, tags.iter().cloned());
+            self.notify(|o| o.on_stack_changed(id));
+        }
+    }
+
+    fn visit_publish_shares(
+        &mut self,
+        block: &Block,
+        id: Id,
+        shares: &[SecretShare],
+        proofs: &[SecretShareProof],
+    ) {
+        self.valid = self.valid
+            && self
+                .live_stack(&id)
+                .map(|src| {
+                    src.iter()
+                        .zip(shares.iter())
+                        .zip(proofs.iter())
+                        .all(|((m, s), p)| {
+                            self.check(|| self.state.vtmf.verify_unmask(m, &block.signer(), s, p))
+                        })
+                })
+                .unwrap_or(false);
+
+        if self.valid {
+            self.state
+                .stacks
+                .add_secret_share(id, block.signer(), shares.to_vec());
+            self.notify(|o| o.on_stack_changed(id));
+        }
+        self.record_proof(block, ProofKind::Shares, vec![id]);
+    }
+
+    fn visit_publish_shares_at(
+        &mut self,
+        block: &Block,
+        id: Id,
+        indices: &[usize],
+        shares: &[SecretShare],
+        proofs: &[SecretShareProof],
+    ) {
+        self.valid = self.valid
+            && indices.len() == shares.len()
+            && indices.len() == proofs.len()
+            && self
+                .live_stack(&id)
+                .map(|src| {
+                    indices.iter().all(|&i| i < src.len())
+                        && indices.iter().zip(shares.iter()).zip(proofs.iter()).all(
+                            |((&i, s), p)| {
+                                self.check(|| {
+                                    self.state
+                                        .vtmf
+                                        .verify_unmask(&src[i], &block.signer(), s, p)
+                                })
+                            },
+                        )
+                })
+                .unwrap_or(false);
+
+        if self.valid {
+            self.state
+                .stacks
+                .add_secret_share_at(id, block.signer(), indices, shares.to_vec());
+            self.notify(|o| o.on_stack_changed(id));
+        }
+        self.record_proof(block, ProofKind::Shares, vec![id]);
+    }
+
+    fn visit_insert_stack(&mut self, _: &Block, source: Id, inserted: Id, rng: &str, target: Id) {
+        let stack = Some(rng)
+            .filter(|&rng| rng == State::insert_rng_name(&source, &inserted))
+            .and_then(|_| self.state.insert_position(&source, &inserted))
+            .and_then(|at| self.state.stacks.preview_insert(&source, &inserted, at))
+            .filter(|(_, id)| *id == target);
+        self.valid = self.valid && source != inserted && stack.is_some();
+
+        if self.valid {
+            self.state.insert_rngs.insert(rng.into());
+            self.state.stacks.insert(stack.unwrap().0);
+            self.notify(|o| o.on_stack_changed(target));
+        }
+    }
+
+    fn visit_select_hash(&mut self, _: &Block, algorithm: HashAlgorithm) {
+        // only the genesis block can choose, before anyone's key is known
+        self.valid = self.valid && self.state.chain.is_empty() && self.state.vtmf.parties() == 0;
+
+        if self.valid {
+            self.state.vtmf.set_hash_algorithm(algorithm);
+        }
+    }
+
+    fn visit_burn_stack(&mut self, _: &Block, source: Id, indices: &[usize], target: Id) {
+        let stack = self.take_stack(source, indices, target);
+        self.valid = self.valid && stack.is_some();
+
+        if self.valid {
+            self.state.stacks.insert(stack.unwrap());
+            self.state.stacks.burn(target);
+            self.notify(|o| o.on_stack_changed(target));
+        }
+    }
+
+    fn visit_deal_face_up(&mut self, _: &Block, source: Id, indices: &[usize], target: Id) {
+        let stack = self.take_stack(source, indices, target);
+        self.valid = self.valid && stack.is_some();
+
+        if self.valid {
+            self.state.stacks.insert(stack.unwrap());
+            self.state.stacks.deal_face_up(target);
+            self.notify(|o| o.on_stack_changed(target));
+        }
+    }
+
+    fn visit_random_spec(&mut self, _: &Block, name: &str, spec: &str) {
+        let e = self.state.rngs.get(name);
+        self.valid = self.valid && e.map(|rng| rng.spec() == spec).unwrap_or(true);
+
+        if self.valid && e.is_none() {
+            match Rng::new(self.state.vtmf.parties(), spec) {
+                Ok(rng) => {
+                    self.state.rngs.insert(name.into(), rng);
+                }
+                Err(err) => {
+                    record_error(&self.error, Err(err));
+                    self.valid = false;
+                }
+            }
+        }
+    }
+
+    fn visit_random_entropy(&mut self, block: &Block, name: &str, entropy: &Mask) {
+        let fp = block.signer();
+        let e = self.state.rngs.get_mut(name);
+        self.valid = self.valid
+            && e.as_ref()
+                .map(|rng| !rng.is_generated() && !rng.entropy_parties().contains(&fp))
+                .unwrap_or(false);
+
+        if self.valid {
+            let rng = e.unwrap();
+            rng.add_entropy(fp, entropy);
+            let generated = rng.is_generated();
+            self.state.vtmf.mark_masked();
+            if generated {
+                self.notify(|o| o.on_rng_generated(name));
+            }
+        }
+    }
+
+    fn visit_beacon_entropy(
+        &mut self,
+        block: &Block,
+        name: &str,
+        round: u64,
+        value: &[u8],
+        _: &[u8],
+    ) {
+        // the beacon's own proof is recorded but not checked yet
+        let fp = block.signer();
+        self.valid = self.valid && self.state.vtmf.fingerprints().any(|p| p == fp);
+        let e = self.state.rngs.get_mut(name);
+        self.valid = self.valid
+            && record_error(
+                &self.error,
+                e.ok_or(Error::InvalidPayload)
+                    .and_then(|rng| rng.add_beacon(round, value)),
+            );
+
+        if self.valid && self.state.rngs[name].is_generated() {
+            self.notify(|o| o.on_rng_generated(name));
+        }
+    }
+
+    fn visit_random_reveal(
+        &mut self,
+        block: &Block,
+        name: &str,
+        share: &SecretShare,
+        proof: &SecretShareProof,
+    ) {
+        let fp = block.signer();
+        let verify = self.verify;
+        let error = &self.error;
+        let vtmf = &self.state.vtmf;
+        let e = self.state.rngs.get_mut(name);
+        self.valid = self.valid
+            && e.as_ref()
+                .map(|rng| {
+                    !rng.is_revealed()
+                        && !rng.awaits_beacon()
+                        && !rng.secret_parties().contains(&fp)
+                        && (!verify
+                            || record_error(
+                                error,
+                                vtmf.verify_unmask(rng.mask(), &fp, share, proof),
+                            ))
+                })
+                .unwrap_or(false);
+
+        if self.valid {
+            let rng = e.unwrap();
+            rng.add_secret(fp, share);
+            if rng.is_revealed() {
+                self.notify(|o| o.on_rng_revealed(name));
+            }
+        }
+        self.record_proof(block, ProofKind::RandomReveal, vec![]);
+    }
+
+    fn visit_cast_ballot(
+        &mut self,
+        block: &Block,
+        topic: &str,
+        ballot: &Mask,
+        proof: &PrivateMaskProof,
+    ) {
+        let fp = block.signer();
+        let mut tally = self
+            .state
+            .tallies
+            .get(topic)
+            .cloned()
+            .unwrap_or_else(|| Tally::new(self.state.vtmf.fingerprints().collect()));
+        self.valid = self.valid && tally.add_ballot(fp, ballot).is_ok();
+        self.valid = self.valid
+            && self.check(|| {
+                self.state
+                    .vtmf
+                    .verify_ballot(topic, &fp, &Tally::choices(), ballot, proof)
+            });
+
+        if self.valid {
+            self.state.tallies.insert(topic.into(), tally);
+            self.state.vtmf.mark_masked();
+        }
+        self.record_proof(block, ProofKind::Ballot, vec![]);
+    }
+
+    fn visit_reveal_tally(
+        &mut self,
+        block: &Block,
+        topic: &str,
+        share: &SecretShare,
+        proof: &SecretShareProof,
+    ) {
+        let fp = block.signer();
+        let verify = self.verify;
+        let error = &self.error;
+        let vtmf = &self.state.vtmf;
+        let e = self.state.tallies.get_mut(topic);
+        self.valid = self.valid
+            && e.as_ref()
+                .map(|tally| {
+                    tally.parties().contains(&fp)
+                        && !tally.is_revealed()
+                        && (!verify
+                            || record_error(
+                                error,
+                                vtmf.verify_unmask(tally.mask(), &fp, share, proof),
+                            ))
+                })
+                .unwrap_or(false);
+
+        self.valid = self.valid
+            && e.map(|tally| tally.add_secret(fp, share).is_ok())
+                .unwrap_or(false);
+        self.record_proof(block, ProofKind::Tally, vec![]);
+    }
+
+    fn visit_peek(&mut self, block: &Block, id: Id, commit: Id) {
+        self.valid = self.valid && self.live_stack(&id).is_some();
+
+        if self.valid {
+            self.state
+                .peeks
+                .entry(id)
+                .or_default()
+                .push((block.signer(), commit));
+        }
+    }
+
+    fn visit_open_peek(&mut self, block: &Block, id: Id, nonce: Id) {
+        let signer = block.signer();
+        let commit = self
+            .live_stack(&id)
+            .and_then(|stack| self.state.unmask_publicly(stack))
+            .map(|open| State::peek_commitment(&open, &nonce));
+        self.valid = self.valid
+            && commit
+                .and_then(|commit| {
+                    self.state
+                        .peeks
+                        .get(&id)
+                        .map(|peeks| peeks.contains(&(signer, commit)))
+                })
+                .unwrap_or(false);
+        self.record_proof(block, ProofKind::Peek, vec![id]);
+    }
+
+    fn visit_commit_shift(&mut self, block: &Block, id: Id, commit: Id) {
+        let key = (id, block.signer());
+        self.valid = self.valid
+            && self.live_stack(&id).is_some()
+            && !self.state.cut_commits.contains_key(&key);
+
+        if self.valid {
+            self.state.cut_commits.insert(key, (commit, None));
+        }
+    }
+
+    fn visit_reveal_shift(
+        &mut self,
+        block: &Block,
+        id: Id,
+        k: usize,
+        nonce: Id,
+        secrets: &[Scalar],
+    ) {
+        let key = (id, block.signer());
+        let target = self
+            .state
+            .cut_commits
+            .get(&key)
+            .filter(|(commit, _)| *commit == State::cut_commitment(&id, k, &nonce))
+            .and_then(|(_, target)| *target)
+            .filter(|target| {
+                self.live_stack(&id)
+                    .and_then(|src| self.state.vtmf.apply_shift(src, k, secrets))
+                    .map(|stack| stack.id() == *target)
+                    .unwrap_or(false)
+            });
+        self.valid = self.valid && target.is_some();
+
+        if self.valid {
+            self.state.cut_commits.remove(&key);
+            self.state.cuts.insert(target.unwrap(), k);
+        }
+    }
+
+    fn visit_prove_entanglement(
+        &mut self,
+        block: &Block,
+        source_ids: &[Id],
+        shuffle_ids: &[Id],
+        proof: &EntanglementProof,
+    ) {
+        let stack_ids = source_ids
+            .iter()
+            .chain(shuffle_ids.iter())
+            .cloned()
+            .collect();
+        let stacks = &self.state.stacks;
+        let sources: Vec<_> = source_ids.iter().map(|id| stacks.get_by_id(id)).collect();
+        let shuffles: Vec<_> = shuffle_ids.iter().map(|id| stacks.get_by_id(id)).collect();
+
+        self.valid = self.valid
+            && sources.iter().all(Option::is_some)
+            && shuffles.iter().all(Option::is_some);
+
+        if !self.valid {
+            self.record_proof(block, ProofKind::Entanglement, stack_ids);
+            return;
+        }
+
+        let sources = sources.iter().map(|s| s.unwrap());
+        let shuffles = shuffles.iter().map(|s| s.unwrap());
+
+        self.valid = self.valid
+            && self.check(|| {
+                self.state
+                    .vtmf
+                    .verify_entanglement(sources, shuffles, proof)
+            });
+        self.record_proof(block, ProofKind::Entanglement, stack_ids);
+    }
+
+    fn visit_prove_greater(
+        &mut self,
+        block: &Block,
+        a: &Mask,
+        b: &Mask,
+        max: usize,
+        proof: &PrivateMaskProof,
+    ) {
+        let signer = block.signer();
+        let views = self
+            .state
+            .private_view(a, &signer)
+            .and_then(|a| Some((a, self.state.private_view(b, &signer)?)));
+        self.valid = self.valid
+            && views
+                .map(|(a, b)| {
+                    self.check(|| {
+                        self.state
+                            .vtmf
+                            .verify_greater(&a, &b, &signer, max as u64, proof)
+                    })
+                })
+                .unwrap_or(false);
+
+        if self.valid {
+            self.state.greater.insert((*a, *b));
+        }
+        self.record_proof(block, ProofKind::Greater, vec![]);
+    }
+
+    fn visit_checkpoint(&mut self, _: &Block, state: Id) {
+        self.valid = self.valid
+            && record_error(
+                &self.error,
+                if self.state.digest() == state {
+                    Ok(())
+                } else {
+                    Err(Error::Diverged)
+                },
+            );
+    }
+
+    fn visit_prove_field(
+        &mut self,
+        block: &Block,
+        mask: &Mask,
+        field_mask: u64,
+        field_value: u64,
+        max: u64,
+        proof: &PrivateMaskProof,
+    ) {
+        self.valid = self.valid
+            && self.check(|| {
+                self.state
+                    .vtmf
+                    .verify_field(mask, field_mask, field_value, max, proof)
+            });
+
+        if self.valid {
+            self.state.fields.insert((*mask, field_mask, field_value));
+        }
+        self.record_proof(block, ProofKind::Field, vec![]);
+    }
+
+    fn visit_prove_distinct(
+        &mut self,
+        block: &Block,
+        id: Id,
+        blinded: &Stack,
+        proof: &DistinctProof,
+    ) {
+        let parties = self.state.vtmf.fingerprints().collect();
+        let mut check = match self.state.distinct_checks.get(&id) {
+            Some(check) => Some(check.clone()),
+            None if self.state.unfixed_tokens => None,
+            None => self
+                .state
+                .stacks
+                .get_by_id(&id)
+                .map(|stack| DistinctCheck::new(parties, stack.clone())),
+        };
+        self.valid = self.valid
+            && check
+                .as_mut()
+                .map(|check| {
+                    self.check(|| {
+                        self.state.vtmf.verify_distinct(
+                            check.blinded(),
+                            blinded,
+                            &block.signer(),
+                            proof,
+                        )
+                    }) && record_error(
+                        &self.error,
+                        check.add_blinding(block.signer(), blinded.clone()),
+                    )
+                })
+                .unwrap_or(false);
+
+        if self.valid {
+            self.state.distinct_checks.insert(id, check.unwrap());
+        }
+        self.record_proof(block, ProofKind::Blinding, vec![id]);
+    }
+
+    fn visit_reveal_distinct(
+        &mut self,
+        block: &Block,
+        id: Id,
+        shares: &[SecretShare],
+        proofs: &[SecretShareProof],
+    ) {
+        let fp = block.signer();
+        let mut check = self.state.distinct_checks.get(&id).cloned();
+        self.valid = self.valid
+            && shares.len() == proofs.len()
+            && check
+                .as_mut()
+                .map(|check| {
+                    check.is_blinded()
+                        && check
+                            .blinded()
+                            .iter()
+                            .zip(shares.iter())
+                            .zip(proofs.iter())
+                            .all(|((m, s), p)| {
+                                self.check(|| self.state.vtmf.verify_unmask(m, &fp, s, p))
+                            })
+                        && record_error(&self.error, check.add_secrets(fp, shares))
+                })
+                .unwrap_or(false);
+
+        if !self.valid {
+            self.record_proof(block, ProofKind::Shares, vec![id]);
+            return;
+        }
+        let check = check.unwrap();
+        let result = check.result(&self.state.vtmf);
+        self.state.distinct_checks.insert(id, check);
+        match result {
+            Some(distinct) => {
+                if distinct {
+                    self.state.distinct.insert(id);
+                }
+                self.state.proofs.push(ProofRecord {
+                    block: block.id(),
+                    kind: ProofKind::Distinct,
+                    stacks: vec![id],
+                    signer: fp,
+                    verified: distinct,
+                });
+            }
+            None => self.record_proof(block, ProofKind::Shares, vec![id]),
+        }
+    }
+
+    fn visit_prove_equal_multiset(
+        &mut self,
+        block: &Block,
+        hidden: Id,
+        reference: Id,
+        proof: &ShuffleProof,
+    ) {
+        let stacks = self
+            .state
+            .stacks
+            .get_by_id(&hidden)
+            .zip(self.state.stacks.get_by_id(&reference));
+        self.valid = self.valid
+            && stacks.is_some()
+            && self.check(|| {
+                let (hidden, reference) = stacks.unwrap();
+                self.state
+                    .vtmf
+                    .verify_equal_multiset(hidden, reference, proof)
+            });
+
+        if self.valid {
+            self.state.equal_multisets.insert((hidden, reference));
+        }
+        self.record_proof(block, ProofKind::EqualMultiset, vec![hidden, reference]);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        AuditFinding, ClaimView, DeckTemplate, DistinctStep, PendingBlock, ProofKind, Reference,
+        RevealError, State, StateObserver, Tally, MAX_PENDING_BLOCKS,
+    };
+    use crate::{
+        chain::{Block, BlockBuilder, Chain, Id, Payload},
+        crypto::{
+            hash::HashAlgorithm,
+            keys::{Fingerprint, PrivateKey},
+            map,
+            perm::Shuffles,
+            vtmf::{Mask, PairedStack, Stack, Vtmf},
+        },
+        serde::Message,
+        Error,
+    };
+    use digest::XofReader;
+    use rand::{thread_rng, Rng};
+    use std::{cell::RefCell, rc::Rc};
+
+    // a state whose only player joined as "foo" in a genesis block that also
+    // holds the given payloads
+    fn single_player(sk: &PrivateKey, payloads: Vec<Payload>) -> State {
+        let mut state = State::new(sk.clone());
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishKey("foo".into(), sk.public_key()));
+        for payload in payloads {
+            b.add_payload(payload);
+        }
+        let b = b.build(sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        state
+    }
+
+    // a state where "foo" joined first, in a genesis block that also holds
+    // the given payloads, and "bar" joined next
+    fn two_players(sk0: &PrivateKey, sk1: &PrivateKey, payloads: Vec<Payload>) -> State {
+        let mut state = single_player(sk0, payloads);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishKey("bar".into(), sk1.public_key()));
+        let b = b.build(sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        state
+    }
+
+    // adds a block with a single payload to all states, expecting the same
+    // result from each
+    fn add(states: &mut [State], sk: &PrivateKey, payload: Payload) -> Result<(), Error> {
+        let mut b = states[0].chain.build_block();
+        b.add_payload(payload);
+        let b = b.build(sk);
+        let r = states[0].add_block(&b);
+        for state in states[1..].iter_mut() {
+            assert_eq!(state.add_block(&b), r);
+        }
+        r
+    }
+
+    #[test]
+    fn burned_stacks_cannot_be_reused() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let burned: Stack = vec![stack[1]].into_iter().collect();
+
+        let mut state = single_player(&sk, vec![Payload::OpenStack(stack.clone())]);
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::BurnStack(stack.id(), vec![1], burned.id()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(
+            state.burned_stacks().collect::<Vec<_>>(),
+            vec![&burned.id()]
+        );
+
+        let pile: Stack = stack.iter().chain(burned.iter()).cloned().collect();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PileStacks(
+            vec![stack.id(), burned.id()],
+            pile.id(),
+        ));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+    }
+
+    #[test]
+    fn burned_tokens_cannot_be_taken_again() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut state = single_player(&sk, vec![]);
+
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let (masked, proofs): (Vec<_>, Vec<_>) = stack
+            .iter()
+            .map(|m| {
+                let (c, _, proof) = state.vtmf.remask(m);
+                (c, proof)
+            })
+            .unzip();
+        let masked: Stack = masked.into_iter().collect();
+        let burned: Stack = vec![masked[1]].into_iter().collect();
+        let rest: Stack = vec![masked[0], masked[2]].into_iter().collect();
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        b.add_payload(Payload::MaskStack(stack.id(), masked.clone(), proofs));
+        b.add_payload(Payload::TakeStack(masked.id(), vec![0, 2], rest.id()));
+        b.add_payload(Payload::BurnStack(masked.id(), vec![1], burned.id()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let retaken: Stack = vec![masked[1], masked[2]].into_iter().collect();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::TakeStack(masked.id(), vec![1, 2], retaken.id()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let (share, proof) = state.vtmf.unmask_share(&masked[1]);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishSharesAt(
+            masked.id(),
+            vec![1],
+            vec![share],
+            vec![proof],
+        ));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let top: Stack = vec![rest[0]].into_iter().collect();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::TakeStack(rest.id(), vec![0], top.id()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+    }
+
+    #[test]
+    fn face_up_deals_await_everyones_shares() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let mut state = two_players(&sk0, &sk1, vec![]);
+        let mut vtmf1 = Vtmf::new(sk1.clone());
+        vtmf1.add_key(sk0.public_key());
+        vtmf1.set_domain(*state.vtmf.domain());
+
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let (masked, proofs): (Vec<_>, Vec<_>) = stack
+            .iter()
+            .map(|m| {
+                let (c, _, proof) = state.vtmf.remask(m);
+                (c, proof)
+            })
+            .unzip();
+        let masked: Stack = masked.into_iter().collect();
+        let dealt: Stack = vec![masked[2]].into_iter().collect();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        b.add_payload(Payload::MaskStack(stack.id(), masked.clone(), proofs));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::DealFaceUp(masked.id(), vec![2], stack.id()));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::DealFaceUp(masked.id(), vec![2], dealt.id()));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(
+            state.face_up_stacks().collect::<Vec<_>>(),
+            vec![&dealt.id()]
+        );
+        assert_eq!(state.awaiting_face_up_shares(&masked.id()), None);
+        let awaiting = state.awaiting_face_up_shares(&dealt.id()).unwrap();
+        assert_eq!(awaiting.len(), 2);
+        assert!(awaiting.contains(&sk0.fingerprint()));
+        assert!(awaiting.contains(&sk1.fingerprint()));
+
+        let (share, proof) = vtmf1.unmask_share(&dealt[0]);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishShares(dealt.id(), vec![share], vec![proof]));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(
+            state.awaiting_face_up_shares(&dealt.id()),
+            Some(vec![sk0.fingerprint()])
+        );
+
+        let (share, proof) = state.vtmf.unmask_share(&dealt[0]);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishShares(dealt.id(), vec![share], vec![proof]));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.awaiting_face_up_shares(&dealt.id()), Some(vec![]));
+        assert_eq!(state.reveal_values(&dealt.id()), Ok(vec![2]));
+    }
+
+    #[test]
+    fn deck_templates_open_fixed_stacks() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut state = single_player(&sk, vec![Payload::DeckTemplate("standard52".into())]);
+        let deck = DeckTemplate::Standard52.stack();
+        assert_eq!(state.stacks.get_by_id(&deck.id()), Some(&deck));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::DeckTemplate("tarot".into()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+    }
+
+    #[test]
+    fn players_can_only_rename_themselves() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let mut state = two_players(&sk0, &sk1, vec![]);
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RenamePlayer(sk0.fingerprint(), "baz".into()));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+        assert_eq!(state.names[&sk0.fingerprint()], "foo");
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RenamePlayer(sk1.fingerprint(), "baz".into()));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.names[&sk0.fingerprint()], "foo");
+        assert_eq!(state.names[&sk1.fingerprint()], "baz");
+    }
+
+    #[test]
+    fn multi_round_reveals_open_after_everyone_commits() {
+        let mut rng = thread_rng();
+        let sks = [PrivateKey::random(&mut rng), PrivateKey::random(&mut rng)];
+        let mut states: Vec<_> = sks.iter().cloned().map(State::new).collect();
+        for (sk, name) in sks.iter().zip(["foo", "bar"].iter()) {
+            let key = Payload::PublishKey(name.to_string(), sk.public_key());
+            assert_eq!(add(&mut states, sk, key), Ok(()));
+        }
+        let id = Id::random(&mut rng);
+
+        let commit = states[0].multi_round_reveal(id, 2).unwrap();
+        assert_eq!(add(&mut states, &sks[0], commit), Ok(()));
+        assert_eq!(states[0].multi_round_reveal(id, 2), None);
+        let early = Payload::OpenShares(id, 0, Id::random(&mut rng));
+        assert_eq!(add(&mut states, &sks[0], early), Err(Error::InvalidPayload));
+        let other = Payload::CommitShares(id, 3, 0, Id::random(&mut rng));
+        assert_eq!(add(&mut states, &sks[1], other), Err(Error::InvalidPayload));
+
+        for round in 0..2 {
+            if round > 0 {
+                let commit = states[0].multi_round_reveal(id, 2).unwrap();
+                assert_eq!(add(&mut states, &sks[0], commit), Ok(()));
+            }
+            let commit = states[1].multi_round_reveal(id, 2).unwrap();
+            assert_eq!(add(&mut states, &sks[1], commit), Ok(()));
+
+            let forged = Payload::OpenShares(id, round, Id::random(&mut rng));
+            assert_eq!(add(&mut states, &sks[0], forged), Err(Error::BadProof));
+            for (i, sk) in sks.iter().enumerate() {
+                let open = states[i].multi_round_reveal(id, 2).unwrap();
+                assert_eq!(add(&mut states, sk, open), Ok(()));
+            }
+            assert_eq!(states[0].reveals[&id].outputs().len(), round as usize + 1);
+        }
+
+        assert!(states[0].reveals[&id].is_complete());
+        assert_eq!(
+            states[0].reveals[&id].outputs(),
+            states[1].reveals[&id].outputs()
+        );
+        assert_eq!(states[0].multi_round_reveal(id, 2), None);
+    }
+
+    #[test]
+    fn paired_shuffles_publish_entangled_stacks() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let cards: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let attributes: Stack = (8..12).map(|i| Mask::open(map::to_curve(i))).collect();
+        let paired = PairedStack::new(cards.clone(), attributes.clone()).unwrap();
+
+        let mut state = single_player(&sk, vec![
+            Payload::OpenStack(cards),
+            Payload::OpenStack(attributes),
+        ]);
+
+        let pi = rng.sample(Shuffles(paired.len()));
+        let (shuffled, _, proof) = state.vtmf.mask_shuffle_paired(&paired, &pi);
+        let mut b = state.chain.build_block();
+        for payload in Payload::paired_shuffle(&paired, &shuffled, proof) {
+            b.add_payload(payload);
+        }
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        let record = state.proof_log().pop().unwrap();
+        assert_eq!(record.kind, ProofKind::Entanglement);
+        assert!(record.verified);
+        assert_eq!(record.stacks, [paired.ids(), shuffled.ids()].concat());
+    }
+
+    #[test]
+    fn multi_round_reveals_reject_outsiders() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let outsider = PrivateKey::random(&mut rng);
+        let mut state = State::new(sk1.clone());
+        let id = Id::random(&mut rng);
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishKey("foo".into(), sk0.public_key()));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishKey("bar".into(), sk1.public_key()));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        let commit = state.multi_round_reveal(id, 1).unwrap();
+        let mut b = state.chain.build_block();
+        b.add_payload(commit);
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishKey("baz".into(), outsider.public_key()));
+        let b = b.build(&outsider);
+        assert_eq!(state.add_block(&b), Ok(()));
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CommitShares(id, 1, 0, Id::random(&mut rng)));
+        let b = b.build(&outsider);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+        assert!(!state.reveals[&id].is_committed());
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::OpenShares(id, 0, Id::random(&mut rng)));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+    }
+
+    #[test]
+    fn observers_see_accepted_payloads() {
+        struct Recorder(Rc<RefCell<Vec<String>>>);
+        impl StateObserver for Recorder {
+            fn on_key_published(&mut self, name: &str, _: Fingerprint) {
+                self.0.borrow_mut().push(format!("key {}", name));
+            }
+
+            fn on_stack_changed(&mut self, id: Id) {
+                self.0.borrow_mut().push(format!("stack {}", id));
+            }
+
+            fn on_rng_generated(&mut self, name: &str) {
+                self.0.borrow_mut().push(format!("rng {}", name));
+            }
+        }
+
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut state = State::new(sk.clone());
+        let events = Rc::new(RefCell::new(Vec::new()));
+        state.add_observer(Box::new(Recorder(events.clone())));
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishKey("foo".into(), sk.public_key()));
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        b.add_payload(Payload::RandomSpec("bar".into(), "1d6".into()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let entropy = state.vtmf.mask_random(&mut rng);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RandomEntropy("bar".into(), entropy));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::NameStack(Id::random(&mut rng), "baz".into()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        assert_eq!(*events.borrow(), vec![
+            "key foo".to_string(),
+            format!("stack {}", stack.id()),
+            "rng bar".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn moves_replay_to_the_computed_stacks() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let hand: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let discard: Stack = (4..6).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut state = single_player(&sk, vec![
+            Payload::OpenStack(hand.clone()),
+            Payload::OpenStack(discard.clone()),
+            Payload::NameStack(hand.id(), "hand".into()),
+            Payload::NameStack(discard.id(), "discard".into()),
+        ]);
+
+        assert_eq!(state.move_tokens("hand", &[4], "discard"), None);
+        assert_eq!(state.move_tokens("hand", &[1, 1], "discard"), None);
+        assert_eq!(state.move_tokens("hand", &[1], "hand"), None);
+        assert_eq!(state.move_tokens("deck", &[1], "discard"), None);
+
+        let payloads = state.move_tokens("hand", &[2, 0], "discard").unwrap();
+        let mut b = state.chain.build_block();
+        for p in payloads {
+            b.add_payload(p);
+        }
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let hand_after: Stack = vec![hand[1], hand[3]].into_iter().collect();
+        let discard_after: Stack = vec![hand[2], hand[0], discard[0], discard[1]]
+            .into_iter()
+            .collect();
+        assert_eq!(state.stacks.get_by_name("hand"), Some(&hand_after));
+        assert_eq!(state.stacks.get_by_name("discard"), Some(&discard_after));
+    }
+
+    #[test]
+    fn merged_builders_keep_dependent_payloads_in_order() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let hand: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let discard: Stack = (4..6).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut state = single_player(&sk, vec![
+            Payload::OpenStack(hand.clone()),
+            Payload::OpenStack(discard.clone()),
+            Payload::NameStack(hand.id(), "hand".into()),
+            Payload::NameStack(discard.id(), "discard".into()),
+        ]);
+
+        let mut moves = BlockBuilder::new();
+        for p in state.move_tokens("hand", &[3, 1], "discard").unwrap() {
+            moves.add_payload(p);
+        }
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::Text("moving".into()));
+        b.merge(moves);
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let hand_after: Stack = vec![hand[0], hand[2]].into_iter().collect();
+        assert_eq!(state.stacks.get_by_name("hand"), Some(&hand_after));
+        assert_eq!(state.stacks.get_by_name("discard").unwrap().len(), 4);
+    }
+
+    #[test]
+    fn inserts_follow_revealed_generators() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let deck: Stack = (0..5).map(|i| Mask::open(map::to_curve(i))).collect();
+        let card: Stack = (9..10).map(|i| Mask::open(map::to_curve(i))).collect();
+        let spot = State::insert_rng_name(&deck.id(), &card.id());
+
+        let mut state = single_player(&sk, vec![
+            Payload::OpenStack(deck.clone()),
+            Payload::OpenStack(card.clone()),
+            Payload::NameStack(deck.id(), "deck".into()),
+            Payload::NameStack(card.id(), "card".into()),
+            Payload::RandomSpec(spot.clone(), "1d1000".into()),
+            Payload::RandomSpec("other".into(), "1d1000".into()),
+        ]);
+
+        for name in [&spot[..], "other"].iter() {
+            let entropy = state.vtmf.mask_random(&mut rng);
+            let mut b = state.chain.build_block();
+            b.add_payload(Payload::RandomEntropy(name.to_string(), entropy));
+            let b = b.build(&sk);
+            assert_eq!(state.add_block(&b), Ok(()));
+        }
+        assert_eq!(state.insert_at_rng("deck", "card"), None);
+
+        for name in [&spot[..], "other"].iter() {
+            let (share, proof) = state.vtmf.unmask_share(state.rngs[*name].mask());
+            let mut b = state.chain.build_block();
+            b.add_payload(Payload::RandomReveal(name.to_string(), share, proof));
+            let b = b.build(&sk);
+            assert_eq!(state.add_block(&b), Ok(()));
+        }
+        assert_eq!(state.insert_at_rng("deck", "deck"), None);
+
+        let at = state.insert_position(&deck.id(), &card.id()).unwrap();
+        let value = state.rngs[&spot].gen(&state.vtmf).unwrap();
+        assert_eq!(at as u64, value % 6);
+        let (_, wrong) = state
+            .stacks
+            .preview_insert(&deck.id(), &card.id(), (at + 1) % 6)
+            .unwrap();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::InsertStack(
+            deck.id(),
+            card.id(),
+            spot.clone(),
+            wrong,
+        ));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        // another revealed generator can't be picked instead
+        let other = state.rngs["other"].gen(&state.vtmf).unwrap();
+        let (_, picked) = state
+            .stacks
+            .preview_insert(&deck.id(), &card.id(), (other % 6) as usize)
+            .unwrap();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::InsertStack(
+            deck.id(),
+            card.id(),
+            "other".into(),
+            picked,
+        ));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let payloads = state.insert_at_rng("deck", "card").unwrap();
+        let insert = payloads[0].clone();
+        let mut b = state.chain.build_block();
+        for p in payloads {
+            b.add_payload(p);
+        }
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let mut expected: Vec<_> = deck.iter().cloned().collect();
+        expected.insert(at, card[0]);
+        let expected: Stack = expected.into_iter().collect();
+        assert_eq!(state.stacks.get_by_name("deck"), Some(&expected));
+        assert_eq!(state.stacks.get_by_name("card"), Some(&Stack::default()));
+
+        // each generator picks a single insertion
+        assert_eq!(state.insert_position(&deck.id(), &card.id()), None);
+        let mut b = state.chain.build_block();
+        b.add_payload(insert);
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+    }
+
+    #[test]
+    fn returned_tokens_are_reshuffled_into_the_deck() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let deck: Stack = (0..5).map(|i| Mask::open(map::to_curve(i))).collect();
+        let hand: Stack = vec![Mask::open(map::to_curve(7))].into_iter().collect();
+
+        let mut state = single_player(&sk, vec![
+            Payload::OpenStack(deck.clone()),
+            Payload::OpenStack(hand.clone()),
+            Payload::NameStack(deck.id(), "deck".into()),
+            Payload::NameStack(hand.id(), "hand".into()),
+        ]);
+        assert_eq!(state.return_and_reshuffle("deck", "deck"), None);
+
+        let (payloads, shuffled, secrets) = state.return_and_reshuffle("hand", "deck").unwrap();
+        assert_eq!(secrets.len(), 6);
+        let mut b = state.chain.build_block();
+        for p in payloads {
+            b.add_payload(p);
+        }
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        assert_eq!(state.stacks.get_by_name("deck"), Some(&shuffled));
+        assert!(state.stacks.get_by_name("hand").unwrap().is_empty());
+        assert_eq!(
+            state.reshuffled_from(&shuffled.id()),
+            Some(&[hand.id(), deck.id()][..])
+        );
+        assert_eq!(state.reshuffled_from(&deck.id()), None);
+        let record = state.proof_log().pop().unwrap();
+        assert_eq!(record.kind, ProofKind::Reshuffle);
+        assert_eq!(record.stacks, vec![hand.id(), deck.id(), shuffled.id()]);
+        assert!(record.verified);
+
+        let mut values: Vec<_> = shuffled
+            .iter()
+            .map(|m| map::from_curve(&state.vtmf.unmask_open(&state.vtmf.unmask_private(m))))
+            .map(Option::unwrap)
+            .collect();
+        values.sort();
+        assert_eq!(values, vec![0, 1, 2, 3, 4, 7]);
+    }
+
+    #[test]
+    fn discard_draw_keeps_the_hand_size() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let hand: Stack = (0..5).map(|i| Mask::open(map::to_curve(i))).collect();
+        let deck: Stack = (5..10).map(|i| Mask::open(map::to_curve(i))).collect();
+        let discard: Stack = (10..11).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut state = single_player(&sk, vec![
+            Payload::OpenStack(hand.clone()),
+            Payload::OpenStack(deck.clone()),
+            Payload::OpenStack(discard.clone()),
+            Payload::NameStack(hand.id(), "hand".into()),
+            Payload::NameStack(deck.id(), "deck".into()),
+            Payload::NameStack(discard.id(), "discard".into()),
+        ]);
+
+        assert_eq!(state.discard_draw("hand", &[], "deck", "discard"), None);
+        assert_eq!(state.discard_draw("hand", &[5], "deck", "discard"), None);
+        assert_eq!(state.discard_draw("hand", &[1, 1], "deck", "discard"), None);
+        assert_eq!(state.discard_draw("hand", &[1], "hand", "discard"), None);
+        assert_eq!(
+            state.discard_draw("hand", &[0, 1, 2, 3, 4], "discard", "deck"),
+            None
+        );
+
+        let payloads = state
+            .discard_draw("hand", &[3, 1], "deck", "discard")
+            .unwrap();
+        let mut b = state.chain.build_block();
+        for p in payloads {
+            b.add_payload(p);
+        }
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let hand_after: Stack = vec![hand[0], deck[1], hand[2], deck[0], hand[4]]
+            .into_iter()
+            .collect();
+        let deck_after: Stack = deck.iter().skip(2).cloned().collect();
+        let discard_after: Stack = vec![hand[3], hand[1], discard[0]].into_iter().collect();
+        assert_eq!(state.stacks.get_by_name("hand"), Some(&hand_after));
+        assert_eq!(state.stacks.get_by_name("deck"), Some(&deck_after));
+        assert_eq!(state.stacks.get_by_name("discard"), Some(&discard_after));
+    }
+
+    #[test]
+    fn verification_cache_skips_known_blocks() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut state = single_player(&sk, vec![Payload::OpenStack(stack.clone())]);
+
+        let pi = rng.sample(Shuffles(stack.len()));
+        let (shuffled, ..) = state.vtmf.mask_shuffle(&stack, &pi);
+        let (_, _, bad_proof) = state.vtmf.mask_shuffle(&stack, &pi);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShuffleStack(stack.id(), shuffled, bad_proof));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::BadProof));
+
+        let key = state.vtmf.shared_key().fingerprint();
+        state
+            .verified
+            .insert(b.id(), PrivateKey::random(&mut rng).fingerprint());
+        assert_eq!(state.add_block(&b), Err(Error::BadProof));
+        state.verified.insert(b.id(), key);
+        assert_eq!(state.add_block(&b), Ok(()));
+    }
+
+    #[test]
+    fn proofs_are_bound_to_their_game() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let mut states: Vec<_> = ["foo", "bar"]
+            .iter()
+            .map(|name| {
+                let mut state = State::new(sk.clone());
+                let mut b = state.chain.build_block();
+                b.add_payload(Payload::PublishKey((*name).into(), sk.public_key()));
+                b.add_payload(Payload::OpenStack(stack.clone()));
+                let b = b.build(&sk);
+                assert_eq!(state.add_block(&b), Ok(()));
+                assert_eq!(&state.vtmf.domain()[..], &b.id()[..]);
+                state
+            })
+            .collect();
+
+        let pi = rng.sample(Shuffles(stack.len()));
+        let (shuffled, _, proof) = states[0].vtmf.mask_shuffle(&stack, &pi);
+        let shuffle = Payload::ShuffleStack(stack.id(), shuffled, proof);
+        for (i, state) in states.iter_mut().enumerate() {
+            let mut b = state.chain.build_block();
+            b.add_payload(shuffle.clone());
+            let b = b.build(&sk);
+            let expected = if i == 0 { Ok(()) } else { Err(Error::BadProof) };
+            assert_eq!(state.add_block(&b), expected);
+        }
+    }
+
+    #[test]
+    fn audits_report_every_failure() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stranger = PrivateKey::random(&mut rng);
+        let mut state = State::new(sk.clone());
+        let stack: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let mut chain = Chain::new();
+
+        let mut b = chain.build_block();
+        b.add_payload(Payload::PublishKey("foo".into(), sk.public_key()));
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        chain.add_block(b.build(&sk));
+        assert_eq!(state.add_block(chain.blocks().last().unwrap()), Ok(()));
+
+        let pi = rng.sample(Shuffles(stack.len()));
+        let (shuffled, ..) = state.vtmf.mask_shuffle(&stack, &pi);
+        let (_, _, bad_proof) = state.vtmf.mask_shuffle(&stack, &pi);
+        let bad_shuffle = Payload::ShuffleStack(stack.id(), shuffled, bad_proof);
+        let mut b = chain.build_block();
+        b.add_payload(bad_shuffle.clone());
+        b.add_payload(Payload::TagStack(stack.id(), vec!["dealt".into()]));
+        let b = b.build(&sk);
+        let bad_proof_block = b.id();
+        chain.add_block(b);
+
+        let bad_take = Payload::TakeStack(Id::default(), vec![0], Id::default());
+        let mut b = chain.build_block();
+        b.add_payload(bad_take.clone());
+        b.add_payload(Payload::NameStack(stack.id(), "deck".into()));
+        let b = b.build(&stranger);
+        let stranger_block = b.id();
+        chain.add_block(b);
+
+        let mut state = State::new(sk);
+        let findings = state.audit(&chain);
+        assert_eq!(findings, vec![
+            AuditFinding {
+                block: bad_proof_block,
+                payload: Some(bad_shuffle.id()),
+                error: Error::BadProof,
+            },
+            AuditFinding {
+                block: stranger_block,
+                payload: Some(bad_take.id()),
+                error: Error::InvalidPayload,
+            },
+            AuditFinding {
+                block: stranger_block,
+                payload: None,
+                error: Error::UnknownSigner,
+            },
+        ]);
+        assert_eq!(state.chain.count(), 3);
+        assert_eq!(state.stacks.tags(&stack.id()).collect::<Vec<_>>(), vec![
+            "dealt"
+        ]);
+        assert_eq!(state.stacks.get_by_name("deck"), Some(&stack));
+    }
+
+    #[test]
+    fn hash_choices_change_random_generators() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let pk = sk.public_key();
+
+        let mut plain = State::new(sk.clone());
+        let mut b = plain.chain.build_block();
+        b.add_payload(Payload::PublishKey("foo".into(), pk.clone()));
+        let b = b.build(&sk);
+        assert_eq!(plain.add_block(&b), Ok(()));
+
+        let mut shake = State::new(sk.clone());
+        let mut b = shake.chain.build_block();
+        b.add_payload(Payload::SelectHash(HashAlgorithm::Shake256));
+        b.add_payload(Payload::PublishKey("foo".into(), pk.clone()));
+        let b = Block::decode(&b.build(&sk).encode().unwrap()).unwrap();
+        assert_eq!(shake.add_block(&b), Ok(()));
+
+        assert_eq!(plain.vtmf.hash_algorithm(), HashAlgorithm::Strobe);
+        assert_eq!(shake.vtmf.hash_algorithm(), HashAlgorithm::Shake256);
+        let m = plain.vtmf.mask_random(&mut rng);
+        let mut plain_bytes = [0u8; 32];
+        plain.vtmf.unmask_random(&m).read(&mut plain_bytes);
+        let mut shake_bytes = [0u8; 32];
+        shake.vtmf.unmask_random(&m).read(&mut shake_bytes);
+        assert_ne!(plain_bytes, shake_bytes);
+
+        let mut b = shake.chain.build_block();
+        b.add_payload(Payload::SelectHash(HashAlgorithm::KangarooTwelve));
+        let b = b.build(&sk);
+        assert_eq!(shake.add_block(&b), Err(Error::InvalidPayload));
+    }
+
+    #[test]
+    fn hash_choices_change_fingerprints() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let pk = sk.public_key();
+
+        let mut plain = State::new(sk.clone());
+        let mut b = plain.chain.build_block();
+        b.add_payload(Payload::PublishKey("foo".into(), pk.clone()));
+        assert_eq!(plain.add_block(&b.build(&sk)), Ok(()));
+
+        let mut shake = State::new(sk.clone());
+        let mut b = shake.chain.build_block();
+        b.add_payload(Payload::SelectHash(HashAlgorithm::Shake256));
+        b.add_payload(Payload::PublishKey("foo".into(), pk.clone()));
+        let b = b.build(&sk);
+        assert_eq!(shake.add_block(&b), Ok(()));
+
+        let plain_fp = plain.vtmf.fingerprint();
+        let shake_fp = shake.vtmf.fingerprint();
+        assert_eq!(plain_fp, sk.fingerprint());
+        assert_ne!(plain_fp, shake_fp);
+        assert_eq!(shake_fp, sk.fingerprint_with(HashAlgorithm::Shake256));
+        assert_eq!(b.signer(), shake_fp);
+        assert_eq!(shake.vtmf.fingerprints().collect::<Vec<_>>(), vec![
+            shake_fp
+        ]);
+        assert_eq!(shake.names.keys().collect::<Vec<_>>(), vec![&shake_fp]);
+        assert_eq!(shake.chain.hash_algorithm(), HashAlgorithm::Shake256);
+        assert_eq!(shake.chain.validate_structure(), Ok(()));
+
+        let mut b = shake.chain.build_block();
+        b.add_payload(Payload::RenamePlayer(shake_fp, "bar".into()));
+        let b = b.build(&sk);
+        assert_eq!(b.signer(), shake_fp);
+        assert_eq!(shake.add_block(&b), Ok(()));
+        assert_eq!(shake.names[&shake_fp], "bar");
+
+        let mut b = BlockBuilder::new();
+        b.acknowledge(shake.chain.heads()[0]);
+        b.add_payload(Payload::RenamePlayer(plain_fp, "baz".into()));
+        assert_eq!(shake.add_block(&b.build(&sk)), Err(Error::InvalidPayload));
+    }
+
+    #[test]
+    fn trusted_blocks_skip_proofs_but_not_the_cache() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut state = State::new(sk.clone());
+        let stack: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishKey("foo".into(), sk.public_key()));
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        let b0 = b.build(&sk);
+        assert_eq!(state.add_block_trusted(&b0), Ok(()));
+        assert!(state.verified.is_empty());
+
+        let pi = rng.sample(Shuffles(stack.len()));
+        let (shuffled, ..) = state.vtmf.mask_shuffle(&stack, &pi);
+        let (_, _, bad_proof) = state.vtmf.mask_shuffle(&stack, &pi);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShuffleStack(
+            stack.id(),
+            shuffled.clone(),
+            bad_proof,
+        ));
+        let b1 = b.build(&sk);
+        assert_eq!(state.add_block_trusted(&b1), Ok(()));
+        assert!(state.stacks.get_by_id(&shuffled.id()).is_some());
+        assert!(state.verified.is_empty());
+
+        let mut checked = State::new(sk);
+        assert_eq!(checked.add_block(&b0), Ok(()));
+        assert_eq!(checked.add_block(&b1), Err(Error::BadProof));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::NameStack(Id::default(), "nothing".into()));
+        let b2 = b.build(&state.vtmf.private_key());
+        assert_eq!(state.add_block_trusted(&b2), Err(Error::InvalidPayload));
+    }
+
+    #[test]
+    fn concurrent_names_resolve_the_same_for_everyone() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut state = State::new(sk.clone());
+        let red: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let blue: Stack = (4..8).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishKey("foo".into(), sk.public_key()));
+        b.add_payload(Payload::OpenStack(red.clone()));
+        b.add_payload(Payload::OpenStack(blue.clone()));
+        let b0 = b.build(&sk);
+        assert_eq!(state.add_block(&b0), Ok(()));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::NameStack(red.id(), "deck".into()));
+        let b1 = b.build(&sk);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::NameStack(blue.id(), "deck".into()));
+        let b2 = b.build(&sk);
+        let winner = if b1.id() > b2.id() { &red } else { &blue };
+
+        let mut other = State::new(sk.clone());
+        assert_eq!(other.add_block(&b0), Ok(()));
+        assert_eq!(state.add_block(&b1), Ok(()));
+        assert_eq!(state.add_block(&b2), Ok(()));
+        assert_eq!(other.add_block(&b2), Ok(()));
+        assert_eq!(other.add_block(&b1), Ok(()));
+        assert_eq!(state.stacks.current_named("deck"), Some(winner));
+        assert_eq!(other.stacks.current_named("deck"), Some(winner));
+
+        let merge = state.chain.build_block().build(&sk);
+        assert_eq!(state.add_block(&merge), Ok(()));
+        assert_eq!(state.stacks.get_by_str("deck"), Some(winner));
+
+        let loser = if b1.id() > b2.id() { &blue } else { &red };
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::NameStack(loser.id(), "deck".into()));
+        let b3 = b.build(&sk);
+        assert_eq!(state.add_block(&b3), Ok(()));
+        assert_eq!(state.stacks.get_by_str("deck"), Some(loser));
+    }
+
+    #[test]
+    fn tags_round_trip_and_accumulate() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let other: Stack = (4..6).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let tag = Payload::TagStack(stack.id(), vec!["trump".into(), "face-up".into()]);
+        let decoded = Payload::decode(&tag.encode().unwrap()).unwrap();
+        assert_eq!(decoded, tag);
+        assert_eq!(decoded.id(), tag.id());
+
+        let mut state = single_player(&sk, vec![
+            Payload::OpenStack(stack.clone()),
+            Payload::OpenStack(other.clone()),
+            Payload::NameStack(stack.id(), "deck".into()),
+            decoded,
+            Payload::TagStack(stack.id(), vec!["dealt".into()]),
+            Payload::TagStack(other.id(), vec!["trump".into()]),
+        ]);
+
+        let tags: Vec<_> = state.stacks.tags(&stack.id()).collect();
+        assert_eq!(tags, vec!["dealt", "face-up", "trump"]);
+        assert_eq!(state.stacks.get_by_name("deck"), Some(&stack));
+        let mut trumps: Vec<_> = state.stacks.tagged("trump").map(Stack::id).collect();
+        trumps.sort();
+        let mut expected = vec![stack.id(), other.id()];
+        expected.sort();
+        assert_eq!(trumps, expected);
+        assert_eq!(state.stacks.tagged("dealt").count(), 1);
+        assert_eq!(state.stacks.tags(&Id::default()).count(), 0);
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::TagStack(Id::default(), vec!["nothing".into()]));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+    }
+
+    #[test]
+    fn cut_and_reveal_top_reveals_the_new_top() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..6).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut state = single_player(&sk, vec![
+            Payload::OpenStack(stack.clone()),
+            Payload::NameStack(stack.id(), "deck".into()),
+        ]);
+        assert_eq!(state.cut_and_reveal_top("nothing"), None);
+
+        let (payloads, shifted, secrets) = state.cut_and_reveal_top("deck").unwrap();
+        assert_eq!(payloads.len(), 3);
+        assert_eq!(secrets.len(), stack.len());
+        let mut bad = payloads.clone();
+        if let Payload::PublishSharesAt(_, idxs, ..) = &mut bad[1] {
+            idxs[0] = 1;
+        }
+
+        let mut forged = State::new(sk.clone());
+        forged
+            .add_block(state.chain.blocks().next().unwrap())
+            .unwrap();
+        let mut b = forged.chain.build_block();
+        for p in bad {
+            b.add_payload(p);
+        }
+        let b = b.build(&sk);
+        assert_eq!(forged.add_block(&b), Err(Error::BadProof));
+
+        let mut b = state.chain.build_block();
+        for p in payloads {
+            b.add_payload(p);
+        }
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.stacks.get_by_name("deck"), Some(&shifted));
+        let top = state.reveal_token(&shifted[0]).unwrap();
+        let k = top as usize;
+        let mut expected: Vec<_> = (0..6).collect();
+        expected.rotate_left(k);
+        let values: Vec<_> = shifted
+            .iter()
+            .map(|m| map::from_curve(&state.vtmf.unmask_open(&state.vtmf.unmask_private(m))))
+            .map(Option::unwrap)
+            .collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn tallies_reveal_only_the_sum() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let mut state = two_players(&sk0, &sk1, vec![]);
+        let mut vtmf1 = Vtmf::new(sk1.clone());
+        vtmf1.add_key(sk0.public_key());
+        vtmf1.set_domain(*state.vtmf.domain());
+
+        let (ballot, _, proof) = state.vtmf.mask_ballot("bar", &Tally::choices(), 1);
+        let (bad, _, bad_proof) = state
+            .vtmf
+            .mask_ballot("bar", &[map::to_curve_additive(2)], 0);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CastBallot("bar".into(), bad, bad_proof));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Err(Error::BadProof));
+        assert!(!state.tallies.contains_key("bar"));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CastBallot("baz".into(), ballot, proof.clone()));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Err(Error::BadProof));
+        assert!(!state.tallies.contains_key("baz"));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CastBallot("bar".into(), ballot, proof.clone()));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(!state.tallies["bar"].is_closed());
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CastBallot("bar".into(), ballot, proof.clone()));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Err(Error::BadProof));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CastBallot("bar".into(), ballot, proof));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let sk2 = PrivateKey::random(&mut rng);
+        let mut vtmf2 = Vtmf::new(sk2.clone());
+        vtmf2.add_key(sk0.public_key());
+        vtmf2.set_domain(*state.vtmf.domain());
+        let (ballot, _, proof) = vtmf2.mask_ballot("bar", &Tally::choices(), 1);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CastBallot("bar".into(), ballot, proof));
+        let b = b.build(&sk2);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+        assert!(!state.tallies["bar"].is_closed());
+
+        let (ballot, _, proof) = vtmf1.mask_ballot("bar", &Tally::choices(), 0);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CastBallot("bar".into(), ballot, proof));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(state.tallies["bar"].is_closed());
+        assert_eq!(state.tallies["bar"].result(&state.vtmf), None);
+
+        let mask = *state.tallies["bar"].mask();
+        let (share0, proof0) = state.vtmf.unmask_share(&mask);
+        let (share1, proof1) = vtmf1.unmask_share(&mask);
+        let (share2, proof2) = vtmf2.unmask_share(&mask);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RevealTally("bar".into(), share2, proof2));
+        let b = b.build(&sk2);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RevealTally("bar".into(), share0, proof0));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RevealTally("bar".into(), share1, proof1));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.tallies["bar"].result(&state.vtmf), Some(1));
+    }
+
+    #[test]
+    fn greater_proofs_are_recorded() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut state = single_player(&sk, vec![]);
+
+        let (x, ..) = state.vtmf.mask(&map::to_curve_fixed(7));
+        let y = Mask::open(map::to_curve_fixed(4));
+        let (z, ..) = state.vtmf.mask(&map::to_curve(9));
+        assert!(state.prove_greater(&y, &x, 8).is_none());
+        assert!(state.prove_greater(&x, &y, 7).is_none());
+        assert!(state.prove_greater(&z, &y, 10).is_none());
+        let proof = match state.prove_greater(&x, &y, 8) {
+            Some(Payload::ProveGreater(_, _, 8, proof)) => proof,
+            p => panic!("unexpected payload {:?}", p),
+        };
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ProveGreater(y, x, 8, proof.clone()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::BadProof));
+        assert!(!state.is_proven_greater(&y, &x));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ProveGreater(x, y, 8, proof));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(state.is_proven_greater(&x, &y));
+        assert!(!state.is_proven_greater(&y, &x));
+        assert_eq!(state.proof_log()[1].kind, ProofKind::Greater);
+    }
+
+    #[test]
+    fn field_proofs_are_recorded() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut state = single_player(&sk, vec![]);
+
+        let (x, r, _) = state.vtmf.mask(&map::to_curve_fixed(0x23));
+        let proof = state.vtmf.prove_field(&x, 0x23, &r, 0xf0, 0x20, 0x40);
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ProveField(x, 0xf0, 0x30, 0x40, proof.clone()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::BadProof));
+        assert!(!state.is_proven_field(&x, 0xf0, 0x30));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ProveField(x, 0xf0, 0x20, 0x40, proof));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(state.is_proven_field(&x, 0xf0, 0x20));
+        assert_eq!(state.proof_log()[1].kind, ProofKind::Field);
+    }
+
+    #[test]
+    fn distinct_checks_reveal_only_duplicates() {
+        let mut rng = thread_rng();
+        let sks = [PrivateKey::random(&mut rng), PrivateKey::random(&mut rng)];
+        let mut states: Vec<_> = sks.iter().cloned().map(State::new).collect();
+        for (sk, name) in sks.iter().zip(["foo", "bar"].iter()) {
+            let key = Payload::PublishKey(name.to_string(), sk.public_key());
+            assert_eq!(add(&mut states, sk, key), Ok(()));
+        }
+
+        let open: Stack = [1, 3, 4, 1, 5]
+            .iter()
+            .map(|&i| Mask::open(map::to_curve_fixed(i)))
+            .collect();
+        let (masked, proofs): (Vec<_>, Vec<_>) = open
+            .iter()
+            .map(|m| {
+                let (c, _, proof) = states[0].vtmf.remask(m);
+                (c, proof)
+            })
+            .unzip();
+        let masked: Stack = masked.into_iter().collect();
+        let distinct: Stack = masked[1..].iter().cloned().collect();
+        let dupes: Stack = masked[..4].iter().cloned().collect();
+        let payloads = vec![
+            Payload::OpenStack(open.clone()),
+            Payload::MaskStack(open.id(), masked.clone(), proofs),
+            Payload::TakeStack(masked.id(), vec![1, 2, 3, 4], distinct.id()),
+            Payload::TakeStack(masked.id(), vec![0, 1, 2, 3], dupes.id()),
+        ];
+        for payload in payloads {
+            assert_eq!(add(&mut states, &sks[0], payload), Ok(()));
+        }
+
+        // shares can't be revealed before everyone blinded
+        let blinding = states[0].prove_distinct(&dupes.id()).unwrap();
+        let early = match blinding.clone() {
+            Payload::ProveDistinct(id, blinded, _) => {
+                let (shares, proofs) = blinded
+                    .iter()
+                    .map(|m| states[0].vtmf.unmask_share(m))
+                    .unzip();
+                Payload::RevealDistinct(id, shares, proofs)
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            add(&mut states, &sks[1], blinding.clone()),
+            Err(Error::BadProof)
+        );
+        assert_eq!(add(&mut states, &sks[0], blinding.clone()), Ok(()));
+        let fps: Vec<_> = sks.iter().map(|sk| sk.fingerprint()).collect();
+        let check = &states[0].distinct_checks[&dupes.id()];
+        assert_eq!(check.next_step(&fps[0]), DistinctStep::Wait);
+        assert_eq!(check.next_step(&fps[1]), DistinctStep::Blind);
+        assert_eq!(add(&mut states, &sks[0], early), Err(Error::InvalidPayload));
+        assert_eq!(states[0].prove_distinct(&dupes.id()), None);
+
+        for id in [dupes.id(), distinct.id()].iter() {
+            while let Some((i, payload)) =
+                (0..2).find_map(|i| states[i].prove_distinct(id).map(|p| (i, p)))
+            {
+                assert_eq!(add(&mut states, &sks[i], payload), Ok(()));
+            }
+        }
+        assert!(!states[0].is_proven_distinct(&dupes.id()));
+        assert!(states[0].is_proven_distinct(&distinct.id()));
+        let check = &states[0].distinct_checks[&dupes.id()];
+        assert_eq!(check.next_step(&fps[1]), DistinctStep::Done);
+
+        let claims = states[1].claims_view();
+        assert_eq!(claims.len(), 2);
+        assert_eq!(claims[0].kind, ProofKind::Distinct);
+        assert_eq!(claims[0].stacks, vec![dupes.id()]);
+        assert!(!claims[0].verified);
+        assert_eq!(claims[1].stacks, vec![distinct.id()]);
+        assert!(claims[1].verified);
+
+        // a value with several points could pass as distinct from itself
+        let unfixed: Stack = (0..2).map(|_| Mask::open(map::to_curve(1))).collect();
+        let part: Stack = distinct[..2].iter().cloned().collect();
+        let payloads = vec![
+            Payload::OpenStack(unfixed),
+            Payload::TakeStack(distinct.id(), vec![0, 1], part.id()),
+        ];
+        for payload in payloads {
+            assert_eq!(add(&mut states, &sks[0], payload), Ok(()));
+        }
+        assert!(!states[0].has_only_fixed_tokens());
+        assert_eq!(states[0].prove_distinct(&part.id()), None);
+        let (blinded, proof) = states[0].vtmf.prove_distinct(&part);
+        let blinding = Payload::ProveDistinct(part.id(), blinded, proof);
+        assert_eq!(
+            add(&mut states, &sks[0], blinding),
+            Err(Error::InvalidPayload)
+        );
+    }
+
+    #[test]
+    fn reshuffles_track_their_reference() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let deck: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let other: Stack = (4..8).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut state = single_player(&sk, vec![
+            Payload::OpenStack(deck.clone()),
+            Payload::OpenStack(other.clone()),
+        ]);
+
+        let pi = rng.sample(Shuffles(deck.len()));
+        let (shuffled, _, proof) = state.vtmf.mask_shuffle(&deck, &pi);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShuffleStack(deck.id(), shuffled.clone(), proof));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(state.is_permutation_of(&shuffled.id(), &deck.id()));
+        assert!(!state.is_permutation_of(&shuffled.id(), &other.id()));
+
+        let (foreign, _, proof) = state.vtmf.prove_permutation_of(&other.id(), &other, &pi);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ReshuffleFrom(
+            deck.id(),
+            other.id(),
+            foreign,
+            proof,
+        ));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let (wrong, _, proof) = state.vtmf.prove_permutation_of(&other.id(), &shuffled, &pi);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ReshuffleFrom(
+            deck.id(),
+            shuffled.id(),
+            wrong,
+            proof,
+        ));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::BadProof));
+
+        let (reshuffled, _, proof) = state.vtmf.prove_permutation_of(&deck.id(), &shuffled, &pi);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ReshuffleFrom(
+            deck.id(),
+            shuffled.id(),
+            reshuffled.clone(),
+            proof,
+        ));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(state.is_permutation_of(&reshuffled.id(), &deck.id()));
+        let record = state.proofs.last().unwrap();
+        assert_eq!(record.kind, ProofKind::PermutationOf);
+        assert_eq!(record.stacks, vec![deck.id(), reshuffled.id()]);
+        assert!(record.verified);
+    }
+
+    #[test]
+    fn equal_multiset_proofs_are_recorded() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let deck: Stack = (0..6).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut state = single_player(&sk, vec![Payload::OpenStack(deck.clone())]);
+
+        let pi = rng.sample(Shuffles(deck.len()));
+        let (hidden, _, proof) = state.vtmf.prove_equal_multiset(&deck, &pi);
+        let (rehidden, _, bad_proof) = state.vtmf.prove_equal_multiset(&hidden, &pi);
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ReshuffleFrom(
+            deck.id(),
+            deck.id(),
+            hidden.clone(),
+            proof.clone(),
+        ));
+        b.add_payload(Payload::ReshuffleFrom(
+            hidden.id(),
+            hidden.id(),
+            rehidden.clone(),
+            bad_proof.clone(),
+        ));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ProveEqualMultiset(
+            rehidden.id(),
+            hidden.id(),
+            bad_proof,
+        ));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::BadProof));
+        assert!(!state.is_proven_equal_multiset(&rehidden.id(), &hidden.id()));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ProveEqualMultiset(hidden.id(), deck.id(), proof));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(state.is_proven_equal_multiset(&hidden.id(), &deck.id()));
+        let record = state.proof_log().pop().unwrap();
+        assert_eq!(record.kind, ProofKind::EqualMultiset);
+        assert_eq!(record.stacks, vec![hidden.id(), deck.id()]);
+
+        let claims = state.claims_view();
+        assert_eq!(claims.len(), 2);
+        assert!(!claims[0].verified);
+        assert_eq!(claims[1], ClaimView {
+            block: b.id(),
+            kind: ProofKind::EqualMultiset,
+            stacks: vec![hidden.id(), deck.id()],
+            parties: vec![sk.fingerprint()],
+            verified: true,
+        });
+    }
+
+    #[test]
+    fn peeks_open_stacks_privately() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let mut state = two_players(&sk0, &sk1, vec![]);
+        let mut vtmf1 = Vtmf::new(sk1.clone());
+        vtmf1.add_key(sk0.public_key());
+        vtmf1.set_domain(*state.vtmf.domain());
+
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let (masked, proofs): (Vec<_>, Vec<_>) = stack
+            .iter()
+            .map(|m| {
+                let (c, _, proof) = state.vtmf.remask(m);
+                (c, proof)
+            })
+            .unzip();
+        let masked: Stack = masked.into_iter().collect();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        b.add_payload(Payload::MaskStack(stack.id(), masked.clone(), proofs));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.peek(&masked.id()), None);
+
+        let (shares, proofs): (Vec<_>, Vec<_>) =
+            masked.iter().map(|m| vtmf1.unmask_share(m)).unzip();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishShares(masked.id(), shares, proofs));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let (open, nonce, commit) = state.peek(&masked.id()).unwrap();
+        assert_eq!(open, stack);
+        assert_eq!(State::peek_commitment(&open, &nonce), commit);
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::Peek(masked.id(), commit));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.peeks[&masked.id()], vec![(sk0.fingerprint(), commit)]);
+
+        let wrong = state
+            .open_peek(&masked.id(), &Id::random(&mut rng))
+            .unwrap();
+        assert_eq!(wrong.len(), 2);
+        let mut b = state.chain.build_block();
+        for payload in wrong {
+            b.add_payload(payload);
+        }
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let mut b = state.chain.build_block();
+        for payload in state.open_peek(&masked.id(), &nonce).unwrap() {
+            b.add_payload(payload);
+        }
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        let record = state.proofs.last().unwrap();
+        assert_eq!(record.kind, ProofKind::Peek);
+        assert!(record.verified);
+        assert_eq!(state.open_peek(&masked.id(), &nonce).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reveal_values_names_missing_parties() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let mut state = two_players(&sk0, &sk1, vec![]);
+        let mut vtmf1 = Vtmf::new(sk1.clone());
+        vtmf1.add_key(sk0.public_key());
+        vtmf1.set_domain(*state.vtmf.domain());
+
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let (masked, proofs): (Vec<_>, Vec<_>) = stack
+            .iter()
+            .map(|m| {
+                let (c, _, proof) = state.vtmf.remask(m);
+                (c, proof)
+            })
+            .unzip();
+        let masked: Stack = masked.into_iter().collect();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        b.add_payload(Payload::MaskStack(stack.id(), masked.clone(), proofs));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.reveal_values(&stack.id()), Ok(vec![0, 1, 2]));
+        assert_eq!(
+            state.reveal_values(&masked.id()),
+            Err(RevealError::MissingShares(vec![sk1.fingerprint()]))
+        );
+        assert_eq!(
+            state.reveal_values(&masked[..1].iter().cloned().collect::<Stack>().id()),
+            Err(RevealError::UnknownStack)
+        );
+        assert_eq!(
+            state.reveal_values(&masked.id()).map_err(Error::from),
+            Err(Error::MissingShares)
+        );
+
+        let (shares, proofs): (Vec<_>, Vec<_>) =
+            masked.iter().map(|m| vtmf1.unmask_share(m)).unzip();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishShares(masked.id(), shares, proofs));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.reveal_values(&masked.id()), Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn blocks_wait_for_the_stacks_they_use() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut sender = State::new(sk.clone());
+
+        let mut blocks = Vec::new();
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        for payload in [
+            Payload::PublishKey("foo".into(), sk.public_key()),
+            Payload::OpenStack(stack.clone()),
+            Payload::NameStack(stack.id(), "deck".into()),
+        ] {
+            let mut b = sender.chain.build_block();
+            b.add_payload(payload);
+            let b = b.build(&sk);
+            assert_eq!(sender.add_block(&b), Ok(()));
+            blocks.push(b);
+        }
+
+        let mut state = State::new(sk);
+        assert_eq!(state.add_block(&blocks[0]), Ok(()));
+        assert_eq!(state.add_block(&blocks[2]), Ok(()));
+        assert_eq!(state.pending_blocks(), vec![PendingBlock {
+            block: blocks[2].id(),
+            missing: vec![Reference::Stack(stack.id())],
+        }]);
+        assert_eq!(state.stacks.get_by_name("deck"), None);
+
+        assert_eq!(state.add_block(&blocks[1]), Ok(()));
+        assert_eq!(state.pending_blocks(), vec![]);
+        assert_eq!(state.stacks.get_by_name("deck"), Some(&stack));
+        assert_eq!(state.chain.count(), 3);
+        assert_eq!(state.take_pending_errors(), vec![]);
+    }
+
+    #[test]
+    fn failed_pending_blocks_are_reported_separately() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let mut sender = single_player(&sk, vec![]);
+        let mut b = sender.chain.build_block();
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        let open = b.build(&sk);
+        assert_eq!(sender.add_block(&open), Ok(()));
+        let mut b = sender.chain.build_block();
+        b.add_payload(Payload::TakeStack(stack.id(), vec![7], Id::default()));
+        let bad = b.build(&sk);
+
+        let mut state = single_player(&sk, vec![]);
+        assert_eq!(state.add_block(&bad), Ok(()));
+        assert_eq!(state.pending_blocks().len(), 1);
+        assert_eq!(state.add_block(&open), Ok(()));
+        assert_eq!(state.pending_blocks(), vec![]);
+        assert_eq!(state.take_pending_errors(), vec![(
+            bad.id(),
+            Error::InvalidPayload
+        )]);
+        assert_eq!(state.take_pending_errors(), vec![]);
+        assert!(state.stacks.get_by_id(&stack.id()).is_some());
+    }
+
+    #[test]
+    fn pending_blocks_are_capped() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let mut sender = single_player(&sk, vec![]);
+        let mut b = sender.chain.build_block();
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        let b = b.build(&sk);
+        assert_eq!(sender.add_block(&b), Ok(()));
+
+        let mut state = single_player(&sk, vec![]);
+        for i in 0..=MAX_PENDING_BLOCKS {
+            let mut b = sender.chain.build_block();
+            b.add_payload(Payload::NameStack(stack.id(), format!("name{}", i)));
+            let b = b.build(&sk);
+            let expected = if i < MAX_PENDING_BLOCKS {
+                Ok(())
+            } else {
+                Err(Error::TooManyPending)
+            };
+            assert_eq!(state.add_block(&b), expected);
+        }
+        assert_eq!(state.pending_blocks().len(), MAX_PENDING_BLOCKS);
+    }
+
+    #[test]
+    fn beacon_entropy_completes_generation() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut state = single_player(&sk, vec![Payload::RandomSpec(
+            "die".into(),
+            "1d6,beacon:42".into(),
+        )]);
+
+        let entropy = state.vtmf.mask_random(&mut rng);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RandomEntropy("die".into(), entropy));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(!state.rngs["die"].is_generated());
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::BeaconEntropy(
+            "die".into(),
+            41,
+            b"beacon".to_vec(),
+            vec![],
+        ));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let outsider = PrivateKey::random(&mut rng);
+        let beacon = Payload::BeaconEntropy("die".into(), 42, b"beacon".to_vec(), vec![]);
+        let mut b = state.chain.build_block();
+        b.add_payload(beacon.clone());
+        let b = b.build(&outsider);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(beacon);
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(state.rngs["die"].is_generated());
+        assert_eq!(state.rngs["die"].beacon(), Some(&b"beacon"[..]));
+    }
+
+    #[test]
+    fn beacons_cannot_follow_reveals() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut state = single_player(&sk, vec![Payload::RandomSpec(
+            "die".into(),
+            "1d6,beacon:42".into(),
+        )]);
+
+        let entropy = state.vtmf.mask_random(&mut rng);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RandomEntropy("die".into(), entropy));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let (share, proof) = state.vtmf.unmask_share(state.rngs["die"].mask());
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RandomReveal("die".into(), share, proof));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+        assert!(state.rngs["die"].secret_parties().is_empty());
+    }
+
+    #[test]
+    fn checkpoints_match_the_same_state_for_everyone() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let mut state0 = State::new(sk0.clone());
+        let mut state1 = State::new(sk1.clone());
+
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        for (name, sk) in [("foo", &sk0), ("bar", &sk1)] {
+            let mut b = state0.chain.build_block();
+            b.add_payload(Payload::PublishKey(name.into(), sk.public_key()));
+            let b = b.build(sk);
+            assert_eq!(state0.add_block(&b), Ok(()));
+            assert_eq!(state1.add_block(&b), Ok(()));
+        }
+        let mut b = state0.chain.build_block();
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        b.add_payload(Payload::NameStack(stack.id(), "deck".into()));
+        b.add_payload(Payload::RandomSpec("die".into(), "1d6".into()));
+        let b = b.build(&sk0);
+        assert_eq!(state0.add_block(&b), Ok(()));
+        assert_eq!(state1.add_block(&b), Ok(()));
+
+        let digest = state0.digest();
+        assert_eq!(state1.digest(), digest);
+
+        let mut b = state0.chain.build_block();
+        b.add_payload(Payload::Checkpoint(Id::default()));
+        let b = b.build(&sk0);
+        assert_eq!(state1.add_block(&b), Err(Error::Diverged));
+
+        let mut b = state0.chain.build_block();
+        b.add_payload(Payload::Checkpoint(digest));
+        b.add_payload(Payload::NameStack(stack.id(), "pile".into()));
+        let b = b.build(&sk0);
+        assert_eq!(state1.add_block(&b), Ok(()));
+        assert_ne!(state1.digest(), digest);
+
+        let mut b = state0.chain.build_block();
+        b.add_payload(Payload::NameStack(stack.id(), "discard".into()));
+        b.add_payload(Payload::Checkpoint(digest));
+        let b = b.build(&sk0);
+        assert_eq!(state1.add_block(&b), Err(Error::Diverged));
+
+        // claims are part of the state too
+        let digest = state1.digest();
+        state1.distinct.insert(stack.id());
+        assert_ne!(state1.digest(), digest);
+    }
+
+    #[test]
+    fn revealed_differences_are_multisets() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let state = two_players(&sk0, &sk1, vec![]);
+
+        let open =
+            |v: &[u64]| -> Stack { v.iter().map(|&i| Mask::open(map::to_curve(i))).collect() };
+        let full = open(&[4, 1, 2, 1, 3]);
+        let partial = open(&[1, 3, 5]);
+        assert_eq!(
+            state.revealed_difference(&full, &partial),
+            Ok(vec![4, 2, 1])
+        );
+        assert_eq!(state.revealed_difference(&partial, &full), Ok(vec![5]));
+
+        let (masked, ..) = state.vtmf.mask(&map::to_curve(1));
+        let hidden: Stack = vec![masked].into_iter().collect();
+        assert_eq!(
+            state.revealed_difference(&full, &hidden),
+            Err(RevealError::MissingShares(vec![sk1.fingerprint()]))
+        );
+    }
+
+    #[test]
+    fn partial_reveals_open_only_selected_tokens() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let mut state = two_players(&sk0, &sk1, vec![]);
+        let mut vtmf1 = Vtmf::new(sk1.clone());
+        vtmf1.add_key(sk0.public_key());
+        vtmf1.set_domain(*state.vtmf.domain());
+
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let (masked, proofs): (Vec<_>, Vec<_>) = stack
+            .iter()
+            .map(|m| {
+                let (c, _, proof) = state.vtmf.remask(m);
+                (c, proof)
+            })
+            .unzip();
+        let masked: Stack = masked.into_iter().collect();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        b.add_payload(Payload::MaskStack(stack.id(), masked.clone(), proofs));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let (share, proof) = vtmf1.unmask_share(&masked[2]);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishSharesAt(
+            masked.id(),
+            vec![3],
+            vec![share],
+            vec![proof.clone()],
+        ));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishSharesAt(
+            masked.id(),
+            vec![2],
+            vec![share],
+            vec![proof],
+        ));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.reveal_token(&masked[2]), Ok(2));
+        assert_eq!(state.missing_shares(&masked[2]), vec![sk0.fingerprint()]);
+        assert_eq!(state.missing_shares(&stack[2]), vec![]);
+        assert_eq!(
+            state.reveal_token(&masked[0]),
+            Err(RevealError::MissingShares(vec![sk1.fingerprint()]))
+        );
+
+        let (shares, proofs): (Vec<_>, Vec<_>) =
+            masked.iter().map(|m| vtmf1.unmask_share(m)).unzip();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishSharesAt(
+            masked.id(),
+            vec![0, 1, 2],
+            shares,
+            proofs,
+        ));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.reveal_values(&masked.id()), Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn joint_shuffles_need_every_party() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let mut state = two_players(&sk0, &sk1, vec![]);
+        let mut vtmf1 = Vtmf::new(sk1.clone());
+        vtmf1.add_key(sk0.public_key());
+        vtmf1.set_domain(*state.vtmf.domain());
+
+        let stack: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let pi = rng.sample(Shuffles(stack.len()));
+        let (shuffle0, _, proof) = state.vtmf.mask_shuffle(&stack, &pi);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        b.add_payload(Payload::ShuffleStack(stack.id(), shuffle0.clone(), proof));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(!state.is_jointly_shuffled(&shuffle0.id(), &stack.id()));
+
+        let pi = rng.sample(Shuffles(stack.len()));
+        let (shuffle1, _, proof) = vtmf1.mask_shuffle(&shuffle0, &pi);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShuffleStack(
+            shuffle0.id(),
+            shuffle1.clone(),
+            proof,
+        ));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(state.is_jointly_shuffled(&shuffle1.id(), &stack.id()));
+        assert!(!state.is_jointly_shuffled(&shuffle1.id(), &shuffle0.id()));
+        assert!(!state.is_jointly_shuffled(&stack.id(), &shuffle1.id()));
+    }
+
+    #[test]
+    fn proof_log_records_verification_results() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let other: Stack = (4..8).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut state = single_player(&sk, vec![
+            Payload::OpenStack(stack.clone()),
+            Payload::OpenStack(other.clone()),
+        ]);
+        assert!(state.proof_log().is_empty());
+
+        let pi = rng.sample(Shuffles(stack.len()));
+        let (shuffle, _, proof) = state.vtmf.mask_shuffle(&stack, &pi);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShuffleStack(
+            stack.id(),
+            shuffle.clone(),
+            proof.clone(),
+        ));
+        let good = b.build(&sk);
+        assert_eq!(state.add_block(&good), Ok(()));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShuffleStack(other.id(), shuffle.clone(), proof));
+        let bad = b.build(&sk);
+        assert_eq!(state.add_block(&bad), Err(Error::BadProof));
+
+        let log = state.proof_log();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].block, good.id());
+        assert_eq!(log[0].kind, ProofKind::Shuffle);
+        assert_eq!(log[0].stacks, vec![stack.id(), shuffle.id()]);
+        assert!(log[0].verified);
+        assert_eq!(log[1].block, bad.id());
+        assert_eq!(log[1].stacks, vec![other.id(), shuffle.id()]);
+        assert!(!log[1].verified);
+    }
+
+    #[test]
+    fn committed_cuts_are_checked_on_reveal() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut state = single_player(&sk, vec![Payload::OpenStack(stack.clone())]);
+
+        let nonce = Id::random(&mut rng);
+        let commit = State::cut_commitment(&stack.id(), 1, &nonce);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CommitShift(stack.id(), commit));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        let key = (stack.id(), sk.fingerprint());
+        assert_eq!(state.cut_commits[&key], (commit, None));
+
+        let (shifted, secrets, proof) = state.vtmf.mask_shift(&stack, 1);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShiftStack(stack.id(), shifted.clone(), proof));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RevealShift(stack.id(), 2, nonce, secrets.clone()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RevealShift(stack.id(), 1, nonce, secrets));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(state.cut_commits.is_empty());
+        assert_eq!(state.cuts[&shifted.id()], 1);
+    }
+
+    #[test]
+    fn committed_cuts_are_bound_to_their_committer() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let stack: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+
+        let mut state = two_players(&sk0, &sk1, vec![Payload::OpenStack(stack.clone())]);
+
+        // a squatter's commitment does not block the cutter's
+        let nonce = Id::random(&mut rng);
+        let commit = State::cut_commitment(&stack.id(), 1, &nonce);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CommitShift(stack.id(), commit));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::CommitShift(stack.id(), commit));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        // a plain shift from another party does not open the commitment
+        let (foreign, foreign_secrets, proof) = state.vtmf.mask_shift(&stack, 1);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShiftStack(stack.id(), foreign.clone(), proof));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Ok(()));
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RevealShift(stack.id(), 1, nonce, foreign_secrets));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let (shifted, secrets, proof) = state.vtmf.mask_shift(&stack, 1);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShiftStack(stack.id(), shifted.clone(), proof));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        // only one shift per commitment
+        let (again, _, proof) = state.vtmf.mask_shift(&stack, 1);
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::ShiftStack(stack.id(), again, proof));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        // a foreign reveal of the cutter's shift fails
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RevealShift(stack.id(), 1, nonce, secrets.clone()));
+        let b = b.build(&sk1);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+        assert!(state.cuts.is_empty());
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::RevealShift(stack.id(), 1, nonce, secrets));
+        let b = b.build(&sk0);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert_eq!(state.cuts[&shifted.id()], 1);
+        assert!(!state.cuts.contains_key(&foreign.id()));
+        assert!(state
+            .cut_commits
+            .contains_key(&(stack.id(), sk1.fingerprint())));
+    }
+
+    #[test]
+    fn rejoiners_verify_earlier_masks() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let mut state0 = State::new(sk0.clone());
+        let mut state1 = State::new(sk1.clone());
+
+        let stack: Stack = (0..3).map(|i| Mask::open(map::to_curve(i))).collect();
+        let mut b = state0.chain.build_block();
+        b.add_payload(Payload::PublishKey("foo".into(), sk0.public_key()));
+        b.add_payload(Payload::OpenStack(stack.clone()));
+        let b = b.build(&sk0);
+        assert_eq!(state0.add_block(&b), Ok(()));
+        assert_eq!(state1.add_block(&b), Ok(()));
+
+        let (masked, proofs): (Vec<_>, Vec<_>) = stack
+            .iter()
+            .map(|m| {
+                let (c, _, proof) = state0.vtmf.remask(m);
+                (c, proof)
+            })
+            .unzip();
+        let masked: Stack = masked.into_iter().collect();
+        let mut b = state0.chain.build_block();
+        b.add_payload(Payload::MaskStack(stack.id(), masked.clone(), proofs));
+        let b = b.build(&sk0);
+        assert_eq!(state0.add_block(&b), Ok(()));
+        assert_eq!(state1.add_block(&b), Ok(()));
+
+        let mut b = state1.chain.build_block();
+        b.add_payload(Payload::PublishKey("bar".into(), sk1.public_key()));
+        let b = b.build(&sk1);
+        assert_eq!(state0.add_block(&b), Ok(()));
+        assert_eq!(state1.add_block(&b), Ok(()));
+        assert!(state1.masked_before_join(&masked.id()));
+        assert!(!state0.masked_before_join(&masked.id()));
+        assert!(state0.is_late_join(&sk1.fingerprint()));
+        assert!(state1.is_late_join(&sk1.fingerprint()));
+        assert!(!state0.is_late_join(&sk0.fingerprint()));
+        assert_eq!(state0.reveal_values(&masked.id()), Ok(vec![0, 1, 2]));
+        assert_eq!(
+            state1.reveal_values(&masked.id()),
+            Err(RevealError::MissingShares(vec![sk0.fingerprint()]))
+        );
+
+        let (shares, proofs): (Vec<_>, Vec<_>) =
+            masked.iter().map(|m| state0.vtmf.unmask_share(m)).unzip();
+        let mut b = state0.chain.build_block();
+        b.add_payload(Payload::PublishShares(masked.id(), shares, proofs));
+        let b = b.build(&sk0);
+        assert_eq!(state1.add_block(&b), Ok(()));
+        assert_eq!(state1.reveal_values(&masked.id()), Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn open_stacks_only_hold_declared_tokens() {
+        let mut rng = thread_rng();
+        let sk = PrivateKey::random(&mut rng);
+        let mut state = State::new(sk.clone());
+        state.declare_tokens(0..4);
+
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::PublishKey("foo".into(), sk.public_key()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+
+        let bad: Stack = vec![2, 7]
+            .into_iter()
+            .map(|i| Mask::open(map::to_curve(i)))
+            .collect();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::OpenStack(bad));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Err(Error::InvalidPayload));
+
+        let good: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let mut b = state.chain.build_block();
+        b.add_payload(Payload::OpenStack(good.clone()));
+        let b = b.build(&sk);
+        assert_eq!(state.add_block(&b), Ok(()));
+        assert!(state.stacks.get_by_id(&good.id()).is_some());
     }
 }
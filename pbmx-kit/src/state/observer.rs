@@ -0,0 +1,30 @@
+use crate::{chain::Id, crypto::keys::Fingerprint};
+use std::fmt::{self, Debug, Formatter};
+
+/// An observer of state transitions
+///
+/// Observers are notified as payloads are applied to a
+/// [`State`](super::State), and only for payloads that were accepted. All
+/// methods do nothing by default.
+pub trait StateObserver {
+    /// Called when a party publishes its key
+    fn on_key_published(&mut self, _name: &str, _fp: Fingerprint) {}
+    /// Called when a party changes its name
+    fn on_player_renamed(&mut self, _name: &str, _fp: Fingerprint) {}
+    /// Called when a stack is created, named, or has secret shares published
+    fn on_stack_changed(&mut self, _id: Id) {}
+    /// Called when all parties have added entropy to an rng
+    fn on_rng_generated(&mut self, _name: &str) {}
+    /// Called when all parties have revealed their secrets for an rng, so its
+    /// value can be computed
+    fn on_rng_revealed(&mut self, _name: &str) {}
+}
+
+#[derive(Default)]
+pub(super) struct Observers(pub(super) Vec<Box<dyn StateObserver>>);
+
+impl Debug for Observers {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} observers", self.0.len())
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{
+    chain::{Block, Id, PayloadVisitor},
+    crypto::vtmf::{
+        DistinctProof, EntanglementProof, Mask, MaskProof, SecretShare, SecretShareProof,
+        ShiftProof, ShuffleProof, Stack,
+    },
+    state::{DeckTemplate, State},
+};
+use curve25519_dalek::scalar::Scalar;
+use std::collections::HashSet;
+
+/// Something a block refers to that is not in a state yet
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Reference {
+    /// A stack, by ID
+    Stack(Id),
+    /// A random number generator, by name
+    Rng(String),
+}
+
+/// A block waiting for the blocks it refers to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingBlock {
+    /// The waiting block
+    pub block: Id,
+    /// What the block refers to that is still missing
+    pub missing: Vec<Reference>,
+}
+
+/// Finds the stacks and generators a block uses that are neither in the
+/// state nor created earlier in the block itself
+pub(super) fn missing_references(state: &State, block: &Block) -> Vec<Reference> {
+    let mut finder = ReferenceFinder {
+        state,
+        created: HashSet::new(),
+        missing: Vec::new(),
+    };
+    for payload in block.payloads() {
+        finder.visit_payload(block, payload);
+    }
+    finder.missing
+}
+
+struct ReferenceFinder<'a> {
+    state: &'a State,
+    created: HashSet<Reference>,
+    missing: Vec<Reference>,
+}
+
+impl<'a> ReferenceFinder<'a> {
+    fn create(&mut self, r: Reference) {
+        self.created.insert(r);
+    }
+
+    fn require(&mut self, r: Reference) {
+        let known = match &r {
+            Reference::Stack(id) => self.state.stacks.get_by_id(id).is_some(),
+            Reference::Rng(name) => self.state.rngs.contains_key(name),
+        };
+        if !known && !self.created.contains(&r) && !self.missing.contains(&r) {
+            self.missing.push(r);
+        }
+    }
+
+    fn derive(&mut self, source: Id, target: Id) {
+        self.require(Reference::Stack(source));
+        self.create(Reference::Stack(target));
+    }
+}
+
+impl<'a> PayloadVisitor for ReferenceFinder<'a> {
+    fn visit_open_stack(&mut self, _: &Block, stack: &Stack) {
+        self.create(Reference::Stack(stack.id()));
+    }
+
+    fn visit_deck_template(&mut self, _: &Block, name: &str) {
+        if let Some(template) = DeckTemplate::get(name) {
+            self.create(Reference::Stack(template.stack().id()));
+        }
+    }
+
+    fn visit_mask_stack(&mut self, _: &Block, source: Id, stack: &Stack, _: &[MaskProof]) {
+        self.derive(source, stack.id());
+    }
+
+    fn visit_shuffle_stack(&mut self, _: &Block, source: Id, stack: &Stack, _: &ShuffleProof) {
+        self.derive(source, stack.id());
+    }
+
+    fn visit_shift_stack(&mut self, _: &Block, source: Id, stack: &Stack, _: &ShiftProof) {
+        self.derive(source, stack.id());
+    }
+
+    fn visit_reshuffle_from(
+        &mut self,
+        _: &Block,
+        reference: Id,
+        source: Id,
+        stack: &Stack,
+        _: &ShuffleProof,
+    ) {
+        self.require(Reference::Stack(reference));
+        self.derive(source, stack.id());
+    }
+
+    fn visit_name_stack(&mut self, _: &Block, id: Id, _: &str) {
+        self.require(Reference::Stack(id));
+    }
+
+    fn visit_tag_stack(&mut self, _: &Block, id: Id, _: &[String]) {
+        self.require(Reference::Stack(id));
+    }
+
+    fn visit_take_stack(&mut self, _: &Block, source: Id, _: &[usize], target: Id) {
+        self.derive(source, target);
+    }
+
+    fn visit_pile_stack(&mut self, _: &Block, sources: &[Id], target: Id) {
+        for &id in sources {
+            self.require(Reference::Stack(id));
+        }
+        self.create(Reference::Stack(target));
+    }
+
+    fn visit_publish_shares(
+        &mut self,
+        _: &Block,
+        id: Id,
+        _: &[SecretShare],
+        _: &[SecretShareProof],
+    ) {
+        self.require(Reference::Stack(id));
+    }
+
+    fn visit_publish_shares_at(
+        &mut self,
+        _: &Block,
+        id: Id,
+        _: &[usize],
+        _: &[SecretShare],
+        _: &[SecretShareProof],
+    ) {
+        self.require(Reference::Stack(id));
+    }
+
+    fn visit_random_spec(&mut self, _: &Block, name: &str, _: &str) {
+        self.create(Reference::Rng(name.into()));
+    }
+
+    fn visit_random_entropy(&mut self, _: &Block, name: &str, _: &Mask) {
+        self.require(Reference::Rng(name.into()));
+    }
+
+    fn visit_random_reveal(
+        &mut self,
+        _: &Block,
+        name: &str,
+        _: &SecretShare,
+        _: &SecretShareProof,
+    ) {
+        self.require(Reference::Rng(name.into()));
+    }
+
+    fn visit_beacon_entropy(&mut self, _: &Block, name: &str, _: u64, _: &[u8], _: &[u8]) {
+        self.require(Reference::Rng(name.into()));
+    }
+
+    fn visit_prove_entanglement(
+        &mut self,
+        _: &Block,
+        sources: &[Id],
+        shuffles: &[Id],
+        _: &EntanglementProof,
+    ) {
+        for &id in sources.iter().chain(shuffles.iter()) {
+            self.require(Reference::Stack(id));
+        }
+    }
+
+    fn visit_insert_stack(&mut self, _: &Block, source: Id, inserted: Id, rng: &str, target: Id) {
+        self.require(Reference::Rng(rng.into()));
+        self.require(Reference::Stack(inserted));
+        self.derive(source, target);
+    }
+
+    fn visit_burn_stack(&mut self, _: &Block, source: Id, _: &[usize], target: Id) {
+        self.derive(source, target);
+    }
+
+    fn visit_deal_face_up(&mut self, _: &Block, source: Id, _: &[usize], target: Id) {
+        self.derive(source, target);
+    }
+
+    fn visit_peek(&mut self, _: &Block, id: Id, _: Id) {
+        self.require(Reference::Stack(id));
+    }
+
+    fn visit_open_peek(&mut self, _: &Block, id: Id, _: Id) {
+        self.require(Reference::Stack(id));
+    }
+
+    fn visit_commit_shift(&mut self, _: &Block, id: Id, _: Id) {
+        self.require(Reference::Stack(id));
+    }
+
+    fn visit_reveal_shift(&mut self, _: &Block, id: Id, _: usize, _: Id, _: &[Scalar]) {
+        self.require(Reference::Stack(id));
+    }
+
+    fn visit_prove_distinct(&mut self, _: &Block, id: Id, _: &Stack, _: &DistinctProof) {
+        self.require(Reference::Stack(id));
+    }
+
+    fn visit_reveal_distinct(
+        &mut self,
+        _: &Block,
+        id: Id,
+        _: &[SecretShare],
+        _: &[SecretShareProof],
+    ) {
+        self.require(Reference::Stack(id));
+    }
+
+    fn visit_prove_equal_multiset(
+        &mut self,
+        _: &Block,
+        hidden: Id,
+        reference: Id,
+        _: &ShuffleProof,
+    ) {
+        self.require(Reference::Stack(hidden));
+        self.require(Reference::Stack(reference));
+    }
+}
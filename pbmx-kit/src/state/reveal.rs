@@ -0,0 +1,139 @@
+use crate::{
+    chain::Id,
+    crypto::{hash::TranscriptHash, keys::Fingerprint},
+    Error,
+};
+use std::collections::HashMap;
+
+/// A commit-reveal of random shares over several rounds
+///
+/// In each round every party first commits to a share, and only once all
+/// commitments are in can the shares be opened. Each round's output chains
+/// the previous one with all the opened shares, so no party can bias a round
+/// after seeing the others' contributions to it.
+#[derive(Debug, Clone)]
+pub struct MultiRoundReveal {
+    parties: Vec<Fingerprint>,
+    rounds: u64,
+    commits: HashMap<Fingerprint, Id>,
+    shares: HashMap<Fingerprint, Id>,
+    outputs: Vec<Id>,
+}
+
+impl MultiRoundReveal {
+    /// Creates a new reveal distributed over the given parties
+    pub fn new(parties: Vec<Fingerprint>, rounds: u64) -> Self {
+        Self {
+            parties,
+            rounds,
+            commits: HashMap::new(),
+            shares: HashMap::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Computes the commitment to a share
+    pub fn commitment(id: &Id, round: u64, party: &Fingerprint, share: &Id) -> Id {
+        let mut h = TranscriptHash::new(b"reveal");
+        h.append(b"reveal", id);
+        h.append(b"round", &round);
+        h.append(b"party", party);
+        h.append(b"share", share);
+        let mut buf = [0; 32];
+        h.finish(&mut buf);
+        buf.into()
+    }
+
+    /// Gets the total number of rounds
+    pub fn rounds(&self) -> u64 {
+        self.rounds
+    }
+
+    /// Gets the current round, or the number of rounds once complete
+    pub fn round(&self) -> u64 {
+        self.outputs.len() as u64
+    }
+
+    /// Tests whether all rounds have been opened
+    pub fn is_complete(&self) -> bool {
+        self.round() == self.rounds
+    }
+
+    /// Tests whether all parties have committed in the current round
+    pub fn is_committed(&self) -> bool {
+        self.commits.len() == self.parties.len()
+    }
+
+    /// Tests whether a party has committed in the current round
+    pub fn has_committed(&self, party: &Fingerprint) -> bool {
+        self.commits.contains_key(party)
+    }
+
+    /// Tests whether a party has opened its share in the current round
+    pub fn has_opened(&self, party: &Fingerprint) -> bool {
+        self.shares.contains_key(party)
+    }
+
+    /// Gets the outputs of all rounds opened so far
+    pub fn outputs(&self) -> &[Id] {
+        &self.outputs
+    }
+
+    /// Adds a party's commitment for the current round
+    ///
+    /// Only the parties the reveal was created for can commit.
+    pub fn add_commit(&mut self, party: Fingerprint, round: u64, commit: Id) -> Result<(), Error> {
+        if !self.parties.contains(&party)
+            || self.is_complete()
+            || round != self.round()
+            || self.is_committed()
+            || self.has_committed(&party)
+        {
+            return Err(Error::InvalidPayload);
+        }
+        self.commits.insert(party, commit);
+        Ok(())
+    }
+
+    /// Opens a party's share for the current round
+    ///
+    /// Shares can only be opened after everyone committed, and must match
+    /// the party's commitment. The round is closed once all shares are open.
+    pub fn add_share(
+        &mut self,
+        id: &Id,
+        party: Fingerprint,
+        round: u64,
+        share: Id,
+    ) -> Result<(), Error> {
+        if self.is_complete()
+            || round != self.round()
+            || !self.is_committed()
+            || self.has_opened(&party)
+        {
+            return Err(Error::InvalidPayload);
+        }
+        let commit = self.commits.get(&party).ok_or(Error::InvalidPayload)?;
+        if *commit != Self::commitment(id, round, &party, &share) {
+            return Err(Error::BadProof);
+        }
+        self.shares.insert(party, share);
+
+        if self.shares.len() == self.parties.len() {
+            let mut shares: Vec<_> = self.shares.drain().collect();
+            shares.sort();
+            let mut h = TranscriptHash::new(b"reveal-round");
+            h.append(b"previous", self.outputs.last().unwrap_or(id));
+            h.append(b"round", &round);
+            for (party, share) in shares {
+                h.append(b"party", &party);
+                h.append(b"share", &share);
+            }
+            let mut buf = [0; 32];
+            h.finish(&mut buf);
+            self.outputs.push(buf.into());
+            self.commits.clear();
+        }
+        Ok(())
+    }
+}
@@ -14,6 +14,7 @@ use std::fmt::{self, Debug, Display, Formatter};
 pub struct Rng {
     parties: usize,
     spec: RngSpec,
+    beacon: Option<Vec<u8>>,
     entropy: Mask,
     entropy_fp: Vec<Fingerprint>,
     secret: SecretShare,
@@ -27,6 +28,7 @@ impl Rng {
         Ok(Self {
             parties,
             spec: RngSpec::parse(spec)?,
+            beacon: None,
             entropy: Mask::open(RistrettoPoint::identity()),
             entropy_fp: Vec::new(),
             secret: SecretShare(RistrettoPoint::identity()),
@@ -50,6 +52,34 @@ impl Rng {
         self.entropy_fp.push(party);
     }
 
+    /// Gets the round of the external beacon this RNG mixes in, if any
+    pub fn beacon_round(&self) -> Option<u64> {
+        self.spec.beacon
+    }
+
+    /// Gets the recorded output of the external beacon, if any
+    pub fn beacon(&self) -> Option<&[u8]> {
+        self.beacon.as_deref()
+    }
+
+    /// Records the output of the external beacon
+    ///
+    /// Fails if this RNG does not use a beacon, if the round is not the one in
+    /// the spec, if the beacon was already recorded, or once any secret was
+    /// revealed, since the beacon could then be picked to steer the result.
+    pub fn add_beacon(&mut self, round: u64, bytes: &[u8]) -> Result<(), Error> {
+        if self.beacon.is_some() || self.spec.beacon != Some(round) || !self.secret_fp.is_empty() {
+            return Err(Error::InvalidPayload);
+        }
+        self.beacon = Some(bytes.to_vec());
+        Ok(())
+    }
+
+    /// Tests whether this RNG still waits for its external beacon
+    pub fn awaits_beacon(&self) -> bool {
+        self.spec.beacon.is_some() && self.beacon.is_none()
+    }
+
     /// Adds a secret to this RNG
     pub fn add_secret(&mut self, party: Fingerprint, share: &SecretShare) {
         self.secret.0 += share.0;
@@ -67,8 +97,10 @@ impl Rng {
     }
 
     /// Tests whether all entropy for generation has been collected
+    ///
+    /// This includes the external beacon, if the RNG uses one.
     pub fn is_generated(&self) -> bool {
-        self.entropy_parties().len() == self.parties
+        self.entropy_parties().len() == self.parties && !self.awaits_beacon()
     }
 
     /// Tests whether all secrets for revealing the result have been collected
@@ -76,20 +108,78 @@ impl Rng {
         self.secret_parties().len() == self.parties
     }
 
+    /// Tests whether every one of the given parties has contributed entropy
+    ///
+    /// Unlike [`is_generated`](Rng::is_generated), this does not rely on
+    /// counting contributions, and so fails if any party was skipped.
+    pub fn entropy_complete(&self, parties: &[Fingerprint]) -> bool {
+        parties.iter().all(|fp| self.entropy_fp.contains(fp))
+    }
+
+    /// Tests whether this RNG reveals the sum of secret contributions
+    ///
+    /// For these generators each party provides a masked value (see
+    /// [`to_curve_additive`](crate::crypto::map::to_curve_additive)) instead of
+    /// random entropy, and only the total is ever revealed.
+    pub fn is_sum(&self) -> bool {
+        match self.spec.spec {
+            spec::Spec::Sum(_) => true,
+            spec::Spec::Dice(_) => false,
+        }
+    }
+
     /// Generates the result
-    pub fn gen(&self, vtmf: &Vtmf) -> u64 {
+    ///
+    /// Fails for a sum that exceeds the specified bound, or if the external
+    /// beacon has not been recorded yet.
+    pub fn gen(&self, vtmf: &Vtmf) -> Option<u64> {
+        let r = vtmf.unmask(&self.entropy, &self.secret);
+        match &self.spec.spec {
+            spec::Spec::Sum(max) => vtmf.unmask_sum(&r, max.unwrap_or(DEFAULT_SUM_MAX)),
+            spec::Spec::Dice(expr) => {
+                let mut reader = self.reader(vtmf)?;
+                Some(expr.apply(&mut spec::bits(&mut reader)))
+            }
+        }
+    }
+
+    /// Reads raw bytes from the shared random stream
+    ///
+    /// Every party gets the same bytes, whatever the spec, so they can be
+    /// used as a shared seed. The stream depends on `n`, so reading fewer
+    /// bytes does not give a prefix of a longer read. Fails if the external
+    /// beacon has not been recorded yet.
+    pub fn gen_bytes(&self, vtmf: &Vtmf, n: usize) -> Option<Vec<u8>> {
+        let mut bytes = vec![0; n];
+        self.reader(vtmf)?.read(&mut bytes);
+        Some(bytes)
+    }
+
+    fn reader(&self, vtmf: &Vtmf) -> Option<impl XofReader> {
+        if self.awaits_beacon() {
+            return None;
+        }
         let r = vtmf.unmask(&self.entropy, &self.secret);
-        let mut reader = vtmf.unmask_random(&r);
-        self.spec.gen(&mut reader)
+        Some(vtmf.unmask_random_mixed(&r, self.beacon()))
     }
 }
 
+/// The largest sum a `sum` generator can reveal unless given another bound
+pub const DEFAULT_SUM_MAX: u64 = 1 << 16;
+
 #[derive(Clone)]
-struct RngSpec(spec::Expr);
+struct RngSpec {
+    spec: spec::Spec,
+    beacon: Option<u64>,
+}
 
 impl Display for RngSpec {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.spec)?;
+        if let Some(round) = self.beacon {
+            write!(f, ",beacon:{}", round)?;
+        }
+        Ok(())
     }
 }
 
@@ -101,11 +191,8 @@ impl Debug for RngSpec {
 
 impl RngSpec {
     fn parse(input: &str) -> Result<Self, spec::ParseError> {
-        Ok(Self(spec::Expr::parse(input)?))
-    }
-
-    fn gen(&self, reader: &mut dyn XofReader) -> u64 {
-        self.0.apply(&mut spec::bits(reader))
+        let (spec, beacon) = spec::Spec::parse(input)?;
+        Ok(Self { spec, beacon })
     }
 }
 
@@ -160,6 +247,50 @@ mod spec {
         }
     }
 
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Spec {
+        Dice(Expr),
+        Sum(Option<u64>),
+    }
+
+    impl Display for Spec {
+        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+            match self {
+                Spec::Dice(e) => write!(f, "{}", e),
+                Spec::Sum(None) => write!(f, "sum"),
+                Spec::Sum(Some(max)) => write!(f, "sum:{}", max),
+            }
+        }
+    }
+
+    impl Spec {
+        /// Parses a spec, along with the round of the beacon it mixes in
+        pub fn parse(input: &str) -> Result<(Self, Option<u64>), ParseError> {
+            let pairs = RngParser::parse(Rule::spec, input).map_err(|_| ParseError)?;
+            match pairs.peek() {
+                Some(pair) if pair.as_rule() == Rule::sum => {
+                    let max = match pair.into_inner().next() {
+                        Some(max) => Some(max.as_str().parse::<u64>().map_err(|_| ParseError)?),
+                        None => None,
+                    };
+                    Ok((Spec::Sum(max), None))
+                }
+                _ => {
+                    let beacon = match pairs.clone().find(|p| p.as_rule() == Rule::beacon) {
+                        Some(pair) => Some(
+                            pair.into_inner()
+                                .as_str()
+                                .parse::<u64>()
+                                .map_err(|_| ParseError)?,
+                        ),
+                        None => None,
+                    };
+                    Ok((Spec::Dice(Expr::new(parse(pairs))), beacon))
+                }
+            }
+        }
+    }
+
     #[derive(Debug, Clone, PartialEq, Eq)]
     pub enum Node {
         Const(u64),
@@ -225,13 +356,6 @@ mod spec {
     }
 
     impl Expr {
-        pub fn parse(input: &str) -> Result<Self, ParseError> {
-            RngParser::parse(Rule::spec, input)
-                .map(parse)
-                .map(Expr::new)
-                .map_err(|_| ParseError)
-        }
-
         pub fn apply(&self, bits: &mut BitIterator) -> u64 {
             self.0.apply(bits)
         }
@@ -293,3 +417,151 @@ mod spec {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Rng;
+    use crate::crypto::{
+        keys::PrivateKey,
+        map,
+        vtmf::{Mask, Vtmf},
+    };
+    use curve25519_dalek::{ristretto::RistrettoPoint, traits::Identity};
+    use rand::thread_rng;
+
+    #[test]
+    fn entropy_is_complete_only_with_every_party() {
+        let mut rng = thread_rng();
+        let fp0 = PrivateKey::random(&mut rng).fingerprint();
+        let fp1 = PrivateKey::random(&mut rng).fingerprint();
+        let entropy = Mask::open(RistrettoPoint::identity());
+
+        let mut r = Rng::new(2, "1d6").unwrap();
+        r.add_entropy(fp0, &entropy);
+        r.add_entropy(fp0, &entropy);
+        assert!(r.is_generated());
+        assert!(!r.entropy_complete(&[fp0, fp1]));
+
+        r.add_entropy(fp1, &entropy);
+        assert!(r.entropy_complete(&[fp0, fp1]));
+    }
+
+    #[test]
+    fn sum_specs_round_trip() {
+        assert_eq!(Rng::new(2, "sum").unwrap().spec(), "sum");
+        assert_eq!(Rng::new(2, "sum:100").unwrap().spec(), "sum:100");
+        assert!(Rng::new(2, "sum").unwrap().is_sum());
+        assert!(!Rng::new(2, "2d6").unwrap().is_sum());
+        assert!(Rng::new(2, "sum:0").is_err());
+        assert!(Rng::new(2, "sum+1").is_err());
+        assert!(Rng::new(2, "1d6+sum").is_err());
+    }
+
+    #[test]
+    fn sums_reveal_only_the_total() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let (pk0, pk1) = (sk0.public_key(), sk1.public_key());
+        let (fp0, fp1) = (pk0.fingerprint(), pk1.fingerprint());
+        let mut vtmf0 = Vtmf::new(sk0);
+        let mut vtmf1 = Vtmf::new(sk1);
+        vtmf0.add_key(pk1);
+        vtmf1.add_key(pk0);
+
+        let mut sum = Rng::new(2, "sum:50").unwrap();
+        let mut small = Rng::new(2, "sum:40").unwrap();
+        for r in [&mut sum, &mut small].iter_mut() {
+            r.add_entropy(fp0, &vtmf0.mask(&map::to_curve_additive(30)).0);
+            r.add_entropy(fp1, &vtmf1.mask(&map::to_curve_additive(12)).0);
+            let (d0, _) = vtmf0.unmask_share(r.mask());
+            let (d1, _) = vtmf1.unmask_share(r.mask());
+            r.add_secret(fp0, &d0);
+            r.add_secret(fp1, &d1);
+            assert!(r.is_revealed());
+        }
+
+        assert_eq!(sum.gen(&vtmf0), Some(42));
+        assert_eq!(sum.gen(&vtmf1), Some(42));
+        assert_eq!(small.gen(&vtmf0), None);
+    }
+
+    #[test]
+    fn raw_bytes_are_shared() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let (pk0, pk1) = (sk0.public_key(), sk1.public_key());
+        let (fp0, fp1) = (pk0.fingerprint(), pk1.fingerprint());
+        let mut vtmf0 = Vtmf::new(sk0);
+        let mut vtmf1 = Vtmf::new(sk1);
+        vtmf0.add_key(pk1);
+        vtmf1.add_key(pk0);
+
+        let mut r = Rng::new(2, "1d6").unwrap();
+        r.add_entropy(fp0, &vtmf0.mask_random(&mut rng));
+        r.add_entropy(fp1, &vtmf1.mask_random(&mut rng));
+        let (d0, _) = vtmf0.unmask_share(r.mask());
+        let (d1, _) = vtmf1.unmask_share(r.mask());
+        r.add_secret(fp0, &d0);
+        r.add_secret(fp1, &d1);
+
+        let bytes = r.gen_bytes(&vtmf0, 40).unwrap();
+        assert_eq!(bytes.len(), 40);
+        assert_eq!(Some(bytes), r.gen_bytes(&vtmf1, 40));
+    }
+
+    #[test]
+    fn beacon_specs_round_trip() {
+        let r = Rng::new(2, "2d6+1,beacon:1234").unwrap();
+        assert_eq!(r.spec(), "2d6+1,beacon:1234");
+        assert_eq!(r.beacon_round(), Some(1234));
+        assert_eq!(Rng::new(2, "2d6").unwrap().beacon_round(), None);
+        assert!(Rng::new(2, "sum,beacon:1").is_err());
+        assert!(Rng::new(2, "1d6,beacon:").is_err());
+        assert!(Rng::new(2, "1d6,beacon:1,beacon:2").is_err());
+    }
+
+    #[test]
+    fn beacons_are_mixed_in() {
+        let mut rng = thread_rng();
+        let sk0 = PrivateKey::random(&mut rng);
+        let sk1 = PrivateKey::random(&mut rng);
+        let (pk0, pk1) = (sk0.public_key(), sk1.public_key());
+        let (fp0, fp1) = (pk0.fingerprint(), pk1.fingerprint());
+        let mut vtmf0 = Vtmf::new(sk0);
+        let mut vtmf1 = Vtmf::new(sk1);
+        vtmf0.add_key(pk1);
+        vtmf1.add_key(pk0);
+
+        let e0 = vtmf0.mask_random(&mut rng);
+        let e1 = vtmf1.mask_random(&mut rng);
+        let mut plain = Rng::new(2, "1d6").unwrap();
+        let mut mixed = Rng::new(2, "1d6,beacon:7").unwrap();
+        let mut late = Rng::new(2, "1d6,beacon:7").unwrap();
+        for r in [&mut plain, &mut mixed, &mut late].iter_mut() {
+            r.add_entropy(fp0, &e0);
+            r.add_entropy(fp1, &e1);
+        }
+
+        assert!(!mixed.is_generated());
+        assert_eq!(mixed.gen(&vtmf0), None);
+        assert_eq!(mixed.gen_bytes(&vtmf0, 32), None);
+        assert!(mixed.add_beacon(8, b"beacon").is_err());
+        assert!(mixed.add_beacon(7, b"beacon").is_ok());
+        assert!(mixed.add_beacon(7, b"other").is_err());
+        assert!(mixed.is_generated());
+
+        for r in [&mut plain, &mut mixed, &mut late].iter_mut() {
+            let (d0, _) = vtmf0.unmask_share(r.mask());
+            let (d1, _) = vtmf1.unmask_share(r.mask());
+            r.add_secret(fp0, &d0);
+            r.add_secret(fp1, &d1);
+        }
+        assert!(late.add_beacon(7, b"beacon").is_err());
+
+        assert_eq!(mixed.gen(&vtmf0), mixed.gen(&vtmf1));
+        assert_eq!(mixed.gen_bytes(&vtmf0, 32), mixed.gen_bytes(&vtmf1, 32));
+        assert_ne!(mixed.gen_bytes(&vtmf0, 32), plain.gen_bytes(&vtmf0, 32));
+    }
+}
@@ -9,11 +9,52 @@ use crate::{
     Error,
 };
 use qp_trie::Trie;
-use std::{collections::HashMap, str};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    convert::TryFrom,
+    str,
+};
 
 /// A map of published secrets
 pub type SecretMap = HashMap<Mask, (SecretShare, Vec<Fingerprint>)>;
 
+impl Proto for SecretMap {
+    type Message = proto::SecretMap;
+
+    fn to_proto(&self) -> Result<Self::Message, Error> {
+        Ok(proto::SecretMap {
+            map: self
+                .iter()
+                .map(|(k, (share, owners))| {
+                    Ok(proto::SecretEntry {
+                        key: Some(k.to_proto()?),
+                        share: Some(share.to_proto()?),
+                        owners: owners.iter().map(|fp| fp.to_vec()).collect(),
+                    })
+                })
+                .collect::<Result<_, Error>>()?,
+        })
+    }
+
+    fn from_proto(m: &Self::Message) -> Result<Self, Error> {
+        m.map
+            .iter()
+            .map(|e| {
+                Ok((
+                    Mask::from_proto(e.key.as_ref().ok_or(Error::Decoding)?)?,
+                    (
+                        SecretShare::from_proto(e.share.as_ref().ok_or(Error::Decoding)?)?,
+                        e.owners
+                            .iter()
+                            .map(Fingerprint::try_from)
+                            .collect::<Result<_, Error>>()?,
+                    ),
+                ))
+            })
+            .collect()
+    }
+}
+
 /// A map of private secrets
 pub type PrivateSecretMap = HashMap<Mask, Mask>;
 
@@ -53,10 +94,23 @@ pub struct StackMap {
     len: usize,
     map: Trie<Id, Stack>,
     name_map: HashMap<String, Id>,
+    name_ranks: HashMap<String, NameRank>,
     secrets: SecretMap,
     private_secrets: PrivateSecretMap,
+    burned: HashSet<Id>,
+    burned_masks: HashSet<Mask>,
+    face_up: HashSet<Id>,
+    decks: HashMap<String, HashSet<Id>>,
+    tags: HashMap<Id, BTreeSet<String>>,
 }
 
+/// The separator between a deck and a stack name
+pub const DECK_SEPARATOR: char = '/';
+
+/// The point in a chain where a name was given: the height of the naming
+/// block, then its ID
+pub type NameRank = (usize, Id);
+
 impl StackMap {
     /// Creates a new empty map
     pub fn new() -> Self {
@@ -81,6 +135,52 @@ impl StackMap {
         }
     }
 
+    /// Inserts a new stack in the map as part of a deck
+    pub fn insert_in(&mut self, deck: &str, stack: Stack) {
+        self.decks
+            .entry(deck.to_string())
+            .or_default()
+            .insert(stack.id());
+        self.insert(stack);
+    }
+
+    /// Gets all stacks in a deck
+    ///
+    /// These are the stacks inserted in the deck, and those named within it.
+    pub fn stacks_in<'a>(&'a self, deck: &str) -> impl Iterator<Item = &'a Stack> + 'a {
+        let mut ids: HashSet<Id> = self
+            .name_map
+            .iter()
+            .filter(|(n, _)| Self::split_name(n).0 == Some(deck))
+            .map(|(_, id)| *id)
+            .collect();
+        if let Some(inserted) = self.decks.get(deck) {
+            ids.extend(inserted.iter().cloned());
+        }
+        ids.into_iter().filter_map(move |id| self.get_by_id(&id))
+    }
+
+    /// Gets all deck names in the map
+    pub fn decks(&self) -> impl Iterator<Item = &str> {
+        let mut decks: Vec<_> = self
+            .name_map
+            .keys()
+            .filter_map(|n| Self::split_name(n).0)
+            .chain(self.decks.keys().map(String::as_str))
+            .collect();
+        decks.sort();
+        decks.dedup();
+        decks.into_iter()
+    }
+
+    /// Splits a stack name into its deck, if any, and its name within the deck
+    pub fn split_name(name: &str) -> (Option<&str>, &str) {
+        match name.find(DECK_SEPARATOR) {
+            Some(i) => (Some(&name[..i]), &name[i + 1..]),
+            None => (None, name),
+        }
+    }
+
     /// Tests whether the map contains a stack with the given ID
     pub fn contains(&mut self, id: &Id) -> bool {
         self.map.contains_key(id)
@@ -94,10 +194,162 @@ impl StackMap {
             .or_insert(id);
     }
 
+    /// Names a stack, unless the name was last given at a later point in the
+    /// chain
+    ///
+    /// Returns whether the name was set. See
+    /// [`current_named`](StackMap::current_named) for how points are ordered.
+    pub fn set_name_ranked(&mut self, id: Id, name: String, rank: NameRank) -> bool {
+        if matches!(self.name_ranks.get(&name), Some(r) if *r > rank) {
+            return false;
+        }
+        self.name_ranks.insert(name.clone(), rank);
+        self.set_name(id, name);
+        true
+    }
+
+    /// Adds tags to a stack, keeping any it already has
+    pub fn add_tags<I: IntoIterator<Item = String>>(&mut self, id: Id, tags: I) {
+        self.tags.entry(id).or_default().extend(tags);
+    }
+
+    /// Gets the tags of a stack, in sorted order
+    pub fn tags(&self, id: &Id) -> impl Iterator<Item = &str> {
+        self.tags
+            .get(id)
+            .into_iter()
+            .flat_map(|t| t.iter().map(String::as_str))
+    }
+
+    /// Gets all stacks with a given tag
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a Stack> + 'a {
+        self.tags
+            .iter()
+            .filter(move |(_, t)| t.contains(tag))
+            .filter_map(move |(id, _)| self.get_by_id(id))
+    }
+
+    /// Marks a stack as burned
+    ///
+    /// Its face-down tokens are retired too, so no other stack holding any of
+    /// them can be used any more.
+    pub fn burn(&mut self, id: Id) {
+        self.burned.insert(id);
+        if let Some(stack) = self.map.get(&id) {
+            self.burned_masks
+                .extend(stack.iter().filter(|m| !m.is_open()).cloned());
+        }
+    }
+
+    /// Tests whether a stack has been burned
+    pub fn is_burned(&self, id: &Id) -> bool {
+        self.burned.contains(id)
+    }
+
+    /// Gets the IDs of all burned stacks
+    pub fn burned(&self) -> impl Iterator<Item = &Id> {
+        self.burned.iter()
+    }
+
+    /// Marks a stack as dealt face up
+    pub fn deal_face_up(&mut self, id: Id) {
+        self.face_up.insert(id);
+    }
+
+    /// Tests whether a stack was dealt face up
+    pub fn is_face_up(&self, id: &Id) -> bool {
+        self.face_up.contains(id)
+    }
+
+    /// Gets the IDs of all stacks dealt face up
+    pub fn face_up(&self) -> impl Iterator<Item = &Id> {
+        self.face_up.iter()
+    }
+
+    /// Computes the stack that taking some tokens from a source stack
+    /// produces, and its ID
+    ///
+    /// Returns `None` if the source is unknown or burned, or if any index is
+    /// out of range.
+    pub fn preview_take(&self, source: &Id, indices: &[usize]) -> Option<(Stack, Id)> {
+        let src = self.get_live(source)?;
+        if !indices.iter().all(|i| *i < src.len()) {
+            return None;
+        }
+        let stack: Stack = indices.iter().map(|i| src[*i]).collect();
+        let id = stack.id();
+        Some((stack, id))
+    }
+
+    /// Computes the stack that piling several stacks, from top to bottom,
+    /// produces, and its ID
+    ///
+    /// Returns `None` if any source is unknown or burned.
+    pub fn preview_pile(&self, sources: &[Id]) -> Option<(Stack, Id)> {
+        let stack: Stack = sources
+            .iter()
+            .map(|id| self.get_live(id))
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .flat_map(|stk| stk.iter())
+            .cloned()
+            .collect();
+        let id = stack.id();
+        Some((stack, id))
+    }
+
+    /// Computes the stack that inserting a stack into another at a given
+    /// position produces, and its ID
+    ///
+    /// Returns `None` if either stack is unknown or burned, or if the
+    /// position is past the bottom of the source.
+    pub fn preview_insert(&self, source: &Id, inserted: &Id, at: usize) -> Option<(Stack, Id)> {
+        let src = self.get_live(source)?;
+        let ins = self.get_live(inserted)?;
+        if at > src.len() {
+            return None;
+        }
+        let stack: Stack = src[..at]
+            .iter()
+            .chain(ins.iter())
+            .chain(src[at..].iter())
+            .cloned()
+            .collect();
+        let id = stack.id();
+        Some((stack, id))
+    }
+
+    /// Gets a stack by its ID, unless it was burned or holds a burned token
+    pub fn get_live(&self, id: &Id) -> Option<&Stack> {
+        if self.is_burned(id) {
+            return None;
+        }
+        self.get_by_id(id)
+            .filter(|stack| !stack.iter().any(|m| self.burned_masks.contains(m)))
+    }
+
+    /// Tests whether a stack is known and can still be used
+    pub fn is_live(&self, id: &Id) -> bool {
+        self.get_live(id).is_some()
+    }
+
     /// Adds a share of a stack's secret
     pub fn add_secret_share(&mut self, id: Id, owner: Fingerprint, shares: Vec<SecretShare>) {
-        let stack = &mut self.map[&id];
-        for (m, di) in stack.iter().zip(shares.iter()) {
+        let indices: Vec<_> = (0..shares.len()).collect();
+        self.add_secret_share_at(id, owner, &indices, shares);
+    }
+
+    /// Adds shares of the secrets of some tokens in a stack
+    pub fn add_secret_share_at(
+        &mut self,
+        id: Id,
+        owner: Fingerprint,
+        indices: &[usize],
+        shares: Vec<SecretShare>,
+    ) {
+        let stack = &self.map[&id];
+        let tokens = indices.iter().filter_map(|&i| stack.get(i));
+        for (m, di) in tokens.zip(shares.iter()) {
             self.secrets
                 .entry(*m)
                 .and_modify(|(d, fp)| {
@@ -110,8 +362,28 @@ impl StackMap {
         }
     }
 
+    /// Merges published secrets exported from another map
+    ///
+    /// An imported share replaces a known one only if it combines the shares
+    /// of all the parties the known one does, and more.
+    pub fn add_published_secrets<It>(&mut self, it: It)
+    where
+        It: Iterator<Item = (Mask, (SecretShare, Vec<Fingerprint>))>,
+    {
+        for (m, (d, owners)) in it {
+            let known = self.secrets.get(&m).map(|(_, fps)| fps);
+            let newer = match known {
+                Some(fps) => owners.len() > fps.len() && fps.iter().all(|fp| owners.contains(fp)),
+                None => true,
+            };
+            if newer {
+                self.secrets.insert(m, (d, owners));
+            }
+        }
+    }
+
     /// Stores a private secret
-    pub fn add_private_secrets<It>(&mut self, it: It) -> Result<(), ()>
+    pub fn add_private_secrets<It>(&mut self, it: It) -> Result<(), Error>
     where
         It: Iterator<Item = (Mask, Mask)>,
     {
@@ -167,7 +439,141 @@ impl StackMap {
     }
 
     /// Finds a stack by its name
+    ///
+    /// This is the same as [`current_named`](StackMap::current_named).
     pub fn get_by_name(&self, name: &str) -> Option<&Stack> {
+        self.current_named(name)
+    }
+
+    /// Finds the stack that currently holds a name
+    ///
+    /// When divergent branches give the same name to different stacks, the
+    /// most recent `NameStack` payload wins. Blocks are ordered by height
+    /// (see [`Chain::height`](crate::chain::Chain::height)) and then by ID,
+    /// which is a topological order that every peer agrees on regardless of
+    /// the order blocks were received in; within a block, later payloads win.
+    pub fn current_named(&self, name: &str) -> Option<&Stack> {
         self.get_by_id(self.name_map.get(name)?)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{PrivateSecretMap, SecretMap, StackMap};
+    use crate::{
+        crypto::{
+            map,
+            vtmf::{Mask, Stack, Vtmf},
+        },
+        random::thread_rng,
+        serde::Message,
+    };
+
+    #[test]
+    fn previews_match_resulting_stacks() {
+        let a: Stack = (0..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let b: Stack = (4..6).map(|i| Mask::open(map::to_curve(i))).collect();
+        let mut stacks = StackMap::new();
+        stacks.insert(a.clone());
+        stacks.insert(b.clone());
+
+        let (taken, id) = stacks.preview_take(&a.id(), &[3, 1]).unwrap();
+        assert_eq!(taken, vec![a[3], a[1]].into_iter().collect::<Stack>());
+        assert_eq!(id, taken.id());
+        assert!(stacks.preview_take(&a.id(), &[4]).is_none());
+
+        let (piled, id) = stacks.preview_pile(&[b.id(), a.id()]).unwrap();
+        assert_eq!(piled, b.iter().chain(a.iter()).cloned().collect::<Stack>());
+        assert_eq!(id, piled.id());
+        assert!(stacks.preview_pile(&[taken.id()]).is_none());
+
+        stacks.burn(b.id());
+        assert!(stacks.preview_take(&b.id(), &[0]).is_none());
+        assert!(stacks.preview_pile(&[a.id(), b.id()]).is_none());
+    }
+
+    #[test]
+    fn decks_group_stacks_by_name() {
+        let a: Stack = (0..2).map(|i| Mask::open(map::to_curve(i))).collect();
+        let b: Stack = (2..4).map(|i| Mask::open(map::to_curve(i))).collect();
+        let c: Stack = (4..6).map(|i| Mask::open(map::to_curve(i))).collect();
+        let mut stacks = StackMap::new();
+        stacks.insert(a.clone());
+        stacks.insert(b.clone());
+        stacks.insert_in("blue", c.clone());
+        stacks.set_name(a.id(), "red/hand".into());
+        stacks.set_name(b.id(), "blue/hand".into());
+
+        assert_eq!(stacks.decks().collect::<Vec<_>>(), vec!["blue", "red"]);
+        assert_eq!(stacks.get_by_name("red/hand"), Some(&a));
+        assert_eq!(stacks.get_by_name("blue/hand"), Some(&b));
+        assert_eq!(stacks.stacks_in("red").collect::<Vec<_>>(), vec![&a]);
+        let mut blue: Vec<_> = stacks.stacks_in("blue").map(Stack::id).collect();
+        blue.sort();
+        let mut expected = vec![b.id(), c.id()];
+        expected.sort();
+        assert_eq!(blue, expected);
+        assert_eq!(StackMap::split_name("hand"), (None, "hand"));
+    }
+
+    #[test]
+    fn secret_maps_round_trip() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let fps: Vec<_> = vtmfs
+            .iter()
+            .map(|v| v.private_key().fingerprint())
+            .collect();
+        let (stack, r, _) = vtmfs[0].mask_all(&[map::to_curve(1), map::to_curve(2)]);
+        let mut stacks = StackMap::new();
+        stacks.insert(stack.clone());
+        for (vtmf, fp) in vtmfs.iter().zip(fps.iter()) {
+            let shares = stack.iter().map(|m| vtmf.unmask_share(m).0).collect();
+            stacks.add_secret_share(stack.id(), *fp, shares);
+        }
+        let base = Mask::open(map::to_curve(3));
+        let private = stack.iter().cloned().zip(r.iter().map(|r| r * base));
+        stacks.add_private_secrets(private).unwrap();
+
+        let exported = stacks.secrets().encode().unwrap();
+        let decoded = SecretMap::decode(&exported).unwrap();
+        assert_eq!(&decoded, stacks.secrets());
+        let exported = stacks.private_secrets().encode().unwrap();
+        let decoded = PrivateSecretMap::decode(&exported).unwrap();
+        assert_eq!(&decoded, stacks.private_secrets());
+    }
+
+    #[test]
+    fn published_secrets_merge_into_substitutes() {
+        let mut rng = thread_rng();
+        let vtmfs = Vtmf::test_group(2, &mut rng);
+        let fps: Vec<_> = vtmfs
+            .iter()
+            .map(|v| v.private_key().fingerprint())
+            .collect();
+        let (stack, ..) = vtmfs[0].mask_all(&[map::to_curve(1)]);
+        let shares: Vec<_> = vtmfs
+            .iter()
+            .map(|v| vec![v.unmask_share(&stack[0]).0])
+            .collect();
+
+        let mut original = StackMap::new();
+        original.insert(stack.clone());
+        original.add_secret_share(stack.id(), fps[0], shares[0].clone());
+        original.add_secret_share(stack.id(), fps[1], shares[1].clone());
+        let mut substitute = StackMap::new();
+        substitute.insert(stack.clone());
+        substitute.add_secret_share(stack.id(), fps[1], shares[1].clone());
+
+        let exported = original.secrets().encode().unwrap();
+        let imported = SecretMap::decode(&exported).unwrap();
+        substitute.add_published_secrets(imported.into_iter());
+        assert_eq!(substitute.secrets(), original.secrets());
+
+        let mut partial = StackMap::new();
+        partial.insert(stack.clone());
+        partial.add_secret_share(stack.id(), fps[0], shares[0].clone());
+        substitute.add_published_secrets(partial.secrets().clone().into_iter());
+        assert_eq!(substitute.secrets(), original.secrets());
+    }
+}
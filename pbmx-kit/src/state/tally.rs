@@ -0,0 +1,104 @@
+use crate::{
+    crypto::{
+        keys::Fingerprint,
+        map,
+        vtmf::{Mask, SecretShare, Vtmf},
+    },
+    Error,
+};
+use curve25519_dalek::{ristretto::RistrettoPoint, traits::Identity};
+
+/// A verifiable yes/no vote tally
+///
+/// Each party casts a masked ballot for 0 or 1, and only the sum of all
+/// ballots is ever unmasked.
+#[derive(Debug, Clone)]
+pub struct Tally {
+    parties: Vec<Fingerprint>,
+    ballots: Mask,
+    voters: Vec<Fingerprint>,
+    secret: SecretShare,
+    secret_fp: Vec<Fingerprint>,
+}
+
+impl Tally {
+    /// Creates a new tally distributed over the given parties
+    pub fn new(parties: Vec<Fingerprint>) -> Self {
+        Self {
+            parties,
+            ballots: Mask::open(RistrettoPoint::identity()),
+            voters: Vec::new(),
+            secret: SecretShare(RistrettoPoint::identity()),
+            secret_fp: Vec::new(),
+        }
+    }
+
+    /// Gets the points a ballot can mask, for no and yes respectively
+    pub fn choices() -> Vec<RistrettoPoint> {
+        vec![map::to_curve_additive(0), map::to_curve_additive(1)]
+    }
+
+    /// Gets this tally's aggregate mask
+    pub fn mask(&self) -> &Mask {
+        &self.ballots
+    }
+
+    /// Adds a ballot to this tally
+    ///
+    /// Only the parties the tally was created for can vote, once each.
+    pub fn add_ballot(&mut self, party: Fingerprint, ballot: &Mask) -> Result<(), Error> {
+        if !self.parties.contains(&party) || self.voters.contains(&party) {
+            return Err(Error::InvalidPayload);
+        }
+        self.ballots += ballot;
+        self.voters.push(party);
+        Ok(())
+    }
+
+    /// Adds a secret to this tally
+    ///
+    /// Only the parties the tally was created for can reveal, once each, and
+    /// only after everyone voted.
+    pub fn add_secret(&mut self, party: Fingerprint, share: &SecretShare) -> Result<(), Error> {
+        if !self.parties.contains(&party) || !self.is_closed() || self.secret_fp.contains(&party) {
+            return Err(Error::InvalidPayload);
+        }
+        self.secret.0 += share.0;
+        self.secret_fp.push(party);
+        Ok(())
+    }
+
+    /// Gets the list of parties the tally was created for
+    pub fn parties(&self) -> &[Fingerprint] {
+        &self.parties
+    }
+
+    /// Gets a list of parties that have voted
+    pub fn voters(&self) -> &[Fingerprint] {
+        &self.voters
+    }
+
+    /// Gets a list of parties that have revealed secrets
+    pub fn secret_parties(&self) -> &[Fingerprint] {
+        &self.secret_fp
+    }
+
+    /// Tests whether all parties have voted
+    pub fn is_closed(&self) -> bool {
+        self.voters().len() == self.parties.len()
+    }
+
+    /// Tests whether all secrets for revealing the result have been collected
+    pub fn is_revealed(&self) -> bool {
+        self.secret_parties().len() == self.parties.len()
+    }
+
+    /// Gets the number of yes votes, once revealed
+    pub fn result(&self, vtmf: &Vtmf) -> Option<u64> {
+        if !self.is_revealed() {
+            return None;
+        }
+        let r = vtmf.unmask(&self.ballots, &self.secret);
+        vtmf.unmask_sum(&r, self.parties.len() as u64)
+    }
+}
@@ -0,0 +1,139 @@
+use crate::crypto::{
+    map,
+    vtmf::{Mask, Stack},
+};
+
+/// A built-in deck of tokens
+///
+/// Templates expand into open stacks whose tokens are mapped with
+/// [`map::to_curve_fixed`], so every party produces the same points and the
+/// same stack ID from a template's name. Opening the same template twice thus
+/// yields the same stack.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DeckTemplate {
+    /// A standard 52-card deck
+    ///
+    /// Tokens 1 to 52 are the spades, hearts, diamonds and clubs, each from
+    /// ace to king.
+    Standard52,
+    /// A standard 52-card deck with two jokers, as tokens 53 and 54
+    Standard54,
+    /// An UNO deck
+    ///
+    /// Tokens 1 to 52 are the red, yellow, green and blue cards, each from 0
+    /// to 9 followed by skip, reverse and draw two; tokens 53 and 54 are the
+    /// wild and wild draw four cards. The deck holds 108 cards, with repeated
+    /// tokens for repeated cards.
+    Uno,
+}
+
+const RANKS: [&str; 13] = [
+    "A", "2", "3", "4", "5", "6", "7", "8", "9", "10", "J", "Q", "K",
+];
+const SUITS: [&str; 4] = ["\u{2660}", "\u{2665}", "\u{2666}", "\u{2663}"];
+const UNO_FACES: [&str; 13] = [
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "skip", "reverse", "+2",
+];
+const UNO_COLORS: [&str; 4] = ["red", "yellow", "green", "blue"];
+
+impl DeckTemplate {
+    /// Gets the names of all built-in templates
+    pub fn names() -> &'static [&'static str] {
+        &["standard52", "standard54", "uno"]
+    }
+
+    /// Gets a built-in template by name
+    pub fn get(name: &str) -> Option<DeckTemplate> {
+        match name {
+            "standard52" => Some(DeckTemplate::Standard52),
+            "standard54" => Some(DeckTemplate::Standard54),
+            "uno" => Some(DeckTemplate::Uno),
+            _ => None,
+        }
+    }
+
+    /// Gets this template's name
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeckTemplate::Standard52 => "standard52",
+            DeckTemplate::Standard54 => "standard54",
+            DeckTemplate::Uno => "uno",
+        }
+    }
+
+    /// Gets this template's tokens in canonical order
+    pub fn tokens(&self) -> Vec<u64> {
+        match self {
+            DeckTemplate::Standard52 => (1..=52).collect(),
+            DeckTemplate::Standard54 => (1..=54).collect(),
+            DeckTemplate::Uno => {
+                let mut tokens = Vec::with_capacity(108);
+                for color in 0..4 {
+                    let first = color * 13 + 1;
+                    tokens.push(first);
+                    for face in 1..13 {
+                        tokens.push(first + face);
+                        tokens.push(first + face);
+                    }
+                }
+                tokens.extend([53; 4].iter().chain([54; 4].iter()));
+                tokens
+            }
+        }
+    }
+
+    /// Gets the name of one of this template's tokens
+    pub fn token_name(&self, token: u64) -> Option<String> {
+        let i = token.checked_sub(1)? as usize;
+        let (group, face) = (i / 13, i % 13);
+        match self {
+            DeckTemplate::Standard52 | DeckTemplate::Standard54 if token <= 52 => {
+                Some(format!("{}{}", RANKS[face], SUITS[group]))
+            }
+            DeckTemplate::Standard54 if token <= 54 => Some(format!("joker {}", token - 52)),
+            DeckTemplate::Uno if token <= 52 => {
+                Some(format!("{} {}", UNO_COLORS[group], UNO_FACES[face]))
+            }
+            DeckTemplate::Uno if token == 53 => Some("wild".into()),
+            DeckTemplate::Uno if token == 54 => Some("wild +4".into()),
+            _ => None,
+        }
+    }
+
+    /// Expands this template into an open stack
+    pub fn stack(&self) -> Stack {
+        self.tokens()
+            .into_iter()
+            .map(|t| Mask::open(map::to_curve_fixed(t)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DeckTemplate;
+    use crate::crypto::map;
+
+    #[test]
+    fn templates_expand_identically() {
+        for name in DeckTemplate::names() {
+            let template = DeckTemplate::get(name).unwrap();
+            assert_eq!(template.name(), *name);
+            assert_eq!(template.stack(), template.stack());
+            assert_eq!(template.stack().id(), template.stack().id());
+        }
+
+        let deck = DeckTemplate::Standard52.stack();
+        assert_eq!(deck.len(), 52);
+        assert_eq!(DeckTemplate::Standard54.stack().len(), 54);
+        assert_eq!(DeckTemplate::Uno.stack().len(), 108);
+        assert_eq!(map::from_curve(&deck[0].1), Some(1));
+        assert_eq!(
+            DeckTemplate::Standard52.token_name(1),
+            Some("A\u{2660}".into())
+        );
+        assert_eq!(DeckTemplate::Uno.token_name(54), Some("wild +4".into()));
+        assert_eq!(DeckTemplate::Standard52.token_name(53), None);
+        assert_eq!(DeckTemplate::get("tarot"), None);
+    }
+}
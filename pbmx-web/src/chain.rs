@@ -2,14 +2,14 @@ use crate::{
     keys::{Fingerprint, PrivateKey, PublicKey},
     utils,
     vtmf::{
-        EntanglementProof, Mask, MaskProof, RotationProof, SecretShare, SecretShareProof,
-        ShuffleProof, Stack,
+        DistinctProof, EntanglementProof, Mask, MaskProof, PrivateMaskProof, RotationProof,
+        SecretShare, SecretShareProof, ShuffleProof, Stack,
     },
 };
 use js_sys::Array;
 use wasm_bindgen::prelude::*;
 
-use pbmx_kit::chain as kit;
+use pbmx_kit::{chain as kit, crypto::vtmf as kit_vtmf};
 
 #[wasm_bindgen]
 #[repr(transparent)]
@@ -23,6 +23,13 @@ pub struct BlockBuilder(pub(crate) kit::BlockBuilder);
 #[repr(transparent)]
 pub struct Payload(pub(crate) kit::Payload);
 
+#[wasm_bindgen]
+pub struct PublishSharesBuilder {
+    id: kit::Id,
+    shares: Vec<kit_vtmf::SecretShare>,
+    proofs: Vec<kit_vtmf::SecretShareProof>,
+}
+
 #[wasm_bindgen]
 impl Block {
     pub fn id(&self) -> Fingerprint {
@@ -81,6 +88,23 @@ impl BlockBuilder {
     }
 }
 
+#[wasm_bindgen]
+impl PublishSharesBuilder {
+    #[wasm_bindgen(js_name = pushShare)]
+    pub fn push_share(&mut self, share: SecretShare, proof: SecretShareProof) {
+        self.shares.push(share.0);
+        self.proofs.push(proof.0);
+    }
+
+    pub fn finish(self) -> Payload {
+        Payload(kit::Payload::PublishShares(
+            self.id,
+            self.shares,
+            self.proofs,
+        ))
+    }
+}
+
 #[wasm_bindgen]
 impl Payload {
     pub fn id(&self) -> Fingerprint {
@@ -124,11 +148,39 @@ impl Payload {
         Payload(kit::Payload::NameStack(id.0, name))
     }
 
+    #[wasm_bindgen(js_name = tagStack)]
+    pub fn tag_stack(id: Fingerprint, tags: Vec<JsValue>) -> Payload {
+        Payload(kit::Payload::TagStack(
+            id.0,
+            tags.iter().filter_map(JsValue::as_string).collect(),
+        ))
+    }
+
     #[wasm_bindgen(js_name = takeStack)]
     pub fn take_stack(id1: Fingerprint, indices: Vec<usize>, id2: Fingerprint) -> Payload {
         Payload(kit::Payload::TakeStack(id1.0, indices, id2.0))
     }
 
+    #[wasm_bindgen(js_name = burnStack)]
+    pub fn burn_stack(id1: Fingerprint, indices: Vec<usize>, id2: Fingerprint) -> Payload {
+        Payload(kit::Payload::BurnStack(id1.0, indices, id2.0))
+    }
+
+    #[wasm_bindgen(js_name = insertStack)]
+    pub fn insert_stack(
+        id1: Fingerprint,
+        id2: Fingerprint,
+        rng: String,
+        id3: Fingerprint,
+    ) -> Payload {
+        Payload(kit::Payload::InsertStack(id1.0, id2.0, rng, id3.0))
+    }
+
+    #[wasm_bindgen(js_name = dealFaceUp)]
+    pub fn deal_face_up(id1: Fingerprint, indices: Vec<usize>, id2: Fingerprint) -> Payload {
+        Payload(kit::Payload::DealFaceUp(id1.0, indices, id2.0))
+    }
+
     #[wasm_bindgen(js_name = pileStacks)]
     pub fn pile_stacks(ids: &[u32], id: Fingerprint) -> Payload {
         let ids = utils::vec_from_wasm(ids)
@@ -148,6 +200,31 @@ impl Payload {
         Payload(kit::Payload::PublishShares(id.0, shares, proofs))
     }
 
+    #[wasm_bindgen(js_name = publishSharesAt)]
+    pub fn publish_shares_at(
+        id: Fingerprint,
+        indices: Vec<usize>,
+        shares: &[u32],
+        proofs: &[u32],
+    ) -> Payload {
+        let shares = utils::vec_from_wasm(shares)
+            .map(|s: SecretShare| s.0)
+            .collect();
+        let proofs = utils::vec_from_wasm(proofs)
+            .map(|p: SecretShareProof| p.0)
+            .collect();
+        Payload(kit::Payload::PublishSharesAt(id.0, indices, shares, proofs))
+    }
+
+    #[wasm_bindgen(js_name = beginPublishShares)]
+    pub fn begin_publish_shares(id: Fingerprint) -> PublishSharesBuilder {
+        PublishSharesBuilder {
+            id: id.0,
+            shares: Vec::new(),
+            proofs: Vec::new(),
+        }
+    }
+
     #[wasm_bindgen(js_name = randomSpec)]
     pub fn random_spec(name: String, spec: String) -> Payload {
         Payload(kit::Payload::RandomSpec(name, spec))
@@ -158,6 +235,11 @@ impl Payload {
         Payload(kit::Payload::RandomEntropy(name, entropy.0))
     }
 
+    #[wasm_bindgen(js_name = beaconEntropy)]
+    pub fn beacon_entropy(name: String, round: u64, value: Vec<u8>, proof: Vec<u8>) -> Payload {
+        Payload(kit::Payload::BeaconEntropy(name, round, value, proof))
+    }
+
     #[wasm_bindgen(js_name = randomReveal)]
     pub fn random_reveal(name: String, share: SecretShare, proof: SecretShareProof) -> Payload {
         Payload(kit::Payload::RandomReveal(name, share.0, proof.0))
@@ -174,6 +256,126 @@ impl Payload {
         Payload(kit::Payload::ProveEntanglement(ids1, ids2, proof.0))
     }
 
+    #[wasm_bindgen(js_name = castBallot)]
+    pub fn cast_ballot(topic: String, ballot: Mask, proof: PrivateMaskProof) -> Payload {
+        Payload(kit::Payload::CastBallot(topic, ballot.0, proof.0))
+    }
+
+    #[wasm_bindgen(js_name = revealTally)]
+    pub fn reveal_tally(topic: String, share: SecretShare, proof: SecretShareProof) -> Payload {
+        Payload(kit::Payload::RevealTally(topic, share.0, proof.0))
+    }
+
+    pub fn peek(id: Fingerprint, commit: Fingerprint) -> Payload {
+        Payload(kit::Payload::Peek(id.0, commit.0))
+    }
+
+    #[wasm_bindgen(js_name = openPeek)]
+    pub fn open_peek(id: Fingerprint, nonce: Fingerprint) -> Payload {
+        Payload(kit::Payload::OpenPeek(id.0, nonce.0))
+    }
+
+    #[wasm_bindgen(js_name = commitShift)]
+    pub fn commit_shift(id: Fingerprint, commit: Fingerprint) -> Payload {
+        Payload(kit::Payload::CommitShift(id.0, commit.0))
+    }
+
+    #[wasm_bindgen(js_name = commitShares)]
+    pub fn commit_shares(id: Fingerprint, rounds: u64, round: u64, commit: Fingerprint) -> Payload {
+        Payload(kit::Payload::CommitShares(id.0, rounds, round, commit.0))
+    }
+
+    #[wasm_bindgen(js_name = openShares)]
+    pub fn open_shares(id: Fingerprint, round: u64, share: Fingerprint) -> Payload {
+        Payload(kit::Payload::OpenShares(id.0, round, share.0))
+    }
+
+    #[wasm_bindgen(js_name = deckTemplate)]
+    pub fn deck_template(name: String) -> Payload {
+        Payload(kit::Payload::DeckTemplate(name))
+    }
+
+    #[wasm_bindgen(js_name = proveGreater)]
+    pub fn prove_greater(a: Mask, b: Mask, max: usize, proof: PrivateMaskProof) -> Payload {
+        Payload(kit::Payload::ProveGreater(a.0, b.0, max, proof.0))
+    }
+
+    #[wasm_bindgen(js_name = proveField)]
+    pub fn prove_field(
+        mask: Mask,
+        field_mask: u64,
+        field_value: u64,
+        max: u64,
+        proof: PrivateMaskProof,
+    ) -> Payload {
+        Payload(kit::Payload::ProveField(
+            mask.0,
+            field_mask,
+            field_value,
+            max,
+            proof.0,
+        ))
+    }
+
+    pub fn checkpoint(state: Fingerprint) -> Payload {
+        Payload(kit::Payload::Checkpoint(state.0))
+    }
+
+    #[wasm_bindgen(js_name = proveDistinct)]
+    pub fn prove_distinct(id: Fingerprint, blinded: Stack, proof: DistinctProof) -> Payload {
+        Payload(kit::Payload::ProveDistinct(id.0, blinded.0, proof.0))
+    }
+
+    #[wasm_bindgen(js_name = revealDistinct)]
+    pub fn reveal_distinct(id: Fingerprint, shares: &[u32], proofs: &[u32]) -> Payload {
+        let shares = utils::vec_from_wasm(shares)
+            .map(|s: SecretShare| s.0)
+            .collect();
+        let proofs = utils::vec_from_wasm(proofs)
+            .map(|p: SecretShareProof| p.0)
+            .collect();
+        Payload(kit::Payload::RevealDistinct(id.0, shares, proofs))
+    }
+
+    #[wasm_bindgen(js_name = proveEqualMultiset)]
+    pub fn prove_equal_multiset(
+        hidden: Fingerprint,
+        reference: Fingerprint,
+        proof: ShuffleProof,
+    ) -> Payload {
+        Payload(kit::Payload::ProveEqualMultiset(
+            hidden.0,
+            reference.0,
+            proof.0,
+        ))
+    }
+
+    #[wasm_bindgen(js_name = reshuffleFrom)]
+    pub fn reshuffle_from(
+        reference: Fingerprint,
+        source: Fingerprint,
+        stk: Stack,
+        proof: ShuffleProof,
+    ) -> Payload {
+        Payload(kit::Payload::ReshuffleFrom(
+            reference.0,
+            source.0,
+            stk.0,
+            proof.0,
+        ))
+    }
+
+    #[wasm_bindgen(js_name = selectHash)]
+    pub fn select_hash(algorithm: &str) -> Result<Payload, JsValue> {
+        let algorithm = algorithm.parse().map_err(|_| "unknown hash algorithm")?;
+        Ok(Payload(kit::Payload::SelectHash(algorithm)))
+    }
+
+    #[wasm_bindgen(js_name = renamePlayer)]
+    pub fn rename_player(fp: Fingerprint, name: String) -> Payload {
+        Payload(kit::Payload::RenamePlayer(fp.0, name))
+    }
+
     pub fn text(s: String) -> Payload {
         Payload(kit::Payload::Text(s))
     }
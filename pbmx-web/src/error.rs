@@ -0,0 +1,17 @@
+use pbmx_kit::Error as KitError;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[repr(transparent)]
+pub struct Error(pub(crate) KitError);
+
+#[wasm_bindgen]
+impl Error {
+    pub fn code(&self) -> u32 {
+        self.0.code()
+    }
+
+    pub fn message(&self) -> String {
+        self.0.to_string()
+    }
+}
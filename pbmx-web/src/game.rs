@@ -1,5 +1,6 @@
 use crate::{
     chain::{Block, BlockBuilder, Payload},
+    error::Error,
     keys::{Fingerprint, PrivateKey, PublicKey},
     vtmf::{Mask, SecretShare, SecretShareProof},
 };
@@ -80,6 +81,19 @@ impl Game {
         Some(Block(block))
     }
 
+    #[wasm_bindgen(js_name = tryAddBlock)]
+    pub fn try_add_block(&mut self, block: Block) -> Result<Block, JsValue> {
+        self.0.add_block(&block.0).map_err(Error)?;
+        Ok(block)
+    }
+
+    #[wasm_bindgen(js_name = tryFinishBlock)]
+    pub fn try_finish_block(&mut self, builder: BlockBuilder) -> Result<Block, JsValue> {
+        let block = builder.0.build(&self.0.vtmf.private_key());
+        self.0.add_block(&block).map_err(Error)?;
+        Ok(Block(block))
+    }
+
     pub fn join(&mut self, name: String) -> BlockBuilder {
         let pk = PublicKey(self.0.vtmf.public_key());
         let mut builder = self.build_block();
@@ -93,7 +107,7 @@ impl Game {
 
     #[wasm_bindgen(js_name = playerFingerprint)]
     pub fn player_fingerprint(&self) -> Fingerprint {
-        Fingerprint(self.0.vtmf.public_key().fingerprint())
+        Fingerprint(self.0.vtmf.fingerprint())
     }
 
     pub fn rngs(&self) -> Map {
@@ -109,6 +123,16 @@ impl Game {
         Mask(self.0.vtmf.mask_random(&mut pbmx_kit::random::thread_rng()))
     }
 
+    #[wasm_bindgen(js_name = maskValue)]
+    pub fn mask_value(&self, value: u64) -> Mask {
+        Mask(
+            self.0
+                .vtmf
+                .mask(&pbmx_kit::crypto::map::to_curve_additive(value))
+                .0,
+        )
+    }
+
     #[wasm_bindgen(js_name = unmaskShare)]
     pub fn unmask_share(&self, mask: &Mask) -> Array {
         let array = Array::new();
@@ -125,6 +149,11 @@ impl Rng {
         self.0.spec()
     }
 
+    #[wasm_bindgen(js_name = isSum)]
+    pub fn is_sum(&self) -> bool {
+        self.0.is_sum()
+    }
+
     pub fn mask(&self) -> Mask {
         Mask(*self.0.mask())
     }
@@ -147,10 +176,12 @@ impl Rng {
     pub fn state(&self, game: &Game) -> String {
         let fp = game.player_fingerprint().0;
         if !self.0.is_generated() {
-            if self.0.entropy_parties().contains(&fp) {
-                "waitEntropy"
-            } else {
+            if !self.0.entropy_parties().contains(&fp) {
                 "entropy"
+            } else if self.0.awaits_beacon() {
+                "beacon"
+            } else {
+                "waitEntropy"
             }
         } else if !self.0.is_revealed() {
             if self.0.secret_parties().contains(&fp) {
@@ -164,7 +195,17 @@ impl Rng {
         .into()
     }
 
-    pub fn value(&self, game: &Game) -> u64 {
+    pub fn value(&self, game: &Game) -> Option<u64> {
         self.0.gen(&game.0.vtmf)
     }
+
+    #[wasm_bindgen(js_name = rawBytes)]
+    pub fn raw_bytes(&self, game: &Game, n: usize) -> Option<Vec<u8>> {
+        self.0.gen_bytes(&game.0.vtmf, n)
+    }
+
+    #[wasm_bindgen(js_name = beaconRound)]
+    pub fn beacon_round(&self) -> Option<u64> {
+        self.0.beacon_round()
+    }
 }
@@ -1,6 +1,8 @@
 pub mod chain;
+pub mod error;
 pub mod game;
 pub mod keys;
+pub mod secrets;
 pub mod vtmf;
 
 mod utils;
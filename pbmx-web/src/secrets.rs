@@ -0,0 +1,72 @@
+use crate::game::Game;
+use wasm_bindgen::prelude::*;
+
+use pbmx_kit::state as kit;
+
+#[wasm_bindgen]
+#[repr(transparent)]
+pub struct SecretMap(pub(crate) kit::SecretMap);
+
+#[wasm_bindgen]
+#[repr(transparent)]
+pub struct PrivateSecretMap(pub(crate) kit::PrivateSecretMap);
+
+#[wasm_bindgen]
+impl SecretMap {
+    pub fn export(&self) -> String {
+        use pbmx_kit::serde::Message;
+        base64::encode_config(self.0.encode().unwrap(), base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn import(s: &str) -> Result<SecretMap, JsValue> {
+        use pbmx_kit::serde::Message;
+        let e = "invalid secret map";
+        Ok(Self(
+            kit::SecretMap::decode(
+                &base64::decode_config(s, base64::URL_SAFE_NO_PAD).map_err(|_| e)?,
+            )
+            .map_err(|_| e)?,
+        ))
+    }
+}
+
+#[wasm_bindgen]
+impl PrivateSecretMap {
+    pub fn export(&self) -> String {
+        use pbmx_kit::serde::Message;
+        base64::encode_config(self.0.encode().unwrap(), base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn import(s: &str) -> Result<PrivateSecretMap, JsValue> {
+        use pbmx_kit::serde::Message;
+        let e = "invalid private secret map";
+        Ok(Self(
+            kit::PrivateSecretMap::decode(
+                &base64::decode_config(s, base64::URL_SAFE_NO_PAD).map_err(|_| e)?,
+            )
+            .map_err(|_| e)?,
+        ))
+    }
+}
+
+#[wasm_bindgen]
+impl Game {
+    pub fn secrets(&self) -> SecretMap {
+        SecretMap(self.0.stacks.secrets().clone())
+    }
+
+    #[wasm_bindgen(js_name = privateSecrets)]
+    pub fn private_secrets(&self) -> PrivateSecretMap {
+        PrivateSecretMap(self.0.stacks.private_secrets().clone())
+    }
+
+    #[wasm_bindgen(js_name = addSecrets)]
+    pub fn add_secrets(&mut self, secrets: SecretMap) {
+        self.0.add_published_secrets(secrets.0.into_iter());
+    }
+
+    #[wasm_bindgen(js_name = addPrivateSecrets)]
+    pub fn add_private_secrets(&mut self, secrets: PrivateSecretMap) {
+        self.0.add_secrets(secrets.0.into_iter()).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 use crate::keys::Fingerprint;
+use js_sys::Array;
 use wasm_bindgen::prelude::*;
 
 use pbmx_kit::crypto::vtmf as kit;
@@ -19,6 +20,10 @@ pub struct Stack(pub(crate) kit::Stack);
 #[repr(transparent)]
 pub struct MaskProof(pub(crate) kit::MaskProof);
 
+#[wasm_bindgen]
+#[repr(transparent)]
+pub struct PrivateMaskProof(pub(crate) kit::PrivateMaskProof);
+
 #[wasm_bindgen]
 #[repr(transparent)]
 pub struct SecretShareProof(pub(crate) kit::SecretShareProof);
@@ -35,9 +40,22 @@ pub struct RotationProof(pub(crate) kit::ShiftProof);
 #[repr(transparent)]
 pub struct EntanglementProof(pub(crate) kit::EntanglementProof);
 
+#[wasm_bindgen]
+#[repr(transparent)]
+pub struct DistinctProof(pub(crate) kit::DistinctProof);
+
 #[wasm_bindgen]
 impl Stack {
     pub fn id(&self) -> Fingerprint {
         Fingerprint(self.0.id())
     }
+
+    #[wasm_bindgen(js_name = positionalIds)]
+    pub fn positional_ids(&self) -> Array {
+        self.0
+            .positional_ids()
+            .into_iter()
+            .map(|id| JsValue::from(Fingerprint(id)))
+            .collect()
+    }
 }